@@ -0,0 +1,11 @@
+#![no_main]
+
+use hack::{difftest, gen::JackProgram};
+use libfuzzer_sys::fuzz_target;
+
+// Any failure of the toolchain or divergence between the VM and the CPU is a bug.
+fuzz_target!(|program: JackProgram| {
+    if let Err(e) = difftest::run(&program) {
+        panic!("{e:?}");
+    }
+});
@@ -4,6 +4,6 @@ use libfuzzer_sys::fuzz_target;
 
 fuzz_target!(|data: &[u8]| {
     if let Ok(data) = std::str::from_utf8(data) {
-        let _  = jack::parser::parse(data);
+        let _ = jack_compiler::parser::parse(data);
     }
 });
@@ -0,0 +1,285 @@
+//! Emulator of the Hack CPU, which runs the machine code generated by `compile`.
+//!
+//! Every C-instruction is evaluated by the ALU bits of `comp` rather than by looking up
+//! `codegen::OPCODE`, so that the emulator does not share mistakes with the assembler.
+use anyhow::{bail, Context as _};
+
+/// Number of words in the RAM, which is the address space of A-instructions.
+pub const RAM_SIZE: usize = 1 << 15;
+
+/// Why `Cpu::run` stopped.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Exit {
+    /// the program reached an infinite loop of `(END) @END 0;JMP`
+    Halted,
+    /// the program ran `max_steps` instructions without halting
+    StepLimit,
+}
+
+pub struct Cpu {
+    rom: Vec<u16>,
+    ram: Vec<i16>,
+    a: i16,
+    d: i16,
+    pc: usize,
+    steps: usize,
+}
+
+impl Cpu {
+    /// Loads `binary`, the 16-bit words in the textual form `compile` generates.
+    pub fn new(binary: &[String]) -> anyhow::Result<Self> {
+        let rom = binary
+            .iter()
+            .map(|word| {
+                if word.len() != 16 {
+                    bail!("not a 16-bit word: {word}");
+                }
+                u16::from_str_radix(word, 2).with_context(|| format!("not a binary: {word}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Cpu {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            a: 0,
+            d: 0,
+            pc: 0,
+            steps: 0,
+        })
+    }
+
+    pub fn ram(&self) -> &[i16] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [i16] {
+        &mut self.ram
+    }
+
+    /// Address of the next instruction in the ROM, which is the jump of `(END) @END 0;JMP` after
+    /// halting.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Runs the program until it halts, or `max_steps` instructions are executed.
+    pub fn run(&mut self, max_steps: usize) -> anyhow::Result<Exit> {
+        for _ in 0..max_steps {
+            if self.step()? {
+                return Ok(Exit::Halted);
+            }
+        }
+
+        Ok(Exit::StepLimit)
+    }
+
+    // Executes an instruction, and returns `true` if the program halted.
+    fn step(&mut self) -> anyhow::Result<bool> {
+        let Some(&insn) = self.rom.get(self.pc) else {
+            bail!("program counter out of the ROM: {}", self.pc);
+        };
+        self.steps += 1;
+
+        // A-instruction
+        if insn & 0x8000 == 0 {
+            self.a = insn as i16;
+            self.pc += 1;
+            return Ok(false);
+        }
+
+        // C-instruction: 111a cccc ccdd djjj
+        let y = if insn & 0x1000 != 0 {
+            self.read()?
+        } else {
+            self.a
+        };
+        let out = alu(self.d, y, (insn >> 6) & 0b11_1111);
+
+        let addr = self.a;
+        if insn & 0b001_000 != 0 {
+            self.write(out)?;
+        }
+        if insn & 0b100_000 != 0 {
+            self.a = out;
+        }
+        if insn & 0b010_000 != 0 {
+            self.d = out;
+        }
+
+        let jump = insn & 0b111;
+        let taken = (jump & 0b100 != 0 && out < 0)
+            || (jump & 0b010 != 0 && out == 0)
+            || (jump & 0b001 != 0 && out > 0);
+        if !taken {
+            self.pc += 1;
+            return Ok(false);
+        }
+
+        let target = usize::try_from(addr).context("jump to a negative address")?;
+        // `(END) @END 0;JMP`
+        if target + 1 == self.pc && self.rom[target] == target as u16 {
+            return Ok(true);
+        }
+        self.pc = target;
+
+        Ok(false)
+    }
+
+    fn read(&self) -> anyhow::Result<i16> {
+        usize::try_from(self.a)
+            .ok()
+            .and_then(|addr| self.ram.get(addr).copied())
+            .with_context(|| format!("memory access out of range: {}", self.a))
+    }
+
+    fn write(&mut self, value: i16) -> anyhow::Result<()> {
+        let addr = self.a;
+        let Some(word) = usize::try_from(addr).ok().and_then(|addr| self.ram.get_mut(addr)) else {
+            bail!("memory access out of range: {addr}");
+        };
+        *word = value;
+        Ok(())
+    }
+}
+
+// The Hack ALU, controlled by the bits `zx nx zy ny f no`.
+fn alu(x: i16, y: i16, control: u16) -> i16 {
+    let bit = |n: u16| control & (1 << (5 - n)) != 0;
+
+    let x = if bit(0) { 0 } else { x };
+    let x = if bit(1) { !x } else { x };
+    let y = if bit(2) { 0 } else { y };
+    let y = if bit(3) { !y } else { y };
+    let out = if bit(4) { x.wrapping_add(y) } else { x & y };
+    if bit(5) {
+        !out
+    } else {
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+
+    fn run(asm: &str) -> Cpu {
+        let program: String = asm.chars().filter(|c| *c != ' ').collect();
+        let mut cpu = Cpu::new(&compile(&program).unwrap()).unwrap();
+        assert_eq!(cpu.run(1000).unwrap(), Exit::Halted);
+        cpu
+    }
+
+    #[test]
+    fn add_two_constants() {
+        let cpu = run("
+            @2
+            D = A
+            @3
+            D = D + A
+            @0
+            M = D
+            (END)
+            @END
+            0; JMP
+        ");
+
+        assert_eq!(cpu.ram()[0], 5);
+    }
+
+    #[test]
+    fn halt_on_the_jump_of_the_end_loop() {
+        let asm = "// start\n@2\nD = A\n(END)\n@END\n0; JMP\n";
+        let cpu = run(asm);
+
+        let addresses = crate::parser::addresses(asm);
+        assert_eq!(addresses, [0, 0, 1, 2, 2, 3]);
+        assert_eq!(cpu.pc(), usize::from(addresses[5]));
+    }
+
+    #[test]
+    fn every_comp_of_the_assembler() {
+        // D = 6, A = 3, M = RAM[3] = -2
+        let cases = [
+            ("0", 0),
+            ("1", 1),
+            ("-1", -1),
+            ("D", 6),
+            ("A", 3),
+            ("!D", !6),
+            ("!A", !3),
+            ("-D", -6),
+            ("-A", -3),
+            ("D + 1", 7),
+            ("A + 1", 4),
+            ("D - 1", 5),
+            ("A - 1", 2),
+            ("D + A", 9),
+            ("D - A", 3),
+            ("A - D", -3),
+            ("D & A", 2),
+            ("D | A", 7),
+            ("M", -2),
+            ("!M", 1),
+            ("-M", 2),
+            ("M + 1", -1),
+            ("M - 1", -3),
+            ("D + M", 4),
+            ("D - M", 8),
+            ("M - D", -8),
+            ("D & M", 6),
+            ("D | M", -2),
+        ];
+
+        for (comp, expected) in cases {
+            let cpu = run(&format!(
+                "
+                @2
+                D = -A
+                @3
+                M = D
+                @6
+                D = A
+                @3
+                D = {comp}
+                @16
+                M = D
+                (END)
+                @END
+                0; JMP
+            "
+            ));
+            assert_eq!(cpu.ram()[16], expected, "{comp}");
+        }
+    }
+
+    #[test]
+    fn loop_until_zero() {
+        // RAM[1] = 1 + 2 + ... + 10
+        let cpu = run("
+            @10
+            D = A
+            @0
+            M = D
+            (LOOP)
+            @0
+            D = M
+            @1
+            M = M + D
+            @0
+            MD = M - 1
+            @LOOP
+            D; JGT
+            (END)
+            @END
+            0; JMP
+        ");
+
+        assert_eq!(cpu.ram()[1], 55);
+    }
+}
@@ -8,6 +8,7 @@ use symbol::SymbolTable;
 
 pub mod codegen;
 pub mod commands;
+pub mod emulator;
 pub mod parser;
 pub mod symbol;
 
@@ -15,11 +15,7 @@ pub fn parse(program: &str) -> ParseResult {
     let mut errors = Vec::new();
 
     for (row, line) in program.lines().enumerate() {
-        let valid_code_range = line.find(COMMENT).unwrap_or(line.len());
-        let asm = line[..valid_code_range].trim();
-        if asm.is_empty() {
-            continue;
-        }
+        let Some(asm) = code(line) else { continue };
 
         match Command::parse(asm) {
             Ok(command) => commands.push(command),
@@ -33,6 +29,29 @@ pub fn parse(program: &str) -> ParseResult {
     Ok((commands, errors))
 }
 
+/// ROM address of every line of `program`: the address of the instruction on the line, or of the
+/// next one for labels, comments and blank lines.
+pub fn addresses(program: &str) -> Vec<u16> {
+    let mut pc = 0;
+    program
+        .lines()
+        .map(|line| {
+            let address = pc;
+            if code(line).is_some_and(|asm| !asm.starts_with('(')) {
+                pc += 1;
+            }
+            address
+        })
+        .collect()
+}
+
+// the command on `line` without the comment, if any
+fn code(line: &str) -> Option<&str> {
+    let valid_code_range = line.find(COMMENT).unwrap_or(line.len());
+    let asm = line[..valid_code_range].trim();
+    (!asm.is_empty()).then_some(asm)
+}
+
 pub fn load_symbol<'s>(commands: &[Command<'s>], table: &mut SymbolTable<'s>) {
     // program counter
     let mut pc = 0;
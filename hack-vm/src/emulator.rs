@@ -0,0 +1,384 @@
+//! Emulator which runs VM programs directly, without translating them into Hack assembly.
+//!
+//! The memory follows the same conventions as `HackVm`, so that the RAM after running a program
+//! here can be compared with the RAM after running its translation on the Hack CPU:
+//! - `SP`, `LCL`, `ARG`, `THIS` and `THAT` live in `RAM[0..5]`, and `temp` in `RAM[5..13]`
+//! - static variables are allocated from `RAM[16]` in the order of their first appearance
+//! - the stack starts at `RAM[256]` if `Sys.init` is defined
+//!
+//! Arithmetic wraps around like the Hack ALU, while comparisons follow the mathematical order of
+//! the 16-bit values.
+use anyhow::{bail, Context as _};
+use rustc_hash::FxHashMap;
+
+use crate::{segment::Segment, InsnKind, Instruction};
+
+/// Number of words in the RAM, which is the address space of A-instructions.
+pub const RAM_SIZE: usize = 1 << 15;
+
+const SP: usize = 0;
+const LCL: usize = 1;
+const ARG: usize = 2;
+const THIS: usize = 3;
+const THAT: usize = 4;
+const TEMP: usize = 5;
+const STATIC_BASE: usize = 16;
+const STACK_BASE: i16 = 256;
+
+/// Why `Emulator::run` stopped.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Exit {
+    /// the program reached an infinite loop of a `goto` to the label right before it, which is
+    /// how `Sys.init` ends
+    Halted,
+    /// the program ran `max_steps` instructions without halting
+    StepLimit,
+}
+
+pub struct Emulator<'p, 's> {
+    program: Vec<&'p InsnKind<'s>>,
+    // resolved jump target of every `goto` and `if-goto`
+    targets: FxHashMap<usize, usize>,
+    functions: FxHashMap<&'s str, usize>,
+    // address of static variables for every `push`/`pop static`
+    statics: FxHashMap<usize, usize>,
+    ram: Vec<i16>,
+    pc: usize,
+    steps: usize,
+}
+
+impl<'p, 's> Emulator<'p, 's> {
+    /// Loads `instructions` of every file, in the same form as `HackVm::interpret` takes.
+    pub fn new(instructions: &'p [(&'s str, Vec<Instruction<'s>>)]) -> anyhow::Result<Self> {
+        use InsnKind::*;
+
+        let mut program = Vec::new();
+        // labels are scoped by the function which defines them, like `HackVm::label`
+        let mut labels = FxHashMap::default();
+        let mut jumps = Vec::new();
+        let mut functions = FxHashMap::default();
+        let mut statics = FxHashMap::default();
+        let mut static_addrs = FxHashMap::default();
+        let mut current_fn = None;
+
+        for (file, is) in instructions {
+            for insn in is {
+                let pc = program.len();
+                let scoped = |label: &str| match current_fn {
+                    Some(f) => format!("{f}${label}"),
+                    None => label.to_string(),
+                };
+
+                match insn.kind {
+                    Label(label) => {
+                        labels.insert(scoped(label), pc);
+                    }
+                    Goto(label) | IfGoto(label) => jumps.push((pc, scoped(label))),
+                    DefFn(name, _) => {
+                        current_fn = Some(name);
+                        functions.insert(name, pc);
+                    }
+                    Push(Segment::Static, index) | Pop(Segment::Static, index) => {
+                        let next = STATIC_BASE + static_addrs.len();
+                        let addr = *static_addrs.entry((*file, index)).or_insert(next);
+                        statics.insert(pc, addr);
+                    }
+                    _ => {}
+                }
+                program.push(&insn.kind);
+            }
+        }
+
+        let mut targets = FxHashMap::default();
+        for (pc, label) in jumps {
+            let target = labels
+                .get(&label)
+                .with_context(|| format!("undefined label: {label}"))?;
+            targets.insert(pc, *target);
+        }
+
+        let mut emulator = Emulator {
+            program,
+            targets,
+            functions,
+            statics,
+            ram: vec![0; RAM_SIZE],
+            pc: 0,
+            steps: 0,
+        };
+
+        if emulator.functions.contains_key("Sys.init") {
+            emulator.ram[SP] = STACK_BASE;
+            // there is no instruction to return to, as `Sys.init` is not supposed to return
+            emulator.call("Sys.init", 0, usize::MAX)?;
+        }
+
+        Ok(emulator)
+    }
+
+    pub fn ram(&self) -> &[i16] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [i16] {
+        &mut self.ram
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Runs the program until it halts, or `max_steps` instructions are executed.
+    pub fn run(&mut self, max_steps: usize) -> anyhow::Result<Exit> {
+        for _ in 0..max_steps {
+            if self.step()? {
+                return Ok(Exit::Halted);
+            }
+        }
+
+        Ok(Exit::StepLimit)
+    }
+
+    // Executes an instruction, and returns `true` if the program halted.
+    fn step(&mut self) -> anyhow::Result<bool> {
+        use InsnKind::*;
+
+        let Some(insn) = self.program.get(self.pc) else {
+            bail!("program counter out of the program: {}", self.pc);
+        };
+        self.steps += 1;
+        let mut next = self.pc + 1;
+
+        match *insn {
+            Add => self.binary(i16::wrapping_add)?,
+            Sub => self.binary(i16::wrapping_sub)?,
+            And => self.binary(|x, y| x & y)?,
+            Or => self.binary(|x, y| x | y)?,
+            Neg => self.unary(i16::wrapping_neg)?,
+            Not => self.unary(|x| !x)?,
+            Eq => self.binary(|x, y| -i16::from(x == y))?,
+            Gt => self.binary(|x, y| -i16::from(x > y))?,
+            Lt => self.binary(|x, y| -i16::from(x < y))?,
+            Push(Segment::Constant, index) => {
+                let value = i16::try_from(*index)
+                    .with_context(|| format!("constant out of range: {index}"))?;
+                self.push(value)?;
+            }
+            Push(ref segment, index) => {
+                let addr = self.address(segment, *index)?;
+                let value = self.read(addr)?;
+                self.push(value)?;
+            }
+            Pop(ref segment, index) => {
+                let addr = self.address(segment, *index)?;
+                let value = self.pop()?;
+                self.write(addr, value)?;
+            }
+            Label(_) => {}
+            Goto(_) => {
+                let target = self.targets[&self.pc];
+                // `label END; goto END`
+                if target + 1 == self.pc {
+                    return Ok(true);
+                }
+                next = target;
+            }
+            IfGoto(_) => {
+                if self.pop()? != 0 {
+                    next = self.targets[&self.pc];
+                }
+            }
+            DefFn(_, n_locals) => {
+                for _ in 0..*n_locals {
+                    self.push(0)?;
+                }
+            }
+            CallFn(name, n_args) => {
+                self.call(name, *n_args, next)?;
+                next = self.pc;
+            }
+            Return => next = self.ret()?,
+        }
+
+        self.pc = next;
+        Ok(false)
+    }
+
+    fn call(&mut self, name: &str, n_args: u16, ret: usize) -> anyhow::Result<()> {
+        let Some(&target) = self.functions.get(name) else {
+            bail!("undefined function: {name}");
+        };
+
+        // the return address is kept as an index of the instruction, or `-1` for `Sys.init`
+        let ret = i16::try_from(ret).unwrap_or(-1);
+        self.push(ret)?;
+        for reg in [LCL, ARG, THIS, THAT] {
+            self.push(self.ram[reg])?;
+        }
+        self.ram[ARG] = self.ram[SP].wrapping_sub(n_args as i16).wrapping_sub(5);
+        self.ram[LCL] = self.ram[SP];
+        self.pc = target;
+
+        Ok(())
+    }
+
+    // Returns the index of the instruction to return to.
+    fn ret(&mut self) -> anyhow::Result<usize> {
+        let frame = self.ram[LCL];
+        // read before the return value overwrites it if there are no arguments
+        let ret = self.read(frame.wrapping_sub(5))?;
+        let retval = self.pop()?;
+        self.write(self.ram[ARG], retval)?;
+        self.ram[SP] = self.ram[ARG].wrapping_add(1);
+        self.ram[THAT] = self.read(frame.wrapping_sub(1))?;
+        self.ram[THIS] = self.read(frame.wrapping_sub(2))?;
+        self.ram[ARG] = self.read(frame.wrapping_sub(3))?;
+        self.ram[LCL] = self.read(frame.wrapping_sub(4))?;
+
+        usize::try_from(ret).context("returned from `Sys.init`")
+    }
+
+    fn address(&self, segment: &Segment, index: u16) -> anyhow::Result<i16> {
+        use Segment::*;
+
+        let index = index as i16;
+        let addr = match segment {
+            Local => self.ram[LCL].wrapping_add(index),
+            Argument => self.ram[ARG].wrapping_add(index),
+            This => self.ram[THIS].wrapping_add(index),
+            That => self.ram[THAT].wrapping_add(index),
+            Pointer => (THIS as i16).wrapping_add(index),
+            Temp => (TEMP as i16).wrapping_add(index),
+            Static => self.statics[&self.pc] as i16,
+            Constant => bail!("Cannot pop value into the constant segment"),
+        };
+
+        Ok(addr)
+    }
+
+    fn read(&self, addr: i16) -> anyhow::Result<i16> {
+        usize::try_from(addr)
+            .ok()
+            .and_then(|addr| self.ram.get(addr).copied())
+            .with_context(|| format!("memory access out of range: {addr}"))
+    }
+
+    fn write(&mut self, addr: i16, value: i16) -> anyhow::Result<()> {
+        let Some(word) = usize::try_from(addr).ok().and_then(|addr| self.ram.get_mut(addr)) else {
+            bail!("memory access out of range: {addr}");
+        };
+        *word = value;
+        Ok(())
+    }
+
+    fn push(&mut self, value: i16) -> anyhow::Result<()> {
+        self.write(self.ram[SP], value)?;
+        self.ram[SP] = self.ram[SP].wrapping_add(1);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> anyhow::Result<i16> {
+        self.ram[SP] = self.ram[SP].wrapping_sub(1);
+        self.read(self.ram[SP])
+    }
+
+    fn unary(&mut self, f: impl FnOnce(i16) -> i16) -> anyhow::Result<()> {
+        let x = self.pop()?;
+        self.push(f(x))
+    }
+
+    fn binary(&mut self, f: impl FnOnce(i16, i16) -> i16) -> anyhow::Result<()> {
+        let y = self.pop()?;
+        let x = self.pop()?;
+        self.push(f(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn run(files: &[(&'static str, &'static str)]) -> Vec<i16> {
+        let instructions: Vec<_> = files
+            .iter()
+            .map(|(name, src)| (*name, parser::parse(src).unwrap().0))
+            .collect();
+        let mut emulator = Emulator::new(&instructions).unwrap();
+        assert_eq!(emulator.run(10_000).unwrap(), Exit::Halted);
+        emulator.ram().to_vec()
+    }
+
+    #[test]
+    fn arithmetic_wraps_and_compares_by_value() {
+        let ram = run(&[(
+            "Sys",
+            "function Sys.init 0
+             push constant 32767
+             push constant 1
+             add
+             pop temp 0
+             push constant 32767
+             push constant 1
+             neg
+             gt
+             pop temp 1
+             label END
+             goto END",
+        )]);
+
+        assert_eq!(ram[TEMP], i16::MIN);
+        assert_eq!(ram[TEMP + 1], -1);
+    }
+
+    #[test]
+    fn call_and_return() {
+        let ram = run(&[
+            (
+                "Main",
+                "function Main.double 1
+                 push argument 0
+                 push argument 0
+                 add
+                 pop local 0
+                 push local 0
+                 return",
+            ),
+            (
+                "Sys",
+                "function Sys.init 0
+                 push constant 21
+                 call Main.double 1
+                 pop static 0
+                 label END
+                 goto END",
+            ),
+        ]);
+
+        assert_eq!(ram[STATIC_BASE], 42);
+        // only the frame of `Sys.init` is left
+        assert_eq!(ram[SP], STACK_BASE + 5);
+    }
+
+    #[test]
+    fn labels_are_scoped_by_function() {
+        let ram = run(&[(
+            "Sys",
+            "function Sys.f 0
+             goto L
+             push constant 1
+             pop temp 0
+             label L
+             push constant 0
+             return
+             function Sys.init 0
+             call Sys.f 0
+             pop temp 1
+             label L
+             goto L",
+        )]);
+
+        assert_eq!(ram[TEMP], 0);
+    }
+}
@@ -3,6 +3,7 @@
 
 use std::{fs, path::Path};
 
+pub mod emulator;
 pub mod insn;
 pub mod parser;
 pub mod segment;
@@ -11,7 +12,7 @@ pub mod vm;
 
 pub use insn::*;
 pub use segment::*;
-pub use vm::HackVm;
+pub use vm::{HackVm, SourceLine};
 
 pub fn is_vm_file<P: AsRef<Path>>(p: P) -> bool {
     let path = p.as_ref();
@@ -1,5 +1,5 @@
 //! VM Environment implementation for Hack platform
-use std::io;
+use std::io::{self, Write as _};
 
 use anyhow::bail;
 
@@ -11,20 +11,44 @@ struct VmContext<'s> {
     pub current_fn_name: Option<&'s str>,
 }
 
-pub struct HackVm<'s, W: io::Write> {
-    w: &'s mut W,
+/// Where the code of a VM instruction starts in the generated assembly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLine<'s> {
+    /// name of the VM file
+    pub file: &'s str,
+    /// 0-based line of the instruction in the VM file
+    pub vm_line: usize,
+    /// 0-based line of its first command in the assembly
+    pub asm_line: usize,
+}
+
+pub struct HackVm<'w, 's, W: io::Write> {
+    w: LineCounter<&'w mut W>,
     ctx: VmContext<'s>,
+    source_map: Vec<SourceLine<'s>>,
 }
 
-impl<'s, W: io::Write> HackVm<'s, W> {
-    pub fn new(writer: &'s mut W) -> Self {
+impl<'w, 's, W: io::Write> HackVm<'w, 's, W> {
+    pub fn new(writer: &'w mut W) -> Self {
         let ctx = VmContext {
             symbol_table: SymbolTable::new(),
             current_file_name: None,
             current_fn_name: None,
         };
 
-        HackVm { w: writer, ctx }
+        HackVm {
+            w: LineCounter {
+                inner: writer,
+                lines: 0,
+            },
+            ctx,
+            source_map: Vec::new(),
+        }
+    }
+
+    /// Source map of the assembly written by `interpret`, in the order of the instructions.
+    pub fn source_map(&self) -> &[SourceLine<'s>] {
+        &self.source_map
     }
 
     /// Interpret given `instructions` into HackAsm.
@@ -58,6 +82,11 @@ impl<'s, W: io::Write> HackVm<'s, W> {
                 // include the original representation in the comment
                 #[cfg(debug_assertions)]
                 writeln!(self.w, "// {}", insn.lexeme)?;
+                self.source_map.push(SourceLine {
+                    file: name,
+                    vm_line: insn.line,
+                    asm_line: self.w.lines,
+                });
 
                 match insn.kind {
                     // arithmetic
@@ -343,15 +372,45 @@ M = {operand}M
 
     /// Compare the top values using the `operation` and push the result back.
     /// If the result is `true`, the value will be `-1`, otherwise it will be `0`.
+    /// `x - y` overflows if the signs of `x` and `y` differ, in which case `1` or `-1` by the
+    /// sign of `x` is compared against zero instead.
     fn compare(&mut self, operation: &'s str) -> anyhow::Result<()> {
         let ret_label = self.ctx.symbol_table.ret_addr(operation);
         writeln!(
             self.w,
             "\
 {POP}
+@R13
+M = D
+@SP
+A = M - 1
+D = M
+@{ret_label}_XNEG
+D; JLT
+@R13
+D = M
+@{ret_label}_SAME
+D; JGE
+D = 1
+@{ret_label}_CMP
+0; JMP
+({ret_label}_XNEG)
+@R13
+D = M
+@{ret_label}_SAME
+D; JLT
+D = -1
+@{ret_label}_CMP
+0; JMP
+({ret_label}_SAME)
+@R13
+D = M
 @SP
 A = M - 1
 D = M - D
+({ret_label}_CMP)
+@SP
+A = M - 1
 M = -1
 @{ret_label}
 D; {operation}
@@ -369,6 +428,24 @@ M = 0
 /// - Set the top of the stack to the value of the D
 /// - Increment @SP
 #[doc(hidden)]
+// counts the lines written through it
+struct LineCounter<W: io::Write> {
+    inner: W,
+    lines: usize,
+}
+
+impl<W: io::Write> io::Write for LineCounter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.lines += buf[..n].iter().filter(|b| **b == b'\n').count();
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 static PUSH: &str = "\
 @SP
 A = M
@@ -462,3 +539,74 @@ M = D
 A = M
 1;JNE
 ";
+
+#[cfg(test)]
+mod tests {
+    use hack_asm::emulator::{Cpu, Exit};
+
+    use super::*;
+    use crate::parser;
+
+    // translates `Sys.init`, and returns `temp` after running it on the CPU
+    fn run(src: &str) -> Vec<i16> {
+        let (instructions, errors) = parser::parse(src).unwrap();
+        assert!(errors.is_empty());
+        let mut asm = Vec::new();
+        HackVm::new(&mut asm)
+            .interpret(vec![("Sys", instructions)])
+            .unwrap();
+        let asm: String = String::from_utf8(asm)
+            .unwrap()
+            .chars()
+            .filter(|c| *c != ' ')
+            .collect();
+
+        let mut cpu = Cpu::new(&hack_asm::compile(&asm).unwrap()).unwrap();
+        assert_eq!(cpu.run(10_000).unwrap(), Exit::Halted);
+        cpu.ram()[5..13].to_vec()
+    }
+
+    #[test]
+    fn compare_values_whose_difference_overflows() {
+        let temp = run("function Sys.init 0
+             push constant 32767
+             push constant 2
+             neg
+             gt
+             pop temp 0
+             push constant 32767
+             push constant 2
+             neg
+             lt
+             pop temp 1
+             push constant 2
+             neg
+             push constant 32767
+             lt
+             pop temp 2
+             push constant 2
+             neg
+             push constant 32767
+             gt
+             pop temp 3
+             push constant 20000
+             push constant 20000
+             neg
+             eq
+             pop temp 4
+             push constant 3
+             push constant 2
+             gt
+             pop temp 5
+             push constant 2
+             neg
+             push constant 3
+             neg
+             lt
+             pop temp 6
+             label END
+             goto END");
+
+        assert_eq!(temp, [-1, 0, -1, 0, 0, -1, 0, 0]);
+    }
+}
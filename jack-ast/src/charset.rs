@@ -0,0 +1,103 @@
+//! The Hack character set, which string literals are decoded into.
+//!
+//! The printable ASCII characters keep their codes, and `\n` is the newline key of the Hack
+//! keyboard. Nothing else can be represented, including tabs and non-ASCII characters.
+
+/// Code of the newline in the Hack character set.
+pub const NEWLINE: u16 = 128;
+
+/// A character of a string literal which is not in the Hack character set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidChar {
+    /// byte offset in the literal
+    pub offset: usize,
+    /// byte length, including the backslash of an escape
+    pub len: usize,
+    pub kind: InvalidCharKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidCharKind {
+    /// `\c` other than `\"`, `\\` and `\n`
+    UnknownEscape(char),
+    Unsupported(char),
+}
+
+/// Decodes the contents of a string literal between the quotes into Hack character codes.
+pub fn decode(literal: &str) -> Result<Vec<u16>, InvalidChar> {
+    let mut codes = Vec::new();
+    let mut chars = literal.char_indices();
+    while let Some((offset, c)) = chars.next() {
+        let code = match c {
+            '\\' => match chars.next() {
+                Some((_, '"')) => '"' as u16,
+                Some((_, '\\')) => '\\' as u16,
+                Some((_, 'n')) => NEWLINE,
+                Some((_, c)) => {
+                    return Err(InvalidChar {
+                        offset,
+                        len: 1 + c.len_utf8(),
+                        kind: InvalidCharKind::UnknownEscape(c),
+                    })
+                }
+                // a lexer never ends a literal with a backslash, as it escapes the quote
+                None => {
+                    return Err(InvalidChar {
+                        offset,
+                        len: 1,
+                        kind: InvalidCharKind::UnknownEscape('\\'),
+                    })
+                }
+            },
+            ' '..='~' => c as u16,
+            _ => {
+                return Err(InvalidChar {
+                    offset,
+                    len: c.len_utf8(),
+                    kind: InvalidCharKind::Unsupported(c),
+                })
+            }
+        };
+        codes.push(code);
+    }
+
+    Ok(codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_escapes() {
+        assert_eq!(
+            decode(r#"a\"b\\c\n"#).unwrap(),
+            [97, 34, 98, 92, 99, NEWLINE]
+        );
+        assert_eq!(decode("").unwrap(), []);
+    }
+
+    #[test]
+    fn decode_invalid_chars() {
+        assert_eq!(
+            decode(r"ab\t").unwrap_err(),
+            InvalidChar {
+                offset: 2,
+                len: 2,
+                kind: InvalidCharKind::UnknownEscape('t'),
+            }
+        );
+        assert_eq!(
+            decode("caf\u{e9}").unwrap_err(),
+            InvalidChar {
+                offset: 3,
+                len: 2,
+                kind: InvalidCharKind::Unsupported('\u{e9}'),
+            }
+        );
+        assert_eq!(
+            decode("a\tb").unwrap_err().kind,
+            InvalidCharKind::Unsupported('\t')
+        );
+    }
+}
@@ -13,9 +13,12 @@
 //!
 //! ## Structure
 //!
-//! class :== 'class' class_name '{' class_variable_def* fn_def* '}'
+//! class :== 'class' class_name '{' (class_variable_def | const_def | enum_def)* fn_def* '}'
 //! class_variable_def :== ('static' | 'field') type variable_name (',' variable_name)* ';'
 //! type :== 'int' | 'char' | 'boolean' | class_name
+//! const_def :== 'const' type const_name '=' expression ';'
+//! enum_def :== 'enum' enum_name '{' enum_member (',' enum_member)* '}'
+//! enum_member :== const_name ('=' expression)?
 //!
 //! fn_kind :== 'constructor' | 'function' | 'method'
 //! fn_def :== fn_kind ('void' | type) fn_name '(' parameter_list ')' '{' fn_body '}'
@@ -41,7 +44,7 @@
 //! expression :== term (op term)*
 //! term :== integer_const | string_const | keyword_const | variable_name
 //!          | variable_name '[' expression ']' | fn_call | '(' expression ')'
-//!          | unary_op term
+//!          | unary_op term | (class_name | enum_name) '.' const_name
 //! keyword_const :== 'true' | 'false' | 'null' | 'this'
 //!
 //! fn_call :== fn_name '(' arg_list ')'
@@ -53,10 +56,15 @@
 #![forbid(unsafe_code)]
 #![feature(box_patterns)]
 
+pub mod charset;
+pub mod print;
+pub mod source_map;
 pub mod span;
+pub mod visit;
 
 use std::fmt;
 
+pub use source_map::*;
 pub use span::*;
 pub type Ident<'s> = &'s str;
 
@@ -67,9 +75,42 @@ pub struct Class<'s> {
     //       [--------) span
     pub span: Span,
     pub variables: Vec<VariableDef<'s>>,
+    /// `const` members (extension)
+    pub constants: Vec<ConstDef<'s>>,
+    /// `enum` members (extension)
+    pub enums: Vec<EnumDef<'s>>,
     pub functions: Vec<FnDef<'s>>,
 }
 
+/// `const ty name = value;`, which is inlined wherever `name` is used
+#[derive(Debug, PartialEq)]
+pub struct ConstDef<'s> {
+    pub name: Ident<'s>,
+    pub ty: Type<'s>,
+    pub value: Expr<'s>,
+    // const int NAME = value;
+    //           [--) span
+    pub span: Span,
+}
+
+/// `enum Name { A, B = value, .. }`, whose members are `int` constants referred to as `Name.A`
+#[derive(Debug, PartialEq)]
+pub struct EnumDef<'s> {
+    pub name: Ident<'s>,
+    // enum Name { .. }
+    //      [--) span
+    pub span: Span,
+    pub members: Vec<EnumMember<'s>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EnumMember<'s> {
+    pub name: Ident<'s>,
+    /// the value of the previous member plus one (or `0` for the first) if omitted
+    pub value: Option<Expr<'s>>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum Type<'s> {
     Int,
@@ -92,7 +133,7 @@ pub struct FnDef<'s> {
     pub body: FnBody<'s>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum FnKind {
     Ctor,
     Function,
@@ -112,13 +153,13 @@ pub struct FnBody<'s> {
     pub statements: Vec<Stmt<'s>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Stmt<'s> {
     pub kind: StmtKind<'s>,
     pub span: Span,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum StmtKind<'s> {
     /// let `name`([`index_accessor`])? = `expr`;
     Let {
@@ -129,7 +170,7 @@ pub enum StmtKind<'s> {
     If {
         cond: Expr<'s>,
         then_branch: Vec<Stmt<'s>>,
-        else_branch: Option<Vec<Stmt<'s>>>,
+        else_branch: Option<Else<'s>>,
     },
     /// while (`cond`) { `body` }
     While {
@@ -138,9 +179,51 @@ pub enum StmtKind<'s> {
     },
     Do(FnCall<'s>),
     Return(Option<Expr<'s>>),
+    /// let `lhs` `op`= `rhs`; with `op` of `+` or `-` (extension)
+    CompoundLet {
+        lhs: Variable<'s>,
+        op: BinOp,
+        rhs: Expr<'s>,
+    },
+    /// for (`init`; `cond`; `step`) { `body` } (extension)
+    For {
+        init: Box<Stmt<'s>>,
+        cond: Expr<'s>,
+        step: Box<Stmt<'s>>,
+        body: Vec<Stmt<'s>>,
+    },
+    /// break; (extension)
+    Break,
+    /// continue; (extension)
+    Continue,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Else<'s> {
+    /// else { `stmts` }
+    Block(Vec<Stmt<'s>>),
+    /// else if (..) { .. } (extension)
+    If(Box<Stmt<'s>>),
+}
+
+impl<'s> Else<'s> {
+    /// Statements of the branch, where `else if` is the block of a single `if`.
+    pub fn stmts(&self) -> &[Stmt<'s>] {
+        match self {
+            Else::Block(stmts) => stmts,
+            Else::If(stmt) => std::slice::from_ref(stmt),
+        }
+    }
+
+    pub fn stmts_mut(&mut self) -> &mut [Stmt<'s>] {
+        match self {
+            Else::Block(stmts) => stmts,
+            Else::If(stmt) => std::slice::from_mut(stmt),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Expr<'s> {
     pub lhs: Box<Term<'s>>,
     pub rhs: Option<Box<(BinOp, Term<'s>)>>,
@@ -160,13 +243,13 @@ impl<'s> Expr<'s> {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Term<'s> {
     pub kind: TermKind<'s>,
     pub span: Span,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TermKind<'s> {
     Const(Constant<'s>),
     // name [ index_accessor ]?
@@ -175,14 +258,25 @@ pub enum TermKind<'s> {
     FnCall(FnCall<'s>),
     // ( expr )
     Expr(Box<Expr<'s>>),
-    Unary { op: UnaryOp, term: Box<Term<'s>> },
+    Unary {
+        op: UnaryOp,
+        term: Box<Term<'s>>,
+    },
+    /// `scope.name`, a constant of the class or a member of the enum `scope` (extension)
+    Qualified {
+        scope: Ident<'s>,
+        name: Ident<'s>,
+    },
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FnCall<'s> {
     pub receiver: Option<Ident<'s>>,
     pub fn_name: Ident<'s>,
     pub args: Vec<Expr<'s>>,
+    // receiver.fn_name(args..)
+    // [-------------) span
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq)]
@@ -201,7 +295,7 @@ pub enum VariableDefKind {
     Var,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Variable<'s> {
     pub name: &'s str,
     pub index_accessor: Option<Expr<'s>>,
@@ -227,9 +321,14 @@ pub enum UnaryOp {
     Not,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Constant<'s> {
     Integer(u32),
+    /// `0x7FFF` or `0b1010`, along with the literal as written
+    Radix(u32, &'s str),
+    /// `'A'`, which is the integer of its character code
+    Char(char),
+    /// contents between the quotes as written, with escapes decoded by `charset::decode`
     Str(&'s str),
     True,
     False,
@@ -247,7 +346,8 @@ impl fmt::Display for Stmt<'_> {
                 else_branch,
             } => {
                 let else_branch = match else_branch {
-                    Some(els) => format!(" else {{ ({} statements...) }}", els.len()),
+                    Some(Else::Block(els)) => format!(" else {{ ({} statements...) }}", els.len()),
+                    Some(Else::If(stmt)) => format!(" else {stmt}"),
                     None => "".to_owned(),
                 };
                 write!(
@@ -259,11 +359,24 @@ impl fmt::Display for Stmt<'_> {
             StmtKind::While { cond, body } => {
                 write!(f, "while ({cond}) {{ ({} statements...) }}", body.len())
             }
+            StmtKind::CompoundLet { lhs, op, rhs } => write!(f, "let {lhs} {op}= {rhs}"),
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => write!(
+                f,
+                "for ({init}; {cond}; {step}) {{ ({} statements...) }}",
+                body.len()
+            ),
             StmtKind::Do(call) => call.fmt(f),
             StmtKind::Return(retval) => match retval {
                 Some(v) => write!(f, "return {v}"),
                 None => write!(f, "return"),
             },
+            StmtKind::Break => write!(f, "break"),
+            StmtKind::Continue => write!(f, "continue"),
         }
     }
 }
@@ -286,6 +399,7 @@ impl fmt::Display for Term<'_> {
             FnCall(call) => call.fmt(f),
             Expr(e) => e.fmt(f),
             Unary { op, term } => write!(f, "{op}{term}"),
+            Qualified { scope, name } => write!(f, "{scope}.{name}"),
         }
     }
 }
@@ -306,6 +420,17 @@ impl fmt::Display for FnCall<'_> {
     }
 }
 
+impl fmt::Display for FnKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FnKind::Ctor => "constructor",
+            FnKind::Function => "function",
+            FnKind::Method => "method",
+        }
+        .fmt(f)
+    }
+}
+
 impl fmt::Display for VariableDefKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -356,11 +481,27 @@ impl fmt::Display for UnaryOp {
     }
 }
 
+impl Constant<'_> {
+    /// The largest integer constant, as `push constant` takes 15 bits.
+    pub const MAX_INTEGER: u32 = 0x7FFF;
+
+    /// The value of an integer or character constant.
+    pub fn int_value(&self) -> Option<u32> {
+        match *self {
+            Constant::Integer(v) | Constant::Radix(v, _) => Some(v),
+            Constant::Char(c) => Some(c as u32),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for Constant<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use Constant::*;
         match self {
             Integer(v) => return write!(f, "{v}"),
+            Radix(_, literal) => literal,
+            Char(c) => return write!(f, "'{c}'"),
             Str(ref s) => return write!(f, "{s}"),
             True => "true",
             False => "false",
@@ -0,0 +1,404 @@
+//! Printer of ASTs back into Jack sources.
+//!
+//! Unlike `Display`, which is meant for diagnostics, `to_source` emits valid Jack which parses
+//! back to the same AST except for spans. Comments and the original layout are not kept.
+use crate::*;
+
+const INDENT: &str = "    ";
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    // `header {` .. `}`
+    fn block<F: FnOnce(&mut Self)>(&mut self, header: &str, f: F) {
+        self.line(&format!("{header} {{"));
+        self.indent += 1;
+        f(self);
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn class(&mut self, class: &Class<'_>) {
+        self.block(&format!("class {}", class.name), |p| {
+            for var in &class.variables {
+                p.variable_def(var);
+            }
+            for def in &class.constants {
+                p.const_def(def);
+            }
+            for def in &class.enums {
+                p.enum_def(def);
+            }
+            for f in &class.functions {
+                p.fn_def(f);
+            }
+        });
+    }
+
+    fn variable_def(&mut self, var: &VariableDef<'_>) {
+        self.line(&format!(
+            "{} {} {};",
+            var.kind,
+            var.ty.to_source(),
+            var.name
+        ));
+    }
+
+    fn const_def(&mut self, def: &ConstDef<'_>) {
+        self.line(&format!(
+            "const {} {} = {};",
+            def.ty.to_source(),
+            def.name,
+            def.value.to_source()
+        ));
+    }
+
+    fn enum_def(&mut self, def: &EnumDef<'_>) {
+        self.block(&format!("enum {}", def.name), |p| {
+            let last = def.members.len().saturating_sub(1);
+            for (i, member) in def.members.iter().enumerate() {
+                let comma = if i < last { "," } else { "" };
+                p.line(&format!("{}{comma}", member.to_source()));
+            }
+        });
+    }
+
+    fn fn_def(&mut self, f: &FnDef<'_>) {
+        let params = f
+            .params
+            .iter()
+            .map(|p| format!("{} {}", p.ty.to_source(), p.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let header = format!("{} {} {}({params})", f.kind, f.ret.to_source(), f.name);
+
+        self.block(&header, |p| {
+            for var in &f.body.variables {
+                p.variable_def(var);
+            }
+            p.stmts(&f.body.statements);
+        });
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt<'_>]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt<'_>) {
+        match &stmt.kind {
+            StmtKind::Let { lhs, rhs } => {
+                self.line(&format!("let {} = {};", lhs.to_source(), rhs.to_source()));
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => self.if_stmt("", cond, then_branch, else_branch),
+            StmtKind::While { cond, body } => {
+                self.block(&format!("while ({})", cond.to_source()), |p| p.stmts(body));
+            }
+            StmtKind::CompoundLet { lhs, op, rhs } => {
+                self.line(&format!(
+                    "let {} {op}= {};",
+                    lhs.to_source(),
+                    rhs.to_source()
+                ));
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                let header = format!(
+                    "for ({} {}; {})",
+                    init.to_source().trim_end(),
+                    cond.to_source(),
+                    step.to_source().trim_end().trim_end_matches(';'),
+                );
+                self.block(&header, |p| p.stmts(body));
+            }
+            StmtKind::Do(call) => self.line(&format!("do {};", call.to_source())),
+            StmtKind::Return(Some(retval)) => {
+                self.line(&format!("return {};", retval.to_source()));
+            }
+            StmtKind::Return(None) => self.line("return;"),
+            StmtKind::Break => self.line("break;"),
+            StmtKind::Continue => self.line("continue;"),
+        }
+    }
+}
+
+impl Printer {
+    // `if` on a line starting with `opener`, which is `} else ` for `else if`
+    fn if_stmt(
+        &mut self,
+        opener: &str,
+        cond: &Expr<'_>,
+        then_branch: &[Stmt<'_>],
+        else_branch: &Option<Else<'_>>,
+    ) {
+        self.line(&format!("{opener}if ({}) {{", cond.to_source()));
+        self.indent += 1;
+        self.stmts(then_branch);
+        self.indent -= 1;
+        match else_branch {
+            Some(Else::If(box Stmt {
+                kind:
+                    StmtKind::If {
+                        cond,
+                        then_branch,
+                        else_branch,
+                    },
+                ..
+            })) => self.if_stmt("} else ", cond, then_branch, else_branch),
+            Some(else_branch) => {
+                self.line("} else {");
+                self.indent += 1;
+                self.stmts(else_branch.stmts());
+                self.indent -= 1;
+                self.line("}");
+            }
+            None => self.line("}"),
+        }
+    }
+}
+
+impl Class<'_> {
+    pub fn to_source(&self) -> String {
+        let mut printer = Printer::default();
+        printer.class(self);
+        printer.out
+    }
+}
+
+impl FnDef<'_> {
+    pub fn to_source(&self) -> String {
+        let mut printer = Printer::default();
+        printer.fn_def(self);
+        printer.out
+    }
+}
+
+impl Stmt<'_> {
+    pub fn to_source(&self) -> String {
+        let mut printer = Printer::default();
+        printer.stmt(self);
+        printer.out
+    }
+}
+
+impl Expr<'_> {
+    pub fn to_source(&self) -> String {
+        match &self.rhs {
+            Some(rhs) => {
+                let (op, rhs) = &**rhs;
+                format!("{} {op} {}", self.lhs.to_source(), rhs.to_source())
+            }
+            None => self.lhs.to_source(),
+        }
+    }
+}
+
+impl Term<'_> {
+    pub fn to_source(&self) -> String {
+        match &self.kind {
+            TermKind::Const(c) => c.to_source(),
+            TermKind::Variable(v) => v.to_source(),
+            TermKind::FnCall(call) => call.to_source(),
+            TermKind::Expr(e) => format!("({})", e.to_source()),
+            TermKind::Unary { op, term } => format!("{op}{}", term.to_source()),
+            TermKind::Qualified { scope, name } => format!("{scope}.{name}"),
+        }
+    }
+}
+
+impl EnumMember<'_> {
+    pub fn to_source(&self) -> String {
+        match &self.value {
+            Some(value) => format!("{} = {}", self.name, value.to_source()),
+            None => self.name.to_string(),
+        }
+    }
+}
+
+impl Variable<'_> {
+    pub fn to_source(&self) -> String {
+        match &self.index_accessor {
+            Some(index) => format!("{}[{}]", self.name, index.to_source()),
+            None => self.name.to_string(),
+        }
+    }
+}
+
+impl FnCall<'_> {
+    pub fn to_source(&self) -> String {
+        let args = self
+            .args
+            .iter()
+            .map(Expr::to_source)
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self.receiver {
+            Some(receiver) => format!("{receiver}.{}({args})", self.fn_name),
+            None => format!("{}({args})", self.fn_name),
+        }
+    }
+}
+
+impl Constant<'_> {
+    pub fn to_source(&self) -> String {
+        match self {
+            Constant::Str(s) => format!("\"{s}\""),
+            // `Display` already prints integers and characters as literals
+            c => c.to_string(),
+        }
+    }
+}
+
+impl Type<'_> {
+    pub fn to_source(&self) -> String {
+        match self {
+            Type::Class(name) => name.to_string(),
+            ty => ty.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(kind: TermKind<'_>) -> Term<'_> {
+        Term {
+            kind,
+            span: Span::DUMMY,
+        }
+    }
+
+    #[test]
+    fn expr_to_source() {
+        // "a" + (-b[1])
+        let expr = Expr {
+            lhs: Box::new(term(TermKind::Const(Constant::Str("a")))),
+            rhs: Some(Box::new((
+                BinOp::Plus,
+                term(TermKind::Expr(Box::new(Expr {
+                    lhs: Box::new(term(TermKind::Unary {
+                        op: UnaryOp::Minus,
+                        term: Box::new(term(TermKind::Variable(Variable {
+                            name: "b",
+                            index_accessor: Some(Expr {
+                                lhs: Box::new(term(TermKind::Const(Constant::Integer(1)))),
+                                rhs: None,
+                            }),
+                            span: Span::DUMMY,
+                        }))),
+                    })),
+                    rhs: None,
+                }))),
+            ))),
+        };
+
+        assert_eq!(expr.to_source(), r#""a" + (-b[1])"#);
+    }
+
+    #[test]
+    fn stmt_to_source() {
+        let stmt = Stmt {
+            kind: StmtKind::If {
+                cond: Expr {
+                    lhs: Box::new(term(TermKind::Const(Constant::True))),
+                    rhs: None,
+                },
+                then_branch: vec![Stmt {
+                    kind: StmtKind::Return(None),
+                    span: Span::DUMMY,
+                }],
+                else_branch: Some(Else::Block(vec![])),
+            },
+            span: Span::DUMMY,
+        };
+
+        assert_eq!(stmt.to_source(), "if (true) {\n    return;\n} else {\n}\n");
+    }
+
+    #[test]
+    fn class_to_source() {
+        let int = |n| Expr {
+            lhs: Box::new(term(TermKind::Const(Constant::Integer(n)))),
+            rhs: None,
+        };
+        let member = |name, value| EnumMember {
+            name,
+            value,
+            span: Span::DUMMY,
+        };
+        let class = Class {
+            name: "Main",
+            span: Span::DUMMY,
+            variables: vec![],
+            constants: vec![ConstDef {
+                name: "SIZE",
+                ty: Type::Int,
+                value: int(16),
+                span: Span::DUMMY,
+            }],
+            enums: vec![EnumDef {
+                name: "Color",
+                span: Span::DUMMY,
+                members: vec![member("WHITE", None), member("BLACK", Some(int(8)))],
+            }],
+            functions: vec![],
+        };
+
+        assert_eq!(
+            class.to_source(),
+            "class Main {\n    const int SIZE = 16;\n    enum Color {\n        WHITE,\n        BLACK = 8\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn else_if_to_source() {
+        let cond = |name| Expr {
+            lhs: Box::new(term(TermKind::Variable(Variable {
+                name,
+                index_accessor: None,
+                span: Span::DUMMY,
+            }))),
+            rhs: None,
+        };
+        let stmt = |kind| Stmt {
+            kind,
+            span: Span::DUMMY,
+        };
+        let if_stmt = stmt(StmtKind::If {
+            cond: cond("a"),
+            then_branch: vec![stmt(StmtKind::Break)],
+            else_branch: Some(Else::If(Box::new(stmt(StmtKind::If {
+                cond: cond("b"),
+                then_branch: vec![],
+                else_branch: Some(Else::Block(vec![stmt(StmtKind::Continue)])),
+            })))),
+        });
+
+        assert_eq!(
+            if_stmt.to_source(),
+            "if (a) {\n    break;\n} else if (b) {\n} else {\n    continue;\n}\n"
+        );
+    }
+}
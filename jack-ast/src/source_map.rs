@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+
+use crate::BytePos;
+
+/// Index of a file in [`SourceMap`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct FileId(u32);
+
+impl FileId {
+    /// The file of sources which are not in any [`SourceMap`], e.g. ones parsed from a string.
+    /// Resolving it panics rather than pointing into an unrelated file.
+    pub const NONE: FileId = FileId(u32::MAX);
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub id: FileId,
+    pub path: PathBuf,
+    pub src: String,
+}
+
+impl SourceFile {
+    /// name of the file shown in diagnostics
+    pub fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    /// 1-based line and column (in characters) of the given position.
+    pub fn line_col(&self, pos: BytePos) -> (usize, usize) {
+        let before = &self.src[..pos.min(self.src.len())];
+        let line_start = before.rfind('\n').map_or(0, |nl| nl + 1);
+        let line = before.matches('\n').count() + 1;
+        let col = before[line_start..].chars().count() + 1;
+
+        (line, col)
+    }
+}
+
+/// Holds all source files of a compilation, so that spans can be resolved by their [`FileId`].
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    pub fn add_file<P: AsRef<Path>>(&mut self, path: P, src: String) -> FileId {
+        let id = FileId(self.files.len() as u32);
+        self.files.push(SourceFile {
+            id,
+            path: path.as_ref().to_path_buf(),
+            src,
+        });
+
+        id
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.index()]
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col() {
+        let mut sources = SourceMap::new();
+        let id = sources.add_file("Main.jack", "class Main {\n  field int x;\n}".to_owned());
+        let file = sources.file(id);
+
+        assert_eq!(file.line_col(0), (1, 1));
+        assert_eq!(file.line_col(6), (1, 7));
+        // just after the newline
+        assert_eq!(file.line_col(13), (2, 1));
+        assert_eq!(file.line_col(25), (2, 13));
+        assert_eq!(file.line_col(file.src.len()), (3, 2));
+    }
+}
@@ -1,31 +1,43 @@
+use crate::FileId;
+
 pub type BytePos = usize;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Span {
+    // the file which `base` is the offset in
+    pub file: FileId,
     pub base: BytePos,
     pub len: usize,
 }
 
 impl Span {
-    // Span with [lo, hi)
-    pub fn new(lo: BytePos, hi: BytePos) -> Self {
+    /// Span of nodes which are not written in any source, e.g. generated ones.
+    pub const DUMMY: Span = Span {
+        file: FileId::NONE,
+        base: 0,
+        len: 0,
+    };
+
+    // Span with [lo, hi) in `file`
+    pub fn new(file: FileId, lo: BytePos, hi: BytePos) -> Self {
         assert!(lo <= hi);
         Span {
+            file,
             base: lo,
             len: hi - lo,
         }
     }
 
-    pub fn from_len(base: BytePos, len: usize) -> Self {
-        Span { base, len }
+    pub fn from_len(file: FileId, base: BytePos, len: usize) -> Self {
+        Span { file, base, len }
     }
 
     pub fn with_lo(&self, lo: BytePos) -> Self {
-        Span::new(lo, self.hi())
+        Span::new(self.file, lo, self.hi())
     }
 
     pub fn with_hi(&self, hi: BytePos) -> Self {
-        Span::new(self.lo(), hi)
+        Span::new(self.file, self.lo(), hi)
     }
 
     pub fn lo(&self) -> BytePos {
@@ -0,0 +1,639 @@
+//! AST traversal.
+//!
+//! Every `visit_*` method of [`Visitor`] (and [`VisitorMut`]) defaults to the corresponding
+//! `walk_*` function, which visits the children of the node in order of appearance in the source.
+//! An implementor overrides only the nodes it cares about, and calls `walk_*` in the overridden
+//! method to keep descending into children.
+//!
+//! ```
+//! use jack_ast::{
+//!     visit::{walk_fn_call, Visitor},
+//!     Class, FnCall,
+//! };
+//!
+//! struct CountCalls(usize);
+//!
+//! impl<'s> Visitor<'s> for CountCalls {
+//!     fn visit_fn_call(&mut self, call: &FnCall<'s>) {
+//!         self.0 += 1;
+//!         walk_fn_call(self, call);
+//!     }
+//! }
+//!
+//! fn count_calls(class: &Class<'_>) -> usize {
+//!     let mut counter = CountCalls(0);
+//!     counter.visit_class(class);
+//!     counter.0
+//! }
+//! ```
+use crate::*;
+
+pub trait Visitor<'s>: Sized {
+    fn visit_class(&mut self, class: &Class<'s>) {
+        walk_class(self, class)
+    }
+
+    fn visit_variable_def(&mut self, _def: &VariableDef<'s>) {}
+
+    fn visit_const_def(&mut self, def: &ConstDef<'s>) {
+        walk_const_def(self, def)
+    }
+
+    fn visit_enum_def(&mut self, def: &EnumDef<'s>) {
+        walk_enum_def(self, def)
+    }
+
+    fn visit_fn_def(&mut self, f: &FnDef<'s>) {
+        walk_fn_def(self, f)
+    }
+
+    fn visit_parameter(&mut self, _param: &Parameter<'s>) {}
+
+    fn visit_stmt(&mut self, stmt: &Stmt<'s>) {
+        walk_stmt(self, stmt)
+    }
+
+    fn visit_expr(&mut self, expr: &Expr<'s>) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_term(&mut self, term: &Term<'s>) {
+        walk_term(self, term)
+    }
+
+    /// visits both variables read in terms and ones assigned by `let`.
+    fn visit_variable(&mut self, var: &Variable<'s>) {
+        walk_variable(self, var)
+    }
+
+    fn visit_fn_call(&mut self, call: &FnCall<'s>) {
+        walk_fn_call(self, call)
+    }
+}
+
+pub fn walk_class<'s, V: Visitor<'s>>(visitor: &mut V, class: &Class<'s>) {
+    for var in class.variables.iter() {
+        visitor.visit_variable_def(var);
+    }
+
+    for def in class.constants.iter() {
+        visitor.visit_const_def(def);
+    }
+
+    for def in class.enums.iter() {
+        visitor.visit_enum_def(def);
+    }
+
+    for f in class.functions.iter() {
+        visitor.visit_fn_def(f);
+    }
+}
+
+pub fn walk_const_def<'s, V: Visitor<'s>>(visitor: &mut V, def: &ConstDef<'s>) {
+    visitor.visit_expr(&def.value);
+}
+
+pub fn walk_enum_def<'s, V: Visitor<'s>>(visitor: &mut V, def: &EnumDef<'s>) {
+    for value in def.members.iter().filter_map(|m| m.value.as_ref()) {
+        visitor.visit_expr(value);
+    }
+}
+
+pub fn walk_fn_def<'s, V: Visitor<'s>>(visitor: &mut V, f: &FnDef<'s>) {
+    for param in f.params.iter() {
+        visitor.visit_parameter(param);
+    }
+
+    for var in f.body.variables.iter() {
+        visitor.visit_variable_def(var);
+    }
+
+    for stmt in f.body.statements.iter() {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<'s, V: Visitor<'s>>(visitor: &mut V, stmt: &Stmt<'s>) {
+    match &stmt.kind {
+        StmtKind::Let { lhs, rhs } => {
+            visitor.visit_variable(lhs);
+            visitor.visit_expr(rhs);
+        }
+        StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr(cond);
+            for stmt in then_branch.iter() {
+                visitor.visit_stmt(stmt);
+            }
+
+            if let Some(else_branch) = else_branch {
+                for stmt in else_branch.stmts() {
+                    visitor.visit_stmt(stmt);
+                }
+            }
+        }
+        StmtKind::While { cond, body } => {
+            visitor.visit_expr(cond);
+            for stmt in body.iter() {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        StmtKind::CompoundLet { lhs, rhs, .. } => {
+            visitor.visit_variable(lhs);
+            visitor.visit_expr(rhs);
+        }
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            visitor.visit_stmt(init);
+            visitor.visit_expr(cond);
+            visitor.visit_stmt(step);
+            for stmt in body.iter() {
+                visitor.visit_stmt(stmt);
+            }
+        }
+        StmtKind::Do(call) => visitor.visit_fn_call(call),
+        StmtKind::Return(retval) => {
+            if let Some(retval) = retval {
+                visitor.visit_expr(retval);
+            }
+        }
+        StmtKind::Break | StmtKind::Continue => {}
+    }
+}
+
+pub fn walk_expr<'s, V: Visitor<'s>>(visitor: &mut V, expr: &Expr<'s>) {
+    visitor.visit_term(&expr.lhs);
+    if let Some(box (_, term)) = &expr.rhs {
+        visitor.visit_term(term);
+    }
+}
+
+pub fn walk_term<'s, V: Visitor<'s>>(visitor: &mut V, term: &Term<'s>) {
+    match &term.kind {
+        TermKind::Const(_) | TermKind::Qualified { .. } => {}
+        TermKind::Variable(var) => visitor.visit_variable(var),
+        TermKind::FnCall(call) => visitor.visit_fn_call(call),
+        TermKind::Expr(expr) => visitor.visit_expr(expr),
+        TermKind::Unary { term, .. } => visitor.visit_term(term),
+    }
+}
+
+pub fn walk_variable<'s, V: Visitor<'s>>(visitor: &mut V, var: &Variable<'s>) {
+    if let Some(index) = &var.index_accessor {
+        visitor.visit_expr(index);
+    }
+}
+
+pub fn walk_fn_call<'s, V: Visitor<'s>>(visitor: &mut V, call: &FnCall<'s>) {
+    for arg in call.args.iter() {
+        visitor.visit_expr(arg);
+    }
+}
+
+/// [`Visitor`] which can rewrite nodes in place.
+pub trait VisitorMut<'s>: Sized {
+    fn visit_class_mut(&mut self, class: &mut Class<'s>) {
+        walk_class_mut(self, class)
+    }
+
+    fn visit_variable_def_mut(&mut self, _def: &mut VariableDef<'s>) {}
+
+    fn visit_const_def_mut(&mut self, def: &mut ConstDef<'s>) {
+        walk_const_def_mut(self, def)
+    }
+
+    fn visit_enum_def_mut(&mut self, def: &mut EnumDef<'s>) {
+        walk_enum_def_mut(self, def)
+    }
+
+    fn visit_fn_def_mut(&mut self, f: &mut FnDef<'s>) {
+        walk_fn_def_mut(self, f)
+    }
+
+    fn visit_parameter_mut(&mut self, _param: &mut Parameter<'s>) {}
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt<'s>) {
+        walk_stmt_mut(self, stmt)
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr<'s>) {
+        walk_expr_mut(self, expr)
+    }
+
+    fn visit_term_mut(&mut self, term: &mut Term<'s>) {
+        walk_term_mut(self, term)
+    }
+
+    fn visit_variable_mut(&mut self, var: &mut Variable<'s>) {
+        walk_variable_mut(self, var)
+    }
+
+    fn visit_fn_call_mut(&mut self, call: &mut FnCall<'s>) {
+        walk_fn_call_mut(self, call)
+    }
+}
+
+pub fn walk_class_mut<'s, V: VisitorMut<'s>>(visitor: &mut V, class: &mut Class<'s>) {
+    for var in class.variables.iter_mut() {
+        visitor.visit_variable_def_mut(var);
+    }
+
+    for def in class.constants.iter_mut() {
+        visitor.visit_const_def_mut(def);
+    }
+
+    for def in class.enums.iter_mut() {
+        visitor.visit_enum_def_mut(def);
+    }
+
+    for f in class.functions.iter_mut() {
+        visitor.visit_fn_def_mut(f);
+    }
+}
+
+pub fn walk_const_def_mut<'s, V: VisitorMut<'s>>(visitor: &mut V, def: &mut ConstDef<'s>) {
+    visitor.visit_expr_mut(&mut def.value);
+}
+
+pub fn walk_enum_def_mut<'s, V: VisitorMut<'s>>(visitor: &mut V, def: &mut EnumDef<'s>) {
+    for value in def.members.iter_mut().filter_map(|m| m.value.as_mut()) {
+        visitor.visit_expr_mut(value);
+    }
+}
+
+pub fn walk_fn_def_mut<'s, V: VisitorMut<'s>>(visitor: &mut V, f: &mut FnDef<'s>) {
+    for param in f.params.iter_mut() {
+        visitor.visit_parameter_mut(param);
+    }
+
+    for var in f.body.variables.iter_mut() {
+        visitor.visit_variable_def_mut(var);
+    }
+
+    for stmt in f.body.statements.iter_mut() {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_stmt_mut<'s, V: VisitorMut<'s>>(visitor: &mut V, stmt: &mut Stmt<'s>) {
+    match &mut stmt.kind {
+        StmtKind::Let { lhs, rhs } => {
+            visitor.visit_variable_mut(lhs);
+            visitor.visit_expr_mut(rhs);
+        }
+        StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr_mut(cond);
+            for stmt in then_branch.iter_mut() {
+                visitor.visit_stmt_mut(stmt);
+            }
+
+            if let Some(else_branch) = else_branch {
+                for stmt in else_branch.stmts_mut() {
+                    visitor.visit_stmt_mut(stmt);
+                }
+            }
+        }
+        StmtKind::While { cond, body } => {
+            visitor.visit_expr_mut(cond);
+            for stmt in body.iter_mut() {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        StmtKind::CompoundLet { lhs, rhs, .. } => {
+            visitor.visit_variable_mut(lhs);
+            visitor.visit_expr_mut(rhs);
+        }
+        StmtKind::For {
+            init,
+            cond,
+            step,
+            body,
+        } => {
+            visitor.visit_stmt_mut(init);
+            visitor.visit_expr_mut(cond);
+            visitor.visit_stmt_mut(step);
+            for stmt in body.iter_mut() {
+                visitor.visit_stmt_mut(stmt);
+            }
+        }
+        StmtKind::Do(call) => visitor.visit_fn_call_mut(call),
+        StmtKind::Return(retval) => {
+            if let Some(retval) = retval {
+                visitor.visit_expr_mut(retval);
+            }
+        }
+        StmtKind::Break | StmtKind::Continue => {}
+    }
+}
+
+pub fn walk_expr_mut<'s, V: VisitorMut<'s>>(visitor: &mut V, expr: &mut Expr<'s>) {
+    visitor.visit_term_mut(&mut expr.lhs);
+    if let Some(box (_, term)) = &mut expr.rhs {
+        visitor.visit_term_mut(term);
+    }
+}
+
+pub fn walk_term_mut<'s, V: VisitorMut<'s>>(visitor: &mut V, term: &mut Term<'s>) {
+    match &mut term.kind {
+        TermKind::Const(_) | TermKind::Qualified { .. } => {}
+        TermKind::Variable(var) => visitor.visit_variable_mut(var),
+        TermKind::FnCall(call) => visitor.visit_fn_call_mut(call),
+        TermKind::Expr(expr) => visitor.visit_expr_mut(expr),
+        TermKind::Unary { term, .. } => visitor.visit_term_mut(term),
+    }
+}
+
+pub fn walk_variable_mut<'s, V: VisitorMut<'s>>(visitor: &mut V, var: &mut Variable<'s>) {
+    if let Some(index) = &mut var.index_accessor {
+        visitor.visit_expr_mut(index);
+    }
+}
+
+pub fn walk_fn_call_mut<'s, V: VisitorMut<'s>>(visitor: &mut V, call: &mut FnCall<'s>) {
+    for arg in call.args.iter_mut() {
+        visitor.visit_expr_mut(arg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span() -> Span {
+        Span::DUMMY
+    }
+
+    fn stmt(kind: StmtKind<'_>) -> Stmt<'_> {
+        Stmt { kind, span: span() }
+    }
+
+    fn expr(kind: TermKind<'_>) -> Expr<'_> {
+        Expr {
+            lhs: Box::new(Term { kind, span: span() }),
+            rhs: None,
+        }
+    }
+
+    fn var<'s>(name: &'s str, index: Option<Expr<'s>>) -> Variable<'s> {
+        Variable {
+            name,
+            index_accessor: index,
+            span: span(),
+        }
+    }
+
+    fn call<'s>(fn_name: &'s str, args: Vec<Expr<'s>>) -> FnCall<'s> {
+        FnCall {
+            receiver: None,
+            fn_name,
+            args,
+            span: span(),
+        }
+    }
+
+    // a class with every kind of node:
+    //
+    // class Main {
+    //     static int s;
+    //     const int N = 1;
+    //     enum E { A = 2 }
+    //     function int f(int p) {
+    //         var int v;
+    //         let v[0] = E.A;
+    //         if (true) { break; } else if (~p) { continue; }
+    //         while ((v)) { do g(3); }
+    //         let v += 4;
+    //         for (let v = 5; v; let v = 6) {}
+    //         return g();
+    //     }
+    // }
+    fn class() -> Class<'static> {
+        let int = |n| expr(TermKind::Const(Constant::Integer(n)));
+        let variable = |name| expr(TermKind::Variable(var(name, None)));
+        let variable_def = |name, kind| VariableDef {
+            name,
+            kind,
+            ty: Type::Int,
+            span: span(),
+        };
+
+        let statements = vec![
+            stmt(StmtKind::Let {
+                lhs: var("v", Some(int(0))),
+                rhs: expr(TermKind::Qualified {
+                    scope: "E",
+                    name: "A",
+                }),
+            }),
+            stmt(StmtKind::If {
+                cond: expr(TermKind::Const(Constant::True)),
+                then_branch: vec![stmt(StmtKind::Break)],
+                else_branch: Some(Else::If(Box::new(stmt(StmtKind::If {
+                    cond: expr(TermKind::Unary {
+                        op: UnaryOp::Not,
+                        term: Box::new(Term {
+                            kind: TermKind::Variable(var("p", None)),
+                            span: span(),
+                        }),
+                    }),
+                    then_branch: vec![stmt(StmtKind::Continue)],
+                    else_branch: None,
+                })))),
+            }),
+            stmt(StmtKind::While {
+                cond: expr(TermKind::Expr(Box::new(variable("v")))),
+                body: vec![stmt(StmtKind::Do(call("g", vec![int(3)])))],
+            }),
+            stmt(StmtKind::CompoundLet {
+                lhs: var("v", None),
+                op: BinOp::Plus,
+                rhs: int(4),
+            }),
+            stmt(StmtKind::For {
+                init: Box::new(stmt(StmtKind::Let {
+                    lhs: var("v", None),
+                    rhs: int(5),
+                })),
+                cond: variable("v"),
+                step: Box::new(stmt(StmtKind::Let {
+                    lhs: var("v", None),
+                    rhs: int(6),
+                })),
+                body: vec![],
+            }),
+            stmt(StmtKind::Return(Some(expr(TermKind::FnCall(call(
+                "g",
+                vec![],
+            )))))),
+        ];
+
+        Class {
+            name: "Main",
+            span: span(),
+            variables: vec![variable_def("s", VariableDefKind::Static)],
+            constants: vec![ConstDef {
+                name: "N",
+                ty: Type::Int,
+                value: int(1),
+                span: span(),
+            }],
+            enums: vec![EnumDef {
+                name: "E",
+                span: span(),
+                members: vec![EnumMember {
+                    name: "A",
+                    value: Some(int(2)),
+                    span: span(),
+                }],
+            }],
+            functions: vec![FnDef {
+                name: "f",
+                span: span(),
+                kind: FnKind::Function,
+                ret: Type::Int,
+                params: vec![Parameter {
+                    name: "p",
+                    ty: Type::Int,
+                    span: span(),
+                }],
+                body: FnBody {
+                    variables: vec![variable_def("v", VariableDefKind::Var)],
+                    statements,
+                },
+            }],
+        }
+    }
+
+    // records every node visited, in order
+    #[derive(Default)]
+    struct Record(Vec<String>);
+
+    impl<'s> Visitor<'s> for Record {
+        fn visit_class(&mut self, class: &Class<'s>) {
+            self.0.push(format!("class {}", class.name));
+            walk_class(self, class);
+        }
+
+        fn visit_variable_def(&mut self, def: &VariableDef<'s>) {
+            self.0.push(format!("def {}", def.name));
+        }
+
+        fn visit_const_def(&mut self, def: &ConstDef<'s>) {
+            self.0.push(format!("const {}", def.name));
+            walk_const_def(self, def);
+        }
+
+        fn visit_enum_def(&mut self, def: &EnumDef<'s>) {
+            self.0.push(format!("enum {}", def.name));
+            walk_enum_def(self, def);
+        }
+
+        fn visit_fn_def(&mut self, f: &FnDef<'s>) {
+            self.0.push(format!("fn {}", f.name));
+            walk_fn_def(self, f);
+        }
+
+        fn visit_parameter(&mut self, param: &Parameter<'s>) {
+            self.0.push(format!("param {}", param.name));
+        }
+
+        fn visit_stmt(&mut self, stmt: &Stmt<'s>) {
+            let kind = match stmt.kind {
+                StmtKind::Let { .. } => "let",
+                StmtKind::If { .. } => "if",
+                StmtKind::While { .. } => "while",
+                StmtKind::Do(_) => "do",
+                StmtKind::Return(_) => "return",
+                StmtKind::CompoundLet { .. } => "compound let",
+                StmtKind::For { .. } => "for",
+                StmtKind::Break => "break",
+                StmtKind::Continue => "continue",
+            };
+            self.0.push(kind.to_string());
+            walk_stmt(self, stmt);
+        }
+
+        fn visit_expr(&mut self, expr: &Expr<'s>) {
+            self.0.push("expr".to_string());
+            walk_expr(self, expr);
+        }
+
+        fn visit_term(&mut self, term: &Term<'s>) {
+            match &term.kind {
+                TermKind::Const(Constant::Integer(n)) => self.0.push(n.to_string()),
+                TermKind::Const(c) => self.0.push(format!("{c:?}")),
+                TermKind::Qualified { scope, name } => self.0.push(format!("{scope}.{name}")),
+                TermKind::Unary { .. } => self.0.push("unary".to_string()),
+                TermKind::Variable(_) | TermKind::FnCall(_) | TermKind::Expr(_) => {}
+            }
+            walk_term(self, term);
+        }
+
+        fn visit_variable(&mut self, var: &Variable<'s>) {
+            self.0.push(format!("var {}", var.name));
+            walk_variable(self, var);
+        }
+
+        fn visit_fn_call(&mut self, call: &FnCall<'s>) {
+            self.0.push(format!("call {}", call.fn_name));
+            walk_fn_call(self, call);
+        }
+    }
+
+    #[test]
+    fn visit_every_node() {
+        let mut record = Record::default();
+        record.visit_class(&class());
+
+        #[rustfmt::skip]
+        let expected = [
+            "class Main", "def s",
+            "const N", "expr", "1",
+            "enum E", "expr", "2",
+            "fn f", "param p", "def v",
+            "let", "var v", "expr", "0", "expr", "E.A",
+            "if", "expr", "True", "break", "if", "expr", "unary", "var p", "continue",
+            "while", "expr", "expr", "var v", "do", "call g", "expr", "3",
+            "compound let", "var v", "expr", "4",
+            "for", "let", "var v", "expr", "5", "expr", "var v", "let", "var v", "expr", "6",
+            "return", "expr", "call g",
+        ];
+        assert_eq!(record.0, expected);
+    }
+
+    struct Rename;
+
+    impl<'s> VisitorMut<'s> for Rename {
+        fn visit_variable_mut(&mut self, var: &mut Variable<'s>) {
+            var.name = "w";
+            walk_variable_mut(self, var);
+        }
+    }
+
+    #[test]
+    fn rewrite_every_variable() {
+        let mut class = class();
+        Rename.visit_class_mut(&mut class);
+
+        let mut record = Record::default();
+        record.visit_class(&class);
+        let variables: Vec<_> = record
+            .0
+            .iter()
+            .filter(|node| node.starts_with("var "))
+            .collect();
+        assert_eq!(variables.len(), 7);
+        assert!(variables.iter().all(|node| *node == "var w"));
+    }
+}
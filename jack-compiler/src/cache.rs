@@ -0,0 +1,733 @@
+//! Incremental compilation, which skips the codegen of classes whose outputs are up to date.
+//!
+//! A class is rebuilt when its source, the compiler options, the signature of a class it refers
+//! to, or any of its outputs changed since the last build. A signature consists of the name, kind, return and
+//! parameter types of every function, and the values of the constants, which are all that the
+//! codegen of a class needs from the others. With `-O`, functions are inlined across classes, so
+//! a class depends on the whole source of every other class instead.
+//!
+//! Every class is parsed and linted as usual. A class is cached only if its codegen found no
+//! errors, with the warnings it found, which are reported again while the class is up to date as
+//! if it was built. The cache is kept in the directory of the sources as `.jack-cache`:
+//!
+//! ```text
+//! jack-cache 2 0.1.0
+//! class 5f0e3c8a1b2d4e6f 9a8b7c6d5e4f3a2b Main.jack
+//! dep 0c1d2e3f4a5b6c7d Square
+//! dep - Output
+//! output 1a2b3c4d5e6f7a8b Main.vm
+//! mismatch 120 9 int String
+//! ```
+//!
+//! which starts with the version of the format and of the compiler, which must match the current
+//! ones. A class is given by the hashes of its source and of the options, followed by the
+//! classes it depends on with the hashes of their signatures, or `-` for ones not in the program,
+//! the hashes of the outputs written, and the warnings with their spans and types. The hashes are
+//! 64-bit FNV-1a, which stay the same across builds of the compiler.
+use std::{
+    collections::BTreeSet,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use jack_ast::{visit::*, *};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    compiler::{CompilerSession, Options},
+    symbol::SymbolTable,
+    JackError, SemanticError, SemanticErrorKind,
+};
+
+/// Name of the cache file in the directory of the sources.
+pub const FILE_NAME: &str = ".jack-cache";
+// bumped whenever the format of the cache changes
+const VERSION: u32 = 2;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Cache {
+    entries: FxHashMap<PathBuf, Entry>,
+}
+
+/// What the outputs of a class were generated from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub source: u64,
+    pub options: u64,
+    /// classes referred to, with the hashes of their signatures or `None` if they are not defined
+    pub deps: Vec<(String, Option<u64>)>,
+    /// files written for the class, with the hashes of their contents
+    pub outputs: Vec<(PathBuf, u64)>,
+    pub warnings: Vec<Warning>,
+}
+
+/// A type mismatch found by the codegen of a class, which is the only diagnostic of a class that
+/// can be cached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub base: usize,
+    pub len: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Whether a class is rebuilt, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Fresh,
+    New,
+    Options,
+    Source,
+    Dependency(String),
+    /// the outputs were removed or modified
+    Output,
+    /// the cached warnings no longer fit the program
+    Warnings,
+}
+
+impl Status {
+    pub fn is_fresh(&self) -> bool {
+        *self == Status::Fresh
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Fresh => write!(f, "up to date"),
+            Status::New => write!(f, "not built yet"),
+            Status::Options => write!(f, "options changed"),
+            Status::Source => write!(f, "source changed"),
+            Status::Dependency(class) => write!(f, "`{class}` changed"),
+            Status::Output => write!(f, "output changed"),
+            Status::Warnings => write!(f, "warnings cannot be reported again"),
+        }
+    }
+}
+
+impl Cache {
+    /// Reads the cache at `path`, which is empty if it's missing or broken.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|s| Cache::parse(&s))
+            .unwrap_or_default()
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let mut lines = s.lines();
+        if lines.next()? != header() {
+            return None;
+        }
+
+        let mut entries: Vec<Entry> = Vec::new();
+        for line in lines {
+            let mut words = line.splitn(4, ' ');
+            match words.next()? {
+                "class" => {
+                    let source = u64::from_str_radix(words.next()?, 16).ok()?;
+                    let options = u64::from_str_radix(words.next()?, 16).ok()?;
+                    entries.push(Entry {
+                        path: words.next()?.into(),
+                        source,
+                        options,
+                        deps: Vec::new(),
+                        outputs: Vec::new(),
+                        warnings: Vec::new(),
+                    });
+                }
+                "dep" => {
+                    let hash = match words.next()? {
+                        "-" => None,
+                        hash => Some(u64::from_str_radix(hash, 16).ok()?),
+                    };
+                    let name = words.next()?.to_string();
+                    entries.last_mut()?.deps.push((name, hash));
+                }
+                "output" => {
+                    let hash = u64::from_str_radix(words.next()?, 16).ok()?;
+                    let path = words.next()?.into();
+                    entries.last_mut()?.outputs.push((path, hash));
+                }
+                "mismatch" => {
+                    let base = words.next()?.parse().ok()?;
+                    let len = words.next()?.parse().ok()?;
+                    let (expected, actual) = words.next()?.split_once(' ')?;
+                    entries.last_mut()?.warnings.push(Warning {
+                        base,
+                        len,
+                        expected: expected.to_string(),
+                        actual: actual.to_string(),
+                    });
+                }
+                _ => return None,
+            }
+        }
+
+        Some(Cache {
+            entries: entries
+                .into_iter()
+                .map(|entry| (entry.path.clone(), entry))
+                .collect(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&Entry> {
+        self.entries.get(path)
+    }
+
+    pub fn insert(&mut self, entry: Entry) {
+        self.entries.insert(entry.path.clone(), entry);
+    }
+
+    /// Compares `entry` of the current build with the cached one, whose `outputs` must be as they
+    /// were written.
+    pub fn status(&self, entry: &Entry, outputs: &[PathBuf]) -> Status {
+        let Some(cached) = self.entries.get(&entry.path) else {
+            return Status::New;
+        };
+        if cached.options != entry.options {
+            return Status::Options;
+        }
+        if cached.source != entry.source {
+            return Status::Source;
+        }
+        // with `-O`, a class depends on every other one, so classes may be added or removed
+        if cached.deps != entry.deps {
+            let (name, _) = entry
+                .deps
+                .iter()
+                .zip(cached.deps.iter())
+                .find(|(new, old)| new != old)
+                .map(|(new, _)| new)
+                .or_else(|| entry.deps.get(cached.deps.len()))
+                .or_else(|| cached.deps.get(entry.deps.len()))
+                .expect("either list has a dependency the other one does not");
+            return Status::Dependency(name.clone());
+        }
+        let written = |path: &PathBuf| {
+            let cached = cached.outputs.iter().find(|(output, _)| output == path);
+            cached.map(|(_, hash)| *hash) == output_hash(path)
+        };
+        if !outputs.iter().all(written) {
+            return Status::Output;
+        }
+        Status::Fresh
+    }
+}
+
+impl fmt::Display for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", header())?;
+        let mut entries: Vec<_> = self.entries.values().collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        for entry in entries {
+            writeln!(
+                f,
+                "class {:016x} {:016x} {}",
+                entry.source,
+                entry.options,
+                entry.path.display()
+            )?;
+            for (name, hash) in entry.deps.iter() {
+                match hash {
+                    Some(hash) => writeln!(f, "dep {hash:016x} {name}")?,
+                    None => writeln!(f, "dep - {name}")?,
+                }
+            }
+            for (path, hash) in entry.outputs.iter() {
+                writeln!(f, "output {hash:016x} {}", path.display())?;
+            }
+            for Warning {
+                base,
+                len,
+                expected,
+                actual,
+            } in entry.warnings.iter()
+            {
+                writeln!(f, "mismatch {base} {len} {expected} {actual}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The directory which contains every file of `sources`, where the cache is kept.
+pub fn dir(sources: &SourceMap) -> PathBuf {
+    let mut files = sources
+        .files()
+        .map(|file| file.path.parent().unwrap_or(Path::new("")));
+    let Some(mut dir) = files.next() else {
+        return PathBuf::new();
+    };
+    for parent in files {
+        while !parent.starts_with(dir) {
+            dir = dir.parent().unwrap_or(Path::new(""));
+        }
+    }
+    dir.to_path_buf()
+}
+
+/// Entries of the classes of `sess` in the current build, in the order of the classes.
+pub fn entries(sess: &mut CompilerSession<'_, '_>, options: &Options) -> Vec<Entry> {
+    let options_hash = hash(
+        format!(
+            "{:?}",
+            (
+                options.dialect,
+                options.optimize,
+                options.backend,
+                &options.lint_levels,
+                options.deny_warnings,
+                &options.emit,
+                // comments are written in debug builds
+                cfg!(debug_assertions),
+            )
+        )
+        .as_bytes(),
+    );
+    let sources: FxHashMap<&str, u64> = sess
+        .classes
+        .iter()
+        .map(|(file, class)| (class.name, hash(file.src.as_bytes())))
+        .collect();
+    let signatures: FxHashMap<&str, u64> = sess
+        .classes
+        .iter()
+        .map(|(_, class)| {
+            let signature = signature(class, &mut sess.table);
+            (class.name, hash(signature.as_bytes()))
+        })
+        .collect();
+
+    sess.classes
+        .iter()
+        .map(|(file, class)| {
+            let deps = if options.optimize {
+                let mut names: Vec<_> =
+                    sources.keys().filter(|name| **name != class.name).collect();
+                names.sort();
+                names
+                    .into_iter()
+                    .map(|name| (name.to_string(), sources.get(name).copied()))
+                    .collect()
+            } else {
+                let mut deps = Dependencies::default();
+                deps.visit_class(class);
+                deps.classes.remove(class.name);
+                deps.classes
+                    .into_iter()
+                    .map(|name| (name.to_string(), signatures.get(name).copied()))
+                    .collect()
+            };
+            Entry {
+                path: file.path.clone(),
+                source: sources[class.name],
+                options: options_hash,
+                deps,
+                outputs: Vec::new(),
+                warnings: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+impl Warning {
+    /// The warning to cache for `e`, or `None` if `e` cannot be cached.
+    pub fn new(e: &JackError<'_>) -> Option<Self> {
+        let JackError::SemanticError(SemanticError {
+            kind: SemanticErrorKind::TypeMismatch { expected, actual },
+            span,
+        }) = e
+        else {
+            return None;
+        };
+        Some(Warning {
+            base: span.base,
+            len: span.len,
+            expected: expected.to_source(),
+            actual: actual.to_source(),
+        })
+    }
+
+    /// The diagnostic in `file` of the current build, or `None` if the types are not found in it.
+    pub fn replay<'s>(
+        &self,
+        file: FileId,
+        sess: &CompilerSession<'s, '_>,
+    ) -> Option<JackError<'s>> {
+        let kind = SemanticErrorKind::TypeMismatch {
+            expected: ty(&self.expected, sess)?,
+            actual: ty(&self.actual, sess)?,
+        };
+        let span = Span::from_len(file, self.base, self.len);
+        Some(JackError::SemanticError(SemanticError { kind, span }))
+    }
+}
+
+// the type written as `name`, where a class name is borrowed from the OS, or from a class or a
+// declaration of the sources
+fn ty<'s>(name: &str, sess: &CompilerSession<'s, '_>) -> Option<Type<'s>> {
+    let ty = match name {
+        "int" => Type::Int,
+        "char" => Type::Char,
+        "boolean" => Type::Boolean,
+        "void" => Type::Void,
+        _ => {
+            let os = sess
+                .table
+                .functions()
+                .map(|f| f.class)
+                .find(|class| *class == name);
+            let declared = || {
+                sess.classes
+                    .iter()
+                    .flat_map(|(_, class)| declared_types(class))
+                    .find_map(|ty| match ty {
+                        Type::Class(class) if class == name => Some(class),
+                        _ => None,
+                    })
+            };
+            Type::Class(os.or_else(declared)?)
+        }
+    };
+    Some(ty)
+}
+
+// the class itself, and the types of its variables, constants, functions and parameters, which
+// are all the types its codegen may report
+fn declared_types<'a, 's>(class: &'a Class<'s>) -> impl Iterator<Item = Type<'s>> + 'a {
+    let functions = class.functions.iter().flat_map(|f| {
+        let params = f.params.iter().map(|param| param.ty);
+        let variables = f.body.variables.iter().map(|def| def.ty);
+        [f.ret].into_iter().chain(params).chain(variables)
+    });
+    [Type::Class(class.name)]
+        .into_iter()
+        .chain(class.variables.iter().map(|def| def.ty))
+        .chain(class.constants.iter().map(|def| def.ty))
+        .chain(functions)
+}
+
+/// Hash of the contents of the output at `path`, or `None` if it cannot be read.
+pub fn output_hash(path: &Path) -> Option<u64> {
+    fs::read(path).ok().map(|contents| hash(&contents))
+}
+
+// the first line of the cache, which is only read by the same version of the compiler
+fn header() -> String {
+    format!("jack-cache {VERSION} {}", env!("CARGO_PKG_VERSION"))
+}
+
+// 64-bit FNV-1a of `bytes`
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+// what the codegen of other classes depends on, one item per line
+fn signature<'s>(class: &Class<'s>, table: &mut SymbolTable<'_, 's>) -> String {
+    let mut items = Vec::new();
+    for f in class.functions.iter() {
+        let params: Vec<_> = f.params.iter().map(|param| param.ty.to_string()).collect();
+        items.push(format!(
+            "{} {} {}({})",
+            f.kind,
+            f.ret,
+            f.name,
+            params.join(", ")
+        ));
+    }
+
+    table.sess(class.name);
+    for def in class.constants.iter() {
+        let value = table.lookup_const(None, def.name).map(|ctxt| ctxt.value);
+        items.push(format!("const {} {:?}", def.name, value));
+    }
+    for def in class.enums.iter() {
+        for member in def.members.iter() {
+            let value = table
+                .lookup_const(Some(def.name), member.name)
+                .map(|ctxt| ctxt.value);
+            items.push(format!("enum {}.{} {:?}", def.name, member.name, value));
+        }
+    }
+    // the order of functions does not matter to the others
+    items.sort();
+    items.join("\n")
+}
+
+// names which may refer to other classes: types, receivers of calls and scopes of constants
+#[derive(Default)]
+struct Dependencies<'s> {
+    classes: BTreeSet<Ident<'s>>,
+    // variables in scope, whose classes are recorded by their types instead
+    variables: FxHashSet<Ident<'s>>,
+}
+
+impl<'s> Dependencies<'s> {
+    fn ty(&mut self, ty: Type<'s>) {
+        if let Type::Class(name) = ty {
+            self.classes.insert(name);
+        }
+    }
+}
+
+impl<'s> Visitor<'s> for Dependencies<'s> {
+    fn visit_variable_def(&mut self, def: &VariableDef<'s>) {
+        self.ty(def.ty);
+        self.variables.insert(def.name);
+    }
+
+    fn visit_fn_def(&mut self, f: &FnDef<'s>) {
+        self.ty(f.ret);
+        let class_variables = self.variables.clone();
+        walk_fn_def(self, f);
+        self.variables = class_variables;
+    }
+
+    fn visit_parameter(&mut self, param: &Parameter<'s>) {
+        self.ty(param.ty);
+        self.variables.insert(param.name);
+    }
+
+    fn visit_term(&mut self, term: &Term<'s>) {
+        if let TermKind::Qualified { scope, .. } = term.kind {
+            self.classes.insert(scope);
+        }
+        walk_term(self, term);
+    }
+
+    fn visit_fn_call(&mut self, call: &FnCall<'s>) {
+        if let Some(receiver) = call.receiver {
+            if !self.variables.contains(receiver) {
+                self.classes.insert(receiver);
+            }
+        }
+        walk_fn_call(self, call);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compiler::analyze, diagnosis::DiagnosticReporter};
+
+    const MAIN: &str = "class Main { function void main() { do Point.new(1); return; } }";
+
+    fn build(sources: &[(&str, &str)], options: &Options) -> Vec<Entry> {
+        let mut map = SourceMap::new();
+        for (path, src) in sources {
+            map.add_file(path, src.to_string());
+        }
+        let reporter = DiagnosticReporter::new(&map);
+        let mut sess = CompilerSession::new(&reporter);
+        assert!(analyze(&map, &mut sess, options));
+        entries(&mut sess, options)
+    }
+
+    fn statuses(cache: &Cache, entries: &[Entry]) -> Vec<Status> {
+        entries
+            .iter()
+            .map(|entry| cache.status(entry, &[]))
+            .collect()
+    }
+
+    #[test]
+    fn rebuild_on_signature_change() {
+        let options = Options::default();
+        let point = "class Point { function Point new(int x) { return null; } }";
+        let old = build(&[("Main.jack", MAIN), ("Point.jack", point)], &options);
+        let mut cache = Cache::default();
+        assert_eq!(statuses(&cache, &old), [Status::New, Status::New]);
+        for entry in old {
+            cache.insert(entry);
+        }
+
+        // the body is not part of the signature
+        let point = "class Point { function Point new(int x) { var int y; return null; } }";
+        let new = build(&[("Main.jack", MAIN), ("Point.jack", point)], &options);
+        assert_eq!(statuses(&cache, &new), [Status::Fresh, Status::Source]);
+
+        let point = "class Point { function Point new(char x) { return null; } }";
+        let new = build(&[("Main.jack", MAIN), ("Point.jack", point)], &options);
+        assert_eq!(
+            statuses(&cache, &new),
+            [Status::Dependency("Point".into()), Status::Source]
+        );
+
+        let options = Options {
+            optimize: true,
+            ..Options::default()
+        };
+        let new = build(&[("Main.jack", MAIN), ("Point.jack", point)], &options);
+        assert_eq!(statuses(&cache, &new), [Status::Options, Status::Options]);
+    }
+
+    #[test]
+    fn rebuild_on_added_or_removed_class() {
+        // with `-O`, every other class is a dependency
+        let options = Options {
+            optimize: true,
+            ..Options::default()
+        };
+        let point = "class Point { function Point new(int x) { return null; } }";
+        let line = "class Line { }";
+        let mut cache = Cache::default();
+        for entry in build(&[("Main.jack", MAIN), ("Point.jack", point)], &options) {
+            cache.insert(entry);
+        }
+
+        let new = build(
+            &[
+                ("Main.jack", MAIN),
+                ("Point.jack", point),
+                ("Line.jack", line),
+            ],
+            &options,
+        );
+        assert_eq!(
+            statuses(&cache, &new),
+            [
+                Status::Dependency("Line".into()),
+                Status::Dependency("Line".into()),
+                Status::New
+            ]
+        );
+        let new = build(&[("Main.jack", MAIN)], &options);
+        assert_eq!(statuses(&cache, &new), [Status::Dependency("Point".into())]);
+    }
+
+    #[test]
+    fn rebuild_on_output_change() {
+        let dir = std::env::temp_dir().join(format!("jack-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("Main.vm");
+        fs::write(&output, "return").unwrap();
+
+        let mut cache = Cache::default();
+        let mut entry = build(&[("Main.jack", MAIN)], &Options::default()).remove(0);
+        let outputs = [output.clone()];
+        entry.outputs = vec![(output.clone(), output_hash(&output).unwrap())];
+        cache.insert(entry.clone());
+        assert_eq!(cache.status(&entry, &outputs), Status::Fresh);
+
+        fs::write(&output, "push constant 0\nreturn").unwrap();
+        assert_eq!(cache.status(&entry, &outputs), Status::Output);
+        fs::remove_file(&output).unwrap();
+        assert_eq!(cache.status(&entry, &outputs), Status::Output);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut cache = Cache::default();
+        for mut entry in build(&[("src/Main.jack", MAIN)], &Options::default()) {
+            assert_eq!(entry.deps, [("Point".to_string(), None)]);
+            entry.outputs.push(("src/Main.vm".into(), 42));
+            entry.warnings.push(Warning {
+                base: 12,
+                len: 3,
+                expected: "int".to_string(),
+                actual: "Point".to_string(),
+            });
+            cache.insert(entry);
+        }
+        assert_eq!(Cache::parse(&cache.to_string()), Some(cache));
+        let header = header();
+        assert_eq!(
+            Cache::parse(&format!("{header}\nclass 0 0 Main.jack\nfoo")),
+            None
+        );
+        assert_eq!(Cache::parse(&format!("{header}\ndep - Main")), None);
+        // a cache of another version
+        assert_eq!(Cache::parse("class 0 0 Main.jack"), None);
+        assert_eq!(
+            Cache::parse("jack-cache 1 0.1.0\nclass 0 0 Main.jack"),
+            None
+        );
+    }
+
+    #[test]
+    fn stable_hash() {
+        assert_eq!(hash(b""), 0xcbf29ce484222325);
+        assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(hash(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn replay_warnings() {
+        let mut map = SourceMap::new();
+        map.add_file("Main.jack", String::new());
+        let src =
+            "class Point { function Point new() { var int x; let x = Point.new(); return x; } }";
+        let file = map.add_file("Point.jack", src.to_string());
+        let reporter = DiagnosticReporter::new(&map);
+        let mut sess = CompilerSession::new(&reporter);
+        assert!(analyze(&map, &mut sess, &Options::default()));
+
+        let span = Span::from_len(file, 44, 22);
+        let mismatch = |expected, actual| {
+            let kind = SemanticErrorKind::TypeMismatch { expected, actual };
+            JackError::SemanticError(SemanticError { kind, span })
+        };
+        let e = mismatch(Type::Int, Type::Class("Point"));
+        let warning = Warning::new(&e).unwrap();
+        assert_eq!(
+            format!("{:?}", warning.replay(file, &sess)),
+            format!("{:?}", Some(e))
+        );
+        // classes of the OS are not in the sources
+        let e = mismatch(Type::Class("Array"), Type::Char);
+        assert!(Warning::new(&e).unwrap().replay(file, &sess).is_some());
+
+        let missing = Warning {
+            expected: "Line".to_string(),
+            ..warning.clone()
+        };
+        assert!(missing.replay(file, &sess).is_none());
+        // a part of the name of a class is not a class
+        let partial = Warning {
+            expected: "Poi".to_string(),
+            ..warning
+        };
+        assert!(partial.replay(file, &sess).is_none());
+        let e = JackError::SemanticError(SemanticError {
+            kind: SemanticErrorKind::ThisInFunction,
+            span,
+        });
+        assert_eq!(Warning::new(&e), None);
+    }
+
+    #[test]
+    fn variables_are_not_dependencies() {
+        let src = "class Main {
+            field Line line;
+            method void draw(Shape shape) {
+                var Point p;
+                do p.norm();
+                do line.draw();
+                do shape.fill();
+                do Output.println();
+                return;
+            }
+        }";
+        let entry = build(&[("Main.jack", src)], &Options::default()).remove(0);
+        let deps: Vec<_> = entry.deps.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(deps, ["Line", "Output", "Point", "Shape"]);
+    }
+
+    #[test]
+    fn common_dir() {
+        let mut sources = SourceMap::new();
+        sources.add_file("a/b/Main.jack", String::new());
+        sources.add_file("a/c/d/Point.jack", String::new());
+        assert_eq!(dir(&sources), Path::new("a"));
+    }
+}
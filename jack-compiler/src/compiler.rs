@@ -1,35 +1,48 @@
 //! Jack language compiler
 use std::{
     borrow::Cow,
-    fmt, fs,
-    io::{self, BufWriter},
+    fmt, fs, io,
     path::{Path, PathBuf},
     process,
+    str::FromStr,
 };
 
 use hack_vm::Segment;
 use jack_ast::*;
 
 use crate::{
-    diagnosis::{typeck, unused_variable::UnusedVariableVisitor, DiagnosticReporter},
-    is_jack_file, parser,
+    cache::{self, Cache},
+    constant,
+    diagnosis::{
+        control_flow::ControlFlowVisitor, format::MessageFormat, lint::Level, typeck,
+        DiagnosticReporter,
+    },
+    inline, is_jack_file,
+    layout::ClassLayout,
+    lower, native, optimize, parallel,
+    parser::{self, Dialect},
     symbol::{FnCtxt, SymbolTable, VariableCtxt},
+    vm_map::{FunctionMap, VmMap},
     JackError, SemanticError, SemanticErrorKind,
 };
 
 type Result<'a, T> = std::result::Result<T, JackError<'a>>;
 
-#[derive(Debug)]
-struct Program {
-    fname: PathBuf,
-    source: String,
-}
+fn read_sources<P: AsRef<Path>>(programs: &[P]) -> Result<'static, SourceMap> {
+    let mut programs: Vec<&Path> = programs
+        .iter()
+        .map(|p| p.as_ref())
+        .filter(is_jack_file)
+        .collect();
+    // directory entries come in arbitrary order; sort them to compile (and report) deterministically
+    programs.sort();
 
-fn read_program<'a, P: AsRef<Path> + 'a>(path: &'a P) -> Result<'a, Program> {
-    let fname = path.as_ref().with_extension("vm");
-    let source = std::fs::read_to_string(path)?;
+    let mut sources = SourceMap::new();
+    for path in programs {
+        sources.add_file(path, fs::read_to_string(path)?);
+    }
 
-    Ok(Program { fname, source })
+    Ok(sources)
 }
 
 fn abort_on_failure<'a, T>(result: Result<'a, T>, sess: &CompilerSession<'_, 'a>) -> T {
@@ -37,133 +50,638 @@ fn abort_on_failure<'a, T>(result: Result<'a, T>, sess: &CompilerSession<'_, 'a>
         Ok(r) => r,
         Err(e) => {
             sess.reporter.report(&e);
+            sess.reporter.finish();
             process::exit(1);
         }
     }
 }
 
-#[derive(Default)]
+/// Additional outputs requested by `--emit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// field offsets and object size of each class
+    Layout,
+    /// `Class.vm.map` next to each `.vm` file, mapping its lines back to the source
+    SourceMap,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "layout" => Ok(Emit::Layout),
+            "source-map" => Ok(Emit::SourceMap),
+            _ => Err(format!("unknown emit kind: {s}")),
+        }
+    }
+}
+
+/// Target of codegen, chosen by `--backend`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// VM code in `.vm` files
+    #[default]
+    Vm,
+    /// Hack assembly in `.asm` files, generated without the VM
+    Asm,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "vm" => Ok(Backend::Vm),
+            "asm" => Ok(Backend::Asm),
+            _ => Err(format!("unknown backend: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub emit: Vec<Emit>,
+    // lint levels given by `-A/-W/-D`, in order of appearance
+    pub lint_levels: Vec<(String, Level)>,
+    pub deny_warnings: bool,
+    pub message_format: MessageFormat,
+    pub dialect: Dialect,
+    // inline small functions at their call sites, by `-O`
+    pub optimize: bool,
+    pub backend: Backend,
+    // report which classes are rebuilt, by `--verbose`
+    pub verbose: bool,
+}
+
 pub struct CompilerSession<'sess: 's, 's> {
-    pub classes: Vec<(&'sess PathBuf, &'sess str, Class<'sess>)>,
+    pub classes: Vec<(&'sess SourceFile, Class<'sess>)>,
     pub table: SymbolTable<'sess, 's>,
-    pub reporter: DiagnosticReporter,
+    pub reporter: &'sess DiagnosticReporter<'sess>,
     pub has_error: bool,
 }
 impl<'sess, 's> CompilerSession<'sess, 's> {
-    pub fn new() -> Self {
+    pub fn new(reporter: &'sess DiagnosticReporter<'sess>) -> Self {
         CompilerSession {
             classes: Vec::new(),
             table: SymbolTable::new(),
-            reporter: DiagnosticReporter::new(),
+            reporter,
             has_error: false,
         }
     }
 }
 
-pub fn compile<P: AsRef<Path>>(programs: Vec<P>) {
-    let mut sess = CompilerSession::new();
-
-    let programs = abort_on_failure(
-        programs
-            .iter()
-            .filter(is_jack_file)
-            .map(read_program)
-            .collect::<Result<Vec<_>>>(),
-        &sess,
-    );
+pub fn compile<P: AsRef<Path>>(programs: Vec<P>, options: &Options) {
+    let sources = match read_sources(&programs) {
+        Ok(sources) => sources,
+        Err(e) => {
+            eprintln!("{e:?}");
+            process::exit(1);
+        }
+    };
 
-    if programs.is_empty() {
+    if sources.files().next().is_none() {
         eprintln!("Jack program is not given");
         process::exit(0);
     }
 
-    for program in programs.iter() {
-        let class = abort_on_failure(parser::parse(&program.source).map_err(|e| e.into()), &sess);
-        sess.classes
-            .push((&program.fname, program.source.as_str(), class));
+    let mut reporter = DiagnosticReporter::new(&sources);
+    for (name, level) in options.lint_levels.iter() {
+        if let Err(e) = reporter.lints.set_level(name, *level) {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    }
+    if options.deny_warnings {
+        reporter.lints.deny_warnings();
+    }
+    reporter.format = options.message_format;
+
+    let mut sess = CompilerSession::new(&reporter);
+
+    for (file, class) in parse(&sources, options.dialect) {
+        let class = abort_on_failure(class, &sess);
+        sess.classes.push((file, class));
     }
 
-    // register global scope information
-    for (_, src, class) in sess.classes.iter() {
-        sess.table.sess(class.name, src);
-        for f in class.functions.iter() {
-            abort_on_failure(
-                sess.table.register_fn(class.name, f.name, f.ret, f.span),
-                &sess,
-            );
+    if !register_globals(&mut sess) {
+        reporter.finish();
+        process::exit(1);
+    }
+
+    if options.emit.contains(&Emit::Layout) {
+        for (_, class) in sess.classes.iter() {
+            if let Some(layout) = sess.table.layout(class.name) {
+                print!("{layout}");
+            }
         }
     }
 
-    // lint
-    UnusedVariableVisitor::new().check(&sess);
+    lint(&mut sess);
+
+    // skips the classes which are up to date
+    let emit_maps = options.emit.contains(&Emit::SourceMap);
+    let cache_path = cache::dir(&sources).join(cache::FILE_NAME);
+    let mut cache = Cache::load(&cache_path);
+    let extension = match options.backend {
+        Backend::Vm => "vm",
+        Backend::Asm => "asm",
+    };
+    let entries = cache::entries(&mut sess, options);
+    let mut builds: Vec<_> = entries
+        .into_iter()
+        .zip(sess.classes.iter())
+        .map(|(entry, (file, class))| {
+            let mut outputs = vec![file.path.with_extension(extension)];
+            if emit_maps {
+                outputs.push(file.path.with_extension("vm.map"));
+            }
+            let mut status = cache.status(&entry, &outputs);
+            // up to date classes report the warnings cached with them, unless they no longer fit
+            let warnings = cache
+                .get(&entry.path)
+                .filter(|_| status.is_fresh())
+                .and_then(|cached| {
+                    let replayed = cached
+                        .warnings
+                        .iter()
+                        .all(|w| w.replay(file.id, &sess).is_some());
+                    replayed.then(|| cached.warnings.clone())
+                });
+            if status.is_fresh() && warnings.is_none() {
+                status = cache::Status::Warnings;
+            }
+            ClassBuild {
+                name: class.name.to_string(),
+                fresh: status.is_fresh(),
+                status,
+                entry,
+                outputs,
+                warnings,
+            }
+        })
+        .collect();
 
     // codegen
-    process::exit(codegen(&mut sess).unwrap_or(false).into());
-}
+    let mut maps = Vec::new();
+    let has_error = codegen(
+        &mut sess,
+        Output::Files,
+        options,
+        emit_maps.then_some(&mut maps),
+        Some(&mut builds),
+    )
+    .unwrap_or(false);
+    // the source maps of the rebuilt classes
+    let rebuilt = builds.iter().filter(|build| !build.fresh);
+    for (build, map) in rebuilt.zip(maps) {
+        let path = build.entry.path.with_extension("vm.map");
+        if let Err(e) = fs::write(path, map.to_string()) {
+            reporter.report(&e.into());
+        }
+    }
 
-fn codegen<'s>(sess: &'s mut CompilerSession<'_, 's>) -> Result<'s, bool> {
-    let mut unwrap = |result: Result<()>| {
-        if result.is_err() {
-            sess.has_error = true;
+    for build in builds.iter() {
+        let Some(warnings) = &build.warnings else {
+            continue;
+        };
+        // the outputs as they are now, to notice when they are overwritten
+        let hashes: Option<Vec<_>> = build
+            .outputs
+            .iter()
+            .map(|path| cache::output_hash(path))
+            .collect();
+        if let Some(hashes) = hashes {
+            cache.insert(cache::Entry {
+                outputs: build.outputs.iter().cloned().zip(hashes).collect(),
+                warnings: warnings.clone(),
+                ..build.entry.clone()
+            });
         }
+    }
+    if let Err(e) = cache.save(&cache_path) {
+        reporter.report(&e.into());
+    }
+    if options.verbose {
+        for ClassBuild {
+            name,
+            status,
+            fresh,
+            ..
+        } in builds.iter()
+        {
+            match status {
+                _ if *fresh => println!("fresh {name}"),
+                // with `-O`, every class is rebuilt along with the changed ones
+                cache::Status::Fresh => println!("rebuilt {name}: other classes changed"),
+                status => println!("rebuilt {name}: {status}"),
+            }
+        }
+        let count = builds.iter().filter(|build| !build.fresh).count();
+        println!("rebuilt {count} of {} classes", builds.len());
+    }
+    reporter.finish();
+    process::exit(has_error.into());
+}
+
+/// Runs every analysis of `compile` on `sources` without writing any files, and returns `true`
+/// if any errors were found. Unlike `compile`, files which cannot be parsed are reported and
+/// skipped, so that the other files are still checked.
+pub fn check(sources: &SourceMap, reporter: &DiagnosticReporter<'_>, options: &Options) -> bool {
+    let mut sess = CompilerSession::new(reporter);
+    if !analyze(sources, &mut sess, options) {
+        return true;
+    }
+
+    codegen(&mut sess, Output::Discard, options, None, None).unwrap_or(true)
+}
+
+/// Compiles `sources` into VM code, or assembly with `Backend::Asm`, without writing any files,
+/// and returns the code of every class in the order of `sources`, or `None` if any errors were
+/// found.
+pub fn compile_to_vm(
+    sources: &SourceMap,
+    reporter: &DiagnosticReporter<'_>,
+    options: &Options,
+) -> Option<Vec<String>> {
+    let mut sess = CompilerSession::new(reporter);
+    if !analyze(sources, &mut sess, options) || sess.has_error {
+        return None;
+    }
+
+    let mut vm = Vec::new();
+    match codegen(&mut sess, Output::Memory(&mut vm), options, None, None) {
+        Ok(false) => Some(vm),
+        _ => None,
+    }
+}
+
+/// Compiles `sources` into VM code as `compile_to_vm` does, with the source map of every class.
+pub fn compile_with_source_maps(
+    sources: &SourceMap,
+    reporter: &DiagnosticReporter<'_>,
+    options: &Options,
+) -> Option<Vec<(String, VmMap)>> {
+    let mut sess = CompilerSession::new(reporter);
+    if !analyze(sources, &mut sess, options) || sess.has_error {
+        return None;
+    }
+
+    let (mut vm, mut maps) = (Vec::new(), Vec::new());
+    let options = Options {
+        backend: Backend::Vm,
+        ..options.clone()
     };
+    match codegen(
+        &mut sess,
+        Output::Memory(&mut vm),
+        &options,
+        Some(&mut maps),
+        None,
+    ) {
+        Ok(false) => Some(vm.into_iter().zip(maps).collect()),
+        _ => None,
+    }
+}
 
-    for (fname, src, class) in sess.classes.iter() {
-        sess.table.sess(class.name, src);
-        let mut writer = BufWriter::new(fs::File::create(fname)?);
-        let mut generator = Codegen::new(&mut writer, src, &sess.reporter);
+// parses every file, registers globals and runs lints, and returns `false` if the program
+// cannot be analyzed any further.
+pub(crate) fn analyze<'sess>(
+    sources: &'sess SourceMap,
+    sess: &mut CompilerSession<'sess, '_>,
+    options: &Options,
+) -> bool {
+    for (file, class) in parse(sources, options.dialect) {
+        match class {
+            Ok(class) => sess.classes.push((file, class)),
+            Err(e) => {
+                sess.reporter.report(&e);
+                sess.has_error = true;
+            }
+        }
+    }
+
+    if !register_globals(sess) {
+        return false;
+    }
+
+    lint(sess);
+    true
+}
 
-        for var in &class.variables {
-            unwrap(sess.table.register_variable(var.into()));
+// parses and lowers every file on multiple threads, and returns the classes in the order of
+// `sources` so that their errors are reported in the same order on every run
+fn parse(sources: &SourceMap, dialect: Dialect) -> Vec<(&SourceFile, Result<'_, Class<'_>>)> {
+    let files: Vec<_> = sources.files().collect();
+    parallel::map(files, |file| {
+        let class = parser::parse_file(file, dialect).map(|mut class| {
+            lower::lower(&mut class);
+            class
+        });
+        (file, class.map_err(|e| e.into()))
+    })
+}
+
+// registers functions, layouts and constants of every class, and returns `false` at the first
+// conflict.
+fn register_globals(sess: &mut CompilerSession<'_, '_>) -> bool {
+    for (_, class) in sess.classes.iter() {
+        sess.table.sess(class.name);
+        sess.table.register_layout(ClassLayout::new(class));
+        for f in class.functions.iter() {
+            if let Err(e) = sess
+                .table
+                .register_fn(class.name, f.name, f.kind, f.ret, f.span)
+            {
+                sess.reporter.report(&e);
+                return false;
+            }
         }
+    }
 
-        for f in &class.functions {
-            sess.table.scoped(f, |table| {
-                unwrap(generator.fn_def(class, f));
+    let classes: Vec<_> = sess.classes.iter().map(|(_, class)| class).collect();
+    if let Err(e) = constant::register(&classes, &mut sess.table) {
+        sess.reporter.report(&e);
+        return false;
+    }
 
-                if f.kind == FnKind::Method {
-                    // padding an argument register for `this` receiver
-                    // HACK: span for `this` does not exist actually
-                    let ctxt = VariableCtxt::this(Type::Class(class.name), f.span);
-                    unwrap(table.register_variable(ctxt));
-                }
+    true
+}
 
-                for param in &f.params {
-                    unwrap(table.register_variable(param.into()));
-                }
+fn lint(sess: &mut CompilerSession<'_, '_>) {
+    for diag in sess.reporter.lints.check(sess) {
+        sess.reporter.report(&JackError::SemanticError(diag));
+    }
+    sess.has_error |= ControlFlowVisitor::default().check(sess);
+}
 
-                for var in &f.body.variables {
-                    unwrap(table.register_variable(var.into()));
-                }
+/// Where `codegen` writes the code.
+enum Output<'o> {
+    /// `.vm` or `.asm` files next to the sources
+    Files,
+    /// nowhere, only to find errors
+    Discard,
+    /// a string per class
+    Memory(&'o mut Vec<String>),
+}
+
+// a class of an incremental build
+struct ClassBuild {
+    name: String,
+    // what the class is built from, which is cached along with the outputs and the warnings
+    entry: cache::Entry,
+    status: cache::Status,
+    // files written for the class: the code, and the source map if emitted
+    outputs: Vec<PathBuf>,
+    // whether the class is up to date, and skipped
+    fresh: bool,
+    // warnings to be cached, or `None` if they cannot be. those of a class up to date are
+    // reported as if it was built.
+    warnings: Option<Vec<cache::Warning>>,
+}
+
+// generates the code of every class into `output`, and the source maps of VM code into `maps`,
+// skipping the classes up to date in `builds`
+fn codegen<'s>(
+    sess: &'s mut CompilerSession<'_, 's>,
+    output: Output<'_>,
+    options: &Options,
+    mut maps: Option<&mut Vec<VmMap>>,
+    mut builds: Option<&mut [ClassBuild]>,
+) -> Result<'s, bool> {
+    if !options.optimize || options.backend != Backend::Vm || matches!(output, Output::Discard) {
+        return generate(sess, output, options.backend, maps, builds);
+    }
+
+    // functions are inlined across classes, so the whole program is generated first
+    if let Some(builds) = builds.as_deref_mut() {
+        if builds.iter().all(|build| build.fresh) {
+            // nothing is generated, but the warnings are reported
+            return generate(sess, output, Backend::Vm, maps, Some(builds));
+        }
+        for build in builds.iter_mut() {
+            build.fresh = false;
+        }
+    }
+    let paths: Vec<_> = sess
+        .classes
+        .iter()
+        .map(|(file, _)| file.path.with_extension("vm"))
+        .collect();
+    let mut vm = Vec::new();
+    let has_error = generate(
+        sess,
+        Output::Memory(&mut vm),
+        Backend::Vm,
+        maps.as_deref_mut(),
+        builds,
+    )?;
+    let lines = inline::inline(&mut vm);
+    if let Some(maps) = maps {
+        for (map, lines) in maps.iter_mut().zip(lines) {
+            map.relocate(&lines);
+        }
+    }
+
+    match output {
+        Output::Files => {
+            for (path, vm) in paths.iter().zip(vm) {
+                fs::write(path, vm)?;
+            }
+        }
+        Output::Memory(out) => out.extend(vm),
+        Output::Discard => unreachable!(),
+    }
+    Ok(has_error)
+}
+
+// the code of a class, with what its codegen found
+struct Generated<'s> {
+    // VM code, or assembly with `Backend::Asm`
+    code: String,
+    map: VmMap,
+    // diagnostics in the order they were found, to be reported after the classes before it
+    diagnostics: Vec<JackError<'s>>,
+    has_error: bool,
+}
 
-                for stmt in &f.body.statements {
-                    unwrap(generator.statement(stmt, table));
+fn generate<'s>(
+    sess: &'s mut CompilerSession<'_, 's>,
+    mut output: Output<'_>,
+    backend: Backend,
+    mut maps: Option<&mut Vec<VmMap>>,
+    mut builds: Option<&mut [ClassBuild]>,
+) -> Result<'s, bool> {
+    // each class is generated on its own table, which shares the globals with the others
+    let classes: Vec<_> = sess
+        .classes
+        .iter()
+        .enumerate()
+        .map(|(i, (file, class))| {
+            let fresh = builds.as_ref().is_some_and(|builds| builds[i].fresh);
+            (*file, class, (!fresh).then(|| sess.table.fork()))
+        })
+        .collect();
+    let generated = parallel::map(classes, |(file, class, table)| {
+        table.map(|table| generate_class(file, class, table, backend))
+    });
+
+    let reporter = sess.reporter;
+    for (i, generated) in generated.into_iter().enumerate() {
+        let file = sess.classes[i].0;
+        let Some(generated) = generated else {
+            let warnings = builds
+                .as_ref()
+                .and_then(|builds| builds[i].warnings.as_ref());
+            for warning in warnings.into_iter().flatten() {
+                if let Some(e) = warning.replay(file.id, sess) {
+                    reporter.report(&e);
                 }
+            }
+            continue;
+        };
+        let generated = generated?;
+        for e in generated.diagnostics.iter() {
+            reporter.report(e);
+        }
+        sess.has_error |= generated.has_error;
+        if let Some(builds) = builds.as_deref_mut() {
+            builds[i].warnings = match generated.has_error {
+                true => None,
+                false => generated
+                    .diagnostics
+                    .iter()
+                    .map(cache::Warning::new)
+                    .collect(),
+            };
+        }
 
-                Ok(())
-            })?;
+        if let (Some(maps), Backend::Vm) = (maps.as_mut(), backend) {
+            maps.push(generated.map);
+        }
+        match &mut output {
+            Output::Files => {
+                let extension = match backend {
+                    Backend::Vm => "vm",
+                    Backend::Asm => "asm",
+                };
+                fs::write(file.path.with_extension(extension), generated.code)?;
+            }
+            Output::Memory(out) => out.push(generated.code),
+            Output::Discard => {}
         }
     }
 
-    Ok(sess.has_error)
+    Ok(sess.has_error || sess.reporter.has_errors())
+}
+
+// generates the code of `class`, which may run on any thread since nothing is reported yet
+fn generate_class<'s>(
+    file: &SourceFile,
+    class: &'s Class<'s>,
+    mut table: SymbolTable<'_, 's>,
+    backend: Backend,
+) -> Result<'s, Generated<'s>> {
+    table.sess(class.name);
+    let mut buffer = Vec::new();
+    // the VM codegen still runs with `Backend::Asm` to check the program
+    let mut writer: Box<dyn io::Write> = match backend {
+        Backend::Vm => Box::new(&mut buffer),
+        Backend::Asm => Box::new(io::sink()),
+    };
+    let mut generator = Codegen::new(&mut writer);
+    let mut native = native::Codegen::new(class.name);
+    let mut map = VmMap::new(file);
+
+    for var in &class.variables {
+        generator.ok(table.register_variable(var.into()));
+    }
+
+    for f in &class.functions {
+        let vm_line = generator.line;
+        table.scoped(f, |table| {
+            let result = generator.fn_def(class, f, table);
+            let defined = generator.ok(result);
+            if backend == Backend::Asm && defined {
+                generator.ok_or_report(native.fn_def(f, table));
+            }
+
+            if f.kind == FnKind::Method {
+                // padding an argument register for `this` receiver
+                // HACK: span for `this` does not exist actually
+                let ctxt = VariableCtxt::this(Type::Class(class.name), f.span);
+                generator.ok(table.register_variable(ctxt));
+            }
+
+            for param in &f.params {
+                generator.ok(table.register_variable(param.into()));
+            }
+
+            for var in &f.body.variables {
+                generator.ok(table.register_variable(var.into()));
+            }
+
+            let mut statements = f.body.statements.clone();
+            optimize::optimize(&mut statements, table);
+            for stmt in &statements {
+                // the native codegen assumes a statement checked by the VM one
+                let result = generator.statement(stmt, table);
+                if generator.ok(result) && backend == Backend::Asm {
+                    generator.ok_or_report(native.statement(stmt, table));
+                }
+            }
+
+            Ok(())
+        })?;
+
+        let statements = std::mem::take(&mut generator.statements);
+        map.functions
+            .push(FunctionMap::new(file, class.name, f, vm_line, &statements));
+    }
+
+    let (diagnostics, has_error) = (generator.diagnostics, generator.has_error);
+    drop(writer);
+    let code = match backend {
+        Backend::Vm => String::from_utf8_lossy(&buffer).into_owned(),
+        Backend::Asm => native.finish().to_string(),
+    };
+    Ok(Generated {
+        code,
+        map,
+        diagnostics,
+        has_error,
+    })
 }
 
 struct Codegen<'w, 's, W: io::Write> {
     writer: &'w mut W,
-    src: &'s str,
-    reporter: &'s DiagnosticReporter,
+    // errors and warnings found so far, reported by the caller in this order
+    diagnostics: Vec<JackError<'s>>,
+    // whether any part of the class failed to generate
+    has_error: bool,
+    // labels to `continue` and `break` the enclosing loops, innermost last
+    loops: Vec<(String, String)>,
+    // number of lines written
+    line: usize,
+    // the line where each statement starts, for the source map
+    statements: Vec<(usize, Span)>,
 }
 impl<'w, 's, W: io::Write> Codegen<'w, 's, W> {
-    fn new(writer: &'w mut W, src: &'s str, reporter: &'s DiagnosticReporter) -> Self {
+    fn new(writer: &'w mut W) -> Self {
         Codegen {
             writer,
-            src,
-            reporter,
+            diagnostics: Vec::new(),
+            has_error: false,
+            loops: Vec::new(),
+            line: 0,
+            statements: Vec::new(),
         }
     }
 
     fn write<T: fmt::Display>(&mut self, v: T) -> Result<'s, ()> {
-        writeln!(self.writer, "{v}")?;
+        let code = v.to_string();
+        self.line += code.matches('\n').count() + 1;
+        writeln!(self.writer, "{code}")?;
         Ok(())
     }
 
@@ -185,12 +703,19 @@ impl<'w, 's, W: io::Write> Codegen<'w, 's, W> {
         self.write(format!("pop {segment} {v}"))
     }
 
-    fn fn_def(&mut self, class: &Class<'s>, def: &FnDef<'s>) -> Result<'s, ()> {
+    fn fn_def(
+        &mut self,
+        class: &Class<'s>,
+        def: &FnDef<'s>,
+        table: &SymbolTable<'_, 's>,
+    ) -> Result<'s, ()> {
         let n_locals = def.body.variables.len();
         self.write(format!("function {}.{} {}", class.name, def.name, n_locals))?;
         match def.kind {
             FnKind::Ctor => {
-                self.push(Segment::Constant, class.variables.len())?;
+                // statics are not a part of objects
+                let size = table.layout(class.name).map_or(0, |layout| layout.size());
+                self.push(Segment::Constant, size)?;
                 self.write("call Memory.alloc 1")?;
                 // set `this` regiter to the beginning of the allocated memory.
                 self.pop(Segment::Pointer, 0)?;
@@ -226,35 +751,88 @@ impl<'w, 's, W: io::Write> Codegen<'w, 's, W> {
         self.write(format!("goto {label}"))
     }
 
-    fn error(&self, kind: SemanticErrorKind<'s>, span: Span) -> Result<'s, ()> {
-        let err = JackError::SemanticError(SemanticError {
-            src: self.src,
-            kind,
-            span,
-        });
+    fn error(&mut self, kind: SemanticErrorKind<'s>, span: Span) -> Result<'s, ()> {
+        let err = SemanticError { kind, span };
 
-        self.reporter.report(&err);
-        Err(err)
+        self.diagnostics.push(JackError::SemanticError(err.clone()));
+        Err(JackError::SemanticError(err))
     }
 
-    fn warning(&self, kind: SemanticErrorKind<'s>, span: Span) -> Result<'s, ()> {
-        let err = JackError::SemanticError(SemanticError {
-            src: self.src,
-            kind,
-            span,
-        });
-
-        self.reporter.report(&err);
+    fn warning(&mut self, kind: SemanticErrorKind<'s>, span: Span) -> Result<'s, ()> {
+        self.diagnostics
+            .push(JackError::SemanticError(SemanticError { kind, span }));
         Ok(())
     }
 
+    // returns whether `result` is ok, noting that the class has an error otherwise
+    fn ok(&mut self, result: Result<'s, ()>) -> bool {
+        self.has_error |= result.is_err();
+        result.is_ok()
+    }
+
+    // as `ok`, for the errors of the native codegen which does not report them by itself
+    fn ok_or_report(&mut self, result: Result<'s, ()>) -> bool {
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                self.diagnostics.push(e);
+                self.has_error = true;
+                false
+            }
+        }
+    }
+
+    // reports the errors of `stmts` which are never executed, without emitting them
+    fn check_dead(
+        &mut self,
+        stmts: &[Stmt<'s>],
+        table: &mut SymbolTable<'_, 's>,
+    ) -> Result<'s, ()> {
+        let mut sink = io::sink();
+        let mut dead = Codegen::new(&mut sink);
+        dead.loops = self.loops.clone();
+        let result = stmts
+            .iter()
+            .try_for_each(|stmt| dead.statement(stmt, table));
+        self.diagnostics.append(&mut dead.diagnostics);
+        result
+    }
+
+    fn undefined_fn(
+        &mut self,
+        fn_call: &FnCall<'s>,
+        table: &SymbolTable<'_, 's>,
+    ) -> Result<'s, ()> {
+        let name = match fn_call.receiver {
+            Some(receiver) => format!("{receiver}.{}", fn_call.fn_name),
+            None => fn_call.fn_name.to_string(),
+        };
+        let similar = table.similar_fn(fn_call.receiver, fn_call.fn_name);
+
+        self.error(
+            SemanticErrorKind::UndefinedFunction { name, similar },
+            fn_call.span,
+        )
+    }
+
     fn statement(&mut self, stmt: &Stmt<'s>, table: &mut SymbolTable<'_, 's>) -> Result<'s, ()> {
+        self.statements.push((self.line, stmt.span));
         self.comment(stmt)?;
         match &stmt.kind {
             StmtKind::Let { lhs, rhs } => {
                 let Some((var, register)) = table.lookup_variable(lhs.name) else {
+                    if let Some(ctxt) = table.lookup_const(None, lhs.name) {
+                        let defined = ctxt.span;
+                        return self.error(
+                            SemanticErrorKind::AssignToConstant { name: lhs.name, defined },
+                            lhs.span,
+                        );
+                    }
                     return self.error(
-                        SemanticErrorKind::UndefinedVariable(lhs.name),
+                        SemanticErrorKind::UndefinedVariable {
+                            name: lhs.name,
+                            similar: table.similar_variable(lhs.name),
+                        },
                         lhs.span
                     );
                 };
@@ -289,15 +867,23 @@ impl<'w, 's, W: io::Write> Codegen<'w, 's, W> {
                 then_branch,
                 else_branch,
             } => {
+                let else_branch = else_branch.as_ref().map_or(&[][..], Else::stmts);
+                if let Some(n) = optimize::value(cond, table) {
+                    let (live, dead) = match n {
+                        0 => (else_branch, &then_branch[..]),
+                        _ => (&then_branch[..], else_branch),
+                    };
+                    self.check_dead(dead, table)?;
+                    return live.iter().try_for_each(|stmt| self.statement(stmt, table));
+                }
+
                 let label_then = table.label();
                 let label_fi = table.label();
 
                 self.expr(cond, table)?;
                 self.if_goto(&label_then)?;
-                if let Some(else_branch) = else_branch {
-                    for stmt in else_branch {
-                        self.statement(stmt, table)?;
-                    }
+                for stmt in else_branch {
+                    self.statement(stmt, table)?;
                 }
                 self.goto(&label_fi)?;
 
@@ -309,39 +895,57 @@ impl<'w, 's, W: io::Write> Codegen<'w, 's, W> {
                 self.label(&label_fi)?;
             }
             StmtKind::While { cond, body } => {
+                let value = optimize::value(cond, table);
+                if value == Some(0) {
+                    return self.check_dead(body, table);
+                }
                 let label_while = table.label();
                 let label_quit = table.label();
 
                 self.label(&label_while)?;
-                self.expr(cond, table)?;
-                // HACK: inverse condition to align signatures
-                self.write("not")?;
-                self.if_goto(&label_quit)?;
-                for stmt in body {
-                    self.statement(stmt, table)?;
+                // `while (true)` only quits with `break` or `return`
+                if value.is_none() {
+                    self.expr(cond, table)?;
+                    // HACK: inverse condition to align signatures
+                    self.write("not")?;
+                    self.if_goto(&label_quit)?;
                 }
+                self.loops.push((label_while, label_quit));
+                let body = body.iter().try_for_each(|stmt| self.statement(stmt, table));
+                let (label_while, label_quit) = self.loops.pop().unwrap();
+                body?;
                 self.goto(&label_while)?;
                 self.label(&label_quit)?;
             }
-            StmtKind::Do(f) => {
-                let Some(FnCtxt { ty, .. }) = table.lookup_fn(f.receiver, f.fn_name) else {
-                    return self.error(
-                        SemanticErrorKind::InvalidSyntax(
-                            Cow::Borrowed("called function is not defined")
-                        ),
-                        stmt.span
-                    );
+            StmtKind::CompoundLet { .. } | StmtKind::For { .. } => {
+                unreachable!("`{stmt}` is lowered before codegen")
+            }
+            StmtKind::Break | StmtKind::Continue => {
+                // the parser rejects them outside loops
+                let Some((label_continue, label_break)) = self.loops.last() else {
+                    let msg = Cow::Owned(format!("`{stmt}` outside of a loop"));
+                    return self.error(SemanticErrorKind::InvalidSyntax(msg), stmt.span);
                 };
-                if *ty != Type::Void {
-                    self.warning(
-                        SemanticErrorKind::TypeMismatch {
-                            expected: Type::Void,
-                            actual: *ty,
-                        },
-                        stmt.span,
-                    )?;
+                let label = match stmt.kind {
+                    StmtKind::Break => label_break.clone(),
+                    _ => label_continue.clone(),
+                };
+                self.goto(&label)?;
+            }
+            StmtKind::Do(f) => {
+                // an undefined function is reported by `fncall`
+                if let Some(FnCtxt { ty, .. }) = table.lookup_fn(f.receiver, f.fn_name) {
+                    if *ty != Type::Void {
+                        self.warning(
+                            SemanticErrorKind::TypeMismatch {
+                                expected: Type::Void,
+                                actual: *ty,
+                            },
+                            stmt.span,
+                        )?;
+                    }
                 }
-                self.fncall(f, table)?;
+                self.fncall(f, stmt.span, table)?;
                 self.comment("discard return value of the void function")?;
                 self.pop(Segment::Temp, 0)?
             }
@@ -397,45 +1001,71 @@ impl<'w, 's, W: io::Write> Codegen<'w, 's, W> {
         Ok(())
     }
 
-    fn fncall(&mut self, fn_call: &FnCall<'s>, table: &SymbolTable<'_, 's>) -> Result<'s, ()> {
+    fn fncall(
+        &mut self,
+        fn_call: &FnCall<'s>,
+        span: Span,
+        table: &SymbolTable<'_, 's>,
+    ) -> Result<'s, ()> {
         let mut args_len = fn_call.args.len();
         // if a called function is a member method, `FnCall` struct does not have its class specifier
         // (and it's not allowed to call a member method with `this` like `this.method`); so we
         // should treat a fn call as a method call when it does not have its class specifier.
-        let Some(FnCtxt { class, .. }) = table.lookup_fn(fn_call.receiver, fn_call.fn_name) else {
-            return self.error(
-                SemanticErrorKind::UndefinedVariable(fn_call.fn_name),
-                // TODO point span
-                Span::new(0, 0)
-            );
+        let Some(FnCtxt { class, kind, .. }) = table.lookup_fn(fn_call.receiver, fn_call.fn_name) else {
+            let undefined = self.undefined_fn(fn_call, table);
+            // errors in the arguments are independent of the callee, so they are reported together
+            for arg in &fn_call.args {
+                let result = self.expr(arg, table);
+                self.ok(result);
+            }
+            return undefined;
         };
 
-        match fn_call.receiver {
-            Some(receiver) => {
-                if let Some((var, register)) = table.lookup_variable(receiver) {
-                    match var.ty {
-                        Type::Class(_) => {
-                            // push `this` as an argument.
-                            // `instance.method()` will be converted into `Class.method(instance)`.
-                            self.push(var.kind.segment(), register)?;
-                            args_len += 1;
-                        }
-                        _ => {
-                            return self.error(
-                                SemanticErrorKind::InvalidSyntax(Cow::Borrowed(
-                                    "could not call methods of builtin types",
-                                )),
-                                var.span,
-                            );
-                        }
+        let name = fn_call.fn_name;
+        let instance = fn_call.receiver.and_then(|r| table.lookup_variable(r));
+        match (fn_call.receiver, kind) {
+            (Some(_), FnKind::Method) => match instance {
+                Some((var, register)) => match var.ty {
+                    Type::Class(_) => {
+                        // push `this` as an argument.
+                        // `instance.method()` will be converted into `Class.method(instance)`.
+                        self.push(var.kind.segment(), register)?;
+                        args_len += 1;
                     }
+                    _ => {
+                        return self.error(
+                            SemanticErrorKind::InvalidSyntax(Cow::Borrowed(
+                                "could not call methods of builtin types",
+                            )),
+                            var.span,
+                        );
+                    }
+                },
+                None => {
+                    return self.error(
+                        SemanticErrorKind::MethodCalledWithoutInstance { class, name },
+                        span,
+                    );
+                }
+            },
+            (Some(_), kind) => {
+                if instance.is_some() {
+                    let kind = *kind;
+                    return self.error(
+                        SemanticErrorKind::StaticCalledThroughInstance { class, name, kind },
+                        span,
+                    );
                 }
             }
-            None => {
+            (None, FnKind::Method) => {
+                if table.current_fn_kind() == Some(FnKind::Function) {
+                    return self.error(SemanticErrorKind::MethodCalledFromFunction { name }, span);
+                }
                 // assume `this` is omitted
                 self.push(Segment::Pointer, 0)?;
                 args_len += 1;
             }
+            (None, _) => { /* functions and constructors are called without `this` */ }
         }
 
         for arg in &fn_call.args {
@@ -469,27 +1099,43 @@ impl<'w, 's, W: io::Write> Codegen<'w, 's, W> {
     fn term(&mut self, term: &Term<'s>, table: &SymbolTable<'_, 's>) -> Result<'s, ()> {
         match &term.kind {
             TermKind::Const(c) => match c {
-                Constant::This => self.push(Segment::Pointer, 0)?,
-                Constant::Integer(n) => self.push(Segment::Constant, n)?,
+                Constant::This => {
+                    if table.current_fn_kind() == Some(FnKind::Function) {
+                        return self.error(SemanticErrorKind::ThisInFunction, term.span);
+                    }
+                    self.push(Segment::Pointer, 0)?
+                }
+                Constant::Integer(_) | Constant::Radix(..) | Constant::Char(_) => {
+                    let n = c.int_value().unwrap_or_default();
+                    if n > Constant::MAX_INTEGER {
+                        return self.error(SemanticErrorKind::IntegerOutOfRange(n), term.span);
+                    }
+                    self.push(Segment::Constant, n)?
+                }
                 // `null` and `false` are treated as `0`
                 Constant::Null | Constant::False => self.push(Segment::Constant, 0)?,
-                // `true` will be interpreted to `-1`
+                // `true` is `-1`, which is `~0`
                 Constant::True => {
-                    self.push(Segment::Constant, 1)?;
-                    self.write("neg")?;
+                    self.push(Segment::Constant, 0)?;
+                    self.write("not")?;
                 }
                 Constant::Str(s) => {
-                    self.push(Segment::Constant, s.len())?;
+                    // the lexer has rejected the literals which cannot be decoded
+                    let Ok(codes) = charset::decode(s) else {
+                        let msg = Cow::Owned(format!("invalid string literal \"{s}\""));
+                        return self.error(SemanticErrorKind::InvalidSyntax(msg), term.span);
+                    };
+                    self.push(Segment::Constant, codes.len())?;
                     self.write("call String.new 1")?;
 
-                    for c in s.bytes() {
+                    for c in codes {
                         self.push(Segment::Constant, c)?;
                         self.write("call String.appendChar 2")?;
                     }
                 }
             },
             TermKind::Variable(v) => self.variable(v, table)?,
-            TermKind::FnCall(f) => self.fncall(f, table)?,
+            TermKind::FnCall(f) => self.fncall(f, term.span, table)?,
             TermKind::Expr(e) => self.expr(e, table)?,
             TermKind::Unary { op, term } => {
                 self.term(term, table)?;
@@ -499,10 +1145,27 @@ impl<'w, 's, W: io::Write> Codegen<'w, 's, W> {
                 };
                 self.write(op)?;
             }
+            TermKind::Qualified { scope, name } => {
+                let Some(ctxt) = table.lookup_const(Some(scope), name) else {
+                    let name = term.to_string();
+                    return self.error(SemanticErrorKind::UndefinedConstant(name), term.span);
+                };
+                self.constant(ctxt.value)?;
+            }
         }
         Ok(())
     }
 
+    // `push constant` takes only non-negative values, so a negative `n` is pushed as `~n`
+    fn constant(&mut self, n: i16) -> Result<'s, ()> {
+        if n >= 0 {
+            self.push(Segment::Constant, n)
+        } else {
+            self.push(Segment::Constant, !n)?;
+            self.write("not")
+        }
+    }
+
     fn variable(&mut self, variable: &Variable<'s>, table: &SymbolTable<'_, 's>) -> Result<'s, ()> {
         match table.lookup_variable(variable.name) {
             Some((var, register)) => {
@@ -517,12 +1180,23 @@ impl<'w, 's, W: io::Write> Codegen<'w, 's, W> {
                     None => self.push(var.kind.segment(), register),
                 }
             }
-            _ => {
-                return self.error(
-                    SemanticErrorKind::UndefinedVariable(variable.name),
+            None => match table.lookup_const(None, variable.name) {
+                Some(ctxt) if variable.index_accessor.is_none() => self.constant(ctxt.value),
+                Some(_) => {
+                    let msg = format!("cannot index the constant `{}`", variable.name);
+                    self.error(
+                        SemanticErrorKind::InvalidSyntax(Cow::Owned(msg)),
+                        variable.span,
+                    )
+                }
+                None => self.error(
+                    SemanticErrorKind::UndefinedVariable {
+                        name: variable.name,
+                        similar: table.similar_variable(variable.name),
+                    },
                     variable.span,
-                );
-            }
+                ),
+            },
         }
     }
 }
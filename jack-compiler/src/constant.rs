@@ -0,0 +1,378 @@
+//! Evaluation of `const` and `enum` class members (extension).
+//!
+//! Every constant is evaluated once before codegen, which inlines it as `push constant`. Values
+//! are computed with the 16-bit wrapping arithmetic of the Hack machine, and a constant may refer
+//! to the constants of any class regardless of the order of their definitions.
+use std::borrow::Cow;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    symbol::{ConstCtxt, SymbolTable},
+    JackError, SemanticError, SemanticErrorKind as ErrorKind,
+};
+use jack_ast::*;
+
+pub type Result<'s, T> = std::result::Result<T, JackError<'s>>;
+
+// (class_name, enum_name, name), as the keys of the constants in `SymbolTable`
+type Key<'s> = (Ident<'s>, Option<Ident<'s>>, Ident<'s>);
+
+#[derive(Clone, Copy)]
+struct Def<'a, 's> {
+    ty: Type<'s>,
+    span: Span,
+    value: Value<'a, 's>,
+}
+
+#[derive(Clone, Copy)]
+enum Value<'a, 's> {
+    Expr(&'a Expr<'s>),
+    // the value of an enum member is the previous one plus one if it's omitted
+    Next(Option<Key<'s>>),
+}
+
+struct Evaluator<'a, 's> {
+    defs: FxHashMap<Key<'s>, Def<'a, 's>>,
+    values: FxHashMap<Key<'s>, i16>,
+    // constants being evaluated, to find ones defined in terms of themselves
+    evaluating: FxHashSet<Key<'s>>,
+}
+
+/// Evaluates the constants of `classes` and registers them to `table`, stopping at the first
+/// error.
+pub fn register<'s>(classes: &[&Class<'s>], table: &mut SymbolTable<'s, '_>) -> Result<'s, ()> {
+    let mut evaluator = Evaluator {
+        defs: FxHashMap::default(),
+        values: FxHashMap::default(),
+        evaluating: FxHashSet::default(),
+    };
+    let mut keys = Vec::new();
+
+    for class in classes {
+        for def in class.constants.iter() {
+            if !matches!(def.ty, Type::Int | Type::Char | Type::Boolean) {
+                let msg = format!("constants cannot be of type `{}`", def.ty);
+                return error(ErrorKind::InvalidSyntax(Cow::Owned(msg)), def.span);
+            }
+            if let Some(var) = class.variables.iter().find(|v| v.name == def.name) {
+                let kind = ErrorKind::AlreadyDefinedIdent {
+                    name: def.name,
+                    original: Some(var.span),
+                };
+                return error(kind, def.span);
+            }
+
+            let value = Value::Expr(&def.value);
+            let key = (class.name, None, def.name);
+            evaluator.define(key, def.ty, def.span, value)?;
+            keys.push(key);
+        }
+
+        for (i, def) in class.enums.iter().enumerate() {
+            if let Some(other) = class.enums[..i].iter().find(|e| e.name == def.name) {
+                let kind = ErrorKind::AlreadyDefinedIdent {
+                    name: def.name,
+                    original: Some(other.span),
+                };
+                return error(kind, def.span);
+            }
+
+            let mut prev = None;
+            for member in def.members.iter() {
+                let value = match member.value {
+                    Some(ref value) => Value::Expr(value),
+                    None => Value::Next(prev),
+                };
+                let key = (class.name, Some(def.name), member.name);
+                evaluator.define(key, Type::Int, member.span, value)?;
+                keys.push(key);
+                prev = Some(key);
+            }
+        }
+    }
+
+    for key in keys {
+        let value = evaluator.value(key)?;
+        let Def { ty, span, .. } = evaluator.defs[&key];
+        let (class, scope, name) = key;
+        table.register_const(ConstCtxt {
+            class,
+            scope,
+            name,
+            ty,
+            value,
+            span,
+        });
+    }
+
+    Ok(())
+}
+
+fn error<T>(kind: ErrorKind<'_>, span: Span) -> Result<'_, T> {
+    Err(JackError::SemanticError(SemanticError { kind, span }))
+}
+
+impl<'a, 's> Evaluator<'a, 's> {
+    fn define(
+        &mut self,
+        key: Key<'s>,
+        ty: Type<'s>,
+        span: Span,
+        value: Value<'a, 's>,
+    ) -> Result<'s, ()> {
+        if let Some(def) = self.defs.get(&key) {
+            let kind = ErrorKind::AlreadyDefinedIdent {
+                name: key.2,
+                original: Some(def.span),
+            };
+            return error(kind, span);
+        }
+        self.defs.insert(key, Def { ty, span, value });
+        Ok(())
+    }
+
+    fn value(&mut self, key: Key<'s>) -> Result<'s, i16> {
+        if let Some(value) = self.values.get(&key) {
+            return Ok(*value);
+        }
+        let Def { span, value, .. } = self.defs[&key];
+        if !self.evaluating.insert(key) {
+            return error(ErrorKind::CyclicConstant(key.2), span);
+        }
+
+        let value = match value {
+            Value::Expr(expr) => self.expr(key.0, expr)?,
+            Value::Next(Some(prev)) => self.value(prev)?.wrapping_add(1),
+            Value::Next(None) => 0,
+        };
+        self.evaluating.remove(&key);
+        self.values.insert(key, value);
+        Ok(value)
+    }
+
+    // `scope.name` or `name` referred to in `class`, resolved as `SymbolTable::lookup_const`
+    fn resolve(
+        &self,
+        class: Ident<'s>,
+        scope: Option<Ident<'s>>,
+        name: Ident<'s>,
+    ) -> Option<Key<'s>> {
+        let candidates = match scope {
+            None => [Some((class, None, name)), None],
+            Some(scope) => [Some((class, Some(scope), name)), Some((scope, None, name))],
+        };
+        candidates
+            .into_iter()
+            .flatten()
+            .find(|key| self.defs.contains_key(key))
+    }
+
+    fn expr(&mut self, class: Ident<'s>, expr: &Expr<'s>) -> Result<'s, i16> {
+        let lhs = self.term(class, &expr.lhs)?;
+        let Some(box (ref op, ref term)) = expr.rhs else { return Ok(lhs) };
+        let rhs = self.term(class, term)?;
+
+        match binary(op, lhs, rhs) {
+            Some(value) => Ok(value),
+            None => error(ErrorKind::DivisionByZero, term.span),
+        }
+    }
+
+    fn term(&mut self, class: Ident<'s>, term: &Term<'s>) -> Result<'s, i16> {
+        let (scope, name) = match term.kind {
+            TermKind::Const(ref c) => {
+                return match c {
+                    Constant::Integer(_) | Constant::Radix(..) | Constant::Char(_) => {
+                        let n = c.int_value().unwrap_or_default();
+                        if n > Constant::MAX_INTEGER {
+                            return error(ErrorKind::IntegerOutOfRange(n), term.span);
+                        }
+                        Ok(n as i16)
+                    }
+                    Constant::True => Ok(-1),
+                    Constant::False | Constant::Null => Ok(0),
+                    Constant::Str(_) | Constant::This => {
+                        error(ErrorKind::NonConstantExpr(term.to_string()), term.span)
+                    }
+                }
+            }
+            TermKind::Expr(ref expr) => return self.expr(class, expr),
+            TermKind::Unary { ref op, ref term } => {
+                return Ok(unary(op, self.term(class, term)?));
+            }
+            TermKind::Variable(Variable {
+                name,
+                index_accessor: None,
+                ..
+            }) => (None, name),
+            TermKind::Qualified { scope, name } => (Some(scope), name),
+            TermKind::Variable(_) | TermKind::FnCall(_) => {
+                return error(ErrorKind::NonConstantExpr(term.to_string()), term.span);
+            }
+        };
+
+        match self.resolve(class, scope, name) {
+            Some(key) => self.value(key),
+            // a variable can be named in the same way as a constant, but it's not a constant
+            None if scope.is_none() => {
+                error(ErrorKind::NonConstantExpr(term.to_string()), term.span)
+            }
+            None => error(ErrorKind::UndefinedConstant(term.to_string()), term.span),
+        }
+    }
+}
+
+/// Applies `op` as the Hack machine does, with `-1` for true and `0` for false. `None` on division
+/// by zero.
+pub fn binary(op: &BinOp, lhs: i16, rhs: i16) -> Option<i16> {
+    let truth = |b: bool| if b { -1 } else { 0 };
+    Some(match op {
+        BinOp::Plus => lhs.wrapping_add(rhs),
+        BinOp::Minus => lhs.wrapping_sub(rhs),
+        BinOp::Mul => lhs.wrapping_mul(rhs),
+        BinOp::Div if rhs == 0 => return None,
+        BinOp::Div => lhs.wrapping_div(rhs),
+        BinOp::And => lhs & rhs,
+        BinOp::Or => lhs | rhs,
+        BinOp::Equal => truth(lhs == rhs),
+        BinOp::Lt => truth(lhs < rhs),
+        BinOp::Gt => truth(lhs > rhs),
+    })
+}
+
+/// Applies `op` as the Hack machine does.
+pub fn unary(op: &UnaryOp, value: i16) -> i16 {
+    match op {
+        UnaryOp::Minus => value.wrapping_neg(),
+        UnaryOp::Not => !value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_with, Dialect};
+
+    // constants of `src` by their names written as `Class.NAME` or `Class.Enum.NAME`
+    fn evaluate<'s>(src: &[&'s str]) -> Result<'s, Vec<(String, i16)>> {
+        let classes: Vec<_> = src
+            .iter()
+            .map(|src| parse_with(src, Dialect::Extended).unwrap())
+            .collect();
+        let mut table = SymbolTable::new();
+        register(&classes.iter().collect::<Vec<_>>(), &mut table)?;
+
+        let mut values = Vec::new();
+        for class in classes.iter() {
+            table.sess(class.name);
+            for def in class.constants.iter() {
+                let value = table.lookup_const(None, def.name).unwrap().value;
+                values.push((format!("{}.{}", class.name, def.name), value));
+            }
+            for def in class.enums.iter() {
+                for member in def.members.iter() {
+                    let value = table
+                        .lookup_const(Some(def.name), member.name)
+                        .unwrap()
+                        .value;
+                    let name = format!("{}.{}.{}", class.name, def.name, member.name);
+                    values.push((name, value));
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    fn kind(result: Result<'_, Vec<(String, i16)>>) -> ErrorKind<'_> {
+        match result {
+            Err(JackError::SemanticError(e)) => e.kind,
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn evaluate_constants() {
+        let main = "class Main {
+            const int WIDTH = (Board.WIDTH / 8) - 1;
+            const boolean DEBUG = ~(LEVEL < 2);
+            const int LEVEL = 0x10 | 'A';
+            const char NEWLINE = 128;
+            enum Dir { UP, DOWN, LEFT = -(Dir.DOWN + 9), RIGHT }
+        }";
+        let board = "class Board { const int WIDTH = 512; }";
+        let values = evaluate(&[main, board]).unwrap();
+
+        let expected = [
+            ("Main.WIDTH", 63),
+            ("Main.DEBUG", -1),
+            ("Main.LEVEL", 81),
+            ("Main.NEWLINE", 128),
+            ("Main.Dir.UP", 0),
+            ("Main.Dir.DOWN", 1),
+            ("Main.Dir.LEFT", -10),
+            ("Main.Dir.RIGHT", -9),
+            ("Board.WIDTH", 512),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn evaluate_wrapping() {
+        let src = "class Main { const int MIN = 32767 + 1; const int Z = MIN - 2; }";
+        let values = evaluate(&[src]).unwrap();
+        assert_eq!(values[0].1, i16::MIN);
+        assert_eq!(values[1].1, i16::MAX - 1);
+    }
+
+    #[test]
+    fn evaluate_constants_err() {
+        let err = |src| kind(evaluate(&[src]));
+
+        let kind = err("class Main { const int A = B; const int B = A + 1; }");
+        assert!(matches!(kind, ErrorKind::CyclicConstant("A")), "{kind:?}");
+
+        let kind = err("class Main { enum E { A = E.B, B } }");
+        assert!(matches!(kind, ErrorKind::CyclicConstant("A")), "{kind:?}");
+
+        let kind = err("class Main { const int A = 1 / (A - A); }");
+        assert!(matches!(kind, ErrorKind::CyclicConstant("A")), "{kind:?}");
+
+        let kind = err("class Main { const int A = 1 / 0; }");
+        assert!(matches!(kind, ErrorKind::DivisionByZero), "{kind:?}");
+
+        let kind = err("class Main { static int a; const int A = a; }");
+        assert!(matches!(kind, ErrorKind::NonConstantExpr(_)), "{kind:?}");
+
+        let kind = err("class Main { const int A = Math.abs(1); }");
+        assert!(matches!(kind, ErrorKind::NonConstantExpr(_)), "{kind:?}");
+
+        let kind = err("class Main { const int A = Other.A; }");
+        assert!(matches!(kind, ErrorKind::UndefinedConstant(ref name) if name == "Other.A"));
+
+        let kind = err("class Main { const String A = \"a\"; }");
+        assert!(matches!(kind, ErrorKind::InvalidSyntax(_)), "{kind:?}");
+
+        let kind = err("class Main { static int A; const int A = 1; }");
+        assert!(matches!(
+            kind,
+            ErrorKind::AlreadyDefinedIdent { name: "A", .. }
+        ));
+
+        let kind = err("class Main { enum E { A, A } }");
+        assert!(matches!(
+            kind,
+            ErrorKind::AlreadyDefinedIdent { name: "A", .. }
+        ));
+
+        let kind = err("class Main { enum E { A } enum E { B } }");
+        assert!(matches!(
+            kind,
+            ErrorKind::AlreadyDefinedIdent { name: "E", .. }
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+use jack_ast::{visit::Visitor, Constant, Expr, FnDef, Stmt, StmtKind, TermKind, Type};
+
+use crate::{compiler::CompilerSession, JackError, SemanticError, SemanticErrorKind};
+
+/// Whether the control can reach the end of a statement (or a block).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Flow {
+    #[default]
+    Fallthrough,
+    Diverge,
+}
+
+#[derive(Default)]
+pub struct ControlFlowVisitor<'s> {
+    // diagnostics in the order of appearance
+    diagnostics: Vec<SemanticError<'s>>,
+    has_error: bool,
+    // whether the control reaches the end of the statement (or block) visited last
+    flow: Flow,
+}
+
+impl<'s> ControlFlowVisitor<'s> {
+    /// Reports missing returns and unreachable code, and returns `true` if any errors were found.
+    pub fn check(&mut self, sess: &'s CompilerSession<'_, 's>) -> bool {
+        for (_, class) in sess.classes.iter() {
+            for f in class.functions.iter() {
+                self.visit_fn_def(f);
+            }
+        }
+
+        self.emit(sess);
+        self.has_error
+    }
+
+    fn emit(&mut self, sess: &'s CompilerSession<'_, 's>) {
+        for diag in self.diagnostics.drain(..) {
+            sess.reporter.report(&JackError::SemanticError(diag));
+        }
+    }
+
+    fn visit_block(&mut self, stmts: &[Stmt<'s>]) -> Flow {
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.visit_stmt(stmt);
+            if self.flow == Flow::Diverge {
+                // the step of a lowered `for` follows the body, but is written before it
+                let mut unreachable = stmts[i + 1..]
+                    .iter()
+                    .filter(|s| s.span.lo() >= stmt.span.hi());
+                if let Some(first) = unreachable.next() {
+                    let last = unreachable.last().unwrap_or(first);
+                    self.diagnostics.push(SemanticError {
+                        kind: SemanticErrorKind::UnreachableCode { cause: stmt.span },
+                        span: first.span.with_hi(last.span.hi()),
+                    });
+                }
+                return Flow::Diverge;
+            }
+        }
+
+        self.flow = Flow::Fallthrough;
+        Flow::Fallthrough
+    }
+}
+
+impl<'s> Visitor<'s> for ControlFlowVisitor<'s> {
+    fn visit_fn_def(&mut self, f: &FnDef<'s>) {
+        let flow = self.visit_block(&f.body.statements);
+        // the VM function falls through into whatever comes next if it does not return
+        if flow == Flow::Fallthrough && f.ret != Type::Void {
+            self.has_error = true;
+            self.diagnostics.push(SemanticError {
+                kind: SemanticErrorKind::MissingReturn {
+                    name: f.name,
+                    ty: f.ret,
+                },
+                span: f.span,
+            });
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt<'s>) {
+        self.flow = match &stmt.kind {
+            StmtKind::Return(_) => Flow::Diverge,
+            StmtKind::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let then_flow = self.visit_block(then_branch);
+                let else_flow = match else_branch {
+                    Some(else_branch) => self.visit_block(else_branch.stmts()),
+                    None => Flow::Fallthrough,
+                };
+
+                if then_flow == Flow::Diverge && else_flow == Flow::Diverge {
+                    Flow::Diverge
+                } else {
+                    Flow::Fallthrough
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.visit_block(body);
+                if !is_always_true(cond) || body.iter().any(contains_break) {
+                    Flow::Fallthrough
+                } else {
+                    // `while (true)` without `break` can only be left by `return`; either way
+                    // the statements following the loop are never executed.
+                    if !body.iter().any(contains_return) {
+                        self.diagnostics.push(SemanticError {
+                            kind: SemanticErrorKind::InfiniteLoop,
+                            span: stmt.span,
+                        });
+                    }
+                    Flow::Diverge
+                }
+            }
+            StmtKind::Let { .. } | StmtKind::Do(_) => Flow::Fallthrough,
+            // the rest of the loop body is skipped
+            StmtKind::Break | StmtKind::Continue => Flow::Diverge,
+            StmtKind::CompoundLet { .. } | StmtKind::For { .. } => {
+                unreachable!("lowered before analysis")
+            }
+        };
+    }
+}
+
+fn is_always_true(cond: &Expr<'_>) -> bool {
+    if cond.rhs.is_some() {
+        return false;
+    }
+
+    match &cond.lhs.kind {
+        TermKind::Const(Constant::True) => true,
+        TermKind::Expr(expr) => is_always_true(expr),
+        _ => false,
+    }
+}
+
+fn contains_return(stmt: &Stmt<'_>) -> bool {
+    match &stmt.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            then_branch.iter().any(contains_return)
+                || else_branch
+                    .as_ref()
+                    .is_some_and(|els| els.stmts().iter().any(contains_return))
+        }
+        StmtKind::While { body, .. } => body.iter().any(contains_return),
+        StmtKind::Let { .. } | StmtKind::Do(_) | StmtKind::Break | StmtKind::Continue => false,
+        StmtKind::CompoundLet { .. } | StmtKind::For { .. } => {
+            unreachable!("lowered before analysis")
+        }
+    }
+}
+
+// whether `stmt` leaves the loop it is in, not counting `break` of nested loops
+fn contains_break(stmt: &Stmt<'_>) -> bool {
+    match &stmt.kind {
+        StmtKind::Break => true,
+        StmtKind::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            then_branch.iter().any(contains_break)
+                || else_branch
+                    .as_ref()
+                    .is_some_and(|els| els.stmts().iter().any(contains_break))
+        }
+        StmtKind::While { .. }
+        | StmtKind::Let { .. }
+        | StmtKind::Do(_)
+        | StmtKind::Return(_)
+        | StmtKind::Continue => false,
+        StmtKind::CompoundLet { .. } | StmtKind::For { .. } => {
+            unreachable!("lowered before analysis")
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Machine-readable diagnostics for `--message-format=json|sarif`.
+use std::str::FromStr;
+
+use jack_ast::SourceFile;
+use miette::Severity;
+use serde::{Serialize, Serializer};
+
+use super::lint::LintStore;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// graphical output of miette
+    #[default]
+    Human,
+    /// one JSON object per line for each diagnostic
+    Json,
+    /// a single SARIF 2.1.0 log once the compilation finished
+    Sarif,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            "sarif" => Ok(MessageFormat::Sarif),
+            _ => Err(format!("unknown message format: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Label {
+    // 1-based lines and columns of the beginning and the end
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    #[serde(rename = "label")]
+    pub text: Option<String>,
+}
+
+/// A diagnostic flattened out of [`miette::Report`], serialized as a line of
+/// `--message-format=json`.
+#[derive(Debug, Serialize)]
+pub struct Message {
+    #[serde(serialize_with = "serialize_severity")]
+    pub severity: Severity,
+    // name of the lint which raised the diagnostic
+    pub code: Option<&'static str>,
+    #[serde(rename = "message")]
+    pub text: String,
+    // name of the source file
+    pub file: Option<String>,
+    // the first one is the primary label
+    #[serde(rename = "spans")]
+    pub labels: Vec<Label>,
+    pub help: Option<String>,
+}
+
+fn serialize_severity<S: Serializer>(
+    severity: &Severity,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Advice => "help",
+    })
+}
+
+impl Message {
+    pub fn new(
+        report: &miette::Report,
+        code: Option<&'static str>,
+        file: Option<&SourceFile>,
+    ) -> Self {
+        let labels = match (file, report.labels()) {
+            (Some(file), Some(labels)) => labels
+                .map(|label| {
+                    let byte_start = label.offset();
+                    let byte_end = label.offset() + label.len();
+                    let (line_start, column_start) = file.line_col(byte_start);
+                    let (line_end, column_end) = file.line_col(byte_end);
+                    Label {
+                        line_start,
+                        column_start,
+                        line_end,
+                        column_end,
+                        byte_start,
+                        byte_end,
+                        text: label.label().map(|s| s.to_string()),
+                    }
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Message {
+            severity: report.severity().unwrap_or(Severity::Error),
+            code,
+            text: report.to_string(),
+            file: file.map(|file| file.name()),
+            labels,
+            help: report.help().map(|help| help.to_string()),
+        }
+    }
+
+    /// an error without any location, e.g. I/O errors
+    pub fn unlocated(text: String) -> Self {
+        Message {
+            severity: Severity::Error,
+            code: None,
+            text,
+            file: None,
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("messages are serializable")
+    }
+
+    fn to_sarif_result(&self) -> SarifResult<'_> {
+        let level = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Advice => "note",
+        };
+        let text = match &self.help {
+            Some(help) => format!("{}\nhelp: {help}", self.text),
+            None => self.text.clone(),
+        };
+        let location = |label: &Label| Location {
+            physical_location: PhysicalLocation {
+                artifact_location: ArtifactLocation {
+                    uri: self.file.as_deref(),
+                },
+                region: Region {
+                    start_line: label.line_start,
+                    start_column: label.column_start,
+                    end_line: label.line_end,
+                    end_column: label.column_end,
+                },
+            },
+            message: label.text.clone().map(|text| Text { text }),
+        };
+
+        SarifResult {
+            rule_id: self.code,
+            level,
+            message: Text { text },
+            locations: self
+                .labels
+                .split_first()
+                .map(|(primary, related)| Locations {
+                    locations: [location(primary)],
+                    related_locations: related.iter().map(location).collect(),
+                }),
+        }
+    }
+}
+
+// the subset of SARIF 2.1.0 written by `to_sarif`
+
+#[derive(Serialize)]
+struct SarifLog<'a> {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: [Run<'a>; 1],
+}
+
+#[derive(Serialize)]
+struct Run<'a> {
+    tool: Tool,
+    results: Vec<SarifResult<'a>>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Serialize)]
+struct Driver {
+    name: &'static str,
+    rules: Vec<Rule>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Rule {
+    id: &'static str,
+    short_description: Text,
+}
+
+#[derive(Serialize)]
+struct Text {
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule_id: Option<&'static str>,
+    level: &'static str,
+    message: Text,
+    // results without any label have no location at all
+    #[serde(flatten)]
+    locations: Option<Locations<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Locations<'a> {
+    locations: [Location<'a>; 1],
+    related_locations: Vec<Location<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location<'a> {
+    physical_location: PhysicalLocation<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<Text>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation<'a> {
+    artifact_location: ArtifactLocation<'a>,
+    region: Region,
+}
+
+#[derive(Serialize)]
+struct ArtifactLocation<'a> {
+    uri: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+    start_column: usize,
+    end_line: usize,
+    end_column: usize,
+}
+
+/// Renders collected diagnostics as a SARIF log, with lints as the rules of the tool.
+pub(crate) fn to_sarif(messages: &[Message], lints: &LintStore) -> String {
+    let rules = lints
+        .lints()
+        .map(|lint| Rule {
+            id: lint.name(),
+            short_description: Text {
+                text: lint.description().to_string(),
+            },
+        })
+        .collect();
+    let log = SarifLog {
+        schema: "https://json.schemastore.org/sarif-2.1.0.json",
+        version: "2.1.0",
+        runs: [Run {
+            tool: Tool {
+                driver: Driver {
+                    name: "jack-compiler",
+                    rules,
+                },
+            },
+            results: messages.iter().map(Message::to_sarif_result).collect(),
+        }],
+    };
+
+    serde_json::to_string(&log).expect("SARIF logs are serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_json_string() {
+        let message = Message::unlocated("\"a\"\n\\\u{1}".to_string());
+        assert_eq!(
+            message.to_json(),
+            r#"{"severity":"error","code":null,"message":"\"a\"\n\\\u0001","file":null,"spans":[],"help":null}"#
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! Configurable lints.
+//!
+//! Each lint has a unique name and a default [`Level`], which can be overridden by `-A/-W/-D <lint>`
+//! or `--deny-warnings`. A lint either runs its own check over the parsed classes, or is raised by
+//! other passes (e.g. `type_mismatch` is raised while generating code); in both cases its
+//! diagnostics are filtered and graded by the [`LintStore`] when reported.
+use std::{fmt, str::FromStr};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    compiler::CompilerSession,
+    diagnosis::{
+        uninitialized::UninitializedVariableVisitor, unused_variable::UnusedVariableVisitor,
+    },
+    SemanticError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// the lint is not checked nor reported
+    Allow,
+    /// the lint is reported as a warning
+    Warn,
+    /// the lint is reported as an error, and fails the compilation
+    Deny,
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(Level::Allow),
+            "warn" => Ok(Level::Warn),
+            "deny" => Ok(Level::Deny),
+            _ => Err(format!("unknown lint level: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Allow => "allow",
+            Level::Warn => "warn",
+            Level::Deny => "deny",
+        }
+        .fmt(f)
+    }
+}
+
+pub trait Lint {
+    /// unique name used in `-A/-W/-D` flags
+    fn name(&self) -> &'static str;
+
+    fn default_level(&self) -> Level;
+
+    fn description(&self) -> &'static str;
+
+    /// Checks the whole program before code generation.
+    /// Lints raised by other passes do not have to implement this.
+    fn check<'s>(&self, _sess: &'s CompilerSession<'_, 's>) -> Vec<SemanticError<'s>> {
+        Vec::new()
+    }
+}
+
+/// Declares a unit struct implementing [`Lint`], with an associated `NAME` to refer to it by.
+macro_rules! declare_lint {
+    (
+        $(#[$attr:meta])*
+        $lint:ident, $name:literal, $level:ident, $description:literal
+        $(, check($sess:ident) => $check:expr)?
+    ) => {
+        $(#[$attr])*
+        pub struct $lint;
+
+        impl $lint {
+            pub const NAME: &'static str = $name;
+        }
+
+        impl Lint for $lint {
+            fn name(&self) -> &'static str {
+                Self::NAME
+            }
+
+            fn default_level(&self) -> Level {
+                Level::$level
+            }
+
+            fn description(&self) -> &'static str {
+                $description
+            }
+
+            $(
+                fn check<'s>(&self, $sess: &'s CompilerSession<'_, 's>) -> Vec<SemanticError<'s>> {
+                    $check
+                }
+            )?
+        }
+    };
+}
+
+declare_lint!(
+    UnusedVariables,
+    "unused_variables",
+    Warn,
+    "detects variables which are never read",
+    check(sess) => UnusedVariableVisitor::new().check(sess)
+);
+
+declare_lint!(
+    UseBeforeAssignment,
+    "use_before_assignment",
+    Warn,
+    "detects `var` locals which might be read before any assignment",
+    check(sess) => UninitializedVariableVisitor::new().check(sess)
+);
+
+declare_lint!(
+    /// Raised by codegen. Types in Jack language are not strict well, so warn by default instead of
+    /// deny.
+    TypeMismatch,
+    "type_mismatch",
+    Warn,
+    "detects values whose types do not match the expected ones"
+);
+
+declare_lint!(
+    /// Raised by the control flow analysis.
+    UnreachableCode,
+    "unreachable_code",
+    Warn,
+    "detects statements which are never executed"
+);
+
+declare_lint!(
+    /// Raised by the control flow analysis.
+    InfiniteLoop,
+    "infinite_loop",
+    Warn,
+    "detects `while (true)` loops without `return`"
+);
+
+/// Registry of lints and their configured levels.
+pub struct LintStore {
+    lints: Vec<Box<dyn Lint>>,
+    levels: FxHashMap<&'static str, Level>,
+    deny_warnings: bool,
+}
+
+impl Default for LintStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LintStore {
+    pub fn new() -> Self {
+        let mut store = LintStore {
+            lints: Vec::new(),
+            levels: FxHashMap::default(),
+            deny_warnings: false,
+        };
+
+        store.register(Box::new(UnusedVariables));
+        store.register(Box::new(UseBeforeAssignment));
+        store.register(Box::new(TypeMismatch));
+        store.register(Box::new(UnreachableCode));
+        store.register(Box::new(InfiniteLoop));
+        store
+    }
+
+    pub fn register(&mut self, lint: Box<dyn Lint>) {
+        debug_assert!(self.find(lint.name()).is_none());
+        self.lints.push(lint);
+    }
+
+    pub fn lints(&self) -> impl Iterator<Item = &dyn Lint> {
+        self.lints.iter().map(|lint| lint.as_ref())
+    }
+
+    fn find(&self, name: &str) -> Option<&dyn Lint> {
+        self.lints().find(|lint| lint.name() == name)
+    }
+
+    pub fn set_level(&mut self, name: &str, level: Level) -> Result<(), String> {
+        let Some(lint) = self.find(name) else {
+            return Err(format!("unknown lint: `{name}`"));
+        };
+
+        let name = lint.name();
+        self.levels.insert(name, level);
+        Ok(())
+    }
+
+    /// promotes every lint at `warn` level to `deny`.
+    pub fn deny_warnings(&mut self) {
+        self.deny_warnings = true;
+    }
+
+    pub fn level(&self, name: &str) -> Level {
+        let level = match self.levels.get(name) {
+            Some(level) => *level,
+            None => self
+                .find(name)
+                .map_or(Level::Warn, |lint| lint.default_level()),
+        };
+
+        match level {
+            Level::Warn if self.deny_warnings => Level::Deny,
+            level => level,
+        }
+    }
+
+    /// Runs the checks of enabled lints, and returns their diagnostics in order of appearance.
+    pub fn check<'s>(&self, sess: &'s CompilerSession<'_, 's>) -> Vec<SemanticError<'s>> {
+        let position = |diag: &SemanticError<'_>| (diag.span.file, diag.span.lo());
+
+        let mut diagnostics = Vec::new();
+        for lint in self.lints() {
+            if self.level(lint.name()) == Level::Allow {
+                continue;
+            }
+
+            let mut diags = lint.check(sess);
+            diags.sort_by_key(position);
+            diagnostics.extend(diags);
+        }
+
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lint_level_override() {
+        let mut store = LintStore::new();
+        assert_eq!(store.level(UnusedVariables::NAME), Level::Warn);
+
+        store
+            .set_level(UnusedVariables::NAME, Level::Allow)
+            .unwrap();
+        assert_eq!(store.level(UnusedVariables::NAME), Level::Allow);
+        assert_eq!(store.level(TypeMismatch::NAME), Level::Warn);
+
+        assert!(store.set_level("no_such_lint", Level::Deny).is_err());
+    }
+
+    #[test]
+    fn deny_warnings_does_not_enable_allowed_lints() {
+        let mut store = LintStore::new();
+        store.set_level(InfiniteLoop::NAME, Level::Allow).unwrap();
+        store.deny_warnings();
+
+        assert_eq!(store.level(TypeMismatch::NAME), Level::Deny);
+        assert_eq!(store.level(InfiniteLoop::NAME), Level::Allow);
+    }
+}
@@ -1,30 +1,117 @@
+pub mod control_flow;
+pub mod format;
+pub mod lint;
 pub mod report;
+pub mod suggest;
 pub mod typeck;
+pub mod uninitialized;
 pub mod unused_variable;
 
+use std::{
+    cell::{Cell, RefCell},
+    sync::Once,
+};
+
 use crate::JackError;
+use format::{Message, MessageFormat};
+use jack_ast::SourceMap;
+use lint::{Level, LintStore};
+use miette::Severity;
 use report::Report;
 
-#[derive(Default)]
-pub struct DiagnosticReporter;
-impl DiagnosticReporter {
-    pub fn new() -> Self {
-        miette::set_hook(Box::new(|_| {
-            Box::new(miette::MietteHandlerOpts::new().unicode(false).build())
-        }))
-        .unwrap_or_else(|ie| panic!("failed to setup miette: {ie}"));
+pub struct DiagnosticReporter<'sess> {
+    // sources which spans of diagnostics point to
+    sources: &'sess SourceMap,
+    pub lints: LintStore,
+    pub format: MessageFormat,
+    // number of reported errors, including denied lints
+    errors: Cell<usize>,
+    // hold diagnostics to be taken by `take_messages` instead of printing them
+    capture: bool,
+    // diagnostics held until `finish` (since SARIF is a single document) or `take_messages`
+    held: RefCell<Vec<Message>>,
+}
+impl<'sess> DiagnosticReporter<'sess> {
+    pub fn new(sources: &'sess SourceMap) -> Self {
+        // the hook is global, while reporters are created for each compilation
+        static MIETTE_HOOK: Once = Once::new();
+        MIETTE_HOOK.call_once(|| {
+            miette::set_hook(Box::new(|_| {
+                Box::new(miette::MietteHandlerOpts::new().unicode(false).build())
+            }))
+            .unwrap_or_else(|ie| panic!("failed to setup miette: {ie}"));
+        });
+
+        DiagnosticReporter {
+            sources,
+            lints: LintStore::new(),
+            format: MessageFormat::default(),
+            errors: Cell::new(0),
+            capture: false,
+            held: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Holds diagnostics to be taken by [`DiagnosticReporter::take_messages`] instead of printing
+    /// them, e.g. to send them to editors.
+    pub fn capture(&mut self) {
+        self.capture = true;
+    }
 
-        DiagnosticReporter
+    pub fn take_messages(&self) -> Vec<Message> {
+        self.held.take()
     }
 
     pub fn report(&self, e: &JackError<'_>) {
-        let report = match e {
-            JackError::SemanticError(e) => e.report(),
-            JackError::ParseError(e) => e.report(),
-            JackError::LexError(ref e) => e.report(),
-            _ => return eprintln!("{e:?}"),
+        let (report, code, span) = match e {
+            JackError::SemanticError(e) => {
+                let code = e.kind.lint();
+                let level = code.map_or(Level::Deny, |lint| self.lints.level(lint));
+                let severity = match level {
+                    Level::Allow => return,
+                    Level::Warn => Severity::Warning,
+                    Level::Deny => Severity::Error,
+                };
+                (e.report(severity, self.sources), code, e.span)
+            }
+            JackError::ParseError(e) => (e.report(Severity::Error, self.sources), None, e.span),
+            JackError::LexError(ref e) => (e.report(Severity::Error, self.sources), None, e.span),
+            _ => {
+                self.errors.set(self.errors.get() + 1);
+                return match self.format {
+                    MessageFormat::Human if !self.capture => eprintln!("{e:?}"),
+                    _ => self.emit(Message::unlocated(e.to_string())),
+                };
+            }
         };
 
-        eprintln!("{report:?}");
+        if report.severity().unwrap_or(Severity::Error) == Severity::Error {
+            self.errors.set(self.errors.get() + 1);
+        }
+        let file = self.sources.file(span.file);
+        match self.format {
+            MessageFormat::Human if !self.capture => eprintln!("{}", report::render(&report, file)),
+            _ => self.emit(Message::new(&report, code, Some(file))),
+        }
+    }
+
+    fn emit(&self, message: Message) {
+        match self.format {
+            _ if self.capture => self.held.borrow_mut().push(message),
+            MessageFormat::Json => eprintln!("{}", message.to_json()),
+            MessageFormat::Sarif => self.held.borrow_mut().push(message),
+            MessageFormat::Human => unreachable!("human-readable output is rendered by miette"),
+        }
+    }
+
+    /// Flushes diagnostics held until the end of the compilation.
+    pub fn finish(&self) {
+        if self.format == MessageFormat::Sarif && !self.capture {
+            eprintln!("{}", format::to_sarif(&self.held.borrow(), &self.lints));
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors.get() > 0
     }
 }
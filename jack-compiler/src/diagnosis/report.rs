@@ -1,33 +1,200 @@
-use crate::{LexError, ParseError, SemanticError, SemanticErrorKind};
-use miette::{LabeledSpan, MietteDiagnostic, Severity};
+use crate::{
+    diagnosis::suggest, LexError, LexErrorKind, ParseError, ParseErrorKind, SemanticError,
+    SemanticErrorKind,
+};
+use jack_ast::{Constant, SourceFile, SourceMap, Span};
+use miette::{LabeledSpan, MietteDiagnostic, NamedSource, Severity};
 
 pub(crate) trait Report {
-    fn report(&self) -> miette::Report;
+    fn report(&self, severity: Severity, sources: &SourceMap) -> miette::Report;
+}
+
+// the source file which `span` belongs to, named by its path
+fn source_of(span: Span, sources: &SourceMap) -> NamedSource {
+    let file = sources.file(span.file);
+    NamedSource::new(file.name(), file.src.clone())
+}
+
+/// Renders `report` for humans. miette heads each snippet with `path:line:col` of where the
+/// snippet starts, which is replaced with the position of the (first) label in the snippet.
+pub(crate) fn render(report: &miette::Report, file: &SourceFile) -> String {
+    // the primary label comes first
+    let labels: Vec<_> = report
+        .labels()
+        .into_iter()
+        .flatten()
+        .map(|label| file.line_col(label.offset()))
+        .collect();
+
+    let rendered = format!("{report:?}");
+    let lines: Vec<_> = rendered.split_inclusive('\n').collect();
+    let mut out = String::with_capacity(rendered.len());
+    for (i, line) in lines.iter().enumerate() {
+        if !strip_ansi(line).trim_start().starts_with(",-[") {
+            out.push_str(line);
+            continue;
+        }
+
+        // line numbers in the gutter of the snippet, until its end
+        let numbers: Vec<usize> = lines[i + 1..]
+            .iter()
+            .map(|line| strip_ansi(line))
+            .take_while(|line| !line.trim_start().starts_with("`-"))
+            .filter_map(|line| line.split_once(" |")?.0.trim().parse().ok())
+            .collect();
+        let (Some(first), Some(last)) = (numbers.first(), numbers.last()) else {
+            out.push_str(line);
+            continue;
+        };
+        let Some((line_no, col)) = labels.iter().find(|(l, _)| (first..=last).contains(&l)) else {
+            out.push_str(line);
+            continue;
+        };
+
+        // `[path:line:col]`, where the path may contain `:` itself
+        let end = line.rfind(']').unwrap_or(line.len());
+        let mut head = &line[..end];
+        for _ in 0..2 {
+            head = head.rsplit_once(':').map_or(head, |(head, _)| head);
+        }
+        out.push_str(&format!("{head}:{line_no}:{col}{}", &line[end..]));
+    }
+
+    out
+}
+
+// `s` without the escape sequences of colors and styles
+fn strip_ansi(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // `ESC [ params letter`
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            stripped.push(c);
+        }
+    }
+
+    stripped
+}
+
+fn similar_help(what: &str, name: &str, similar: &str) -> String {
+    if suggest::is_case_mismatch(name, similar) {
+        format!("names are case-sensitive: replace `{name}` with `{similar}`")
+    } else {
+        format!("a {what} with a similar name exists: `{similar}`")
+    }
+}
+
+fn integer_range_help() -> String {
+    format!(
+        "integer constants must be at most {} (`0x7FFF`)",
+        Constant::MAX_INTEGER
+    )
 }
 
 impl<'s> Report for SemanticError<'s> {
-    fn report(&self) -> miette::Report {
+    fn report(&self, severity: Severity, sources: &SourceMap) -> miette::Report {
         let mut diag = MietteDiagnostic::new(self.to_string());
         match self.kind {
             SemanticErrorKind::AlreadyDefinedIdent { name, original } => {
                 let label = Some(format!("`{name}` redefined here"));
-                let hint = Some(format!("previous definition of `{name}` here"));
-                diag = diag
-                    .and_label(LabeledSpan::new_with_span(label, self.span))
-                    .and_label(LabeledSpan::new_with_span(hint, original));
+                diag = diag.and_label(LabeledSpan::new_with_span(label, self.span));
+                match original {
+                    Some(original) => {
+                        let hint = Some(format!("previous definition of `{name}` here"));
+                        diag = diag.and_label(LabeledSpan::new_with_span(hint, original));
+                    }
+                    None => diag = diag.with_help(format!("`{name}` is a function of the OS")),
+                }
+            }
+            SemanticErrorKind::UndefinedVariable { name, similar } => {
+                let label = Some(self.to_string());
+                diag = diag.and_label(LabeledSpan::new_with_span(label, self.span));
+                if let Some(similar) = similar {
+                    diag = diag.with_help(similar_help("variable", name, similar));
+                }
+            }
+            SemanticErrorKind::UndefinedFunction {
+                ref name,
+                ref similar,
+            } => {
+                let label = Some(self.to_string());
+                diag = diag.and_label(LabeledSpan::new_with_span(label, self.span));
+                if let Some(similar) = similar {
+                    diag = diag.with_help(similar_help("function", name, similar));
+                }
             }
             SemanticErrorKind::TypeMismatch { expected, actual } => {
                 let label = Some(format!("expected `{expected}`, found `{actual}`"));
-                // types in Jack language are not strict well, so set severity to warn instead of error
-                diag = diag
-                    .and_label(LabeledSpan::new_with_span(label, self.span))
-                    .with_severity(Severity::Warning);
+                diag = diag.and_label(LabeledSpan::new_with_span(label, self.span));
             }
             SemanticErrorKind::UnusedVariable(name) => {
                 let label = Some(format!("variable `{name}` is defined here"));
+                diag = diag.and_label(LabeledSpan::new_with_span(label, self.span));
+            }
+            SemanticErrorKind::MethodCalledWithoutInstance { class, name } => {
+                let label = Some(format!("`{name}` is a method of `{class}`"));
                 diag = diag
                     .and_label(LabeledSpan::new_with_span(label, self.span))
-                    .with_severity(Severity::Warning);
+                    .with_help(format!("call it through an instance of `{class}`"));
+            }
+            SemanticErrorKind::MethodCalledFromFunction { name } => {
+                let label = Some(format!("`{name}` requires `this`"));
+                diag = diag
+                    .and_label(LabeledSpan::new_with_span(label, self.span))
+                    .with_help("functions do not have `this`; use a method instead");
+            }
+            SemanticErrorKind::StaticCalledThroughInstance { class, name, kind } => {
+                let label = Some(format!("`{name}` is a {kind}"));
+                diag = diag
+                    .and_label(LabeledSpan::new_with_span(label, self.span))
+                    .with_help(format!("call it through its class: `{class}.{name}(..)`"));
+            }
+            SemanticErrorKind::ThisInFunction => {
+                let label = Some("functions do not have `this`".to_string());
+                diag = diag.and_label(LabeledSpan::new_with_span(label, self.span));
+            }
+            SemanticErrorKind::MissingReturn { name, ty } => {
+                let label = Some(format!(
+                    "`{name}` can reach its end without returning `{ty}`"
+                ));
+                diag = diag
+                    .and_label(LabeledSpan::new_with_span(label, self.span))
+                    .with_help("add a `return` statement to every path of the function");
+            }
+            SemanticErrorKind::UnreachableCode { cause } => {
+                let label = Some("unreachable statement".to_string());
+                let hint = Some("any code following this statement is unreachable".to_string());
+                diag = diag
+                    .and_label(LabeledSpan::new_with_span(label, self.span))
+                    .and_label(LabeledSpan::new_with_span(hint, cause));
+            }
+            SemanticErrorKind::InfiniteLoop => {
+                let label = Some("this loop has no `return` to exit".to_string());
+                diag = diag.and_label(LabeledSpan::new_with_span(label, self.span));
+            }
+            SemanticErrorKind::UseBeforeAssignment { name, defined } => {
+                let label = Some(format!("`{name}` is read here before any `let`"));
+                let hint = Some(format!("`{name}` is declared here"));
+                diag = diag
+                    .and_label(LabeledSpan::new_with_span(label, self.span))
+                    .and_label(LabeledSpan::new_with_span(hint, defined));
+            }
+            SemanticErrorKind::IntegerOutOfRange(_) => {
+                let label = Some("does not fit in `push constant`".to_string());
+                diag = diag
+                    .and_label(LabeledSpan::new_with_span(label, self.span))
+                    .with_help(integer_range_help());
+            }
+            SemanticErrorKind::AssignToConstant { name, defined } => {
+                let label = Some(format!("`{name}` is a constant"));
+                let hint = Some(format!("`{name}` is defined here"));
+                diag = diag
+                    .and_label(LabeledSpan::new_with_span(label, self.span))
+                    .and_label(LabeledSpan::new_with_span(hint, defined))
+                    .with_help("use a `static` variable for a value which changes");
             }
             _ => {
                 let label = Some(self.to_string());
@@ -35,30 +202,58 @@ impl<'s> Report for SemanticError<'s> {
             }
         }
 
-        miette::Report::new(diag).with_source_code(self.src.to_string())
+        miette::Report::new(diag.with_severity(severity))
+            .with_source_code(source_of(self.span, sources))
     }
 }
 
 impl<'s> Report for ParseError<'s> {
-    fn report(&self) -> miette::Report {
+    fn report(&self, severity: Severity, sources: &SourceMap) -> miette::Report {
         let label = Some(self.to_string());
         let mut diag = MietteDiagnostic::new(self.to_string())
             .with_label(LabeledSpan::new_with_span(label, self.span));
 
-        if let Some(help) = &self.help {
-            diag = diag.with_help(help.to_string());
+        match (&self.help, &self.kind) {
+            (Some(help), _) => diag = diag.with_help(help.to_string()),
+            (None, ParseErrorKind::ExtensionRequired(_)) => {
+                diag = diag.with_help("enable the language extensions with `--dialect extended`");
+            }
+            (None, ParseErrorKind::OutsideLoop(keyword)) => {
+                diag = diag.with_help(format!("`{keyword}` can only be used inside `while`"));
+            }
+            _ => {}
         }
 
-        miette::Report::new(diag).with_source_code(self.src.to_string())
+        miette::Report::new(diag.with_severity(severity))
+            .with_source_code(source_of(self.span, sources))
     }
 }
 
 impl<'s> Report for LexError<'s> {
-    fn report(&self) -> miette::Report {
+    fn report(&self, severity: Severity, sources: &SourceMap) -> miette::Report {
         let label = Some(self.to_string());
-        let diag = MietteDiagnostic::new(self.to_string())
+        let mut diag = MietteDiagnostic::new(self.to_string())
             .with_label(LabeledSpan::new_with_span(label, self.span));
 
-        miette::Report::new(diag).with_source_code(self.src.to_string())
+        match self.kind {
+            LexErrorKind::IntegerOutOfRange(_) => diag = diag.with_help(integer_range_help()),
+            LexErrorKind::InvalidCharLiteral => {
+                diag = diag.with_help(
+                    "a character literal is a single printable ASCII character like `'A'`",
+                );
+            }
+            LexErrorKind::UnknownEscape(_) => {
+                diag = diag.with_help(r#"the escape sequences are `\"`, `\\` and `\n`"#);
+            }
+            LexErrorKind::UnsupportedCharacter(_) => {
+                diag = diag.with_help(
+                    r"strings and characters may only contain printable ASCII, or `\n` for a newline",
+                );
+            }
+            _ => {}
+        }
+
+        miette::Report::new(diag.with_severity(severity))
+            .with_source_code(source_of(self.span, sources))
     }
 }
@@ -0,0 +1,83 @@
+//! Suggestions of similar names for typos.
+
+/// Edit distance between `a` and `b` counting insertions, deletions, substitutions and
+/// transpositions of adjacent characters. Differences only in ASCII case cost nothing.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let b: Vec<char> = b.chars().map(|c| c.to_ascii_lowercase()).collect();
+
+    // d[i][j]: distance between a[..i] and b[..j]
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=b.len() {
+        d[0][j] = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// Finds the most similar name among `candidates` which is close enough to be a typo of `name`.
+/// Ties are broken by the lexical order, so that the result does not depend on the order of
+/// `candidates`.
+pub fn find_similar<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    // same threshold as rustc: a third of the length, but allow at least one typo
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Whether `a` and `b` differ only in ASCII case.
+pub fn is_case_mismatch(a: &str, b: &str) -> bool {
+    a != b && a.eq_ignore_ascii_case(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("count", "count"), 0);
+        assert_eq!(edit_distance("count", "coutn"), 1);
+        assert_eq!(edit_distance("count", "counter"), 2);
+        assert_eq!(edit_distance("output", "Output"), 0);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn similar_names() {
+        let candidates = ["counter", "count", "amount", "Output.printString"];
+        assert_eq!(find_similar("coutn", candidates), Some("count"));
+        assert_eq!(find_similar("cnt", candidates), None);
+        assert_eq!(
+            find_similar("output.printString", candidates),
+            Some("Output.printString")
+        );
+        // nothing to suggest for a defined name
+        assert_eq!(find_similar("amount", ["amount"]), None);
+    }
+}
@@ -5,7 +5,7 @@ use crate::{
     JackError,
 };
 
-use jack_ast::{Constant, Expr, Term, TermKind, Type, Variable};
+use jack_ast::{charset, Constant, Expr, Term, TermKind, Type, Variable};
 
 pub fn validate_variable_ty(var: &Variable<'_>, ty: &Type<'_>, table: &SymbolTable) -> bool {
     let Some((ctxt, .. )) = table.lookup_variable(var.name) else {
@@ -43,13 +43,14 @@ pub fn infer_expr_ty<'s>(
         ))))
 }
 
-fn infer_term_ty<'s>(term: &Term<'s>, table: &SymbolTable<'_, 's>) -> Option<Type<'s>> {
+pub fn infer_term_ty<'s>(term: &Term<'s>, table: &SymbolTable<'_, 's>) -> Option<Type<'s>> {
     use TermKind::*;
     match &term.kind {
         Const(c) => match c {
-            Constant::Integer(_) => Some(Type::Int),
+            Constant::Integer(_) | Constant::Radix(..) => Some(Type::Int),
+            Constant::Char(_) => Some(Type::Char),
             Constant::Str(s) => {
-                if s.len() == 1 {
+                if charset::decode(s).map_or(false, |codes| codes.len() == 1) {
                     Some(Type::Char)
                 } else {
                     Some(Type::Class("String"))
@@ -61,8 +62,9 @@ fn infer_term_ty<'s>(term: &Term<'s>, table: &SymbolTable<'_, 's>) -> Option<Typ
         },
         Variable(v) => match table.lookup_variable(v.name) {
             Some((var, ..)) => Some(var.ty),
-            _ => None,
+            _ => table.lookup_const(None, v.name).map(|c| c.ty),
         },
+        Qualified { scope, name } => table.lookup_const(Some(scope), name).map(|c| c.ty),
         FnCall(f) => match table.lookup_fn(f.receiver, f.fn_name) {
             Some(FnCtxt { ty, .. }) => Some(*ty),
             _ => None,
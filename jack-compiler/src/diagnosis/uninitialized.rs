@@ -0,0 +1,168 @@
+use jack_ast::{
+    visit::{walk_fn_call, walk_fn_def, walk_stmt, walk_term, walk_variable, Visitor},
+    FnCall, FnDef, Ident, Span, Stmt, StmtKind, Term, TermKind, Variable,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{compiler::CompilerSession, SemanticError, SemanticErrorKind};
+
+/// Definite-assignment analysis of `var` locals.
+///
+/// `HackVm::define_fn` zero-initializes locals, so reading one before any `let` is not undefined
+/// behaviour; it is however almost always a bug (e.g. calling a method of `null`).
+#[derive(Default)]
+pub struct UninitializedVariableVisitor<'s> {
+    // `var` locals of the current function, and where they are defined
+    locals: FxHashMap<Ident<'s>, Span>,
+    // locals reported once already in the current function
+    reported: FxHashSet<Ident<'s>>,
+    // diagnostics in the order of appearance
+    diagnostics: Vec<SemanticError<'s>>,
+    // locals which might not be assigned at the statement being visited
+    unassigned: State<'s>,
+    // the statement or term of the call being visited, which a read of its receiver points to
+    call_span: Option<Span>,
+}
+
+/// Locals that might not be assigned at a program point.
+/// `None` means the point is unreachable (e.g. after `return`), which unifies with anything.
+type State<'s> = Option<FxHashSet<Ident<'s>>>;
+
+fn join<'s>(lhs: State<'s>, rhs: State<'s>) -> State<'s> {
+    match (lhs, rhs) {
+        (Some(mut lhs), Some(rhs)) => {
+            lhs.extend(rhs);
+            Some(lhs)
+        }
+        (lhs, None) => lhs,
+        (None, rhs) => rhs,
+    }
+}
+
+impl<'s> UninitializedVariableVisitor<'s> {
+    pub fn new() -> Self {
+        UninitializedVariableVisitor::default()
+    }
+
+    pub fn check(&mut self, sess: &'s CompilerSession<'_, 's>) -> Vec<SemanticError<'s>> {
+        for (_, class) in sess.classes.iter() {
+            for f in class.functions.iter() {
+                self.visit_fn_def(f);
+            }
+        }
+
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    fn visit_block(&mut self, stmts: &[Stmt<'s>]) {
+        for stmt in stmts.iter() {
+            self.visit_stmt(stmt);
+        }
+    }
+
+    fn check_read(&mut self, name: Ident<'s>, span: Span) {
+        let Some(unassigned) = &self.unassigned else { return };
+        if !unassigned.contains(name) || !self.reported.insert(name) {
+            return;
+        }
+
+        let defined = self.locals[name];
+        self.diagnostics.push(SemanticError {
+            kind: SemanticErrorKind::UseBeforeAssignment { name, defined },
+            span,
+        });
+    }
+}
+
+impl<'s> Visitor<'s> for UninitializedVariableVisitor<'s> {
+    fn visit_fn_def(&mut self, f: &FnDef<'s>) {
+        self.reported.clear();
+        self.locals = f.body.variables.iter().map(|v| (v.name, v.span)).collect();
+        self.unassigned = Some(self.locals.keys().copied().collect());
+
+        walk_fn_def(self, f);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt<'s>) {
+        // nothing to report in unreachable code
+        if self.unassigned.is_none() {
+            return;
+        }
+
+        match &stmt.kind {
+            StmtKind::Let { lhs, rhs } => {
+                self.visit_expr(rhs);
+                match &lhs.index_accessor {
+                    // `let a[i] = ..` reads `a` to compute the address
+                    Some(_) => self.visit_variable(lhs),
+                    None => {
+                        if let Some(unassigned) = &mut self.unassigned {
+                            unassigned.remove(lhs.name);
+                        }
+                    }
+                }
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.visit_expr(cond);
+                let before = self.unassigned.clone();
+                self.visit_block(then_branch);
+                let then_state = std::mem::replace(&mut self.unassigned, before);
+                if let Some(else_branch) = else_branch {
+                    self.visit_block(else_branch.stmts());
+                }
+
+                self.unassigned = join(then_state, self.unassigned.take());
+            }
+            StmtKind::While { cond, body } => {
+                self.visit_expr(cond);
+                // the body might not be executed at all, so assignments inside
+                // the loop do not count after it. this also covers leaving it by `break`,
+                // as the body only assigns more locals than before the loop.
+                let before = self.unassigned.clone();
+                self.visit_block(body);
+                self.unassigned = join(before, self.unassigned.take());
+            }
+            StmtKind::Do(_) => {
+                self.call_span = Some(stmt.span);
+                walk_stmt(self, stmt);
+            }
+            StmtKind::Return(_) => {
+                walk_stmt(self, stmt);
+                self.unassigned = None;
+            }
+            StmtKind::Break | StmtKind::Continue => self.unassigned = None,
+            StmtKind::CompoundLet { .. } | StmtKind::For { .. } => {
+                unreachable!("lowered before analysis")
+            }
+        }
+    }
+
+    fn visit_term(&mut self, term: &Term<'s>) {
+        if let TermKind::FnCall(_) = term.kind {
+            self.call_span = Some(term.span);
+        }
+        walk_term(self, term);
+    }
+
+    fn visit_variable(&mut self, var: &Variable<'s>) {
+        walk_variable(self, var);
+        self.check_read(var.name, var.span);
+    }
+
+    fn visit_fn_call(&mut self, call: &FnCall<'s>) {
+        // taken before the arguments, which may contain calls of their own
+        let span = self
+            .call_span
+            .take()
+            .expect("calls are in statements or terms");
+        walk_fn_call(self, call);
+
+        if let Some(receiver) = call.receiver {
+            self.check_read(receiver, span);
+        }
+    }
+}
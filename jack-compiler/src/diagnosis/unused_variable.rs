@@ -1,15 +1,19 @@
-use jack_ast::{Class, Expr, FnCall, Ident, Span, Stmt, StmtKind, Term, TermKind};
+use jack_ast::{
+    visit::{walk_fn_call, walk_fn_def, walk_stmt, walk_variable, Visitor},
+    Class, FnCall, FnDef, Ident, Parameter, Span, Stmt, StmtKind, Variable, VariableDef,
+    VariableDefKind,
+};
 use rustc_hash::FxHashMap;
 
-use crate::{compiler::CompilerSession, JackError, SemanticError, SemanticErrorKind};
+use crate::{compiler::CompilerSession, SemanticError, SemanticErrorKind};
 
 #[derive(Default)]
 pub struct UnusedVariableVisitor<'s> {
-    // ident, (is ident used, src, span)
-    used: FxHashMap<Ident<'s>, (bool, &'s str, Span)>,
+    // ident, (is ident used, span)
+    used: FxHashMap<Ident<'s>, (bool, Span)>,
     // `used` for current scope. once check completed, current scope will be merged into `used`
     // and `current` will be reset.
-    current: FxHashMap<Ident<'s>, (bool, &'s str, Span)>,
+    current: FxHashMap<Ident<'s>, (bool, Span)>,
 }
 
 impl<'s> UnusedVariableVisitor<'s> {
@@ -20,124 +24,84 @@ impl<'s> UnusedVariableVisitor<'s> {
         }
     }
 
-    pub fn check(&mut self, sess: &'s CompilerSession<'_, 's>) {
-        let classes: Vec<(&str, &Class)> = sess
-            .classes
-            .iter()
-            .map(|(_, src, class)| (*src, class))
-            .collect();
+    pub fn check(&mut self, sess: &'s CompilerSession<'_, 's>) -> Vec<SemanticError<'s>> {
+        let classes: Vec<&Class> = sess.classes.iter().map(|(_, class)| class).collect();
 
         self.walk(&classes);
-        self.emit(sess);
+        self.emit()
     }
 
-    fn walk(&mut self, classes: &[(&'s str, &'s Class<'s>)]) {
-        for (src, class) in classes.iter() {
-            for local in class.variables.iter() {
-                self.used.insert(local.name, (false, src, local.span));
-            }
+    fn walk(&mut self, classes: &[&'s Class<'s>]) {
+        for class in classes.iter() {
+            self.visit_class(class);
+        }
 
-            for f in class.functions.iter() {
-                // reset for each functions' scope
-                self.used.extend(self.current.iter());
-                self.current.clear();
+        // the scope of the last function
+        self.used.extend(self.current.drain());
+    }
 
-                for param in f.params.iter() {
-                    self.current.insert(param.name, (false, src, param.span));
-                }
+    fn emit(&self) -> Vec<SemanticError<'s>> {
+        self.used
+            .iter()
+            .filter(|(_, (used, ..))| !used)
+            .map(|(ident, (_, span))| SemanticError {
+                kind: SemanticErrorKind::UnusedVariable(ident),
+                span: *span,
+            })
+            .collect()
+    }
 
-                for var in f.body.variables.iter() {
-                    self.current.insert(var.name, (false, src, var.span));
-                }
+    fn mark_used(&mut self, ident: Ident<'s>) {
+        self.current.entry(ident).and_modify(|state| state.0 = true);
+        // HACK: assume that there're no variables with the same name in both
+        // function-scoped and class-scoped.
+        self.used.entry(ident).and_modify(|state| state.0 = true);
+    }
+}
 
-                for stmt in f.body.statements.iter() {
-                    self.check_stmt(stmt);
-                }
-            }
-        }
+impl<'s> Visitor<'s> for UnusedVariableVisitor<'s> {
+    fn visit_variable_def(&mut self, def: &VariableDef<'s>) {
+        let state = (false, def.span);
+        match def.kind {
+            VariableDefKind::Static | VariableDefKind::Field => self.used.insert(def.name, state),
+            VariableDefKind::Var => self.current.insert(def.name, state),
+        };
     }
 
-    fn emit(&self, sess: &'s CompilerSession<'_, 's>) {
-        for (ident, (used, src, span)) in self.used.iter() {
-            if !used {
-                let span = *span;
-                let err = JackError::SemanticError(SemanticError {
-                    kind: SemanticErrorKind::UnusedVariable(ident),
-                    src,
-                    span,
-                });
-                sess.reporter.report(&err);
-            }
-        }
+    fn visit_fn_def(&mut self, f: &FnDef<'s>) {
+        // reset for each functions' scope
+        self.used.extend(self.current.drain());
+        walk_fn_def(self, f);
     }
 
-    fn check_stmt(&mut self, stmt: &Stmt<'s>) {
-        match &stmt.kind {
-            StmtKind::Let { rhs, .. } => {
-                self.check_expr(rhs);
-            }
-            StmtKind::If {
-                cond,
-                then_branch,
-                else_branch,
-            } => {
-                self.check_expr(cond);
-                for stmt in then_branch.iter() {
-                    self.check_stmt(stmt);
-                }
+    fn visit_parameter(&mut self, param: &Parameter<'s>) {
+        self.current.insert(param.name, (false, param.span));
+    }
 
-                if let Some(else_branch) = else_branch {
-                    for stmt in else_branch.iter() {
-                        self.check_stmt(stmt);
-                    }
-                }
-            }
-            StmtKind::While { cond, body } => {
-                self.check_expr(cond);
-                for stmt in body.iter() {
-                    self.check_stmt(stmt);
-                }
-            }
-            StmtKind::Do(f) => self.check_fncall(f),
-            StmtKind::Return(expr) => {
-                if let Some(expr) = expr {
-                    self.check_expr(expr);
+    fn visit_stmt(&mut self, stmt: &Stmt<'s>) {
+        match &stmt.kind {
+            // assignment is not a use of the variable, while its index is, and so is an array
+            // whose element is assigned since its address is read.
+            StmtKind::Let { lhs, rhs } => {
+                if lhs.index_accessor.is_some() {
+                    self.mark_used(lhs.name);
                 }
+                walk_variable(self, lhs);
+                self.visit_expr(rhs);
             }
+            _ => walk_stmt(self, stmt),
         }
     }
 
-    fn check_expr(&mut self, expr: &Expr<'s>) {
-        self.check_term(&expr.lhs);
-        if let Some(box (_, term)) = &expr.rhs {
-            self.check_term(term);
-        }
+    fn visit_variable(&mut self, var: &Variable<'s>) {
+        self.mark_used(var.name);
+        walk_variable(self, var);
     }
 
-    fn check_term(&mut self, term: &Term<'s>) {
-        match &term.kind {
-            TermKind::Variable(v) => self.mark_used(v.name),
-            TermKind::Expr(expr) => self.check_expr(expr),
-            TermKind::Unary { term, .. } => self.check_term(term),
-            TermKind::FnCall(f) => self.check_fncall(f),
-            TermKind::Const(..) => {}
-        }
-    }
-
-    fn check_fncall(&mut self, fncall: &FnCall<'s>) {
-        if let Some(receiver) = fncall.receiver {
+    fn visit_fn_call(&mut self, call: &FnCall<'s>) {
+        if let Some(receiver) = call.receiver {
             self.mark_used(receiver);
         }
-
-        for arg in fncall.args.iter() {
-            self.check_expr(arg);
-        }
-    }
-
-    fn mark_used(&mut self, ident: Ident<'s>) {
-        self.current.entry(ident).and_modify(|state| state.0 = true);
-        // HACK: assume that there're no variables with the same name in both
-        // function-scoped and class-scoped.
-        self.used.entry(ident).and_modify(|state| state.0 = true);
+        walk_fn_call(self, call);
     }
 }
@@ -1,9 +1,12 @@
 use std::{borrow::Cow, io};
 
-use jack_ast::{Span, Type};
+use jack_ast::{FnKind, Span, Type};
 use thiserror::Error;
 
-use crate::token::{KwKind, Token};
+use crate::{
+    diagnosis::lint,
+    token::{KwKind, Token},
+};
 
 #[derive(Error, Debug)]
 pub enum JackError<'source> {
@@ -23,18 +26,26 @@ pub enum JackError<'source> {
     LexError(LexError<'source>),
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 #[error("{kind}")]
 pub struct SemanticError<'s> {
     pub kind: SemanticErrorKind<'s>,
-    pub src: &'s str,
     pub span: Span,
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum SemanticErrorKind<'s> {
-    #[error("undefined variable `{0}` found")]
-    UndefinedVariable(&'s str),
+    #[error("undefined variable `{name}` found")]
+    UndefinedVariable {
+        name: &'s str,
+        similar: Option<&'s str>,
+    },
+
+    #[error("undefined function `{name}` found")]
+    UndefinedFunction {
+        name: String,
+        similar: Option<String>,
+    },
 
     #[error("{0}")]
     InvalidSyntax(Cow<'s, str>),
@@ -49,24 +60,88 @@ pub enum SemanticErrorKind<'s> {
     UnusedVariable(&'s str),
 
     #[error("`{name}` is defined multiple times")]
-    AlreadyDefinedIdent { name: &'s str, original: Span },
+    AlreadyDefinedIdent {
+        name: &'s str,
+        // `None` when the original is a function of the OS
+        original: Option<Span>,
+    },
+
+    #[error("cannot call method `{class}.{name}` without an instance")]
+    MethodCalledWithoutInstance { class: &'s str, name: &'s str },
+
+    #[error("cannot call method `{name}` from a function")]
+    MethodCalledFromFunction { name: &'s str },
+
+    #[error("cannot call {kind} `{class}.{name}` through an instance")]
+    StaticCalledThroughInstance {
+        class: &'s str,
+        name: &'s str,
+        kind: FnKind,
+    },
+
+    #[error("cannot use `this` inside a function")]
+    ThisInFunction,
+
+    #[error("function `{name}` does not return a value on every path")]
+    MissingReturn { name: &'s str, ty: Type<'s> },
+
+    #[error("unreachable statement")]
+    UnreachableCode { cause: Span },
+
+    #[error("loop never exits")]
+    InfiniteLoop,
+
+    #[error("variable `{name}` is used before being assigned")]
+    UseBeforeAssignment { name: &'s str, defined: Span },
+
+    #[error("integer constant `{0}` is out of range")]
+    IntegerOutOfRange(u32),
+
+    #[error("cannot assign to constant `{name}`")]
+    AssignToConstant { name: &'s str, defined: Span },
+
+    #[error("undefined constant `{0}` found")]
+    UndefinedConstant(String),
+
+    #[error("`{0}` is not a constant expression")]
+    NonConstantExpr(String),
+
+    #[error("constant `{0}` is defined in terms of itself")]
+    CyclicConstant(&'s str),
+
+    #[error("attempt to divide by zero")]
+    DivisionByZero,
 }
 
-#[derive(Error, Debug)]
+impl<'s> SemanticErrorKind<'s> {
+    /// name of the lint that raises this diagnostic, if it's not a hard error.
+    pub fn lint(&self) -> Option<&'static str> {
+        let name = match self {
+            SemanticErrorKind::TypeMismatch { .. } => lint::TypeMismatch::NAME,
+            SemanticErrorKind::UnusedVariable(_) => lint::UnusedVariables::NAME,
+            SemanticErrorKind::UnreachableCode { .. } => lint::UnreachableCode::NAME,
+            SemanticErrorKind::InfiniteLoop => lint::InfiniteLoop::NAME,
+            SemanticErrorKind::UseBeforeAssignment { .. } => lint::UseBeforeAssignment::NAME,
+            _ => return None,
+        };
+
+        Some(name)
+    }
+}
+
+#[derive(Error, Debug, Clone)]
 #[error("{kind}")]
 pub struct ParseError<'s> {
     pub kind: ParseErrorKind<'s>,
     pub span: Span,
-    pub src: &'s str,
     pub help: Option<Cow<'s, str>>,
 }
 
 impl<'s> ParseError<'s> {
-    pub fn new(kind: ParseErrorKind<'s>, src: &'s str, span: Span) -> Self {
+    pub fn new(kind: ParseErrorKind<'s>, span: Span) -> Self {
         ParseError {
             kind,
             span,
-            src,
             help: None,
         }
     }
@@ -80,7 +155,7 @@ impl<'s> ParseError<'s> {
     }
 }
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum ParseErrorKind<'s> {
     #[error(transparent)]
     LexError(LexError<'s>),
@@ -94,12 +169,15 @@ pub enum ParseErrorKind<'s> {
     UnexpectedEOF,
     #[error("internal compiler error: {0}")]
     InternalCompilerError(Cow<'s, str>),
+    #[error("`{0}` is a language extension")]
+    ExtensionRequired(&'static str),
+    #[error("`{0}` outside of a loop")]
+    OutsideLoop(&'static str),
 }
 
 #[derive(Error, Debug, Clone)]
 #[error("{kind}")]
 pub struct LexError<'s> {
-    pub src: &'s str,
     pub span: Span,
     pub kind: LexErrorKind<'s>,
 }
@@ -110,6 +188,14 @@ pub enum LexErrorKind<'s> {
     UnexpectedCharacter(char),
     #[error("cannot parse given number: {0}")]
     InvalidNumberFormat(#[from] std::num::ParseIntError),
+    #[error("integer literal `{0}` is out of range")]
+    IntegerOutOfRange(&'s str),
+    #[error("invalid character literal")]
+    InvalidCharLiteral,
+    #[error("unknown escape sequence `\\{0}`")]
+    UnknownEscape(char),
+    #[error("character {0:?} is not in the Hack character set")]
+    UnsupportedCharacter(char),
     #[error("undefined keyword {0} found")]
     UndefinedKeyword(&'s str),
     #[error("could not find matching string quotation")]
@@ -141,7 +227,6 @@ impl<'s> From<LexError<'s>> for ParseError<'s> {
     fn from(e: LexError<'s>) -> Self {
         ParseError {
             span: e.span,
-            src: e.src,
             kind: ParseErrorKind::LexError(e),
             help: None,
         }
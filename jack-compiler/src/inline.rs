@@ -0,0 +1,454 @@
+//! Inlining of small functions at their call sites (`-O`).
+//!
+//! It works on the VM code of the whole program after codegen, so that functions of other
+//! classes (e.g. getters of fields) are inlined as well. At a call site:
+//!
+//! - the arguments are popped into extra locals of the caller, followed by the locals of the
+//!   callee, which are zeroed as `function` does
+//! - `return` jumps to the end of the inlined code, leaving the value on the stack
+//! - `this` of the caller is saved while the callee sets it (e.g. to the receiver of a method);
+//!   `that` isn't, since codegen never keeps it across a call
+//!
+//! The extra locals are shared by the call sites of a caller, as an inlined function runs to the
+//! end before the next one starts.
+use hack_vm::{InsnKind, Instruction, Segment};
+use rustc_hash::FxHashMap;
+
+/// Functions of up to this many VM instructions, including `return`, are inlined.
+pub const MAX_INLINED_SIZE: usize = 16;
+
+/// Inlines small functions into the callers in the VM code of `classes`, and returns for each
+/// class the line of the new code where each line of the old one starts, followed by the number of
+/// lines, to relocate the source maps.
+pub fn inline(classes: &mut [String]) -> Vec<Vec<usize>> {
+    let programs: Vec<Vec<Function>> = classes.iter().map(|vm| functions(vm)).collect();
+    let callees: FxHashMap<&str, Callee> = programs
+        .iter()
+        .flatten()
+        .filter_map(Callee::new)
+        .map(|callee| (callee.name, callee))
+        .collect();
+
+    let inlined: Vec<(String, Vec<usize>)> = classes
+        .iter()
+        .zip(programs.iter())
+        .map(|(old, functions)| {
+            let mut vm = String::new();
+            // byte offsets in the new code of the old lines, including any before a function
+            let leading = old
+                .lines()
+                .take_while(|line| !matches!(instruction(line), Some(InsnKind::DefFn(..))))
+                .count();
+            let mut offsets = vec![0; leading];
+            for f in functions {
+                inline_into(f, &callees, &mut vm, &mut offsets);
+            }
+            offsets.push(vm.len());
+            let lines = line_numbers(&vm, &offsets);
+            (vm, lines)
+        })
+        .collect();
+
+    let mut lines = Vec::new();
+    for (vm, (inlined, relocated)) in classes.iter_mut().zip(inlined) {
+        *vm = inlined;
+        lines.push(relocated);
+    }
+    lines
+}
+
+struct Function<'v> {
+    name: &'v str,
+    locals: u16,
+    // lines after `function` with their instructions, which are `None` for comments
+    body: Vec<(&'v str, Option<InsnKind<'v>>)>,
+}
+
+impl<'v> Function<'v> {
+    fn class(&self) -> &'v str {
+        class_of(self.name)
+    }
+
+    fn code(&self) -> impl Iterator<Item = &InsnKind<'v>> {
+        self.body.iter().filter_map(|(_, insn)| insn.as_ref())
+    }
+}
+
+struct Callee<'a, 'v> {
+    name: &'v str,
+    function: &'a Function<'v>,
+    uses_static: bool,
+    sets_this: bool,
+}
+
+impl<'a, 'v> Callee<'a, 'v> {
+    // `f` if it's small and doesn't call itself
+    fn new(f: &'a Function<'v>) -> Option<Self> {
+        let code: Vec<_> = f.code().collect();
+        if code.len() > MAX_INLINED_SIZE
+            || code
+                .iter()
+                .any(|insn| matches!(insn, InsnKind::CallFn(name, _) if *name == f.name))
+        {
+            return None;
+        }
+
+        Some(Callee {
+            name: f.name,
+            function: f,
+            uses_static: code.iter().any(|insn| {
+                matches!(
+                    insn,
+                    InsnKind::Push(Segment::Static, _) | InsnKind::Pop(Segment::Static, _)
+                )
+            }),
+            sets_this: code
+                .iter()
+                .any(|insn| matches!(insn, InsnKind::Pop(Segment::Pointer, 0))),
+        })
+    }
+
+    // locals which the caller needs to inline a call with `args`
+    fn locals(&self, args: u16) -> u16 {
+        args + self.function.locals + u16::from(self.sets_this)
+    }
+}
+
+// the code of every function in `vm`
+fn functions(vm: &str) -> Vec<Function<'_>> {
+    let mut functions: Vec<Function> = Vec::new();
+    for line in vm.lines() {
+        match instruction(line) {
+            Some(InsnKind::DefFn(name, locals)) => functions.push(Function {
+                name,
+                locals,
+                body: Vec::new(),
+            }),
+            insn => {
+                if let Some(f) = functions.last_mut() {
+                    f.body.push((line, insn));
+                }
+            }
+        }
+    }
+    functions
+}
+
+// writes `f` into `vm`, with the calls to `callees` inlined, and the offset in `vm` where each line
+// of `f` starts into `offsets`
+fn inline_into(
+    f: &Function<'_>,
+    callees: &FxHashMap<&str, Callee<'_, '_>>,
+    vm: &mut String,
+    offsets: &mut Vec<usize>,
+) {
+    let callee = |insn: &InsnKind<'_>| {
+        let InsnKind::CallFn(name, args) = *insn else { return None };
+        let callee = callees.get(name)?;
+        // static variables belong to the class which defines the function
+        let inlinable =
+            callee.name != f.name && !(callee.uses_static && callee.function.class() != f.class());
+        inlinable.then_some((callee, args))
+    };
+
+    let extra = f
+        .code()
+        .filter_map(|insn| callee(insn).map(|(callee, args)| callee.locals(args)))
+        .max()
+        .unwrap_or(0);
+    offsets.push(vm.len());
+    push(vm, format_args!("function {} {}", f.name, f.locals + extra));
+
+    let mut site = 0;
+    for (line, insn) in f.body.iter() {
+        offsets.push(vm.len());
+        match insn.as_ref().and_then(callee) {
+            Some((callee, args)) => {
+                site += 1;
+                expand(callee, args, f.locals, site, vm);
+            }
+            None => push(vm, line),
+        }
+    }
+}
+
+// writes the code of `callee` called with `args` into `vm`, using the locals from `base`. `site`
+// is the number of the call site in the caller, to make the labels unique.
+fn expand(callee: &Callee<'_, '_>, args: u16, base: u16, site: usize, vm: &mut String) {
+    if cfg!(debug_assertions) {
+        push(vm, format_args!("// inlined call {} {args}", callee.name));
+    }
+
+    let locals = base + args;
+    let saved_this = locals + callee.function.locals;
+    for i in (0..args).rev() {
+        push(vm, format_args!("pop local {}", base + i));
+    }
+    for i in 0..callee.function.locals {
+        push(vm, "push constant 0");
+        push(vm, format_args!("pop local {}", locals + i));
+    }
+    if callee.sets_this {
+        push(vm, "push pointer 0");
+        push(vm, format_args!("pop local {saved_this}"));
+    }
+
+    let end = format!("INLINE_END.{site}");
+    let last_return = callee.function.code().count().checked_sub(1);
+    let mut jumps_to_end = false;
+    let mut code = 0;
+    for (line, insn) in callee.function.body.iter() {
+        let Some(insn) = insn else {
+            push(vm, line);
+            continue;
+        };
+        match *insn {
+            InsnKind::Push(Segment::Argument, i) => {
+                push(vm, format_args!("push local {}", base + i))
+            }
+            InsnKind::Pop(Segment::Argument, i) => push(vm, format_args!("pop local {}", base + i)),
+            InsnKind::Push(Segment::Local, i) => {
+                push(vm, format_args!("push local {}", locals + i))
+            }
+            InsnKind::Pop(Segment::Local, i) => push(vm, format_args!("pop local {}", locals + i)),
+            InsnKind::Label(label) => push(vm, format_args!("label {label}.{site}")),
+            InsnKind::Goto(label) => push(vm, format_args!("goto {label}.{site}")),
+            InsnKind::IfGoto(label) => push(vm, format_args!("if-goto {label}.{site}")),
+            InsnKind::Return if Some(code) == last_return => {}
+            InsnKind::Return => {
+                push(vm, format_args!("goto {end}"));
+                jumps_to_end = true;
+            }
+            _ => push(vm, line),
+        }
+        code += 1;
+    }
+
+    if jumps_to_end {
+        push(vm, format_args!("label {end}"));
+    }
+    if callee.sets_this {
+        push(vm, format_args!("push local {saved_this}"));
+        push(vm, "pop pointer 0");
+    }
+}
+
+// the line numbers of byte `offsets` in `vm`, which are in ascending order
+fn line_numbers(vm: &str, offsets: &[usize]) -> Vec<usize> {
+    let starts: Vec<usize> = std::iter::once(0)
+        .chain(vm.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    offsets
+        .iter()
+        .map(|offset| starts.partition_point(|start| start <= offset) - 1)
+        .collect()
+}
+
+fn push(vm: &mut String, line: impl std::fmt::Display) {
+    use std::fmt::Write;
+    writeln!(vm, "{line}").unwrap();
+}
+
+// the instruction on `line`, or `None` for a comment
+fn instruction(line: &str) -> Option<InsnKind<'_>> {
+    let code = line.trim();
+    if code.is_empty() || code.starts_with("//") {
+        return None;
+    }
+    // codegen only writes valid instructions
+    Instruction::parse(code, 0).ok().map(|insn| insn.kind)
+}
+
+fn class_of(function: &str) -> &str {
+    function
+        .split_once('.')
+        .map_or(function, |(class, _)| class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // VM code of `classes` after inlining, without comments
+    fn inlined(classes: &[&str]) -> Vec<String> {
+        let mut vm: Vec<String> = classes.iter().map(|vm| vm.to_string()).collect();
+        let lines = inline(&mut vm);
+        for (vm, lines) in vm.iter().zip(lines) {
+            assert_eq!(lines.last(), Some(&vm.lines().count()));
+        }
+        vm.iter()
+            .map(|vm| {
+                vm.lines()
+                    .filter(|line| !line.starts_with("//"))
+                    .map(|line| format!("{line}\n"))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn code(lines: &[&str]) -> String {
+        lines.iter().map(|line| format!("{line}\n")).collect()
+    }
+
+    #[test]
+    fn inline_method() {
+        let ball = code(&[
+            "function Ball.getLeft 0",
+            "push argument 0",
+            "pop pointer 0",
+            "push this 1",
+            "return",
+        ]);
+        let main = code(&[
+            "function Main.main 1",
+            "// let x = ball.getLeft()",
+            "push static 0",
+            "call Ball.getLeft 1",
+            "pop local 0",
+            "push this 0",
+            "return",
+        ]);
+
+        assert_eq!(
+            inlined(&[&ball, &main]),
+            [
+                ball.clone(),
+                code(&[
+                    "function Main.main 3",
+                    "push static 0",
+                    "pop local 1",
+                    "push pointer 0",
+                    "pop local 2",
+                    "push local 1",
+                    "pop pointer 0",
+                    "push this 1",
+                    "push local 2",
+                    "pop pointer 0",
+                    "pop local 0",
+                    "push this 0",
+                    "return",
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_function_with_locals_and_returns() {
+        let main = code(&[
+            "function Main.abs 1",
+            "push argument 0",
+            "pop local 0",
+            "push local 0",
+            "push constant 0",
+            "lt",
+            "if-goto LABEL_1",
+            "push local 0",
+            "return",
+            "label LABEL_1",
+            "push local 0",
+            "neg",
+            "return",
+            "function Main.main 0",
+            "push constant 3",
+            "call Main.abs 1",
+            "push constant 4",
+            "call Main.abs 1",
+            "add",
+            "return",
+        ]);
+        let abs = |site: usize| {
+            [
+                "pop local 0".to_string(),
+                "push constant 0".to_string(),
+                "pop local 1".to_string(),
+                "push local 0".to_string(),
+                "pop local 1".to_string(),
+                "push local 1".to_string(),
+                "push constant 0".to_string(),
+                "lt".to_string(),
+                format!("if-goto LABEL_1.{site}"),
+                "push local 1".to_string(),
+                format!("goto INLINE_END.{site}"),
+                format!("label LABEL_1.{site}"),
+                "push local 1".to_string(),
+                "neg".to_string(),
+                format!("label INLINE_END.{site}"),
+            ]
+            .join("\n")
+        };
+
+        let expected = format!(
+            "{}\
+function Main.main 2
+push constant 3
+{}
+push constant 4
+{}
+add
+return
+",
+            &main[..main.find("function Main.main").unwrap()],
+            abs(1),
+            abs(2)
+        );
+        assert_eq!(inlined(&[&main]), [expected]);
+
+        // the lines after `call` move down by the inlined code, and comments stay. `function` of
+        // the caller gets more locals.
+        let mut vm = vec![format!("// abs\n{main}")];
+        let lines = inline(&mut vm).remove(0);
+        let old: Vec<_> = main.lines().collect();
+        let new: Vec<_> = vm[0]
+            .lines()
+            .filter(|line| !line.starts_with("//"))
+            .collect();
+        let call = old
+            .iter()
+            .position(|line| *line == "call Main.abs 1")
+            .unwrap();
+        assert_eq!(lines[0], 0);
+        for (i, line) in old.iter().enumerate() {
+            let relocated = vm[0].lines().nth(lines[i + 1]).unwrap();
+            if i != call && i != call + 2 && !line.starts_with("function") {
+                assert_eq!(relocated, *line);
+            }
+        }
+        assert_eq!(lines.len(), old.len() + 2);
+        assert!(new.len() > old.len());
+    }
+
+    #[test]
+    fn keep_calls() {
+        let counter = code(&[
+            "function Counter.next 0",
+            "push static 0",
+            "push constant 1",
+            "add",
+            "pop static 0",
+            "push static 0",
+            "return",
+            "function Counter.loop 0",
+            "call Counter.next 0",
+            "call Counter.loop 0",
+            "return",
+        ]);
+        let main: Vec<_> = std::iter::once("function Main.large 0")
+            .chain(["push constant 1"; MAX_INLINED_SIZE])
+            .chain([
+                "return",
+                "function Main.main 0",
+                "call Counter.next 0",
+                "call Counter.loop 0",
+                "call Main.large 0",
+                "return",
+            ])
+            .collect();
+        let main = code(&main);
+
+        // statics of another class, recursion and a function over the threshold
+        let inlined = inlined(&[&counter, &main]);
+        assert_eq!(inlined[1], main);
+        // the function is inlined in its own class, whose static variables it uses
+        assert!(inlined[0].contains("function Counter.loop 0\npush static 0\n"));
+    }
+}
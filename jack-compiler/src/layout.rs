@@ -0,0 +1,102 @@
+//! Memory layout of Jack objects.
+//!
+//! An object is a contiguous block of `field` variables allocated by `Memory.alloc`.
+//! `static` variables live in the static segment of the VM and never take a slot in objects.
+use std::fmt;
+
+use jack_ast::{Class, Ident, Span, Type, VariableDefKind};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldLayout<'s> {
+    pub name: Ident<'s>,
+    pub ty: Type<'s>,
+    // offset from the base address of the object (= `this` segment index)
+    pub offset: usize,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassLayout<'s> {
+    pub name: Ident<'s>,
+    pub fields: Vec<FieldLayout<'s>>,
+}
+
+impl<'s> ClassLayout<'s> {
+    /// assigns offsets to fields in order of their declarations.
+    pub fn new(class: &Class<'s>) -> Self {
+        let fields = class
+            .variables
+            .iter()
+            .filter(|var| var.kind == VariableDefKind::Field)
+            .enumerate()
+            .map(|(offset, var)| FieldLayout {
+                name: var.name,
+                ty: var.ty,
+                offset,
+                span: var.span,
+            })
+            .collect();
+
+        ClassLayout {
+            name: class.name,
+            fields,
+        }
+    }
+
+    /// number of words to allocate for an instance of the class.
+    pub fn size(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn offset(&self, field: Ident<'_>) -> Option<usize> {
+        self.fields
+            .iter()
+            .find(|f| f.name == field)
+            .map(|f| f.offset)
+    }
+}
+
+impl fmt::Display for ClassLayout<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "class {} (size: {} words)", self.name, self.size())?;
+        for field in self.fields.iter() {
+            writeln!(f, "  {:>4}: {} {}", field.offset, field.ty, field.name)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn layout_skips_statics() {
+        let class = parse(
+            "\
+class Point {
+    static int count;
+    field int x, y;
+    static Point origin;
+    field Point next;
+}",
+        )
+        .unwrap();
+        let layout = ClassLayout::new(&class);
+
+        assert_eq!(layout.size(), 3);
+        assert_eq!(layout.offset("x"), Some(0));
+        assert_eq!(layout.offset("y"), Some(1));
+        assert_eq!(layout.offset("next"), Some(2));
+        assert_eq!(layout.offset("count"), None);
+        assert_eq!(layout.offset("origin"), None);
+    }
+
+    #[test]
+    fn layout_without_fields() {
+        let class = parse("class Main { static int a; }").unwrap();
+        assert_eq!(ClassLayout::new(&class).size(), 0);
+    }
+}
@@ -1,11 +1,14 @@
 //! Lexical analyzer of Jack language
-use std::{iter::Peekable, str::CharIndices};
+use std::{iter::Peekable, num::IntErrorKind, str::CharIndices};
 
 use crate::{
     error::{LexError, LexErrorKind},
-    token::{Token, TokenKind},
+    token::{Token, TokenKind, Trivia, TriviaKind},
+};
+use jack_ast::{
+    charset::{self, InvalidCharKind},
+    BytePos, Constant, FileId, Span,
 };
-use jack_ast::{BytePos, Span};
 
 type Lexed<'s> = Option<Token<'s>>;
 type LexResult<'s> = std::result::Result<Lexed<'s>, LexError<'s>>;
@@ -14,21 +17,63 @@ pub struct Lexer<'source> {
     input: &'source str,
     chars: Peekable<CharIndices<'source>>,
     bytepos: BytePos,
+    file: FileId,
+    // trivia since the last token, collected only if it's `Some`
+    trivia: Option<Vec<Trivia<'source>>>,
 }
 
 impl<'s> Lexer<'s> {
+    /// Creates a lexer of `input` which is not in any [`jack_ast::SourceMap`].
     pub fn new(input: &str) -> Lexer<'_> {
+        Lexer::with_file(input, FileId::NONE)
+    }
+
+    pub fn with_file(input: &str, file: FileId) -> Lexer<'_> {
         Lexer {
             input,
             chars: input.char_indices().peekable(),
             bytepos: 0,
+            file,
+            trivia: None,
+        }
+    }
+
+    /// Creates a lexer which keeps comments and line breaks for [`Lexer::lex_with_trivia`].
+    pub fn with_trivia(input: &str, file: FileId) -> Lexer<'_> {
+        Lexer {
+            trivia: Some(Vec::new()),
+            ..Lexer::with_file(input, file)
+        }
+    }
+
+    /// Lexes a token along with the trivia preceding it. At EOF, the token is `None` and the
+    /// trivia is the rest of the input.
+    pub fn lex_with_trivia(&mut self) -> Result<(Vec<Trivia<'s>>, Lexed<'s>), LexError<'s>> {
+        let token = self.lex()?;
+        let trivia = self.trivia.as_mut().map(std::mem::take).unwrap_or_default();
+
+        Ok((trivia, token))
+    }
+
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    fn span(&self, base: BytePos, len: usize) -> Span {
+        Span::from_len(self.file, base, len)
+    }
+
+    fn push_trivia(&mut self, kind: TriviaKind<'s>, base: BytePos, len: usize) {
+        let span = self.span(base, len);
+        if let Some(trivia) = self.trivia.as_mut() {
+            trivia.push(Trivia { kind, span });
         }
     }
 
     fn next(&mut self) -> Option<(usize, char)> {
         let next = self.chars.next();
-        if next.is_some() {
-            self.bytepos += 1;
+        if let Some((_, c)) = next {
+            self.bytepos += c.len_utf8();
         }
 
         next
@@ -52,6 +97,10 @@ impl<'s> Lexer<'s> {
             return Ok(Some(lexed));
         }
 
+        if let Some(lexed) = self.lex_char()? {
+            return Ok(Some(lexed));
+        }
+
         if let Some(lexed) = self.lex_integer()? {
             return Ok(Some(lexed));
         }
@@ -61,7 +110,8 @@ impl<'s> Lexer<'s> {
         }
 
         if let Some((base, unknown_char)) = self.next() {
-            return self.error(LexErrorKind::UnexpectedCharacter(unknown_char), base, 1);
+            let len = unknown_char.len_utf8();
+            return self.error(LexErrorKind::UnexpectedCharacter(unknown_char), base, len);
         }
 
         // EOF
@@ -76,8 +126,7 @@ impl<'s> Lexer<'s> {
     ) -> Result<T, LexError<'s>> {
         Err(LexError {
             kind,
-            span: Span::from_len(base, offset),
-            src: self.input,
+            span: self.span(base, offset),
         })
     }
 
@@ -113,7 +162,7 @@ impl<'s> Lexer<'s> {
         self.next();
         Some(Token {
             kind,
-            span: Span::from_len(pos, 1),
+            span: self.span(pos, 1),
         })
     }
 
@@ -133,54 +182,105 @@ impl<'s> Lexer<'s> {
             _ => TokenKind::Ident(ident_str),
         };
 
-        let span = Span::from_len(pos, ident_str.len());
+        let span = self.span(pos, ident_str.len());
         Ok(Some(Token { kind, span }))
     }
 
     fn lex_str(&mut self) -> LexResult<'s> {
-        let pos = self.bytepos;
-
         let Some(from) = self.eat_char('"') else { return Ok(None) };
-        let Some(until) = self.eat_while(|c| c != '"') else {
-            return self.error(LexErrorKind::UnterminatedQuote, from, self.bytepos - 1);
+        let until = loop {
+            match self.next() {
+                Some((i, '"')) => break i,
+                // an escaped character never closes the literal
+                Some((_, '\\')) => {
+                    self.next();
+                }
+                Some(_) => {}
+                None => {
+                    return self.error(LexErrorKind::UnterminatedQuote, from, self.bytepos - from)
+                }
+            }
         };
 
-        // Consume the closing quote.
-        // Since `eat_while` have consumed characters where c != (quote),
-        // it's guaranteed that next char is `"`.
-        self.eat_char('"').unwrap();
-        let kind = TokenKind::Str(&self.input[from + 1..=until]);
+        let literal = &self.input[from + 1..until];
+        if let Err(invalid) = charset::decode(literal) {
+            let kind = match invalid.kind {
+                InvalidCharKind::UnknownEscape(c) => LexErrorKind::UnknownEscape(c),
+                InvalidCharKind::Unsupported(c) => LexErrorKind::UnsupportedCharacter(c),
+            };
+            return self.error(kind, from + 1 + invalid.offset, invalid.len);
+        }
 
         // including quotes
-        let span = Span::new(pos, until + 2);
-        Ok(Some(Token { kind, span }))
+        let span = self.span(from, until + 1 - from);
+        Ok(Some(Token {
+            kind: TokenKind::Str(literal),
+            span,
+        }))
+    }
+
+    fn lex_char(&mut self) -> LexResult<'s> {
+        let pos = self.bytepos;
+
+        let Some(from) = self.eat_char('\'') else { return Ok(None) };
+        let c = self.next().map(|(_, c)| c);
+        let close = self.eat_char('\'');
+        match (c, close) {
+            (Some(c), _) if c != ' ' && !c.is_ascii_graphic() => {
+                let len = c.len_utf8();
+                self.error(LexErrorKind::UnsupportedCharacter(c), from + 1, len)
+            }
+            (Some(c), Some(_)) => {
+                let span = self.span(pos, 3);
+                Ok(Some(Token {
+                    kind: TokenKind::Char(c),
+                    span,
+                }))
+            }
+            _ => self.error(LexErrorKind::InvalidCharLiteral, from, self.bytepos - pos),
+        }
     }
 
+    // decimal, `0x` hexadecimal or `0b` binary integer
     fn lex_integer(&mut self) -> LexResult<'s> {
         let pos = self.bytepos;
 
-        let is_integer = |c: char| c.is_ascii_digit();
-        let Some(from) = self.eat_char_matches(is_integer) else { return Ok(None) };
-        let num_str = match self.eat_while(is_integer) {
+        let Some(from) = self.eat_char_matches(|c| c.is_ascii_digit()) else { return Ok(None) };
+        let literal = match self.eat_while(|c| c.is_ascii_alphanumeric()) {
             Some(until) => &self.input[from..=until],
             // EOF
             None => &self.input[from..],
         };
 
-        let span = Span::from_len(pos, num_str.len());
-        let kind = num_str
-            .parse()
-            .map(TokenKind::Integer)
-            .map_err(|e| LexError {
-                span,
-                src: self.input,
-                kind: LexErrorKind::InvalidNumberFormat(e),
-            })?;
+        let span = self.span(pos, literal.len());
+        let (digits, radix) = match literal.get(..2) {
+            Some("0x" | "0X") => (&literal[2..], 16),
+            Some("0b" | "0B") => (&literal[2..], 2),
+            _ => (literal, 10),
+        };
+        let error = |kind| LexError { span, kind };
+
+        let value = u32::from_str_radix(digits, radix).map_err(|e| match e.kind() {
+            IntErrorKind::PosOverflow => error(LexErrorKind::IntegerOutOfRange(literal)),
+            _ => error(LexErrorKind::InvalidNumberFormat(e)),
+        })?;
+        if value > Constant::MAX_INTEGER {
+            return Err(error(LexErrorKind::IntegerOutOfRange(literal)));
+        }
 
+        let kind = match radix {
+            10 => TokenKind::Integer(value),
+            _ => TokenKind::Radix(value, literal),
+        };
         Ok(Some(Token { kind, span }))
     }
 
     fn eat_ws(&mut self) -> Result<bool, LexError<'s>> {
+        if let Some(offset) = self.eat_char('\n') {
+            self.push_trivia(TriviaKind::Newline, offset, 1);
+            return Ok(true);
+        }
+
         Ok(self.eat_char_matches(|c| c.is_whitespace()).is_some()
             || self.eat_line_comment()
             || self.eat_block_comment()?)
@@ -237,16 +337,26 @@ impl<'s> Lexer<'s> {
     }
 
     fn eat_line_comment(&mut self) -> bool {
-        if self.eat_str("//").is_none() {
-            return false;
-        }
+        let Some(base) = self.eat_str("//") else { return false };
 
-        while let Some((_, c)) = self.next() {
+        let mut newline = None;
+        while let Some((i, c)) = self.next() {
             if c == '\n' {
+                newline = Some(i);
                 break;
             }
         }
 
+        let end = newline.unwrap_or(self.input.len());
+        self.push_trivia(
+            TriviaKind::Comment(&self.input[base..end]),
+            base,
+            end - base,
+        );
+        if let Some(offset) = newline {
+            self.push_trivia(TriviaKind::Newline, offset, 1);
+        }
+
         true
     }
 
@@ -254,7 +364,13 @@ impl<'s> Lexer<'s> {
         let Some(base) = self.eat_str("/*") else { return Ok(false); };
 
         loop {
-            if self.eat_str("*/").is_some() {
+            if let Some(end) = self.eat_str("*/") {
+                let end = end + 2;
+                self.push_trivia(
+                    TriviaKind::Comment(&self.input[base..end]),
+                    base,
+                    end - base,
+                );
                 return Ok(true);
             }
 
@@ -315,6 +431,46 @@ mod tests {
         assert!(lex(input).is_ok());
     }
 
+    #[test]
+    fn lex_trivia() {
+        let input = "let x; // trailing\n\n/* block */ do";
+        let mut lexer = Lexer::with_trivia(input, FileId::NONE);
+        let mut lexed = Vec::new();
+        loop {
+            let (trivia, token) = lexer.lex_with_trivia().unwrap();
+            let trivia: Vec<_> = trivia.into_iter().map(|t| t.kind).collect();
+            let Some(token) = token else {
+                lexed.push((trivia, None));
+                break;
+            };
+            lexed.push((trivia, Some(token.kind)));
+        }
+
+        use TriviaKind::*;
+        assert_eq!(
+            lexed,
+            vec![
+                (vec![], Some(Keyword(KwKind::Let))),
+                (vec![], Some(Ident("x"))),
+                (vec![], Some(Semicolon)),
+                (
+                    vec![
+                        Comment("// trailing"),
+                        Newline,
+                        Newline,
+                        Comment("/* block */")
+                    ],
+                    Some(Keyword(KwKind::Do))
+                ),
+                (vec![], None),
+            ]
+        );
+
+        // trivia is dropped by default
+        let (trivia, _) = Lexer::new(input).lex_with_trivia().unwrap();
+        assert!(trivia.is_empty());
+    }
+
     macro_rules! assert_lex_error {
         // $errkind: LexErrorKind
         ($input:expr, $errkind:pat) => {
@@ -345,11 +501,11 @@ mod tests {
         assert_eq!(
             lexed,
             vec![
-                Token { kind: LParen,         span: Span { base: 0, len: 1 }},
-                Token { kind: Integer(12345), span: Span { base: 2, len: 5 }},
-                Token { kind: Semicolon,      span: Span { base: 7, len: 1 }},
+                Token { kind: LParen,         span: Span::from_len(FileId::NONE, 0, 1)},
+                Token { kind: Integer(12345), span: Span::from_len(FileId::NONE, 2, 5)},
+                Token { kind: Semicolon,      span: Span::from_len(FileId::NONE, 7, 1)},
                 // including surrounding quotes
-                Token { kind: Str("hello"),   span: Span { base: 9, len: 7 }},
+                Token { kind: Str("hello"),   span: Span::from_len(FileId::NONE, 9, 7)},
             ]
         );
     }
@@ -372,13 +528,71 @@ mod tests {
         assert_lex!(input, vec![Str("string"), Str("string2")]);
         // empty string
         assert_lex!(r##" "" "##, vec![Str("")]);
+        // escapes are kept as written, and an escaped quote does not close the literal
+        assert_lex!(
+            r##" "say \"hi\"\n" "\\" "##,
+            vec![Str(r#"say \"hi\"\n"#), Str(r"\\")]
+        );
+    }
+
+    #[test]
+    fn lex_string_err() {
+        use LexErrorKind::*;
+
+        assert_lex_error!(r#""unterminated"#, UnterminatedQuote);
+        assert_lex_error!(r#""escaped quote\""#, UnterminatedQuote);
+        assert_lex_error!(r#""tab\t""#, UnknownEscape('t'));
+        assert_lex_error!("\"caf\u{e9}\"", UnsupportedCharacter('\u{e9}'));
+        assert_lex_error!("\"a\tb\"", UnsupportedCharacter('\t'));
+        assert_lex_error!("'\u{e9}'", UnsupportedCharacter('\u{e9}'));
+
+        let err = lex(r#"let s = "ab\q";"#).unwrap_err();
+        assert_eq!(err.span, Span::from_len(FileId::NONE, 11, 2));
+    }
+
+    #[test]
+    fn span_after_non_ascii_comment() {
+        // `é` takes two bytes
+        let lexed = lex("// caf\u{e9}\nx").unwrap();
+        assert_eq!(lexed[0].span, Span::from_len(FileId::NONE, 9, 1));
     }
 
     #[test]
     fn lex_number() {
         let input = r#"12345
-67890"#;
-        assert_lex!(input, vec![Integer(12345), Integer(67890)]);
+32767 0x7fFF 0b1010 0"#;
+        assert_lex!(
+            input,
+            vec![
+                Integer(12345),
+                Integer(32767),
+                Radix(32767, "0x7fFF"),
+                Radix(10, "0b1010"),
+                Integer(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_number_err() {
+        use LexErrorKind::*;
+
+        assert_lex_error!("32768", IntegerOutOfRange("32768"));
+        assert_lex_error!("0x8000", IntegerOutOfRange("0x8000"));
+        assert_lex_error!("99999999999", IntegerOutOfRange(_));
+        assert_lex_error!("0b102", InvalidNumberFormat(_));
+        assert_lex_error!("0x", InvalidNumberFormat(_));
+        assert_lex_error!("12ab", InvalidNumberFormat(_));
+    }
+
+    #[test]
+    fn lex_char() {
+        assert_lex!("'A' ' ' '''", vec![Char('A'), Char(' '), Char('\'')]);
+
+        use LexErrorKind::*;
+        assert_lex_error!("''", InvalidCharLiteral);
+        assert_lex_error!("'AB'", InvalidCharLiteral);
+        assert_lex_error!("'A", InvalidCharLiteral);
     }
 
     #[test]
@@ -1,13 +1,22 @@
 #![forbid(unsafe_code)]
 #![feature(box_patterns)]
 
+pub mod cache;
 pub mod compiler;
+pub mod constant;
 pub mod diagnosis;
 pub mod error;
+pub mod inline;
+pub mod layout;
 pub mod lexer;
+pub mod lower;
+pub mod native;
+pub mod optimize;
+pub mod parallel;
 pub mod parser;
 pub mod symbol;
 pub mod token;
+pub mod vm_map;
 
 use std::path::Path;
 
@@ -0,0 +1,194 @@
+//! Lowering of the language extensions which are sugar for the standard Jack statements, so that
+//! the lints and the code generator only deal with the standard ones.
+//!
+//! - `let x += e` and `-=` become `let x = x + (e)`; the index of `let a[i] += e` is evaluated
+//!   twice
+//! - `for (init; cond; step) { body }` becomes `init; while (cond) { body step }`, with `step`
+//!   also inserted before every `continue` of the loop
+use jack_ast::*;
+
+/// Lowers every function of `class` in place.
+pub fn lower(class: &mut Class<'_>) {
+    for f in class.functions.iter_mut() {
+        lower_block(&mut f.body.statements);
+    }
+}
+
+fn lower_block(stmts: &mut Vec<Stmt<'_>>) {
+    for stmt in std::mem::take(stmts) {
+        lower_stmt(stmt, stmts);
+    }
+}
+
+// pushes the lowered `stmt` into `out`
+fn lower_stmt<'s>(mut stmt: Stmt<'s>, out: &mut Vec<Stmt<'s>>) {
+    let StmtKind::For { init, cond, step, mut body } = stmt.kind else {
+        lower_in_place(&mut stmt);
+        out.push(stmt);
+        return;
+    };
+
+    lower_stmt(*init, out);
+    let mut step = *step;
+    lower_in_place(&mut step);
+
+    lower_block(&mut body);
+    insert_before_continue(&mut body, &step);
+    body.push(step);
+    out.push(Stmt {
+        kind: StmtKind::While { cond, body },
+        span: stmt.span,
+    });
+}
+
+// lowers a statement other than `for`, which is lowered into a single statement
+fn lower_in_place(stmt: &mut Stmt<'_>) {
+    match &mut stmt.kind {
+        StmtKind::CompoundLet { lhs, op, rhs } => {
+            let rhs = Expr {
+                lhs: Box::new(Term {
+                    span: lhs.span,
+                    kind: TermKind::Variable(lhs.clone()),
+                }),
+                rhs: Some(Box::new((op.clone(), into_term(rhs.clone())))),
+            };
+            stmt.kind = StmtKind::Let {
+                lhs: lhs.clone(),
+                rhs,
+            };
+        }
+        StmtKind::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            lower_block(then_branch);
+            match else_branch {
+                Some(Else::Block(stmts)) => lower_block(stmts),
+                Some(Else::If(stmt)) => lower_in_place(stmt),
+                None => {}
+            }
+        }
+        StmtKind::While { body, .. } => lower_block(body),
+        StmtKind::For { .. } => unreachable!("`for` is lowered by `lower_stmt`"),
+        StmtKind::Let { .. }
+        | StmtKind::Do(_)
+        | StmtKind::Return(_)
+        | StmtKind::Break
+        | StmtKind::Continue => {}
+    }
+}
+
+// inserts `step` before `continue` of the loop whose body is `stmts`, not of nested loops
+fn insert_before_continue<'s>(stmts: &mut Vec<Stmt<'s>>, step: &Stmt<'s>) {
+    let mut i = 0;
+    while i < stmts.len() {
+        if stmts[i].kind == StmtKind::Continue {
+            stmts.insert(i, step.clone());
+            i += 1;
+        } else {
+            insert_into_branches(&mut stmts[i], step);
+        }
+        i += 1;
+    }
+}
+
+fn insert_into_branches<'s>(stmt: &mut Stmt<'s>, step: &Stmt<'s>) {
+    let StmtKind::If { then_branch, else_branch, .. } = &mut stmt.kind else { return };
+
+    insert_before_continue(then_branch, step);
+    match else_branch {
+        Some(Else::Block(stmts)) => insert_before_continue(stmts, step),
+        Some(Else::If(stmt)) => insert_into_branches(stmt, step),
+        None => {}
+    }
+}
+
+// `e` itself if it is a single term, or `(e)`
+fn into_term(expr: Expr<'_>) -> Term<'_> {
+    match expr {
+        Expr { lhs, rhs: None } => *lhs,
+        expr => Term {
+            span: expr.span(),
+            kind: TermKind::Expr(Box::new(expr)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_with, Dialect};
+
+    // statements of `body` printed back, after lowering if `lowering`
+    fn source(body: &str, lowering: bool) -> String {
+        let src = format!("class Main {{ function void main() {{ {body} }} }}");
+        let mut class = parse_with(&src, Dialect::Extended).unwrap();
+        if lowering {
+            lower(&mut class);
+        }
+        class.functions[0]
+            .body
+            .statements
+            .iter()
+            .map(|stmt| stmt.to_source())
+            .collect()
+    }
+
+    fn lowered(body: &str) -> String {
+        source(body, true)
+    }
+
+    fn standard(body: &str) -> String {
+        source(body, false)
+    }
+
+    #[test]
+    fn lower_compound_let() {
+        assert_eq!(
+            lowered("let x += 1; let a[i] -= x * 2;"),
+            standard("let x = x + 1; let a[i] = a[i] - (x * 2);")
+        );
+    }
+
+    #[test]
+    fn lower_for() {
+        assert_eq!(
+            lowered(
+                "for (let i = 0; i < n; let i += 1) {
+                    if (i = 3) { continue; } else if (i = 4) { continue; }
+                    while (true) { continue; }
+                    do f(i);
+                }"
+            ),
+            standard(
+                "let i = 0;
+                while (i < n) {
+                    if (i = 3) { let i = i + 1; continue; } else if (i = 4) { let i = i + 1; continue; }
+                    while (true) { continue; }
+                    do f(i);
+                    let i = i + 1;
+                }"
+            )
+        );
+    }
+
+    #[test]
+    fn lower_nested_for() {
+        assert_eq!(
+            lowered(
+                "for (let i = 0; i < n; let i = i + 1) {
+                    for (let j = 0; j < i; let j += 1) { continue; }
+                }"
+            ),
+            standard(
+                "let i = 0;
+                while (i < n) {
+                    let j = 0;
+                    while (j < i) { let j = j + 1; continue; let j = j + 1; }
+                    let i = i + 1;
+                }"
+            )
+        );
+    }
+}
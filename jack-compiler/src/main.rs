@@ -4,15 +4,44 @@ use std::{
     process::exit,
 };
 
-use anyhow::Result;
-use jack_compiler::compiler;
+use anyhow::{bail, Result};
+use jack_compiler::{
+    compiler::{self, Options},
+    diagnosis::lint::{Level, LintStore},
+};
 
 fn help() -> ! {
     println!(
         "\
-Usage: cargo run -p jack-compiler -- <jack file>
-        "
+Usage: cargo run -p jack-compiler -- [options] <jack file or directory>
+
+Options:
+    --emit layout       print field offsets and object size of each class
+    --emit source-map   write `Class.vm.map` mapping VM lines back to the source
+    -O                  inline small functions at their call sites
+    --backend <vm|asm>  write VM code, or Hack assembly without the VM
+    --verbose           list the classes rebuilt, and why; others are up to date
+                        in `.jack-cache`
+    -A <lint>           allow the lint
+    -W <lint>           warn about the lint
+    -D <lint>           deny the lint
+    --deny-warnings     deny every lint at warn level
+    --message-format <human|json|sarif>
+                        format of diagnostics written to stderr
+    --dialect <standard|extended>
+                        accept the language extensions: `else if`, `break`,
+                        `continue`, `for`, `+=`, `-=`, `const` and `enum`
+
+Lints:"
     );
+    for lint in LintStore::new().lints() {
+        println!(
+            "    {:<24}{:<8}{}",
+            lint.name(),
+            lint.default_level(),
+            lint.description()
+        );
+    }
     exit(0);
 }
 
@@ -36,10 +65,52 @@ fn collect_files<'s, P: AsRef<Path> + 's>(path: P) -> Result<Vec<PathBuf>> {
 }
 
 fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    let Some(path) = args.get(1) else { help() };
+    let mut args = env::args().skip(1);
+    let mut options = Options::default();
+    let mut path = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => help(),
+            "--emit" => {
+                let Some(kind) = args.next() else { bail!("--emit requires an argument") };
+                options.emit.push(kind.parse().map_err(anyhow::Error::msg)?);
+            }
+            "-A" | "-W" | "-D" => {
+                let Some(lint) = args.next() else { bail!("{arg} requires a lint name") };
+                let level = match arg.as_str() {
+                    "-A" => Level::Allow,
+                    "-W" => Level::Warn,
+                    _ => Level::Deny,
+                };
+                options.lint_levels.push((lint, level));
+            }
+            "--deny-warnings" => options.deny_warnings = true,
+            "-O" => options.optimize = true,
+            "--verbose" => options.verbose = true,
+            "--backend" => {
+                let Some(backend) = args.next() else { bail!("--backend requires an argument") };
+                options.backend = backend.parse().map_err(anyhow::Error::msg)?;
+            }
+            "--message-format" => {
+                let Some(format) = args.next() else { bail!("--message-format requires an argument") };
+                options.message_format = format.parse().map_err(anyhow::Error::msg)?;
+            }
+            "--dialect" => {
+                let Some(dialect) = args.next() else { bail!("--dialect requires an argument") };
+                options.dialect = dialect.parse().map_err(anyhow::Error::msg)?;
+            }
+            _ if arg.starts_with("--message-format=") => {
+                let format = &arg["--message-format=".len()..];
+                options.message_format = format.parse().map_err(anyhow::Error::msg)?;
+            }
+            _ => path = Some(arg),
+        }
+    }
+
+    let Some(path) = path else { help() };
     let sources = collect_files(path)?;
 
-    compiler::compile(sources);
+    compiler::compile(sources, &options);
     Ok(())
 }
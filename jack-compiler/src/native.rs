@@ -0,0 +1,877 @@
+//! Native backend, which lowers Jack straight to Hack assembly without the VM (`--backend asm`).
+//!
+//! Expressions are evaluated into the D register. The right operand of a binary operator is read
+//! from memory if it's a constant or a variable; otherwise the left one is spilled into a temp
+//! register `R5`-`R12` while the right one is evaluated, or onto the stack if the right one calls
+//! a function, since the callee may use the temp registers.
+//!
+//! Functions follow the calling convention of `hack-vm`, so that they can call and be called by
+//! VM code translated by it, e.g. the OS:
+//!
+//! - the caller pushes the arguments, the return address and `LCL`, `ARG`, `THIS` and `THAT`,
+//!   then jumps to the callee
+//! - the callee pushes its locals, and returns the value in place of the first argument
+//!
+//! `R13`-`R15` are scratch registers which never live across a call, and `THAT` isn't used.
+use std::{borrow::Cow, fmt};
+
+use hack_asm::commands::{ACommand, CCommand, Command, Dest, Jump, LCommand};
+use jack_ast::*;
+
+use crate::{
+    optimize,
+    symbol::{FnCtxt, SymbolTable, VarKind},
+    JackError,
+};
+
+type Result<'s, T> = std::result::Result<T, JackError<'s>>;
+
+// temp registers `R5`-`R12` to spill the left operands into
+const TEMPS: usize = 8;
+
+/// Hack assembly of a class.
+#[derive(Debug, Default)]
+pub struct Asm {
+    insns: Vec<Insn>,
+}
+
+#[derive(Debug)]
+enum Insn {
+    Value(i16),
+    Symbol(String),
+    C(CCommand<'static>),
+    Label(String),
+}
+
+impl Asm {
+    /// The commands to be assembled by `hack_asm::codegen`.
+    pub fn commands(&self) -> Vec<Command<'_>> {
+        self.insns
+            .iter()
+            .map(|insn| match insn {
+                Insn::Value(v) => Command::A(ACommand::Value(*v)),
+                Insn::Symbol(s) => Command::A(ACommand::Symbol(s)),
+                Insn::C(c) => Command::C(CCommand {
+                    dest: c.dest,
+                    comp: c.comp,
+                    jump: c.jump,
+                }),
+                Insn::Label(l) => Command::L(LCommand(l)),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Asm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for insn in self.insns.iter() {
+            match insn {
+                Insn::Value(v) => writeln!(f, "@{v}")?,
+                Insn::Symbol(s) => writeln!(f, "@{s}")?,
+                Insn::C(c) => {
+                    if c.dest != Dest::Null {
+                        write!(f, "{:?}=", c.dest)?;
+                    }
+                    write!(f, "{}", c.comp)?;
+                    if c.jump != Jump::Null {
+                        write!(f, ";{:?}", c.jump)?;
+                    }
+                    writeln!(f)?;
+                }
+                Insn::Label(l) => writeln!(f, "({l})")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// a right operand which is read without evaluating it into D
+enum Operand {
+    Const(i16),
+    Var(VarKind, usize),
+}
+
+// where the left operand is kept while the right one is evaluated
+enum Spill {
+    Temp(usize),
+    Stack,
+}
+
+/// Generates the assembly of a class, one function at a time.
+pub struct Codegen<'s> {
+    class: Ident<'s>,
+    asm: Asm,
+    // labels to `continue` and `break` the enclosing loops, innermost last
+    loops: Vec<(String, String)>,
+    // number of labels generated in the class, to make them unique
+    labels: usize,
+    // number of temp registers holding left operands
+    spilled: usize,
+}
+
+impl<'s> Codegen<'s> {
+    pub fn new(class: Ident<'s>) -> Self {
+        Codegen {
+            class,
+            asm: Asm::default(),
+            loops: Vec::new(),
+            labels: 0,
+            spilled: 0,
+        }
+    }
+
+    /// The assembly of the class, with the return routine shared by its functions.
+    pub fn finish(mut self) -> Asm {
+        let (ret, frame, addr) = ("R13", "R14", "R15");
+        self.label(&self.return_label());
+        self.at(ret);
+        self.c("M=D");
+        self.at("LCL");
+        self.c("D=M");
+        self.at(frame);
+        self.c("M=D");
+        // the return address, which `*ARG` overwrites if there are no arguments
+        self.value(5);
+        self.c("A=D-A");
+        self.c("D=M");
+        self.at(addr);
+        self.c("M=D");
+        self.at(ret);
+        self.c("D=M");
+        self.at("ARG");
+        self.c("A=M");
+        self.c("M=D");
+        self.at("ARG");
+        self.c("D=M+1");
+        self.at("SP");
+        self.c("M=D");
+        for register in ["THAT", "THIS", "ARG", "LCL"] {
+            self.at(frame);
+            self.c("AM=M-1");
+            self.c("D=M");
+            self.at(register);
+            self.c("M=D");
+        }
+        self.at(addr);
+        self.c("A=M");
+        self.c("0;JMP");
+
+        self.asm
+    }
+
+    pub fn fn_def(&mut self, def: &FnDef<'s>, table: &SymbolTable<'_, 's>) -> Result<'s, ()> {
+        self.label(&format!("{}.{}", self.class, def.name));
+        let n_locals = def.body.variables.len();
+        if n_locals > 0 {
+            self.at("SP");
+            self.c("A=M");
+            for _ in 0..n_locals {
+                self.c("M=0");
+                self.c("A=A+1");
+            }
+            self.c("D=A");
+            self.at("SP");
+            self.c("M=D");
+        }
+
+        match def.kind {
+            FnKind::Ctor => {
+                let size = table.layout(self.class).map_or(0, |layout| layout.size());
+                self.constant(size as i16);
+                self.push_d();
+                self.call("Memory.alloc", 1);
+                self.pop_d();
+                self.at("THIS");
+                self.c("M=D");
+            }
+            FnKind::Method => {
+                self.at("ARG");
+                self.c("A=M");
+                self.c("D=M");
+                self.at("THIS");
+                self.c("M=D");
+            }
+            FnKind::Function => {}
+        }
+        Ok(())
+    }
+
+    pub fn statement(&mut self, stmt: &Stmt<'s>, table: &SymbolTable<'_, 's>) -> Result<'s, ()> {
+        match &stmt.kind {
+            StmtKind::Let { lhs, rhs } => {
+                let Some(&(ref var, register)) = table.lookup_variable(lhs.name) else {
+                    return undefined(lhs.name);
+                };
+                let (kind, register) = (var.kind, register);
+                self.expr(rhs, table)?;
+                let Some(index) = &lhs.index_accessor else {
+                    self.address(kind, register, false);
+                    self.c("M=D");
+                    return Ok(());
+                };
+
+                let saved = if calls_expr(index) {
+                    self.push_d();
+                    None
+                } else {
+                    self.at("R15");
+                    self.c("M=D");
+                    Some("R15")
+                };
+                self.expr(index, table)?;
+                self.address(kind, register, false);
+                self.c("D=D+M");
+                self.at("R14");
+                self.c("M=D");
+                match saved {
+                    Some(register) => {
+                        self.at(register);
+                        self.c("D=M");
+                    }
+                    None => self.pop_d(),
+                }
+                self.at("R14");
+                self.c("A=M");
+                self.c("M=D");
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let else_branch = else_branch.as_ref().map_or(&[][..], Else::stmts);
+                if let Some(n) = optimize::value(cond, table) {
+                    let live = if n == 0 { else_branch } else { then_branch };
+                    return self.statements(live, table);
+                }
+
+                let label_else = self.new_label();
+                let label_fi = self.new_label();
+                self.expr(cond, table)?;
+                self.at(&label_else);
+                self.c("D;JEQ");
+                self.statements(then_branch, table)?;
+                if !else_branch.is_empty() {
+                    self.at(&label_fi);
+                    self.c("0;JMP");
+                }
+                self.label(&label_else);
+                self.statements(else_branch, table)?;
+                self.label(&label_fi);
+            }
+            StmtKind::While { cond, body } => {
+                let value = optimize::value(cond, table);
+                if value == Some(0) {
+                    return Ok(());
+                }
+
+                let label_while = self.new_label();
+                let label_quit = self.new_label();
+                self.label(&label_while);
+                if value.is_none() {
+                    self.expr(cond, table)?;
+                    self.at(&label_quit);
+                    self.c("D;JEQ");
+                }
+                self.loops.push((label_while, label_quit));
+                let body = self.statements(body, table);
+                let (label_while, label_quit) = self.loops.pop().unwrap();
+                body?;
+                self.at(&label_while);
+                self.c("0;JMP");
+                self.label(&label_quit);
+            }
+            StmtKind::Break | StmtKind::Continue => {
+                let Some((label_continue, label_break)) = self.loops.last() else {
+                    return ice(format!("`{stmt}` outside of a loop"));
+                };
+                let label = match stmt.kind {
+                    StmtKind::Break => label_break.clone(),
+                    _ => label_continue.clone(),
+                };
+                self.at(&label);
+                self.c("0;JMP");
+            }
+            StmtKind::Do(f) => {
+                self.fncall(f, table)?;
+                // discard the return value
+                self.at("SP");
+                self.c("M=M-1");
+            }
+            StmtKind::Return(retval) => {
+                match retval {
+                    Some(expr) => self.expr(expr, table)?,
+                    None => self.c("D=0"),
+                }
+                self.at(&self.return_label());
+                self.c("0;JMP");
+            }
+            StmtKind::CompoundLet { .. } | StmtKind::For { .. } => {
+                return ice(format!("`{stmt}` is lowered before codegen"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn statements(&mut self, stmts: &[Stmt<'s>], table: &SymbolTable<'_, 's>) -> Result<'s, ()> {
+        stmts
+            .iter()
+            .try_for_each(|stmt| self.statement(stmt, table))
+    }
+
+    // pushes the arguments, calls the function and leaves the return value on the stack
+    fn fncall(&mut self, fn_call: &FnCall<'s>, table: &SymbolTable<'_, 's>) -> Result<'s, ()> {
+        let Some(FnCtxt { class, kind, .. }) = table.lookup_fn(fn_call.receiver, fn_call.fn_name)
+        else {
+            return ice(format!("undefined function `{}`", fn_call.fn_name));
+        };
+
+        let mut n_args = fn_call.args.len();
+        if *kind == FnKind::Method {
+            match fn_call.receiver.and_then(|r| table.lookup_variable(r)) {
+                Some(&(ref var, register)) => self.load_var(var.kind, register),
+                None => {
+                    self.at("THIS");
+                    self.c("D=M");
+                }
+            }
+            self.push_d();
+            n_args += 1;
+        }
+
+        for arg in &fn_call.args {
+            self.expr(arg, table)?;
+            self.push_d();
+        }
+        self.call(&format!("{class}.{}", fn_call.fn_name), n_args);
+        Ok(())
+    }
+
+    // evaluates `expr` into D
+    fn expr(&mut self, expr: &Expr<'s>, table: &SymbolTable<'_, 's>) -> Result<'s, ()> {
+        self.term(&expr.lhs, table)?;
+        let Some(box (ref op, ref rhs)) = expr.rhs else { return Ok(()) };
+
+        if let BinOp::Mul | BinOp::Div = op {
+            self.push_d();
+            self.term(rhs, table)?;
+            self.push_d();
+            let f = if *op == BinOp::Mul {
+                "Math.multiply"
+            } else {
+                "Math.divide"
+            };
+            self.call(f, 2);
+            self.pop_d();
+            return Ok(());
+        }
+
+        match self.operand(rhs, table) {
+            Some(operand) => self.operate_on(op, operand),
+            None => {
+                let spill = if calls_term(rhs) || self.spilled == TEMPS {
+                    Spill::Stack
+                } else {
+                    Spill::Temp(5 + self.spilled)
+                };
+                match spill {
+                    Spill::Temp(register) => {
+                        self.at(&format!("R{register}"));
+                        self.c("M=D");
+                        self.spilled += 1;
+                        let rhs = self.term(rhs, table);
+                        self.spilled -= 1;
+                        rhs?;
+                    }
+                    Spill::Stack => {
+                        self.push_d();
+                        self.term(rhs, table)?;
+                    }
+                }
+                self.operate_spilled(op, spill);
+            }
+        }
+        Ok(())
+    }
+
+    // D = D `op` operand
+    fn operate_on(&mut self, op: &BinOp, operand: Operand) {
+        let comp = match (op, &operand) {
+            (BinOp::Plus, Operand::Const(n)) if *n >= 0 => Some("D=D+A"),
+            (BinOp::Minus, Operand::Const(n)) if *n >= 0 => Some("D=D-A"),
+            (BinOp::And, Operand::Const(n)) if *n >= 0 => Some("D=D&A"),
+            (BinOp::Or, Operand::Const(n)) if *n >= 0 => Some("D=D|A"),
+            (BinOp::Plus, Operand::Var(..)) => Some("D=D+M"),
+            (BinOp::Minus, Operand::Var(..)) => Some("D=D-M"),
+            (BinOp::And, Operand::Var(..)) => Some("D=D&M"),
+            (BinOp::Or, Operand::Var(..)) => Some("D=D|M"),
+            _ => None,
+        };
+        if let Some(comp) = comp {
+            match operand {
+                Operand::Const(n) => self.value(n),
+                Operand::Var(kind, register) => self.address(kind, register, false),
+            }
+            self.c(comp);
+            return;
+        }
+
+        // the left operand in `R13` and the right one in D
+        self.at("R13");
+        self.c("M=D");
+        match operand {
+            Operand::Const(n) => self.constant(n),
+            Operand::Var(kind, register) => self.load_var(kind, register),
+        }
+        match op {
+            BinOp::Plus => self.with_r13("D=M+D"),
+            BinOp::Minus => self.with_r13("D=M-D"),
+            BinOp::And => self.with_r13("D=M&D"),
+            BinOp::Or => self.with_r13("D=M|D"),
+            _ => {
+                self.at("R14");
+                self.c("M=D");
+                self.compare(op);
+            }
+        }
+    }
+
+    // D = (spilled left operand) `op` D
+    fn operate_spilled(&mut self, op: &BinOp, spill: Spill) {
+        let address = |this: &mut Self| match spill {
+            Spill::Temp(register) => this.at(&format!("R{register}")),
+            Spill::Stack => {
+                this.at("SP");
+                this.c("AM=M-1");
+            }
+        };
+        let comp = match op {
+            BinOp::Plus => "D=M+D",
+            BinOp::Minus => "D=M-D",
+            BinOp::And => "D=M&D",
+            BinOp::Or => "D=M|D",
+            _ => {
+                self.at("R14");
+                self.c("M=D");
+                address(self);
+                self.c("D=M");
+                self.at("R13");
+                self.c("M=D");
+                self.compare(op);
+                return;
+            }
+        };
+        address(self);
+        self.c(comp);
+    }
+
+    fn with_r13(&mut self, comp: &'static str) {
+        self.at("R13");
+        self.c(comp);
+    }
+
+    // D = `R13` `op` `R14`, as `-1` or `0`. `x - y` overflows if the signs of `x` and `y` differ,
+    // in which case `1` or `-1` by the sign of `x` is compared against zero instead.
+    fn compare(&mut self, op: &BinOp) {
+        let jump = match op {
+            BinOp::Equal => "D;JEQ",
+            BinOp::Lt => "D;JLT",
+            BinOp::Gt => "D;JGT",
+            _ => unreachable!("`{op}` is not a comparison"),
+        };
+        let [x_neg, same, cmp, truthy, end] = [(); 5].map(|_| self.new_label());
+
+        if *op != BinOp::Equal {
+            self.at("R13");
+            self.c("D=M");
+            self.at(&x_neg);
+            self.c("D;JLT");
+            self.at("R14");
+            self.c("D=M");
+            self.at(&same);
+            self.c("D;JGE");
+            self.c("D=1");
+            self.at(&cmp);
+            self.c("0;JMP");
+            self.label(&x_neg);
+            self.at("R14");
+            self.c("D=M");
+            self.at(&same);
+            self.c("D;JLT");
+            self.c("D=-1");
+            self.at(&cmp);
+            self.c("0;JMP");
+        }
+        self.label(&same);
+        self.at("R13");
+        self.c("D=M");
+        self.at("R14");
+        self.c("D=D-M");
+        self.label(&cmp);
+        self.at(&truthy);
+        self.c(jump);
+        self.c("D=0");
+        self.at(&end);
+        self.c("0;JMP");
+        self.label(&truthy);
+        self.c("D=-1");
+        self.label(&end);
+    }
+
+    // evaluates `term` into D
+    fn term(&mut self, term: &Term<'s>, table: &SymbolTable<'_, 's>) -> Result<'s, ()> {
+        if let Some(operand) = self.operand(term, table) {
+            match operand {
+                Operand::Const(n) => self.constant(n),
+                Operand::Var(kind, register) => self.load_var(kind, register),
+            }
+            return Ok(());
+        }
+
+        match &term.kind {
+            TermKind::Const(Constant::This) => {
+                self.at("THIS");
+                self.c("D=M");
+            }
+            TermKind::Const(Constant::Str(s)) => {
+                let Ok(codes) = charset::decode(s) else {
+                    return ice(format!("invalid string literal \"{s}\""));
+                };
+                self.constant(codes.len() as i16);
+                self.push_d();
+                self.call("String.new", 1);
+                for c in codes {
+                    self.constant(c as i16);
+                    self.push_d();
+                    self.call("String.appendChar", 2);
+                }
+                self.pop_d();
+            }
+            TermKind::Variable(Variable {
+                name,
+                index_accessor: Some(index),
+                ..
+            }) => {
+                let Some(&(ref var, register)) = table.lookup_variable(name) else {
+                    return undefined(name);
+                };
+                let kind = var.kind;
+                self.expr(index, table)?;
+                self.address(kind, register, false);
+                self.c("A=D+M");
+                self.c("D=M");
+            }
+            TermKind::FnCall(f) => {
+                self.fncall(f, table)?;
+                self.pop_d();
+            }
+            TermKind::Expr(e) => self.expr(e, table)?,
+            TermKind::Unary { op, term } => {
+                self.term(term, table)?;
+                self.c(match op {
+                    UnaryOp::Minus => "D=-D",
+                    UnaryOp::Not => "D=!D",
+                });
+            }
+            TermKind::Const(_) | TermKind::Variable(_) | TermKind::Qualified { .. } => {
+                return ice(format!("could not resolve `{term}`"));
+            }
+        }
+        Ok(())
+    }
+
+    // `term` if it's read without evaluating it into D
+    fn operand(&self, term: &Term<'s>, table: &SymbolTable<'_, 's>) -> Option<Operand> {
+        let n = match term.kind {
+            TermKind::Const(Constant::True) => -1,
+            TermKind::Const(Constant::False | Constant::Null) => 0,
+            TermKind::Const(ref c) => c.int_value()? as i16,
+            TermKind::Variable(Variable {
+                name,
+                index_accessor: None,
+                ..
+            }) => match table.lookup_variable(name) {
+                Some(&(ref var, register)) => return Some(Operand::Var(var.kind, register)),
+                None => table.lookup_const(None, name)?.value,
+            },
+            TermKind::Qualified { scope, name } => table.lookup_const(Some(scope), name)?.value,
+            _ => return None,
+        };
+        Some(Operand::Const(n))
+    }
+
+    // D = `n`
+    fn constant(&mut self, n: i16) {
+        match n {
+            0 => self.c("D=0"),
+            1 => self.c("D=1"),
+            -1 => self.c("D=-1"),
+            i16::MIN => {
+                self.value(i16::MAX);
+                self.c("D=!A");
+            }
+            n if n < 0 => {
+                self.value(-n);
+                self.c("D=-A");
+            }
+            n => {
+                self.value(n);
+                self.c("D=A");
+            }
+        }
+    }
+
+    // D = the variable
+    fn load_var(&mut self, kind: VarKind, register: usize) {
+        self.address(kind, register, true);
+        self.c("D=M");
+    }
+
+    // A = the address of the variable, which may clobber D if `d_free`
+    fn address(&mut self, kind: VarKind, register: usize, d_free: bool) {
+        let base = match kind {
+            VarKind::Static => return self.at(&format!("{}.{register}", self.class)),
+            VarKind::Var => "LCL",
+            VarKind::Arg => "ARG",
+            VarKind::Field => "THIS",
+        };
+        if d_free && register > 2 {
+            self.value(register as i16);
+            self.c("D=A");
+            self.at(base);
+            self.c("A=D+M");
+            return;
+        }
+        self.at(base);
+        self.c("A=M");
+        for _ in 0..register {
+            self.c("A=A+1");
+        }
+    }
+
+    // calls `name` with `n_args` arguments on the stack, as `call` of the VM does
+    fn call(&mut self, name: &str, n_args: usize) {
+        let ret = format!("{}$ret.{}", self.class, self.labels);
+        self.labels += 1;
+
+        self.at(&ret);
+        self.c("D=A");
+        self.push_d();
+        for register in ["LCL", "ARG", "THIS", "THAT"] {
+            self.at(register);
+            self.c("D=M");
+            self.push_d();
+        }
+        // ARG = SP - n_args - 5
+        self.value(n_args as i16 + 5);
+        self.c("D=A");
+        self.at("SP");
+        self.c("D=M-D");
+        self.at("ARG");
+        self.c("M=D");
+        // LCL = SP
+        self.at("SP");
+        self.c("D=M");
+        self.at("LCL");
+        self.c("M=D");
+        self.at(name);
+        self.c("0;JMP");
+        self.label(&ret);
+    }
+
+    fn push_d(&mut self) {
+        self.at("SP");
+        self.c("AM=M+1");
+        self.c("A=A-1");
+        self.c("M=D");
+    }
+
+    fn pop_d(&mut self) {
+        self.at("SP");
+        self.c("AM=M-1");
+        self.c("D=M");
+    }
+
+    fn return_label(&self) -> String {
+        format!("{}$return", self.class)
+    }
+
+    fn new_label(&mut self) -> String {
+        self.labels += 1;
+        format!("{}${}", self.class, self.labels)
+    }
+
+    fn at(&mut self, symbol: &str) {
+        self.asm.insns.push(Insn::Symbol(symbol.to_string()));
+    }
+
+    fn value(&mut self, v: i16) {
+        self.asm.insns.push(Insn::Value(v));
+    }
+
+    fn c(&mut self, code: &'static str) {
+        let c = CCommand::parse(code).expect("invalid C command");
+        self.asm.insns.push(Insn::C(c));
+    }
+
+    fn label(&mut self, label: &str) {
+        self.asm.insns.push(Insn::Label(label.to_string()));
+    }
+}
+
+// whether evaluating the term calls a function, which may use the temp registers
+fn calls_term(term: &Term<'_>) -> bool {
+    match &term.kind {
+        TermKind::Const(c) => matches!(c, Constant::Str(_)),
+        TermKind::Variable(v) => v.index_accessor.as_ref().is_some_and(calls_expr),
+        TermKind::FnCall(_) => true,
+        TermKind::Expr(e) => calls_expr(e),
+        TermKind::Unary { term, .. } => calls_term(term),
+        TermKind::Qualified { .. } => false,
+    }
+}
+
+fn calls_expr(expr: &Expr<'_>) -> bool {
+    calls_term(&expr.lhs)
+        || expr
+            .rhs
+            .as_ref()
+            .is_some_and(|box (op, rhs)| matches!(op, BinOp::Mul | BinOp::Div) || calls_term(rhs))
+}
+
+// codegen for VM reports the errors before the native backend runs
+fn ice<'s, T>(msg: String) -> Result<'s, T> {
+    Err(JackError::InternalCompilerError(Cow::Owned(msg)))
+}
+
+fn undefined<'s, T>(name: Ident<'_>) -> Result<'s, T> {
+    ice(format!("undefined variable `{name}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use hack_asm::emulator::{Cpu, Exit};
+    use hack_vm::HackVm;
+    use jack_ast::SourceMap;
+
+    use crate::{
+        compiler::{self, Backend, Options},
+        diagnosis::DiagnosticReporter,
+    };
+
+    // VM code linked with the native code: `Sys.init` stores the value of `Main.main` to `temp 0`,
+    // and `Memory.alloc` never frees
+    const OS_VM: &str = "\
+function Sys.init 0
+call Main.main 0
+pop temp 0
+label END
+goto END
+function Memory.alloc 0
+push static 0
+push constant 2048
+add
+push static 0
+push argument 0
+add
+pop static 0
+return
+";
+
+    // the value returned by `Main.main` of `src` compiled into assembly
+    fn run(src: &str) -> i16 {
+        let mut sources = SourceMap::new();
+        sources.add_file("Main.jack", src.to_string());
+        let mut reporter = DiagnosticReporter::new(&sources);
+        reporter.capture();
+        let options = Options {
+            backend: Backend::Asm,
+            ..Default::default()
+        };
+        let asm = compiler::compile_to_vm(&sources, &reporter, &options).unwrap();
+
+        let (os, errors) = hack_vm::parser::parse(OS_VM).unwrap();
+        assert!(errors.is_empty());
+        let mut program = Vec::new();
+        HackVm::new(&mut program)
+            .interpret(vec![("Sys", os)])
+            .unwrap();
+        let program: String = String::from_utf8(program)
+            .unwrap()
+            .chars()
+            .chain(asm[0].chars())
+            .filter(|c| *c != ' ')
+            .collect();
+
+        let mut cpu = Cpu::new(&hack_asm::compile(&program).unwrap()).unwrap();
+        assert_eq!(cpu.run(100_000).unwrap(), Exit::Halted);
+        cpu.ram()[5]
+    }
+
+    #[test]
+    fn compare_without_overflow() {
+        let main = |cond: &str| {
+            run(&format!(
+                "class Main {{
+                    function int main() {{
+                        var int min, max;
+                        let min = -32767 - 1;
+                        let max = 32767;
+                        if ({cond}) {{ return 1; }}
+                        return 0;
+                    }}
+                }}"
+            ))
+        };
+        assert_eq!(main("min < max"), 1);
+        assert_eq!(main("max > min"), 1);
+        assert_eq!(main("max < min"), 0);
+        assert_eq!(main("min > 1"), 0);
+        assert_eq!(main("(min - 1) = max"), 1);
+        assert_eq!(main("~(min = max)"), 1);
+    }
+
+    #[test]
+    fn spill_nested_operands() {
+        // deeper than the temp registers, with calls in the right operands
+        let src = "class Main {
+            function int one() { return 1; }
+            function int main() {
+                var int x;
+                let x = 2;
+                return x + (x + (x + (x + (x + (x + (x + (x + (x + (x - (Main.one() + x))))))))));
+            }
+        }";
+        assert_eq!(run(src), 2 * 9 - 1);
+    }
+
+    #[test]
+    fn objects_arrays_and_loops() {
+        let src = "class Main {
+            field int value;
+            static Main last;
+            constructor Main new(int v) { let value = v; let last = this; return this; }
+            method int get() { return value; }
+            method void add(int n) { let value = value + n; return; }
+            function int main() {
+                var Main m;
+                var Array a;
+                var int i, sum;
+                let m = Main.new(3);
+                do m.add(4);
+                let a = Main.new(0);
+                let i = 0;
+                while (true) {
+                    if (i > 2) { let a[0] = last.get(); return sum + a[0]; }
+                    let a[i] = m.get() + i;
+                    let sum = sum + a[i];
+                    let i = i + 1;
+                }
+                return -1;
+            }
+        }";
+        // 7 + 8 + 9, then `last.get()` is `a[0]`, since `last` is `a` whose field is at `a[0]`
+        assert_eq!(run(src), 7 + 8 + 9 + 7);
+    }
+}
@@ -0,0 +1,331 @@
+//! Optimization of function bodies, run on a copy of every function right before its codegen.
+//!
+//! - constant expressions, including the `const` and `enum` members, are folded into literals
+//! - `x * 2` up to `x * 16` of a variable `x` become additions of `x` instead of
+//!   `call Math.multiply 2`, which is kept for any other `x`
+//! - identities such as `x + 0`, `x * 1`, `~~x` and redundant parentheses are removed
+//!
+//! An expression is only replaced by one of the same type inferred by `typeck`, so that the
+//! warnings stay the same, and a term which may be reported by codegen (e.g. an undefined
+//! variable or an out of range integer) is never removed. Branches of `if` and `while` on a
+//! folded condition are eliminated by codegen, which still checks the dead ones.
+use crate::{constant, diagnosis::typeck, symbol::SymbolTable};
+use jack_ast::{visit::*, *};
+
+// the greatest `k` for which `x * 2^k` is replaced with additions, evaluating `x` `2^k` times
+const MAX_DOUBLING: u32 = 4;
+
+/// Optimizes `stmts` of the current function of `table`, whose variables are registered.
+pub fn optimize<'s>(stmts: &mut [Stmt<'s>], table: &SymbolTable<'_, 's>) {
+    let mut optimizer = Optimizer { table };
+    for stmt in stmts.iter_mut() {
+        optimizer.visit_stmt_mut(stmt);
+    }
+}
+
+/// The value of `expr` if it's a constant expression, with `-1` for `true` and `0` for `false`.
+pub fn value(expr: &Expr<'_>, table: &SymbolTable<'_, '_>) -> Option<i16> {
+    let lhs = term_value(&expr.lhs, table)?;
+    match expr.rhs {
+        Some(box (ref op, ref rhs)) => constant::binary(op, lhs, term_value(rhs, table)?),
+        None => Some(lhs),
+    }
+}
+
+fn term_value(term: &Term<'_>, table: &SymbolTable<'_, '_>) -> Option<i16> {
+    match term.kind {
+        TermKind::Const(Constant::True) => Some(-1),
+        TermKind::Const(Constant::False) => Some(0),
+        // `null` is left to typeck, and codegen reports integers out of range
+        TermKind::Const(ref c) => match c.int_value()? {
+            n if n <= Constant::MAX_INTEGER => Some(n as i16),
+            _ => None,
+        },
+        TermKind::Unary { ref op, ref term } => Some(constant::unary(op, term_value(term, table)?)),
+        TermKind::Expr(ref expr) => value(expr, table),
+        TermKind::Variable(Variable {
+            name,
+            index_accessor: None,
+            ..
+        }) if table.lookup_variable(name).is_none() => {
+            table.lookup_const(None, name).map(|c| c.value)
+        }
+        TermKind::Qualified { scope, name } => {
+            table.lookup_const(Some(scope), name).map(|c| c.value)
+        }
+        TermKind::Variable(_) | TermKind::FnCall(_) => None,
+    }
+}
+
+struct Optimizer<'a, 'ctx, 's> {
+    table: &'a SymbolTable<'ctx, 's>,
+}
+
+impl<'s> VisitorMut<'s> for Optimizer<'_, '_, 's> {
+    fn visit_expr_mut(&mut self, expr: &mut Expr<'s>) {
+        walk_expr_mut(self, expr);
+        if let Some(simplified) = self.simplify_expr(expr) {
+            let ty = |expr| typeck::infer_expr_ty(expr, self.table).ok();
+            if ty(&simplified) == ty(expr) {
+                *expr = simplified;
+            }
+        }
+    }
+
+    fn visit_term_mut(&mut self, term: &mut Term<'s>) {
+        walk_term_mut(self, term);
+        if let Some(simplified) = self.simplify_term(term) {
+            let ty = |term| typeck::infer_term_ty(term, self.table);
+            if ty(&simplified) == ty(term) {
+                *term = simplified;
+            }
+        }
+    }
+}
+
+impl<'s> Optimizer<'_, '_, 's> {
+    // `expr` whose terms are already simplified
+    fn simplify_expr(&self, expr: &Expr<'s>) -> Option<Expr<'s>> {
+        let span = expr.span();
+        let Some(box (ref op, ref rhs)) = expr.rhs else { return None };
+
+        if let Some(n) = value(expr, self.table) {
+            let ty = typeck::infer_expr_ty(expr, self.table).ok()?;
+            return Some(single(literal(n, ty, span)?, span));
+        }
+
+        let lhs = &expr.lhs;
+        let (lhs_value, rhs_value) = (term_value(lhs, self.table), term_value(rhs, self.table));
+        let rhs_identity = matches!(
+            (op, rhs_value),
+            (BinOp::Plus | BinOp::Minus | BinOp::Or, Some(0))
+                | (BinOp::Mul | BinOp::Div, Some(1))
+                | (BinOp::And, Some(-1))
+        );
+        if rhs_identity {
+            return Some(single((**lhs).clone(), span));
+        }
+        let lhs_identity = matches!(
+            (op, lhs_value),
+            (BinOp::Plus | BinOp::Or, Some(0)) | (BinOp::Mul, Some(1)) | (BinOp::And, Some(-1))
+        );
+        if lhs_identity {
+            return Some(single(rhs.clone(), span));
+        }
+
+        if *op == BinOp::Mul {
+            let doubled = match (lhs_value, rhs_value) {
+                (_, Some(n)) => self.doubled(lhs, n),
+                (Some(n), _) => self.doubled(rhs, n),
+                _ => None,
+            };
+            return doubled.map(|expr| Expr {
+                lhs: Box::new(Term {
+                    kind: TermKind::Expr(Box::new(expr)),
+                    span,
+                }),
+                rhs: None,
+            });
+        }
+
+        None
+    }
+
+    fn simplify_term(&self, term: &Term<'s>) -> Option<Term<'s>> {
+        let span = term.span;
+        if let Some(n) = term_value(term, self.table) {
+            let ty = typeck::infer_term_ty(term, self.table)?;
+            return literal(n, ty, span).filter(|folded| folded.kind != term.kind);
+        }
+
+        match term.kind {
+            // `(t)`, including ones left by simplifying `(x * 1)`
+            TermKind::Expr(box Expr { ref lhs, rhs: None }) => Some(Term {
+                kind: lhs.kind.clone(),
+                span,
+            }),
+            // `~~t` and `--t`
+            TermKind::Unary {
+                ref op,
+                term:
+                    box Term {
+                        kind:
+                            TermKind::Unary {
+                                op: ref inner,
+                                ref term,
+                            },
+                        ..
+                    },
+            } if op == inner => Some(Term {
+                kind: term.kind.clone(),
+                span,
+            }),
+            _ => None,
+        }
+    }
+
+    // `x + x` added `k` times for `x * 2^k` if `term` is a plain variable `x`, as `x` is copied
+    // `2^k` times. A constant `x` is folded instead.
+    fn doubled(&self, term: &Term<'s>, n: i16) -> Option<Expr<'s>> {
+        let k = n
+            .checked_ilog2()
+            .filter(|k| n == 1 << k && (1..=MAX_DOUBLING).contains(k))?;
+        match term.kind {
+            TermKind::Variable(Variable {
+                name,
+                index_accessor: None,
+                ..
+            }) if self.table.lookup_variable(name).is_some() => {}
+            _ => return None,
+        }
+
+        let mut sum = term.clone();
+        for _ in 1..k {
+            let expr = Expr {
+                lhs: Box::new(sum.clone()),
+                rhs: Some(Box::new((BinOp::Plus, sum))),
+            };
+            sum = Term {
+                kind: TermKind::Expr(Box::new(expr)),
+                span: term.span,
+            };
+        }
+        Some(Expr {
+            lhs: Box::new(sum.clone()),
+            rhs: Some(Box::new((BinOp::Plus, sum))),
+        })
+    }
+}
+
+// a literal of `ty` for `n`, if it can be written
+fn literal(n: i16, ty: Type<'_>, span: Span) -> Option<Term<'_>> {
+    let kind = match ty {
+        Type::Int if n >= 0 => TermKind::Const(Constant::Integer(n as u32)),
+        Type::Int => {
+            let (op, n) = match n.checked_neg() {
+                Some(n) => (UnaryOp::Minus, n),
+                None => (UnaryOp::Not, !n),
+            };
+            let term = Term {
+                kind: TermKind::Const(Constant::Integer(n as u32)),
+                span,
+            };
+            TermKind::Unary {
+                op,
+                term: Box::new(term),
+            }
+        }
+        Type::Char if n >= 0 => TermKind::Const(Constant::Char(char::from_u32(n as u32)?)),
+        Type::Boolean if n == -1 => TermKind::Const(Constant::True),
+        Type::Boolean if n == 0 => TermKind::Const(Constant::False),
+        _ => return None,
+    };
+    Some(Term { kind, span })
+}
+
+// `term` as an expression spanning `span`
+fn single(term: Term<'_>, span: Span) -> Expr<'_> {
+    Expr {
+        lhs: Box::new(Term { span, ..term }),
+        rhs: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_with, Dialect};
+
+    // statements of `body` printed back, after optimizing them if `optimizing`
+    fn source(body: &str, optimizing: bool) -> String {
+        let src = format!(
+            "class Main {{
+                const int N = 4;
+                enum Dir {{ UP, DOWN }}
+                function int main(int x, char c, boolean b) {{ var Array a; {body} }}
+            }}"
+        );
+        let class = parse_with(&src, Dialect::Extended).unwrap();
+        let f = &class.functions[0];
+        let mut stmts = f.body.statements.clone();
+
+        let mut table = SymbolTable::new();
+        constant::register(&[&class], &mut table).unwrap();
+        table.sess(class.name);
+        table
+            .scoped(f, |table| {
+                for param in &f.params {
+                    table.register_variable(param.into()).unwrap();
+                }
+                for var in &f.body.variables {
+                    table.register_variable(var.into()).unwrap();
+                }
+                if optimizing {
+                    optimize(&mut stmts, table);
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        stmts.iter().map(|stmt| stmt.to_source()).collect()
+    }
+
+    fn optimized(body: &str) -> String {
+        source(body, true)
+    }
+
+    fn unoptimized(body: &str) -> String {
+        source(body, false)
+    }
+
+    #[test]
+    fn fold_constants() {
+        assert_eq!(
+            optimized(
+                "let x = (3 * 4) + 1; let x = -(2 - 5); let x = ~32767 - 1; let x = N * Dir.DOWN;
+                let b = ~false; let b = (1 < 2) & (x = x); let c = 'A' + 1; let x = 0x10 | 1;"
+            ),
+            unoptimized(
+                "let x = 13; let x = 3; let x = 32767; let x = 4;
+                let b = true; let b = (x = x); let c = 'B'; let x = 17;"
+            )
+        );
+        assert_eq!(
+            optimized("if (~(N = 4)) { return Dir.UP - 1; } while (N > 0) { let x = -32767 - 1; }"),
+            unoptimized("if (0) { return -1; } while (-1) { let x = ~32767; }")
+        );
+    }
+
+    #[test]
+    fn simplify_expressions() {
+        assert_eq!(
+            optimized(
+                "let x = x * 2; let x = 4 * x; let x = x * 16; let x = ((x));
+                let x = x + 0; let x = 1 * x; let x = ~~x; let x = -(-(x)); let b = true & b;"
+            ),
+            unoptimized(
+                "let x = (x + x); let x = ((x + x) + (x + x));
+                let x = ((((x + x) + (x + x)) + ((x + x) + (x + x))) + (((x + x) + (x + x)) + ((x + x) + (x + x))));
+                let x = x; let x = x; let x = x; let x = x; let x = x; let b = b;"
+            )
+        );
+    }
+
+    #[test]
+    fn double_only_variables() {
+        // anything else than a variable would be evaluated `2^k` times
+        let body = "let x = a[0] * 2; let x = (x + 1) * 4; let x = 8 * a[x * 2];";
+        assert_eq!(
+            optimized(body),
+            unoptimized("let x = a[0] * 2; let x = (x + 1) * 4; let x = 8 * a[(x + x)];")
+        );
+    }
+
+    #[test]
+    fn keep_diagnostics() {
+        // errors and type mismatches of the original expressions are still reported by codegen
+        let body = "let x = 1 / 0; let x = null + 0; let x = y * 1;
+            let x = 0 + c; let x = x * 32; let x = x * 3;";
+        assert_eq!(optimized(body), unoptimized(body));
+    }
+}
@@ -0,0 +1,61 @@
+//! Work on every class spread over the threads of the machine.
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+/// Applies `f` to every item on as many threads as available, and returns the results in the
+/// order of `items` regardless of which thread finished first.
+pub fn map<T: Send, R: Send>(items: Vec<T>, f: impl Fn(T) -> R + Sync) -> Vec<R> {
+    let threads = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(items.len());
+    if threads <= 1 {
+        return items.into_iter().map(f).collect();
+    }
+
+    // items are taken one by one, since classes differ a lot in size
+    let next = AtomicUsize::new(0);
+    let items: Vec<_> = items
+        .into_iter()
+        .map(|item| Mutex::new(Some(item)))
+        .collect();
+    let results: Vec<_> = items.iter().map(|_| Mutex::new(None)).collect();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else { break };
+                let item = item.lock().unwrap().take().unwrap();
+                let result = f(item);
+                *results[i].lock().unwrap() = Some(result);
+            });
+        }
+    });
+
+    // every item has been taken, or the scope would have propagated the panic
+    results
+        .into_iter()
+        .map(|result| result.into_inner().unwrap().unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_order() {
+        let items: Vec<u64> = (0..100).collect();
+        // the first items take the longest
+        let squares = map(items, |n| {
+            thread::sleep(std::time::Duration::from_micros(100 - n));
+            n * n
+        });
+        assert_eq!(squares, (0..100).map(|n| n * n).collect::<Vec<_>>());
+    }
+}
@@ -1,6 +1,7 @@
 //! construct syntax tree from lexed Jack program.
 use std::borrow::Cow;
 use std::iter::Peekable;
+use std::str::FromStr;
 
 use crate::{
     lexer::Lexer,
@@ -9,9 +10,42 @@ use crate::{
 };
 use jack_ast::*;
 
+/// The grammar the parser accepts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// the Jack language of the nand2tetris course
+    #[default]
+    Standard,
+    /// Jack with the language extensions: `else if`, `break`, `continue`, `for`, `+=`, `-=`,
+    /// and `const` and `enum` class members
+    Extended,
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Dialect::Standard),
+            "extended" => Ok(Dialect::Extended),
+            _ => Err(format!("unknown dialect: {s}")),
+        }
+    }
+}
+
 pub fn parse(input: &str) -> ParseResult<'_, Class<'_>> {
-    let mut parser = Parser::new(input);
-    parser.parse()
+    parse_with(input, Dialect::Standard)
+}
+
+pub fn parse_with(input: &str, dialect: Dialect) -> ParseResult<'_, Class<'_>> {
+    let mut parser = Parser::new(input, dialect);
+    parser.parse_class()
+}
+
+pub fn parse_file(file: &SourceFile, dialect: Dialect) -> ParseResult<'_, Class<'_>> {
+    let tokens = TokenStream::new(Lexer::with_file(&file.src, file.id));
+    let mut parser = Parser::with_tokens(tokens, dialect);
+    parser.parse_class()
 }
 
 type ParseResult<'s, T> = std::result::Result<T, Box<ParseError<'s>>>;
@@ -21,21 +55,30 @@ struct TokenStream<'source> {
     tokens: Peekable<Lexer<'source>>,
     peeked: Option<Result<Token<'source>, LexError<'source>>>,
     current_span: Span,
+    // the first lex error, which may be discarded by a parser trying alternatives
+    lex_error: Option<LexError<'source>>,
 }
 
 impl<'s> TokenStream<'s> {
     pub fn new(lexer: Lexer<'s>) -> Self {
+        let file = lexer.file();
         let mut tokens = lexer.peekable();
         let peeked = tokens.next();
         let current_span = match peeked {
             Some(Ok(ref token)) => token.span,
-            _ => Span::new(0, 0),
+            _ => Span::new(file, 0, 0),
+        };
+
+        let lex_error = match peeked {
+            Some(Err(ref e)) => Some(e.clone()),
+            _ => None,
         };
 
         TokenStream {
             tokens,
             peeked,
             current_span,
+            lex_error,
         }
     }
 
@@ -49,28 +92,215 @@ impl<'s> TokenStream<'s> {
             self.current_span = token.span;
         }
         self.peeked = self.tokens.next();
+        if let (Some(Err(e)), None) = (&self.peeked, &self.lex_error) {
+            self.lex_error = Some(e.clone());
+        }
 
         next
     }
 }
 
 pub(crate) struct Parser<'source> {
-    input: &'source str,
     tokens: TokenStream<'source>,
+    dialect: Dialect,
+    // number of `while` enclosing the statement being parsed
+    loops: usize,
+    // the first error which must be reported even if the parser recovers from it
+    fatal: Option<Box<ParseError<'source>>>,
 }
 
 impl<'s> Parser<'s> {
-    pub fn new(input: &'s str) -> Parser<'s> {
-        let tokens = TokenStream::new(Lexer::new(input));
-        Parser { tokens, input }
+    pub fn new(input: &'s str, dialect: Dialect) -> Parser<'s> {
+        Parser::with_tokens(TokenStream::new(Lexer::new(input)), dialect)
+    }
+
+    fn with_tokens(tokens: TokenStream<'s>, dialect: Dialect) -> Parser<'s> {
+        Parser {
+            tokens,
+            dialect,
+            loops: 0,
+            fatal: None,
+        }
     }
 
     pub fn parse<P: Parse<'s>>(&mut self) -> ParseResult<'s, P> {
         Parse::<'s>::parse(self)
     }
 
+    // A lex error is reported in preference to any parse error, which it is likely to cause.
+    fn parse_class(&mut self) -> ParseResult<'s, Class<'s>> {
+        let class = self.parse();
+        if let Some(e) = self.tokens.lex_error.take() {
+            return Err(Box::new(e.into()));
+        }
+        match self.fatal.take() {
+            Some(e) => Err(e),
+            None => class,
+        }
+    }
+
     fn error<T>(&self, kind: ErrorKind<'s>, span: Span) -> ParseResult<'s, T> {
-        Err(Box::new(ParseError::new(kind, self.input, span)))
+        Err(Box::new(ParseError::new(kind, span)))
+    }
+
+    // an error which is not discarded when the parser tries alternatives
+    fn fatal<T>(&mut self, kind: ErrorKind<'s>, span: Span) -> ParseResult<'s, T> {
+        let err = Box::new(ParseError::new(kind, span));
+        self.fatal.get_or_insert_with(|| err.clone());
+        Err(err)
+    }
+
+    fn require_extension(&mut self, name: &'static str, span: Span) -> ParseResult<'s, ()> {
+        match self.dialect {
+            Dialect::Extended => Ok(()),
+            Dialect::Standard => self.fatal(ErrorKind::ExtensionRequired(name), span),
+        }
+    }
+
+    fn parse_loop_body(&mut self) -> ParseResult<'s, Vec<Stmt<'s>>> {
+        self.loops += 1;
+        let body = self.parse();
+        self.loops -= 1;
+        body
+    }
+
+    // `let lhs = rhs`, or `let lhs += rhs` and `-=`, without `;`
+    fn parse_let(&mut self) -> ParseResult<'s, StmtKind<'s>> {
+        self.eat_by(TokenKind::Keyword(KwKind::Let))?;
+        let lhs = self.parse()?;
+
+        let token = self.peek_token()?;
+        let op = match token.kind {
+            TokenKind::Plus => BinOp::Plus,
+            TokenKind::Minus => BinOp::Minus,
+            _ => {
+                self.eat_by(TokenKind::Equal)?;
+                let rhs = self.parse()?;
+                return Ok(StmtKind::Let { lhs, rhs });
+            }
+        };
+        let op_token = self.eat_token()?;
+        let equal = self.eat_by(TokenKind::Equal)?;
+        // `+ =` is not an operator
+        if equal.lo() != op_token.span.hi() {
+            let span = op_token.span;
+            return self.fatal(ErrorKind::UnexpectedToken(op_token), span);
+        }
+        let name = if op == BinOp::Plus { "+=" } else { "-=" };
+        self.require_extension(name, op_token.span.with_hi(equal.hi()))?;
+        let rhs = self.parse()?;
+
+        Ok(StmtKind::CompoundLet { lhs, op, rhs })
+    }
+
+    // `for (let init; cond; let step) { body }`, where `for` is an identifier in the standard dialect
+    fn parse_for(&mut self, span: Span) -> ParseResult<'s, Stmt<'s>> {
+        self.require_extension("for", span)?;
+        self.eat_token()?;
+        self.eat_by(TokenKind::LParen)?;
+
+        let init_span = self.peek_token()?.span;
+        let init = self.parse_let()?;
+        self.eat_by(TokenKind::Semicolon)?;
+        let init = Stmt {
+            kind: init,
+            span: init_span.with_hi(self.current_span().hi()),
+        };
+
+        let cond = self.parse()?;
+        self.eat_by(TokenKind::Semicolon)?;
+
+        let step_span = self.peek_token()?.span;
+        let step = self.parse_let()?;
+        let step = Stmt {
+            kind: step,
+            span: step_span.with_hi(self.current_span().hi()),
+        };
+        self.eat_by(TokenKind::RParen)?;
+
+        self.eat_by(TokenKind::LBrace)?;
+        let body = self.parse_loop_body()?;
+        self.eat_by(TokenKind::RBrace)?;
+
+        Ok(Stmt {
+            kind: StmtKind::For {
+                init: Box::new(init),
+                cond,
+                step: Box::new(step),
+                body,
+            },
+            span: span.with_hi(self.current_span().hi()),
+        })
+    }
+
+    // `const type name = value;`, where `const` is an identifier in the standard dialect
+    fn parse_const(&mut self, span: Span) -> ParseResult<'s, ConstDef<'s>> {
+        self.require_extension("const", span)?;
+        self.eat_token()?;
+        let ty = self.parse()?;
+        let name = self.parse()?;
+        let span = self.current_span();
+        self.eat_by(TokenKind::Equal)?;
+        let value = self.parse()?;
+        self.eat_by(TokenKind::Semicolon)?;
+
+        Ok(ConstDef {
+            name,
+            ty,
+            value,
+            span,
+        })
+    }
+
+    // `enum Name { A, B = value }`, where `enum` is an identifier in the standard dialect
+    fn parse_enum(&mut self, span: Span) -> ParseResult<'s, EnumDef<'s>> {
+        self.require_extension("enum", span)?;
+        self.eat_token()?;
+        let name = self.parse()?;
+        let span = self.current_span();
+        self.eat_by(TokenKind::LBrace)?;
+
+        let mut members = Vec::new();
+        loop {
+            let name = self.parse()?;
+            let span = self.current_span();
+            let value = match self.eat_if_matches(&TokenKind::Equal) {
+                Ok(_) => Some(self.parse()?),
+                Err(_) => None,
+            };
+            members.push(EnumMember { name, value, span });
+
+            if self.eat_if_matches(&TokenKind::Comma).is_err() {
+                break;
+            }
+        }
+        self.eat_by(TokenKind::RBrace)?;
+
+        Ok(EnumDef {
+            name,
+            span,
+            members,
+        })
+    }
+
+    // `break;` and `continue;`, whose keywords are identifiers in the standard dialect
+    fn parse_jump(&mut self, keyword: &'static str, span: Span) -> ParseResult<'s, Stmt<'s>> {
+        self.require_extension(keyword, span)?;
+        if self.loops == 0 {
+            return self.fatal(ErrorKind::OutsideLoop(keyword), span);
+        }
+
+        self.eat_token()?;
+        self.eat_by(TokenKind::Semicolon)?;
+        let kind = match keyword {
+            "break" => StmtKind::Break,
+            _ => StmtKind::Continue,
+        };
+
+        Ok(Stmt {
+            kind,
+            span: span.with_hi(self.current_span().hi()),
+        })
     }
 
     fn unexpected_token<T>(&self, token: &Token<'s>) -> ParseResult<'s, T> {
@@ -178,9 +408,25 @@ impl<'s> Parse<'s> for Class<'s> {
         parser.eat_by(TokenKind::LBrace)?;
 
         let mut variables = Vec::new();
+        let mut constants = Vec::new();
+        let mut enums = Vec::new();
         let mut functions = Vec::new();
 
         loop {
+            let token = parser.peek_token()?;
+            let span = token.span;
+            match token.kind {
+                TokenKind::Ident("const") => {
+                    constants.push(parser.parse_const(span)?);
+                    continue;
+                }
+                TokenKind::Ident("enum") => {
+                    enums.push(parser.parse_enum(span)?);
+                    continue;
+                }
+                _ => {}
+            }
+
             match parser.parse::<Vec<_>>() {
                 Ok(vs) => variables.extend(vs),
                 Err(e) => match e.kind {
@@ -206,6 +452,8 @@ impl<'s> Parse<'s> for Class<'s> {
             name,
             span,
             variables,
+            constants,
+            enums,
             functions,
         })
     }
@@ -376,20 +624,22 @@ impl<'s> Parse<'s> for FnBody<'s> {
 impl<'s> Parse<'s> for Stmt<'s> {
     fn parse(parser: &mut Parser<'s>) -> ParseResult<'s, Self> {
         let token = parser.peek_token()?;
-        let TokenKind::Keyword(ref kind) = token.kind else { return parser.unexpected_token(token) };
         let span = token.span;
+        match token.kind {
+            TokenKind::Ident("break") => return parser.parse_jump("break", span),
+            TokenKind::Ident("continue") => return parser.parse_jump("continue", span),
+            TokenKind::Ident("for") => return parser.parse_for(span),
+            _ => {}
+        }
+        let TokenKind::Keyword(ref kind) = token.kind else { return parser.unexpected_token(token) };
 
         let stmt = match kind {
             KwKind::Let => {
-                // consume `let`
-                parser.eat_token()?;
-                let lhs = parser.parse()?;
-                parser.eat_by(TokenKind::Equal)?;
-                let rhs = parser.parse()?;
+                let kind = parser.parse_let()?;
                 parser.eat_by(TokenKind::Semicolon)?;
 
                 Stmt {
-                    kind: StmtKind::Let { lhs, rhs },
+                    kind,
                     span: span.with_hi(parser.current_span().hi()),
                 }
             }
@@ -404,16 +654,21 @@ impl<'s> Parse<'s> for Stmt<'s> {
                 let then_branch = parser.parse()?;
                 parser.eat_by(TokenKind::RBrace)?;
 
-                let else_branch = if parser
-                    .eat_if_matches(&TokenKind::Keyword(KwKind::Else))
-                    .is_ok()
-                {
-                    parser.eat_by(TokenKind::LBrace)?;
-                    let else_branch = parser.parse()?;
-                    parser.eat_by(TokenKind::RBrace)?;
-                    Some(else_branch)
-                } else {
-                    None
+                let else_branch = match parser.eat_if_matches(&TokenKind::Keyword(KwKind::Else)) {
+                    Ok(else_span) => {
+                        let token = parser.peek_token()?;
+                        if token.kind == TokenKind::Keyword(KwKind::If) {
+                            let else_if = else_span.with_hi(token.span.hi());
+                            parser.require_extension("else if", else_if)?;
+                            Some(Else::If(Box::new(parser.parse()?)))
+                        } else {
+                            parser.eat_by(TokenKind::LBrace)?;
+                            let else_branch = parser.parse()?;
+                            parser.eat_by(TokenKind::RBrace)?;
+                            Some(Else::Block(else_branch))
+                        }
+                    }
+                    Err(_) => None,
                 };
 
                 Stmt {
@@ -433,7 +688,7 @@ impl<'s> Parse<'s> for Stmt<'s> {
                 parser.eat_by(TokenKind::RParen)?;
 
                 parser.eat_by(TokenKind::LBrace)?;
-                let body = parser.parse()?;
+                let body = parser.parse_loop_body()?;
                 parser.eat_by(TokenKind::RBrace)?;
 
                 Stmt {
@@ -545,29 +800,42 @@ impl<'s> Parse<'s> for Term<'s> {
                 // FnCall
                 let args = parser.parse_vec(&TokenKind::Comma)?;
                 let hi = parser.eat_by(TokenKind::RParen)?.hi();
-                let span = span.with_hi(hi);
                 return Ok(Term {
                     kind: TermKind::FnCall(FnCall {
                         receiver: None,
                         fn_name: name,
                         args,
+                        span,
                     }),
-                    span,
+                    span: span.with_hi(hi),
                 });
             } else if parser.eat_if_matches(&TokenKind::Dot).is_ok() {
                 // FnCall with receiver
                 let fn_name = parser.parse()?;
+                if parser.dialect == Dialect::Extended
+                    && parser.peek_token()?.kind != TokenKind::LParen
+                {
+                    // a constant or an enum member
+                    return Ok(Term {
+                        kind: TermKind::Qualified {
+                            scope: name,
+                            name: fn_name,
+                        },
+                        span: span.with_hi(parser.current_span().hi()),
+                    });
+                }
+                let name_span = span.with_hi(parser.current_span().hi());
                 parser.eat_by(TokenKind::LParen)?;
                 let args = parser.parse_vec(&TokenKind::Comma)?;
                 let hi = parser.eat_by(TokenKind::RParen)?.hi();
-                let span = span.with_hi(hi);
                 return Ok(Term {
                     kind: TermKind::FnCall(FnCall {
                         receiver: Some(name),
                         fn_name,
                         args,
+                        span: name_span,
                     }),
-                    span,
+                    span: span.with_hi(hi),
                 });
             } else {
                 // Variable
@@ -601,6 +869,7 @@ impl<'s> Parse<'s> for FnCall<'s> {
     // (receiver.)?fn_name( args.. )
     fn parse(parser: &mut Parser<'s>) -> ParseResult<'s, Self> {
         let mut fn_name = parser.parse()?;
+        let lo = parser.current_span().lo();
         let receiver = if parser.eat_if_matches(&TokenKind::Dot).is_ok() {
             let name = parser.parse()?;
             let receiver = fn_name;
@@ -609,6 +878,7 @@ impl<'s> Parse<'s> for FnCall<'s> {
         } else {
             None
         };
+        let span = parser.current_span().with_lo(lo);
         parser.eat_by(TokenKind::LParen)?;
         let args = parser.parse_vec(&TokenKind::Comma)?;
         parser.eat_by(TokenKind::RParen)?;
@@ -616,6 +886,7 @@ impl<'s> Parse<'s> for FnCall<'s> {
             receiver,
             fn_name,
             args,
+            span,
         })
     }
 }
@@ -684,6 +955,8 @@ impl<'s> Parse<'s> for Constant<'s> {
         let token = parser.peek_token()?;
         let constant = match token.kind {
             TokenKind::Integer(n) => Constant::Integer(n),
+            TokenKind::Radix(n, literal) => Constant::Radix(n, literal),
+            TokenKind::Char(c) => Constant::Char(c),
             TokenKind::Str(s) => Constant::Str(s),
             TokenKind::Keyword(ref kwkind) => match kwkind {
                 KwKind::True => Constant::True,
@@ -705,11 +978,16 @@ mod tests {
     use super::*;
     use std::fmt;
 
+    // span of sources parsed without a `SourceMap`
+    fn span(lo: BytePos, hi: BytePos) -> Span {
+        Span::new(FileId::NONE, lo, hi)
+    }
+
     fn assert_parse<'a, P>(input: &'a str, expect: P)
     where
         P: Parse<'a> + PartialEq + fmt::Debug,
     {
-        let parsed = Parser::new(input).parse::<P>().unwrap();
+        let parsed = Parser::new(input, Dialect::Standard).parse::<P>().unwrap();
         assert_eq!(parsed, expect);
     }
 
@@ -729,6 +1007,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn handle_lex_error_in_expr() {
+        let input = "class Main { function void main() { do f(32768); return; } }";
+        match parse(input).unwrap_err().kind {
+            ErrorKind::LexError(_) => {}
+            e => panic!("did not match to expected error kind {e:?}"),
+        }
+    }
+
     #[test]
     fn parse_variable_defs() {
         assert_parse(
@@ -737,7 +1024,7 @@ mod tests {
                 name: "foo",
                 kind: VariableDefKind::Var,
                 ty: Type::Int,
-                span: Span::new(8, 11),
+                span: span(8, 11),
             }],
         );
         assert_parse(
@@ -746,7 +1033,7 @@ mod tests {
                 name: "instance",
                 kind: VariableDefKind::Field,
                 ty: Type::Class("SomeClass"),
-                span: Span::new(16, 24),
+                span: span(16, 24),
             }],
         );
 
@@ -757,13 +1044,13 @@ mod tests {
                     name: "a",
                     kind: VariableDefKind::Static,
                     ty: Type::Int,
-                    span: Span::new(11, 12),
+                    span: span(11, 12),
                 },
                 VariableDef {
                     name: "xy",
                     kind: VariableDefKind::Static,
                     ty: Type::Int,
-                    span: Span::new(14, 16),
+                    span: span(14, 16),
                 },
             ],
         );
@@ -775,7 +1062,7 @@ mod tests {
             "method void dispose() {}",
             FnDef {
                 name: "dispose",
-                span: Span::new(12, 19),
+                span: span(12, 19),
                 kind: FnKind::Method,
                 ret: Type::Void,
                 params: vec![],
@@ -808,7 +1095,7 @@ mod tests {
             Parameter {
                 name: "a",
                 ty: Type::Int,
-                span: Span::new(0, 5),
+                span: span(0, 5),
             },
         );
         assert_parse(
@@ -816,7 +1103,7 @@ mod tests {
             Parameter {
                 name: "s",
                 ty: Type::Class("SomeClass"),
-                span: Span::new(0, 11),
+                span: span(0, 11),
             },
         );
     }
@@ -829,12 +1116,12 @@ mod tests {
                 Parameter {
                     name: "a",
                     ty: Type::Int,
-                    span: Span::new(0, 5),
+                    span: span(0, 5),
                 },
                 Parameter {
                     name: "b",
                     ty: Type::Char,
-                    span: Span::new(7, 13),
+                    span: span(7, 13),
                 },
             ],
         )
@@ -849,17 +1136,17 @@ mod tests {
                     lhs: Variable {
                         name: "a",
                         index_accessor: None,
-                        span: Span::new(4, 5),
+                        span: span(4, 5),
                     },
                     rhs: Expr {
                         lhs: Box::new(Term {
                             kind: TermKind::Const(Constant::Integer(10)),
-                            span: Span::new(8, 10),
+                            span: span(8, 10),
                         }),
                         rhs: None,
                     },
                 },
-                span: Span::new(0, 11),
+                span: span(0, 11),
             },
         );
 
@@ -870,22 +1157,23 @@ mod tests {
                     lhs: Variable {
                         name: "g",
                         index_accessor: None,
-                        span: Span::new(4, 5),
+                        span: span(4, 5),
                     },
                     rhs: Expr {
                         lhs: Box::new(Term {
                             kind: TermKind::FnCall(FnCall {
                                 receiver: Some("Fraction"),
                                 fn_name: "gcd",
+                                span: span(8, 20),
                                 args: vec![
                                     Expr {
                                         lhs: Box::new(Term {
                                             kind: TermKind::Variable(Variable {
                                                 name: "numerator",
                                                 index_accessor: None,
-                                                span: Span::new(21, 30),
+                                                span: span(21, 30),
                                             }),
-                                            span: Span::new(21, 30),
+                                            span: span(21, 30),
                                         }),
                                         rhs: None,
                                     },
@@ -894,20 +1182,20 @@ mod tests {
                                             kind: TermKind::Variable(Variable {
                                                 name: "denominator",
                                                 index_accessor: None,
-                                                span: Span::new(32, 43),
+                                                span: span(32, 43),
                                             }),
-                                            span: Span::new(32, 43),
+                                            span: span(32, 43),
                                         }),
                                         rhs: None,
                                     },
                                 ],
                             }),
-                            span: Span::new(8, 44),
+                            span: span(8, 44),
                         }),
                         rhs: None,
                     },
                 },
-                span: Span::new(0, 45),
+                span: span(0, 45),
             },
         );
         assert_parse(
@@ -921,42 +1209,42 @@ let next = cdr;
                         lhs: Variable {
                             name: "data",
                             index_accessor: None,
-                            span: Span::new(4, 8),
+                            span: span(4, 8),
                         },
                         rhs: Expr {
                             lhs: Box::new(Term {
                                 kind: TermKind::Variable(Variable {
                                     name: "car",
                                     index_accessor: None,
-                                    span: Span::new(11, 14),
+                                    span: span(11, 14),
                                 }),
-                                span: Span::new(11, 14),
+                                span: span(11, 14),
                             }),
                             rhs: None,
                         },
                     },
-                    span: Span::new(0, 15),
+                    span: span(0, 15),
                 },
                 Stmt {
                     kind: StmtKind::Let {
                         lhs: Variable {
                             name: "next",
                             index_accessor: None,
-                            span: Span::new(20, 24),
+                            span: span(20, 24),
                         },
                         rhs: Expr {
                             lhs: Box::new(Term {
                                 kind: TermKind::Variable(Variable {
                                     name: "cdr",
                                     index_accessor: None,
-                                    span: Span::new(27, 30),
+                                    span: span(27, 30),
                                 }),
-                                span: Span::new(27, 30),
+                                span: span(27, 30),
                             }),
                             rhs: None,
                         },
                     },
-                    span: Span::new(16, 31),
+                    span: span(16, 31),
                 },
             ],
         );
@@ -977,9 +1265,9 @@ if (a) {
                             kind: TermKind::Variable(Variable {
                                 name: "a",
                                 index_accessor: None,
-                                span: Span::new(4, 5),
+                                span: span(4, 5),
                             }),
-                            span: Span::new(4, 5),
+                            span: span(4, 5),
                         }),
                         rhs: None,
                     },
@@ -987,11 +1275,11 @@ if (a) {
                         kind: StmtKind::Let {
                             lhs: Variable {
                                 name: "a",
-                                span: Span::new(17, 21),
+                                span: span(17, 21),
                                 index_accessor: Some(Expr {
                                     lhs: Box::new(Term {
                                         kind: TermKind::Const(Constant::Integer(0)),
-                                        span: Span::new(19, 20),
+                                        span: span(19, 20),
                                     }),
                                     rhs: None,
                                 }),
@@ -999,16 +1287,16 @@ if (a) {
                             rhs: Expr {
                                 lhs: Box::new(Term {
                                     kind: TermKind::Const(Constant::Integer(10)),
-                                    span: Span::new(24, 26),
+                                    span: span(24, 26),
                                 }),
                                 rhs: None,
                             },
                         },
-                        span: Span::new(13, 27),
+                        span: span(13, 27),
                     }],
                     else_branch: None,
                 },
-                span: Span::new(0, 29),
+                span: span(0, 29),
             },
         );
         assert_parse(
@@ -1026,9 +1314,9 @@ if (a) {
                             kind: TermKind::Variable(Variable {
                                 name: "a",
                                 index_accessor: None,
-                                span: Span::new(4, 5),
+                                span: span(4, 5),
                             }),
-                            span: Span::new(4, 5),
+                            span: span(4, 5),
                         }),
                         rhs: None,
                     },
@@ -1036,11 +1324,11 @@ if (a) {
                         kind: StmtKind::Let {
                             lhs: Variable {
                                 name: "a",
-                                span: Span::new(17, 21),
+                                span: span(17, 21),
                                 index_accessor: Some(Expr {
                                     lhs: Box::new(Term {
                                         kind: TermKind::Const(Constant::Integer(0)),
-                                        span: Span::new(19, 20),
+                                        span: span(19, 20),
                                     }),
                                     rhs: None,
                                 }),
@@ -1048,32 +1336,32 @@ if (a) {
                             rhs: Expr {
                                 lhs: Box::new(Term {
                                     kind: TermKind::Const(Constant::Integer(42)),
-                                    span: Span::new(24, 26),
+                                    span: span(24, 26),
                                 }),
                                 rhs: None,
                             },
                         },
-                        span: Span::new(13, 27),
+                        span: span(13, 27),
                     }],
-                    else_branch: Some(vec![Stmt {
+                    else_branch: Some(Else::Block(vec![Stmt {
                         kind: StmtKind::Let {
                             lhs: Variable {
                                 name: "a",
                                 index_accessor: None,
-                                span: Span::new(45, 46),
+                                span: span(45, 46),
                             },
                             rhs: Expr {
                                 lhs: Box::new(Term {
                                     kind: TermKind::Const(Constant::Integer(0)),
-                                    span: Span::new(49, 50),
+                                    span: span(49, 50),
                                 }),
                                 rhs: None,
                             },
                         },
-                        span: Span::new(41, 51),
-                    }]),
+                        span: span(41, 51),
+                    }])),
                 },
-                span: Span::new(0, 53),
+                span: span(0, 53),
             },
         );
     }
@@ -1091,7 +1379,7 @@ while (true) {
                     cond: Expr {
                         lhs: Box::new(Term {
                             kind: TermKind::Const(Constant::True),
-                            span: Span::new(7, 11),
+                            span: span(7, 11),
                         }),
                         rhs: None,
                     },
@@ -1100,30 +1388,30 @@ while (true) {
                             lhs: Variable {
                                 name: "a",
                                 index_accessor: None,
-                                span: Span::new(23, 24),
+                                span: span(23, 24),
                             },
                             rhs: Expr {
                                 lhs: Box::new(Term {
                                     kind: TermKind::Variable(Variable {
                                         name: "a",
                                         index_accessor: None,
-                                        span: Span::new(27, 28),
+                                        span: span(27, 28),
                                     }),
-                                    span: Span::new(27, 28),
+                                    span: span(27, 28),
                                 }),
                                 rhs: Some(Box::new((
                                     BinOp::Plus,
                                     Term {
                                         kind: TermKind::Const(Constant::Integer(1)),
-                                        span: Span::new(31, 32),
+                                        span: span(31, 32),
                                     },
                                 ))),
                             },
                         },
-                        span: Span::new(19, 33),
+                        span: span(19, 33),
                     }],
                 },
-                span: Span::new(0, 35),
+                span: span(0, 35),
             },
         );
     }
@@ -1136,9 +1424,10 @@ while (true) {
                 kind: StmtKind::Do(FnCall {
                     receiver: None,
                     fn_name: "some_method",
+                    span: span(3, 14),
                     args: vec![],
                 }),
-                span: Span::new(0, 17),
+                span: span(0, 17),
             },
         );
         assert_parse(
@@ -1147,9 +1436,10 @@ while (true) {
                 kind: StmtKind::Do(FnCall {
                     receiver: Some("cat"),
                     fn_name: "meow",
+                    span: span(3, 11),
                     args: vec![],
                 }),
-                span: Span::new(0, 14),
+                span: span(0, 14),
             },
         );
         assert_parse(
@@ -1158,15 +1448,16 @@ while (true) {
                 kind: StmtKind::Do(FnCall {
                     receiver: None,
                     fn_name: "add",
+                    span: span(3, 6),
                     args: vec![
                         Expr {
                             lhs: Box::new(Term {
                                 kind: TermKind::Variable(Variable {
                                     name: "a",
                                     index_accessor: None,
-                                    span: Span::new(7, 8),
+                                    span: span(7, 8),
                                 }),
-                                span: Span::new(7, 8),
+                                span: span(7, 8),
                             }),
                             rhs: None,
                         },
@@ -1175,15 +1466,15 @@ while (true) {
                                 kind: TermKind::Variable(Variable {
                                     name: "b",
                                     index_accessor: None,
-                                    span: Span::new(10, 11),
+                                    span: span(10, 11),
                                 }),
-                                span: Span::new(10, 11),
+                                span: span(10, 11),
                             }),
                             rhs: None,
                         },
                     ],
                 }),
-                span: Span::new(0, 13),
+                span: span(0, 13),
             },
         )
     }
@@ -1194,7 +1485,7 @@ while (true) {
             "return;",
             Stmt {
                 kind: StmtKind::Return(None),
-                span: Span::new(0, 7),
+                span: span(0, 7),
             },
         );
         assert_parse(
@@ -1203,11 +1494,11 @@ while (true) {
                 kind: StmtKind::Return(Some(Expr {
                     lhs: Box::new(Term {
                         kind: TermKind::Const(Constant::Integer(0)),
-                        span: Span::new(7, 8),
+                        span: span(7, 8),
                     }),
                     rhs: None,
                 })),
-                span: Span::new(0, 9),
+                span: span(0, 9),
             },
         );
     }
@@ -1219,7 +1510,7 @@ while (true) {
             Expr {
                 lhs: Box::new(Term {
                     kind: TermKind::Const(Constant::Integer(123)),
-                    span: Span::new(0, 3),
+                    span: span(0, 3),
                 }),
                 rhs: None,
             },
@@ -1229,13 +1520,13 @@ while (true) {
             Expr {
                 lhs: Box::new(Term {
                     kind: TermKind::Const(Constant::Integer(123)),
-                    span: Span::new(0, 3),
+                    span: span(0, 3),
                 }),
                 rhs: Some(Box::new((
                     BinOp::Plus,
                     Term {
                         kind: TermKind::Const(Constant::Integer(456)),
-                        span: Span::new(6, 9),
+                        span: span(6, 9),
                     },
                 ))),
             },
@@ -1248,9 +1539,9 @@ while (true) {
                     kind: TermKind::Variable(Variable {
                         name: "a",
                         index_accessor: None,
-                        span: Span::new(0, 1),
+                        span: span(0, 1),
                     }),
-                    span: Span::new(0, 1),
+                    span: span(0, 1),
                 }),
                 rhs: Some(Box::new((
                     BinOp::Mul,
@@ -1262,13 +1553,13 @@ while (true) {
                                     index_accessor: Some(Expr {
                                         lhs: Box::new(Term {
                                             kind: TermKind::Const(Constant::Integer(1)),
-                                            span: Span::new(7, 8),
+                                            span: span(7, 8),
                                         }),
                                         rhs: None,
                                     }),
-                                    span: Span::new(5, 9),
+                                    span: span(5, 9),
                                 }),
-                                span: Span::new(5, 9),
+                                span: span(5, 9),
                             }),
                             rhs: Some(Box::new((
                                 BinOp::Plus,
@@ -1277,14 +1568,14 @@ while (true) {
                                         op: UnaryOp::Minus,
                                         term: Box::new(Term {
                                             kind: TermKind::Const(Constant::Integer(123)),
-                                            span: Span::new(13, 16),
+                                            span: span(13, 16),
                                         }),
                                     },
-                                    span: Span::new(12, 16),
+                                    span: span(12, 16),
                                 }),
                             ))),
                         })),
-                        span: Span::new(4, 17),
+                        span: span(4, 17),
                     },
                 ))),
             },
@@ -1297,14 +1588,14 @@ while (true) {
             "12345",
             Term {
                 kind: TermKind::Const(Constant::Integer(12345)),
-                span: Span::new(0, 5),
+                span: span(0, 5),
             },
         );
         assert_parse(
             r##""some string""##,
             Term {
                 kind: TermKind::Const(Constant::Str("some string")),
-                span: Span::new(0, 13),
+                span: span(0, 13),
             },
         );
         assert_parse(
@@ -1313,9 +1604,9 @@ while (true) {
                 kind: TermKind::Variable(Variable {
                     name: "abc",
                     index_accessor: None,
-                    span: Span::new(0, 3),
+                    span: span(0, 3),
                 }),
-                span: Span::new(0, 3),
+                span: span(0, 3),
             },
         );
         assert_parse(
@@ -1325,10 +1616,10 @@ while (true) {
                     op: UnaryOp::Minus,
                     term: Box::new(Term {
                         kind: TermKind::Const(Constant::Integer(123)),
-                        span: Span::new(1, 4),
+                        span: span(1, 4),
                     }),
                 },
-                span: Span::new(0, 4),
+                span: span(0, 4),
             },
         );
         assert_parse(
@@ -1342,22 +1633,22 @@ while (true) {
                                 kind: TermKind::Variable(Variable {
                                     name: "next",
                                     index_accessor: None,
-                                    span: Span::new(2, 6),
+                                    span: span(2, 6),
                                 }),
-                                span: Span::new(2, 6),
+                                span: span(2, 6),
                             }),
                             rhs: Some(Box::new((
                                 BinOp::Equal,
                                 Term {
                                     kind: TermKind::Const(Constant::Null),
-                                    span: Span::new(9, 13),
+                                    span: span(9, 13),
                                 },
                             ))),
                         })),
-                        span: Span::new(1, 14),
+                        span: span(1, 14),
                     }),
                 },
-                span: Span::new(0, 14),
+                span: span(0, 14),
             },
         );
         assert_parse(
@@ -1368,19 +1659,19 @@ while (true) {
                     index_accessor: Some(Expr {
                         lhs: Box::new(Term {
                             kind: TermKind::Const(Constant::Integer(123)),
-                            span: Span::new(2, 5),
+                            span: span(2, 5),
                         }),
                         rhs: Some(Box::new((
                             BinOp::Plus,
                             Term {
                                 kind: TermKind::Const(Constant::Integer(456)),
-                                span: Span::new(8, 11),
+                                span: span(8, 11),
                             },
                         ))),
                     }),
-                    span: Span::new(0, 12),
+                    span: span(0, 12),
                 }),
-                span: Span::new(0, 12),
+                span: span(0, 12),
             },
         );
         assert_parse(
@@ -1389,11 +1680,12 @@ while (true) {
                 kind: TermKind::FnCall(FnCall {
                     receiver: None,
                     fn_name: "some_fn",
+                    span: span(0, 7),
                     args: vec![
                         Expr {
                             lhs: Box::new(Term {
                                 kind: TermKind::Const(Constant::This),
-                                span: Span::new(8, 12),
+                                span: span(8, 12),
                             }),
                             rhs: None,
                         },
@@ -1402,28 +1694,28 @@ while (true) {
                                 kind: TermKind::Variable(Variable {
                                     name: "a",
                                     index_accessor: None,
-                                    span: Span::new(14, 15),
+                                    span: span(14, 15),
                                 }),
-                                span: Span::new(14, 15),
+                                span: span(14, 15),
                             }),
                             rhs: None,
                         },
                         Expr {
                             lhs: Box::new(Term {
                                 kind: TermKind::Const(Constant::Integer(1)),
-                                span: Span::new(17, 18),
+                                span: span(17, 18),
                             }),
                             rhs: Some(Box::new((
                                 BinOp::Plus,
                                 Term {
                                     kind: TermKind::Const(Constant::Integer(2)),
-                                    span: Span::new(21, 22),
+                                    span: span(21, 22),
                                 },
                             ))),
                         },
                     ],
                 }),
-                span: Span::new(0, 23),
+                span: span(0, 23),
             },
         );
     }
@@ -1431,7 +1723,177 @@ while (true) {
     #[test]
     fn parse_constant() {
         assert_parse("12345", Constant::Integer(12345));
+        assert_parse("0x7FFF", Constant::Radix(32767, "0x7FFF"));
+        assert_parse("'A'", Constant::Char('A'));
         assert_parse(r##""example""##, Constant::Str("example"));
         assert_parse("true", Constant::True);
     }
+
+    #[test]
+    fn parse_file_spans_carry_file_id() {
+        let mut sources = SourceMap::new();
+        sources.add_file("Foo.jack", "class Foo {}".to_owned());
+        let bar = sources.add_file("Bar.jack", "class Bar { field int x; }".to_owned());
+        let baz = sources.add_file("Baz.jack", "class".to_owned());
+
+        let class = parse_file(sources.file(bar), Dialect::Standard).unwrap();
+        assert_eq!(class.span.file, bar);
+        assert_eq!(class.variables[0].span.file, bar);
+
+        let err = parse_file(sources.file(baz), Dialect::Standard).unwrap_err();
+        assert_eq!(err.span.file, baz);
+    }
+
+    fn parse_fn_body(body: &str, dialect: Dialect) -> ParseResult<'_, Vec<Stmt<'_>>> {
+        let class = parse_with(body, dialect)?;
+        Ok(class.functions.into_iter().next().unwrap().body.statements)
+    }
+
+    #[test]
+    fn parse_extensions() {
+        let input = "class Main { function void main() {
+            while (true) {
+                if (a) { break; } else if (b) { continue; } else { let a = 1; }
+            }
+            return;
+        } }";
+        let stmts = parse_fn_body(input, Dialect::Extended).unwrap();
+        let StmtKind::While { body, .. } = &stmts[0].kind else { panic!("{:?}", stmts[0]) };
+        let StmtKind::If {
+            then_branch,
+            else_branch: Some(Else::If(else_if)),
+            ..
+        } = &body[0].kind else { panic!("{:?}", body[0]) };
+        assert_eq!(then_branch[0].kind, StmtKind::Break);
+        let StmtKind::If {
+            then_branch,
+            else_branch: Some(Else::Block(else_branch)),
+            ..
+        } = &else_if.kind else { panic!("{else_if:?}") };
+        assert_eq!(then_branch[0].kind, StmtKind::Continue);
+        assert_eq!(else_branch.len(), 1);
+    }
+
+    #[test]
+    fn parse_for_and_compound_let() {
+        let input = "class Main { function void main() {
+            for (let i = 0; i < 10; let i += 2) { let a[i] -= 1; }
+            return;
+        } }";
+        let stmts = parse_fn_body(input, Dialect::Extended).unwrap();
+        let StmtKind::For { init, cond, step, body } = &stmts[0].kind else { panic!("{:?}", stmts[0]) };
+        assert_eq!(init.to_string(), "let i = 0");
+        assert_eq!(init.span, span(53, 63));
+        assert_eq!(cond.to_string(), "i < 10");
+        assert_eq!(step.to_string(), "let i += 2");
+        assert_eq!(step.span, span(72, 82));
+        assert_eq!(body[0].to_string(), "let a[i] -= 1");
+        assert_eq!(stmts[0].span, span(48, 102));
+    }
+
+    #[test]
+    fn parse_constants() {
+        let input = "class Main {
+            static int a;
+            const int MAX = 0x7FFF;
+            enum Dir { UP, DOWN = Dir.UP + 2 }
+            function int f() { return Other.MAX; }
+        }";
+        let class = parse_with(input, Dialect::Extended).unwrap();
+        assert_eq!(class.variables.len(), 1);
+
+        let max = &class.constants[0];
+        assert_eq!((max.name, max.ty), ("MAX", Type::Int));
+        assert_eq!(max.value.to_string(), "0x7FFF");
+        assert_eq!(max.span, span(61, 64));
+
+        let dir = &class.enums[0];
+        assert_eq!((dir.name, dir.span), ("Dir", span(92, 95)));
+        let members: Vec<_> = dir.members.iter().map(EnumMember::to_source).collect();
+        assert_eq!(members, ["UP", "DOWN = Dir.UP + 2"]);
+
+        let StmtKind::Return(Some(retval)) = &class.functions[0].body.statements[0].kind else {
+            panic!("{:?}", class.functions[0])
+        };
+        assert_eq!(
+            *retval.lhs,
+            Term {
+                kind: TermKind::Qualified {
+                    scope: "Other",
+                    name: "MAX"
+                },
+                span: span(160, 169),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_constants_err() {
+        let input = "class Main { const int A = 1; }";
+        let err = parse_with(input, Dialect::Standard).unwrap_err();
+        assert!(
+            matches!(err.kind, ErrorKind::ExtensionRequired("const")),
+            "{err:?}"
+        );
+
+        let input = "class Main { enum E { A } }";
+        let err = parse_with(input, Dialect::Standard).unwrap_err();
+        assert!(
+            matches!(err.kind, ErrorKind::ExtensionRequired("enum")),
+            "{err:?}"
+        );
+
+        // a trailing comma
+        let input = "class Main { enum E { A, } }";
+        assert!(parse_with(input, Dialect::Extended).is_err());
+    }
+
+    #[test]
+    fn parse_extensions_err() {
+        let wrap = |body| format!("class Main {{ function void main() {{ {body} return; }} }}");
+
+        let input = wrap("if (a) { } else if (b) { }");
+        let err = parse_fn_body(&input, Dialect::Standard).unwrap_err();
+        assert!(
+            matches!(err.kind, ErrorKind::ExtensionRequired("else if")),
+            "{err:?}"
+        );
+
+        let input = wrap("while (a) { break; }");
+        let err = parse_fn_body(&input, Dialect::Standard).unwrap_err();
+        assert!(
+            matches!(err.kind, ErrorKind::ExtensionRequired("break")),
+            "{err:?}"
+        );
+
+        let input = wrap("if (a) { continue; }");
+        let err = parse_fn_body(&input, Dialect::Extended).unwrap_err();
+        assert!(
+            matches!(err.kind, ErrorKind::OutsideLoop("continue")),
+            "{err:?}"
+        );
+
+        let input = wrap("let x += 1;");
+        let err = parse_fn_body(&input, Dialect::Standard).unwrap_err();
+        assert!(
+            matches!(err.kind, ErrorKind::ExtensionRequired("+=")),
+            "{err:?}"
+        );
+
+        let input = wrap("let x + = 1;");
+        let err = parse_fn_body(&input, Dialect::Extended).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::UnexpectedToken(_)), "{err:?}");
+
+        let input = wrap("for (let i = 0; i < 3; let i = i + 1) { }");
+        let err = parse_fn_body(&input, Dialect::Standard).unwrap_err();
+        assert!(
+            matches!(err.kind, ErrorKind::ExtensionRequired("for")),
+            "{err:?}"
+        );
+
+        // the keywords are identifiers in both dialects
+        let input = wrap("var int break, for; let break = 1; let for = 2;");
+        assert!(parse_fn_body(&input, Dialect::Standard).is_ok());
+        assert!(parse_fn_body(&input, Dialect::Extended).is_ok());
+    }
 }
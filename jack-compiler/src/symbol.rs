@@ -1,18 +1,23 @@
 use rustc_hash::FxHashMap;
-use std::{borrow::Cow, fmt};
+use std::{borrow::Cow, fmt, sync::Arc};
 
-use crate::{JackError, SemanticError, SemanticErrorKind as ErrorKind};
+use crate::{
+    diagnosis::suggest, layout::ClassLayout, JackError, SemanticError,
+    SemanticErrorKind as ErrorKind,
+};
 use hack_vm::Segment;
 use jack_ast::*;
 
 pub type Result<'s, T> = std::result::Result<T, JackError<'s>>;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FnCtxt<'s> {
     pub class: &'s str,
     pub name: &'s str,
+    pub kind: FnKind,
     pub ty: Type<'s>,
-    pub span: Span,
+    // `None` for the functions of the OS, which are not defined in any file
+    pub span: Option<Span>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -24,6 +29,18 @@ pub struct VariableCtxt<'s> {
     pub span: Span,
 }
 
+/// A `const` or an enum member of `class`, whose value is known at compile time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstCtxt<'s> {
+    pub class: Ident<'s>,
+    // the enum of an enum member
+    pub scope: Option<Ident<'s>>,
+    pub name: Ident<'s>,
+    pub ty: Type<'s>,
+    pub value: i16,
+    pub span: Span,
+}
+
 impl<'s> VariableCtxt<'s> {
     pub fn field(name: Ident<'s>, ty: Type<'s>, span: Span) -> Self {
         VariableCtxt {
@@ -89,16 +106,29 @@ impl<'s> From<&Parameter<'s>> for VariableCtxt<'s> {
     }
 }
 
-#[derive(Default)]
-pub struct SymbolTable<'ctx: 's, 's> {
+// signatures of every class, which are registered before the codegen of any class and then
+// shared by the codegen of each class
+#[derive(Default, Clone)]
+struct Globals<'ctx> {
     // (class_name, fn_name) -> IdentCtxt
     functions: FxHashMap<(Ident<'ctx>, Ident<'ctx>), FnCtxt<'ctx>>,
+    // class_name -> object layout
+    layouts: FxHashMap<Ident<'ctx>, ClassLayout<'ctx>>,
+    // (class_name, enum_name, name) -> ConstCtxt
+    constants: FxHashMap<(Ident<'ctx>, Option<Ident<'ctx>>, Ident<'ctx>), ConstCtxt<'ctx>>,
+}
+
+#[derive(Default)]
+pub struct SymbolTable<'ctx: 's, 's> {
+    // shared with the tables forked from this one, and copied if registered to after forking
+    globals: Arc<Globals<'ctx>>,
     // holds symbol table of current scope
     ctx: ScopedContext<'s>,
-    // current class_name, source
-    current: Option<(Ident<'s>, &'s str)>,
+    // current class_name
+    current: Option<Ident<'s>>,
     // program flows such as `for`, `while` will be interpreted with labels in Hack VM.
-    // `label_counter` holds number of times to generate unique labels.
+    // `label_counter` holds number of times to generate unique labels in the current class, so
+    // that the code of a class does not depend on the classes compiled before it.
     label_counter: usize,
 }
 
@@ -121,6 +151,32 @@ impl VarKind {
     }
 }
 
+// declarations in the Jack syntax, e.g. `method void Main.run` or `field int count`
+impl fmt::Display for FnCtxt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}.{}",
+            self.kind,
+            self.ty.to_source(),
+            self.class,
+            self.name
+        )
+    }
+}
+
+impl fmt::Display for VariableCtxt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            VarKind::Var => "var",
+            VarKind::Arg => "argument",
+            VarKind::Static => "static",
+            VarKind::Field => "field",
+        };
+        write!(f, "{kind} {} {}", self.ty.to_source(), self.name)
+    }
+}
+
 impl fmt::Display for VarKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use VarKind::*;
@@ -152,7 +208,21 @@ struct ScopedContext<'ctx> {
 impl<'ctx: 's, 's> SymbolTable<'ctx, 's> {
     pub fn new() -> Self {
         SymbolTable {
-            functions: load_stl(),
+            globals: Arc::new(Globals {
+                functions: load_stl(),
+                ..Globals::default()
+            }),
+            ctx: ScopedContext::new(),
+            current: None,
+            label_counter: 0,
+        }
+    }
+
+    /// Creates a table with the globals of this one and no class in session, for the codegen of
+    /// a class on another thread.
+    pub fn fork(&self) -> Self {
+        SymbolTable {
+            globals: Arc::clone(&self.globals),
             ctx: ScopedContext::new(),
             current: None,
             label_counter: 0,
@@ -160,14 +230,15 @@ impl<'ctx: 's, 's> SymbolTable<'ctx, 's> {
     }
 
     // starts new session
-    pub fn sess(&mut self, class: Ident<'s>, source: &'s str) {
-        self.current = Some((class, source));
+    pub fn sess(&mut self, class: Ident<'s>) {
+        self.current = Some(class);
         self.ctx = ScopedContext::new();
+        self.label_counter = 0;
     }
 
     pub fn current_class(&self) -> Result<'s, Ident<'s>> {
         match self.current {
-            Some((class, _)) => Ok(class),
+            Some(class) => Ok(class),
             None => Err(JackError::InternalCompilerError(Cow::Borrowed(
                 "SymbolTable: current class is not set",
             ))),
@@ -178,6 +249,10 @@ impl<'ctx: 's, 's> SymbolTable<'ctx, 's> {
         self.ctx.current_fn
     }
 
+    pub fn current_fn_kind(&self) -> Option<FnKind> {
+        self.ctx.current_fn.map(|f| f.kind)
+    }
+
     /// creates a new ScopedContext that is used while executing the given `f`.
     /// After the closure has finished, the context is restored to its previous state.
     /// the closure `f` expect to acquire an argument to avoid the ownership problem.
@@ -213,30 +288,81 @@ impl<'ctx: 's, 's> SymbolTable<'ctx, 's> {
         receiver: Option<Ident<'s>>,
         fn_name: Ident<'s>,
     ) -> Option<&'a FnCtxt<'s>> {
-        let Some(receiver) = receiver.or(self.current_class().ok()) else {
-            return None;
-        };
+        let class_name = self.receiver_class(receiver)?;
+        self.globals.functions.get(&(class_name, fn_name))
+    }
 
-        let class_name = if let Some((
-            VariableCtxt {
-                ty: Type::Class(name),
-                ..
-            },
-            _,
-        )) = self.lookup_variable(receiver)
-        {
-            name
-        } else {
-            receiver
-        };
+    /// functions of every class, including the OS
+    pub fn functions(&self) -> impl Iterator<Item = &FnCtxt<'ctx>> {
+        self.globals.functions.values()
+    }
+
+    // class of the receiver, which is either an instance or a class name
+    fn receiver_class(&self, receiver: Option<Ident<'s>>) -> Option<Ident<'s>> {
+        let receiver = receiver.or(self.current_class().ok())?;
+
+        match self.lookup_variable(receiver) {
+            Some((
+                VariableCtxt {
+                    ty: Type::Class(name),
+                    ..
+                },
+                _,
+            )) => Some(name),
+            _ => Some(receiver),
+        }
+    }
+
+    /// Finds a variable in scope whose name is similar to the undefined `ident`.
+    pub fn similar_variable(&self, ident: Ident<'s>) -> Option<Ident<'s>> {
+        let mut scope = Some(&self.ctx);
+        let mut candidates = Vec::new();
+        while let Some(ctx) = scope {
+            candidates.extend(ctx.idents.keys().copied());
+            scope = ctx.parent.as_deref();
+        }
+
+        // `this` is registered as an argument of methods, but it's a keyword
+        candidates.retain(|name| *name != "this");
+        suggest::find_similar(ident, candidates)
+    }
+
+    /// Finds a function similar to the undefined `receiver.fn_name`, and returns how it should be
+    /// written (e.g. `Output.printString` for `output.printString`).
+    pub fn similar_fn(&self, receiver: Option<Ident<'s>>, fn_name: Ident<'s>) -> Option<String> {
+        let class_name = self.receiver_class(receiver)?;
+
+        // a typo in the function name
+        let in_class = self
+            .globals
+            .functions
+            .keys()
+            .filter(|(class, _)| *class == class_name)
+            .map(|(_, name)| *name);
+        if let Some(name) = suggest::find_similar(fn_name, in_class) {
+            return Some(match receiver {
+                Some(receiver) => format!("{receiver}.{name}"),
+                None => name.to_string(),
+            });
+        }
 
-        self.functions.get(&(class_name, fn_name))
+        // a typo in the class name
+        let receiver = receiver?;
+        let written = format!("{receiver}.{fn_name}");
+        let candidates: Vec<String> = self
+            .globals
+            .functions
+            .keys()
+            .map(|(class, name)| format!("{class}.{name}"))
+            .collect();
+        suggest::find_similar(&written, candidates.iter().map(String::as_str)).map(str::to_string)
     }
 
     pub fn register_fn(
         &mut self,
         class: Ident<'ctx>,
         name: Ident<'ctx>,
+        kind: FnKind,
         ty: Type<'ctx>,
         span: Span,
     ) -> Result<'s, ()> {
@@ -248,13 +374,50 @@ impl<'ctx: 's, 's> SymbolTable<'ctx, 's> {
         let ctxt = FnCtxt {
             class,
             name,
+            kind,
             ty,
-            span,
+            span: Some(span),
         };
-        self.functions.insert((class, name), ctxt);
+        Arc::make_mut(&mut self.globals)
+            .functions
+            .insert((class, name), ctxt);
         Ok(())
     }
 
+    pub fn register_layout(&mut self, layout: ClassLayout<'ctx>) {
+        Arc::make_mut(&mut self.globals)
+            .layouts
+            .insert(layout.name, layout);
+    }
+
+    pub fn layout(&self, class: Ident<'_>) -> Option<&ClassLayout<'ctx>> {
+        self.globals.layouts.get(class)
+    }
+
+    pub fn register_const(&mut self, ctxt: ConstCtxt<'ctx>) {
+        Arc::make_mut(&mut self.globals)
+            .constants
+            .insert((ctxt.class, ctxt.scope, ctxt.name), ctxt);
+    }
+
+    /// Finds the constant `name` of the current class, or `scope.name` which is a member of the
+    /// enum `scope` of the current class or a constant of the class `scope`.
+    pub fn lookup_const<'a>(
+        &'a self,
+        scope: Option<Ident<'s>>,
+        name: Ident<'s>,
+    ) -> Option<&'a ConstCtxt<'s>> {
+        let class = self.current?;
+        match scope {
+            None => self.globals.constants.get(&(class, None, name)),
+            Some(scope) => self
+                .globals
+                .constants
+                .get(&(class, Some(scope), name))
+                .or_else(|| self.globals.constants.get(&(scope, None, name))),
+        }
+    }
+
     // register appeared variable and assign unique register number
     pub fn register_variable(&mut self, var: VariableCtxt<'s>) -> Result<'s, ()> {
         let kind = var.kind;
@@ -270,7 +433,14 @@ impl<'ctx: 's, 's> SymbolTable<'ctx, 's> {
                 self.error(ErrorKind::InvalidSyntax(Cow::Owned(msg)), var.span)
             }
             _ => {
-                let register = self.assign_register(kind);
+                // fields are placed at the offsets of the class layout once it's computed
+                let offset = match (kind, self.current) {
+                    (Field, Some(class)) => self
+                        .layout(class)
+                        .and_then(|layout| layout.offset(var.name)),
+                    _ => None,
+                };
+                let register = offset.unwrap_or_else(|| self.assign_register(kind));
                 self.ctx.idents.insert(var.name, (var, register));
                 Ok(())
             }
@@ -298,11 +468,7 @@ impl<'ctx: 's, 's> SymbolTable<'ctx, 's> {
     }
 
     fn error(&self, kind: ErrorKind<'ctx>, span: Span) -> Result<'s, ()> {
-        let Some((_, src)) = self.current else {
-            let msg = Cow::Borrowed("current source is not set");
-            return Err(JackError::InternalCompilerError(msg));
-        };
-        Err(JackError::SemanticError(SemanticError { src, span, kind }))
+        Err(JackError::SemanticError(SemanticError { span, kind }))
     }
 }
 
@@ -325,15 +491,16 @@ impl<'ctx> ScopedContext<'ctx> {
 }
 
 macro_rules! define_stl {
-    ( $( ($class:ident, $fn_name:ident, $ty:expr) ),* ) => {[
+    ( $( ($class:ident, $fn_name:ident, $kind:expr, $ty:expr) ),* ) => {[
         $(
             (
                 (stringify!($class), stringify!($fn_name)),
                 FnCtxt {
                     class: stringify!($class),
                     name: stringify!($fn_name),
+                    kind: $kind,
                     ty: $ty,
-                    span: Span::new(0, 0)
+                    span: None
                 }
             )
         ),*
@@ -342,60 +509,65 @@ macro_rules! define_stl {
 
 #[rustfmt::skip]
 fn load_stl<'ctx>() -> FxHashMap<(Ident<'ctx>, Ident<'ctx>), FnCtxt<'ctx>> {
+    use FnKind::*;
     FxHashMap::from_iter(define_stl![
         // Array
-        (Array, new,        Type::Class("Array")),
-        (Array, dispose,    Type::Void),
+        (Array, new,        Function,   Type::Class("Array")),
+        (Array, dispose,    Method,     Type::Void),
         // Keyboard
-        (Keyboard, init,        Type::Void),
-        (Keyboard, keyPressed,  Type::Char),
-        (Keyboard, readChar,    Type::Char),
-        (Keyboard, readLine,    Type::Class("String")),
-        (Keyboard, readInt,     Type::Int),
+        (Keyboard, init,        Function,   Type::Void),
+        (Keyboard, keyPressed,  Function,   Type::Char),
+        (Keyboard, readChar,    Function,   Type::Char),
+        (Keyboard, readLine,    Function,   Type::Class("String")),
+        (Keyboard, readInt,     Function,   Type::Int),
         // Math
-        (Math, init,        Type::Void),
-        (Math, abs,         Type::Int),
-        (Math, multiply,    Type::Int),
-        (Math, divide,      Type::Int),
-        (Math, min,         Type::Int),
-        (Math, max,         Type::Int),
-        (Math, sqrt,        Type::Int),
+        (Math, init,        Function,   Type::Void),
+        (Math, abs,         Function,   Type::Int),
+        (Math, multiply,    Function,   Type::Int),
+        (Math, divide,      Function,   Type::Int),
+        (Math, min,         Function,   Type::Int),
+        (Math, max,         Function,   Type::Int),
+        (Math, sqrt,        Function,   Type::Int),
         // Memory
-        (Memory, init,      Type::Void),
-        (Memory, peek,      Type::Int),
-        (Memory, poke,      Type::Void),
-        (Memory, alloc,     Type::Class("Array")),
-        (Memory, deAlloc,   Type::Void),
+        (Memory, init,      Function,   Type::Void),
+        (Memory, peek,      Function,   Type::Int),
+        (Memory, poke,      Function,   Type::Void),
+        (Memory, alloc,     Function,   Type::Class("Array")),
+        (Memory, deAlloc,   Function,   Type::Void),
         // Output
-        (Output, init,          Type::Void),
-        (Output, moveCursor,    Type::Void),
-        (Output, printChar,     Type::Void),
-        (Output, printString,   Type::Void),
-        (Output, printInt,      Type::Void),
-        (Output, println,       Type::Void),
-        (Output, backSpace,     Type::Void),
+        (Output, init,          Function,   Type::Void),
+        (Output, moveCursor,    Function,   Type::Void),
+        (Output, printChar,     Function,   Type::Void),
+        (Output, printString,   Function,   Type::Void),
+        (Output, printInt,      Function,   Type::Void),
+        (Output, println,       Function,   Type::Void),
+        (Output, backSpace,     Function,   Type::Void),
         // Screen
-        (Screen, init,          Type::Void),
-        (Screen, clearScreen,   Type::Void),
-        (Screen, setColor,      Type::Void),
-        (Screen, drawPixel,     Type::Void),
-        (Screen, drawLine,      Type::Void),
-        (Screen, drawRectangle, Type::Void),
-        (Screen, drawCircle,    Type::Void),
+        (Screen, init,          Function,   Type::Void),
+        (Screen, clearScreen,   Function,   Type::Void),
+        (Screen, setColor,      Function,   Type::Void),
+        (Screen, drawPixel,     Function,   Type::Void),
+        (Screen, drawLine,      Function,   Type::Void),
+        (Screen, drawRectangle, Function,   Type::Void),
+        (Screen, drawCircle,    Function,   Type::Void),
         // String
-        (String, new,           Type::Class("String")),
-        (String, length,        Type::Int),
-        (String, charAt,        Type::Char),
-        (String, setCharAt,     Type::Char),
-        (String, appendChar,    Type::Class("String")),
-        (String, eraseLastChar, Type::Void),
-        (String, intValue,      Type::Int),
-        (String, setInt,        Type::Void),
+        (String, new,           Ctor,       Type::Class("String")),
+        (String, dispose,       Method,     Type::Void),
+        (String, length,        Method,     Type::Int),
+        (String, charAt,        Method,     Type::Char),
+        (String, setCharAt,     Method,     Type::Char),
+        (String, appendChar,    Method,     Type::Class("String")),
+        (String, eraseLastChar, Method,     Type::Void),
+        (String, intValue,      Method,     Type::Int),
+        (String, setInt,        Method,     Type::Void),
+        (String, backSpace,     Function,   Type::Char),
+        (String, doubleQuote,   Function,   Type::Char),
+        (String, newLine,       Function,   Type::Char),
         // Sys
-        (Sys, init,     Type::Void),
-        (Sys, halt,     Type::Void),
-        (Sys, error,    Type::Void),
-        (Sys, wait,     Type::Void)
+        (Sys, init,     Function,   Type::Void),
+        (Sys, halt,     Function,   Type::Void),
+        (Sys, error,    Function,   Type::Void),
+        (Sys, wait,     Function,   Type::Void)
     ])
 }
 
@@ -406,7 +578,7 @@ mod tests {
     fn fndef() -> FnDef<'static> {
         FnDef {
             name: "function",
-            span: Span::new(0, 0),
+            span: Span::DUMMY,
             kind: FnKind::Method,
             ret: Type::Void,
             params: vec![],
@@ -421,11 +593,13 @@ mod tests {
     fn symbol_table_scoped() {
         let mut table = SymbolTable::default();
         let fndef = fndef();
-        let some_field = VariableCtxt::field("some_field", Type::Int, Span::new(0, 10));
+        let some_field =
+            VariableCtxt::field("some_field", Type::Int, Span::new(FileId::NONE, 0, 10));
         table.register_variable(some_field).unwrap();
         table
             .scoped(&fndef, |t| {
-                let some_var = VariableCtxt::var("some_var", Type::Char, Span::new(0, 8));
+                let some_var =
+                    VariableCtxt::var("some_var", Type::Char, Span::new(FileId::NONE, 0, 8));
                 t.register_variable(some_var).unwrap();
                 assert_eq!(t.count(VarKind::Var), 1);
                 Ok(())
@@ -434,8 +608,11 @@ mod tests {
 
         table
             .scoped(&fndef, |t| {
-                let some_var =
-                    VariableCtxt::var("some_var_in_other_scope", Type::Char, Span::new(0, 23));
+                let some_var = VariableCtxt::var(
+                    "some_var_in_other_scope",
+                    Type::Char,
+                    Span::new(FileId::NONE, 0, 23),
+                );
 
                 t.register_variable(some_var).unwrap();
                 assert_eq!(t.count(VarKind::Var), 1);
@@ -452,7 +629,7 @@ mod tests {
     #[test]
     fn symbol_table_lookup() {
         let fndef = fndef();
-        let span = Span::new(0, 0);
+        let span = Span::DUMMY;
 
         let mut table = SymbolTable::default();
         assert_eq!(table.lookup_variable("foo"), None);
@@ -15,6 +15,8 @@ impl<'s> Token<'s> {
         match self.kind {
             Keyword(ref kind) => kind.name().to_string(),
             Integer(v) => v.to_string(),
+            Radix(_, literal) => literal.to_string(),
+            Char(c) => format!("'{c}'"),
             Str(s) => s.to_string(),
             Ident(ident) => ident.to_string(),
             LParen => "(".into(),
@@ -46,6 +48,8 @@ impl<'s> fmt::Display for Token<'s> {
         let kind = match self.kind {
             Keyword(ref kind) => format!("Keyword({kind})"),
             Integer(v) => format!("Integer({v})"),
+            Radix(v, literal) => format!("Radix({literal} = {v})"),
+            Char(c) => format!("Char('{c}')"),
             Str(s) => format!("Str({s})"),
             Ident(ident) => format!("Ident({ident})"),
             LParen => "Lparen (".into(),
@@ -74,10 +78,31 @@ impl<'s> fmt::Display for Token<'s> {
     }
 }
 
+/// Comments and line breaks between tokens. They are only kept by [`Lexer::with_trivia`] for
+/// tools which reproduce the source, like the formatter.
+///
+/// [`Lexer::with_trivia`]: crate::lexer::Lexer::with_trivia
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Trivia<'s> {
+    pub kind: TriviaKind<'s>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TriviaKind<'s> {
+    /// `// ..` without the line break, or `/* .. */`
+    Comment(&'s str),
+    Newline,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum TokenKind<'s> {
     Keyword(KwKind),
     Integer(u32),
+    /// `0x7FFF` or `0b1010`, with the literal as written
+    Radix(u32, &'s str),
+    /// `'A'`
+    Char(char),
     Str(&'s str),
     Ident(&'s str),
     /// (
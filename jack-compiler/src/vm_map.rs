@@ -0,0 +1,196 @@
+//! Source maps of VM code (`--emit source-map`), from the lines of a `.vm` file back to the Jack
+//! statements and functions which generated them.
+//!
+//! A map is written next to the VM code as `Class.vm.map`:
+//!
+//! ```text
+//! source Main.jack
+//! function Main.main 0
+//! args
+//! locals x i
+//! 1 4:9
+//! 5 5:9
+//! ```
+//!
+//! where each statement is given by the 0-based line of the VM code where it starts, followed by
+//! its 1-based line and column in the source. A statement spans until the next one starts, so an
+//! `if` only covers its condition before the statements of its branches.
+use std::{fmt, path::PathBuf};
+
+use jack_ast::{FnDef, FnKind, SourceFile, Span};
+
+/// Source map of the VM code of a class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmMap {
+    /// the Jack file of the class
+    pub source: PathBuf,
+    pub functions: Vec<FunctionMap>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionMap {
+    /// `Class.name`, as called in the VM code
+    pub name: String,
+    /// line of `function`
+    pub vm_line: usize,
+    /// names of the `argument` segment, starting with `this` for a method
+    pub args: Vec<String>,
+    /// names of the `local` segment
+    pub locals: Vec<String>,
+    /// statements in the order of their code
+    pub statements: Vec<StmtMap>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StmtMap {
+    pub vm_line: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl VmMap {
+    pub fn new(file: &SourceFile) -> Self {
+        VmMap {
+            source: file.path.clone(),
+            functions: Vec::new(),
+        }
+    }
+
+    /// Moves every line to `lines[line]`, where the code was moved by `inline::inline`.
+    pub fn relocate(&mut self, lines: &[usize]) {
+        let end = lines.last().copied().unwrap_or(0);
+        let relocate = |line: &mut usize| *line = lines.get(*line).copied().unwrap_or(end);
+        for f in self.functions.iter_mut() {
+            relocate(&mut f.vm_line);
+            for stmt in f.statements.iter_mut() {
+                relocate(&mut stmt.vm_line);
+            }
+        }
+    }
+}
+
+impl FunctionMap {
+    // `def` of `class` starting at `vm_line`, with the lines and spans of its statements
+    pub(crate) fn new(
+        file: &SourceFile,
+        class: &str,
+        def: &FnDef<'_>,
+        vm_line: usize,
+        statements: &[(usize, Span)],
+    ) -> Self {
+        let this = (def.kind == FnKind::Method).then(|| "this".to_string());
+        FunctionMap {
+            name: format!("{class}.{}", def.name),
+            vm_line,
+            args: this
+                .into_iter()
+                .chain(def.params.iter().map(|param| param.name.to_string()))
+                .collect(),
+            locals: def
+                .body
+                .variables
+                .iter()
+                .map(|var| var.name.to_string())
+                .collect(),
+            statements: statements
+                .iter()
+                .map(|&(vm_line, span)| {
+                    let (line, column) = file.line_col(span.lo());
+                    StmtMap {
+                        vm_line,
+                        line,
+                        column,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for VmMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "source {}", self.source.display())?;
+        for function in self.functions.iter() {
+            writeln!(f, "function {} {}", function.name, function.vm_line)?;
+            writeln!(f, "{}", words("args", &function.args))?;
+            writeln!(f, "{}", words("locals", &function.locals))?;
+            for stmt in function.statements.iter() {
+                writeln!(f, "{} {}:{}", stmt.vm_line, stmt.line, stmt.column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// `names` after `key`, separated by spaces
+fn words(key: &str, names: &[String]) -> String {
+    std::iter::once(key)
+        .chain(names.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use jack_ast::SourceMap;
+
+    use crate::{compiler, diagnosis::DiagnosticReporter};
+
+    #[test]
+    fn map_statements_to_vm_lines() {
+        let src = "\
+class Main {
+    method int get(int n) { return n; }
+    function int main() {
+        var int x, i;
+        let x = 1;
+        while (i < 3) {
+            let i = i + 1;
+        }
+        return x;
+    }
+}
+";
+        let mut sources = SourceMap::new();
+        sources.add_file("Main.jack", src.to_string());
+        let mut reporter = DiagnosticReporter::new(&sources);
+        reporter.capture();
+        let options = compiler::Options::default();
+        let classes = compiler::compile_with_source_maps(&sources, &reporter, &options).unwrap();
+        let (vm, map) = &classes[0];
+
+        let get = &map.functions[0];
+        assert_eq!(get.name, "Main.get");
+        assert_eq!(get.args, ["this", "n"]);
+        let main = &map.functions[1];
+        assert_eq!(main.locals, ["x", "i"]);
+        let lines: Vec<_> = vm.lines().collect();
+        assert!(lines[main.vm_line].starts_with("function Main.main"));
+
+        // the first instruction of each statement, skipping the comment in debug builds
+        let code: Vec<_> = main
+            .statements
+            .iter()
+            .map(|stmt| {
+                let insn = lines[stmt.vm_line..]
+                    .iter()
+                    .find(|line| !line.starts_with("//"))
+                    .unwrap();
+                (stmt.line, stmt.column, *insn)
+            })
+            .collect();
+        assert_eq!(
+            code,
+            [
+                (5, 9, "push constant 1"),
+                (6, 9, "label LABEL_1"),
+                (7, 13, "push local 1"),
+                (9, 9, "push local 0"),
+            ]
+        );
+        // `this` is set before the statements of a method
+        assert!(map
+            .to_string()
+            .starts_with("source Main.jack\nfunction Main.get 0\nargs this n\nlocals\n3 2:29\n"));
+    }
+}
@@ -95,6 +95,10 @@ trait Xml<'s, W: io::Write> {
 
 impl<'s, W: io::Write> Xml<'s, W> for Class<'s> {
     fn to_xml(&self, ctx: &mut XmlContext<'s, W>) -> anyhow::Result<()> {
+        if !self.constants.is_empty() || !self.enums.is_empty() {
+            anyhow::bail!("no XML for constants of `{}`", self.name);
+        }
+
         ctx.nest("class", |ctx| {
             ctx.keyword("class")?;
             ctx.ident(self.name)?;
@@ -233,7 +237,7 @@ impl<'s, W: io::Write> Xml<'s, W> for Stmt<'s> {
                         ctx.keyword("else")?;
                         ctx.with_brace(|ctx| {
                             ctx.nest("statements", |ctx| {
-                                for stmt in else_branch.iter() {
+                                for stmt in else_branch.stmts() {
                                     stmt.to_xml(ctx)?;
                                 }
                                 Ok(())
@@ -273,6 +277,11 @@ impl<'s, W: io::Write> Xml<'s, W> for Stmt<'s> {
                     ctx.symbol(";")
                 })?;
             }
+            // the fixtures are written in the standard dialect
+            StmtKind::CompoundLet { .. }
+            | StmtKind::For { .. }
+            | StmtKind::Break
+            | StmtKind::Continue => anyhow::bail!("no XML for `{self}`"),
         }
         Ok(())
     }
@@ -296,7 +305,11 @@ impl<'s, W: io::Write> Xml<'s, W> for Term<'s> {
         ctx.nest("term", |ctx| {
             match &self.kind {
                 TermKind::Const(constant) => match constant {
-                    Constant::Integer(n) => ctx.leaf("integerConstant", &n.to_string())?,
+                    // the XML of the course has no other literals than decimal integers
+                    Constant::Integer(_) | Constant::Radix(..) | Constant::Char(_) => {
+                        let n = constant.int_value().unwrap_or_default();
+                        ctx.leaf("integerConstant", &n.to_string())?
+                    }
                     Constant::Str(s) => ctx.leaf("stringConstant", s)?,
                     Constant::True => ctx.keyword("true")?,
                     Constant::False => ctx.keyword("false")?,
@@ -310,6 +323,7 @@ impl<'s, W: io::Write> Xml<'s, W> for Term<'s> {
                 }
                 TermKind::FnCall(fncall) => fncall.to_xml(ctx)?,
                 TermKind::Expr(expr) => ctx.with_paren(|ctx| expr.to_xml(ctx))?,
+                TermKind::Qualified { .. } => anyhow::bail!("no XML for `{self}`"),
             }
             Ok(())
         })
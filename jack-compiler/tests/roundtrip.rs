@@ -0,0 +1,80 @@
+// `parse(print(ast)) == ast` for every fixture, where spans are ignored since the layout of the
+// printed source differs from the original.
+use std::{fs, path::Path};
+
+use jack_ast::{visit::*, *};
+use jack_compiler::parser::parse;
+
+// Resets every span in the AST so that ASTs can be compared by their structure.
+struct EraseSpans;
+
+impl<'s> VisitorMut<'s> for EraseSpans {
+    fn visit_class_mut(&mut self, class: &mut Class<'s>) {
+        class.span = Span::DUMMY;
+        walk_class_mut(self, class);
+    }
+
+    fn visit_variable_def_mut(&mut self, def: &mut VariableDef<'s>) {
+        def.span = Span::DUMMY;
+    }
+
+    fn visit_fn_def_mut(&mut self, f: &mut FnDef<'s>) {
+        f.span = Span::DUMMY;
+        walk_fn_def_mut(self, f);
+    }
+
+    fn visit_parameter_mut(&mut self, param: &mut Parameter<'s>) {
+        param.span = Span::DUMMY;
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt<'s>) {
+        stmt.span = Span::DUMMY;
+        walk_stmt_mut(self, stmt);
+    }
+
+    fn visit_term_mut(&mut self, term: &mut Term<'s>) {
+        term.span = Span::DUMMY;
+        walk_term_mut(self, term);
+    }
+
+    fn visit_variable_mut(&mut self, var: &mut Variable<'s>) {
+        var.span = Span::DUMMY;
+        walk_variable_mut(self, var);
+    }
+
+    fn visit_fn_call_mut(&mut self, call: &mut FnCall<'s>) {
+        call.span = Span::DUMMY;
+        walk_fn_call_mut(self, call);
+    }
+}
+
+#[test]
+fn print_and_parse_fixtures() {
+    let fixtures = Path::new("tests/fixtures");
+    let mut count = 0;
+    for project in fs::read_dir(fixtures).unwrap() {
+        for entry in fs::read_dir(project.unwrap().path()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(true, |ext| ext != "jack") {
+                continue;
+            }
+
+            let src = fs::read_to_string(&path).unwrap();
+            let mut ast = parse(&src).unwrap();
+            let printed = ast.to_source();
+            let mut reparsed = match parse(&printed) {
+                Ok(class) => class,
+                Err(e) => panic!("{}: {e:?}\n{printed}", path.display()),
+            };
+
+            EraseSpans.visit_class_mut(&mut ast);
+            EraseSpans.visit_class_mut(&mut reparsed);
+            assert_eq!(reparsed, ast, "{}", path.display());
+            // printing is stable
+            assert_eq!(reparsed.to_source(), printed, "{}", path.display());
+            count += 1;
+        }
+    }
+
+    assert!(count > 0, "no fixtures found");
+}
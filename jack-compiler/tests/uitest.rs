@@ -12,11 +12,15 @@ fn print_output(output: Output) {
     eprintln!("info: [stderr]\n{}", buf_to_string(output.stderr));
 }
 
+// extra compiler options can be given by an `args` file in the testcase directory
 fn compile<P: AsRef<Path>>(program: &P) -> Output {
+    let options = std::fs::read_to_string(program.as_ref().join("args")).unwrap_or_default();
     let program = program.as_ref().display().to_string();
     match Command::new("cargo")
         .env("CLICOLOR", "0")
-        .args(["run", "--quiet", "--", &program])
+        .args(["run", "--quiet", "--"])
+        .args(options.split_whitespace())
+        .arg(&program)
         .output()
     {
         Ok(output) => output,
@@ -28,24 +32,63 @@ fn collect_path(dir: PathBuf) -> Vec<PathBuf> {
     dir.read_dir().unwrap().map(|p| p.unwrap().path()).collect()
 }
 
+// copies the sources and the `args` of `testcase` into a new temporary directory, so that the
+// outputs do not overwrite the tracked files
+fn copy_to_temp(testcase: &Path, name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("jack-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for file in collect_path(testcase.to_path_buf()) {
+        if file.extension().map_or(false, |ext| ext == "jack") || file.ends_with("args") {
+            std::fs::copy(&file, dir.join(file.file_name().unwrap())).unwrap();
+        }
+    }
+    dir
+}
+
+// testcases are compiled from scratch, regardless of the previous runs
+fn remove_cache(testcase: &Path) {
+    let _ = std::fs::remove_file(testcase.join(".jack-cache"));
+}
+
 #[test]
 fn ui_compile_ok() {
-    let mut testcases = std::env::current_dir().unwrap();
-    testcases.push("tests/fixtures");
+    // relative to the package root, as paths appear in diagnostics
+    let testcases = PathBuf::from("tests/fixtures");
 
     for entry in collect_path(testcases) {
-        let output = compile(&entry);
+        let name = entry.file_name().unwrap().to_string_lossy();
+        let dir = copy_to_temp(&entry, &name);
+        let output = compile(&dir);
         print_output(output.clone());
         assert!(output.status.success(), "test failed {}", entry.display());
+
+        // the generated VM code of every class must match the one next to its source
+        for source in collect_path(entry.clone()) {
+            if source.extension().map_or(true, |ext| ext != "jack") {
+                continue;
+            }
+            let vm = source.with_extension("vm");
+            let expected = std::fs::read_to_string(&vm).expect("could not read .vm file");
+            let generated = dir.join(vm.file_name().unwrap());
+            similar_asserts::assert_eq!(
+                std::fs::read_to_string(generated).unwrap(),
+                expected,
+                "{}",
+                vm.display()
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
 
 #[test]
 fn ui_compile_warning() {
-    let mut testcases = std::env::current_dir().unwrap();
-    testcases.push("tests/ui/warning");
+    // relative to the package root, as paths appear in diagnostics
+    let testcases = PathBuf::from("tests/ui/warning");
 
     for entry in collect_path(testcases) {
+        remove_cache(&entry);
         let output = compile(&entry);
         print_output(output.clone());
         assert!(output.status.success(), "test failed {}", entry.display());
@@ -62,10 +105,11 @@ fn ui_compile_warning() {
 
 #[test]
 fn ui_compile_err() {
-    let mut testcases = std::env::current_dir().unwrap();
-    testcases.push("tests/ui/err");
+    // relative to the package root, as paths appear in diagnostics
+    let testcases = PathBuf::from("tests/ui/err");
 
     for entry in collect_path(testcases) {
+        remove_cache(&entry);
         let output = compile(&entry);
         print_output(output.clone());
         assert!(
@@ -84,3 +128,33 @@ fn ui_compile_err() {
         );
     }
 }
+
+#[test]
+fn ui_compile_incremental() {
+    // `SquareGame` has a type mismatch found by its codegen
+    let testcase = Path::new("tests/fixtures/ExpressionLessSquare");
+    let dir = copy_to_temp(testcase, "incremental");
+    std::fs::write(dir.join("args"), "--verbose").unwrap();
+    let rebuilt = || {
+        let output = compile(&dir);
+        assert!(output.status.success(), "test failed {}", dir.display());
+        let stdout = buf_to_string(output.stdout);
+        let summary = stdout.lines().last().unwrap_or_default().to_string();
+        (summary, buf_to_string(output.stderr))
+    };
+
+    let (summary, warnings) = rebuilt();
+    assert_eq!(summary, "rebuilt 3 of 3 classes");
+    // the warnings are reported as if the classes up to date were built
+    assert_eq!(rebuilt(), ("rebuilt 0 of 3 classes".to_string(), warnings));
+    let game = dir.join("SquareGame.jack");
+    let src = std::fs::read_to_string(&game).unwrap();
+    std::fs::write(
+        &game,
+        src.replace("method void run()", "method void run(int x)"),
+    )
+    .unwrap();
+    // `Main` calls `run`
+    assert_eq!(rebuilt().0, "rebuilt 2 of 3 classes");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,654 @@
+//! Pretty-printer of Jack classes which keeps comments and blank lines of the source.
+//!
+//! The class is printed from its AST, and comments are put back between declarations and
+//! statements by their positions. Comments inside of an expression are moved before the next
+//! declaration or statement.
+use std::collections::VecDeque;
+
+use jack_ast::*;
+use jack_compiler::{
+    lexer::Lexer,
+    parser::{self, Dialect},
+    token::{Token, TokenKind, TriviaKind},
+    JackError, LexError, ParseError, ParseErrorKind,
+};
+use rustc_hash::FxHashSet;
+
+const INDENT: &str = "    ";
+
+/// Formats the class in `file` into the canonical style.
+pub fn format(file: &SourceFile) -> Result<String, JackError<'_>> {
+    // the extensions are a superset of the standard dialect
+    let class = parser::parse_file(file, Dialect::Extended)?;
+    let mut printer = Printer::new(file).map_err(ParseError::from)?;
+    printer.class(&class);
+
+    // The parser silently skips some malformed code, which must not be dropped by formatting.
+    let formatted = Lexer::new(&printer.out).map_while(Result::ok);
+    let mismatch = printer
+        .tokens
+        .iter()
+        .zip(formatted.map(Some).chain(std::iter::repeat(None)))
+        .find(|(token, formatted)| formatted.as_ref().map(|t| &t.kind) != Some(&token.kind));
+    if let Some((token, _)) = mismatch {
+        let kind = ParseErrorKind::UnexpectedToken(token.clone());
+        return Err(ParseError::new(kind, token.span).into());
+    }
+
+    Ok(printer.out)
+}
+
+// `const` and `enum` members, which may be declared between class variables
+enum Definition<'a, 's> {
+    Const(&'a ConstDef<'s>),
+    Enum(&'a EnumDef<'s>),
+}
+
+impl Definition<'_, '_> {
+    fn pos(&self) -> BytePos {
+        match self {
+            Definition::Const(def) => def.span.lo(),
+            Definition::Enum(def) => def.span.lo(),
+        }
+    }
+}
+
+struct Comment<'s> {
+    text: &'s str,
+    lo: BytePos,
+    // on the same line as the previous token
+    trailing: bool,
+    // preceded by a blank line
+    blank_before: bool,
+}
+
+struct Printer<'s> {
+    out: String,
+    indent: usize,
+    // just after `{`, where blank lines are not kept
+    block_start: bool,
+    tokens: Vec<Token<'s>>,
+    // positions of tokens preceded by a blank line
+    blank_before: FxHashSet<BytePos>,
+    // comments not printed yet
+    comments: VecDeque<Comment<'s>>,
+}
+
+impl<'s> Printer<'s> {
+    fn new(file: &'s SourceFile) -> Result<Self, LexError<'s>> {
+        let mut lexer = Lexer::with_trivia(&file.src, file.id);
+        let mut tokens = Vec::new();
+        let mut blank_before = FxHashSet::default();
+        let mut comments = VecDeque::new();
+
+        loop {
+            let (trivia, token) = lexer.lex_with_trivia()?;
+            // line breaks since the last token or comment
+            let mut newlines = 0;
+            for trivia in trivia {
+                match trivia.kind {
+                    TriviaKind::Newline => newlines += 1,
+                    TriviaKind::Comment(text) => {
+                        comments.push_back(Comment {
+                            text: text.trim_end(),
+                            lo: trivia.span.lo(),
+                            trailing: newlines == 0 && !tokens.is_empty(),
+                            blank_before: newlines >= 2,
+                        });
+                        newlines = 0;
+                    }
+                }
+            }
+
+            let Some(token) = token else { break };
+            if newlines >= 2 {
+                blank_before.insert(token.span.lo());
+            }
+            tokens.push(token);
+        }
+
+        Ok(Printer {
+            out: String::new(),
+            indent: 0,
+            block_start: false,
+            tokens,
+            blank_before,
+            comments,
+        })
+    }
+
+    fn class(&mut self, class: &Class<'_>) {
+        self.item(class.span.lo(), false);
+        self.open(format!("class {}", class.name));
+        let close = self.block_after(class.span.lo());
+
+        let mut definitions: Vec<_> = class
+            .constants
+            .iter()
+            .map(Definition::Const)
+            .chain(class.enums.iter().map(Definition::Enum))
+            .collect();
+        definitions.sort_by_key(Definition::pos);
+
+        let mut variables = &class.variables[..];
+        for def in definitions {
+            let n = variables
+                .iter()
+                .take_while(|v| v.span.lo() < def.pos())
+                .count();
+            self.variables(&variables[..n]);
+            variables = &variables[n..];
+
+            match def {
+                Definition::Const(def) => self.const_def(def),
+                Definition::Enum(def) => self.enum_def(def),
+            }
+        }
+        self.variables(variables);
+
+        for f in class.functions.iter() {
+            self.fn_def(f);
+        }
+
+        self.close(close);
+        // comments after the class
+        self.comments_before(BytePos::MAX);
+    }
+
+    fn const_def(&mut self, def: &ConstDef<'_>) {
+        self.item(def.span.lo(), false);
+        self.line(format!(
+            "const {} {} = {};",
+            def.ty.to_source(),
+            def.name,
+            def.value.to_source()
+        ));
+    }
+
+    // Members are printed one per line.
+    fn enum_def(&mut self, def: &EnumDef<'_>) {
+        self.item(def.span.lo(), false);
+        self.open(format!("enum {}", def.name));
+        let close = self.block_after(def.span.lo());
+
+        let last = def.members.len().saturating_sub(1);
+        for (i, member) in def.members.iter().enumerate() {
+            // `item` does not apply, as members are not terminated by `;`
+            self.trailing_comments(member.span.lo());
+            self.comments_before(member.span.lo());
+            let comma = if i < last { "," } else { "" };
+            self.line(format!("{}{comma}", member.to_source()));
+        }
+        self.close(close);
+    }
+
+    fn fn_def(&mut self, f: &FnDef<'_>) {
+        // functions are always separated by a blank line
+        self.item(f.span.lo(), true);
+        let params = f
+            .params
+            .iter()
+            .map(|p| format!("{} {}", p.ty.to_source(), p.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.open(format!(
+            "{} {} {}({params})",
+            f.kind,
+            f.ret.to_source(),
+            f.name
+        ));
+        let close = self.block_after(f.span.lo());
+
+        self.variables(&f.body.variables);
+        self.stmts(&f.body.statements);
+        self.close(close);
+    }
+
+    // Variables declared together (e.g. `field int x, y;`) are printed together.
+    fn variables(&mut self, variables: &[VariableDef<'_>]) {
+        let mut rest = variables;
+        while let Some(first) = rest.first() {
+            let start = self.item_start(first.span.lo());
+            let len = rest
+                .iter()
+                .take_while(|v| self.item_start(v.span.lo()) == start)
+                .count();
+            let (group, remaining) = rest.split_at(len);
+            rest = remaining;
+
+            let names = group.iter().map(|v| v.name).collect::<Vec<_>>().join(", ");
+            self.item(start, false);
+            self.line(format!("{} {} {names};", first.kind, first.ty.to_source()));
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt<'_>]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt<'_>) {
+        let pos = stmt.span.lo();
+        self.item(pos, false);
+
+        match &stmt.kind {
+            StmtKind::Let { lhs, rhs } => {
+                self.line(format!("let {} = {};", lhs.to_source(), rhs.to_source()));
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.open(format!("if ({})", cond.to_source()));
+                self.branches(pos, then_branch, else_branch);
+            }
+            StmtKind::While { cond, body } => {
+                self.open(format!("while ({})", cond.to_source()));
+                let close = self.block_after(pos);
+                self.stmts(body);
+                self.close(close);
+            }
+            StmtKind::CompoundLet { lhs, op, rhs } => {
+                self.line(format!(
+                    "let {} {op}= {};",
+                    lhs.to_source(),
+                    rhs.to_source()
+                ));
+            }
+            StmtKind::For {
+                init,
+                cond,
+                step,
+                body,
+            } => {
+                // `init;` and `step` print themselves with `;`
+                self.open(format!(
+                    "for ({} {}; {})",
+                    init.to_source().trim_end(),
+                    cond.to_source(),
+                    step.to_source().trim_end().trim_end_matches(';'),
+                ));
+                let close = self.block_after(pos);
+                self.stmts(body);
+                self.close(close);
+            }
+            StmtKind::Do(call) => self.line(format!("do {};", call.to_source())),
+            StmtKind::Return(Some(retval)) => self.line(format!("return {};", retval.to_source())),
+            StmtKind::Return(None) => self.line("return;".to_string()),
+            StmtKind::Break => self.line("break;".to_string()),
+            StmtKind::Continue => self.line("continue;".to_string()),
+        }
+    }
+
+    // Prints the branches of the `if` at `pos`, whose header is already opened.
+    fn branches(&mut self, pos: BytePos, then_branch: &[Stmt<'_>], else_branch: &Option<Else<'_>>) {
+        let then_close = self.block_after(pos);
+        self.stmts(then_branch);
+
+        match else_branch {
+            Some(Else::If(stmt)) => {
+                let StmtKind::If { cond, then_branch, else_branch } = &stmt.kind else {
+                    unreachable!("`else if` of a statement other than `if`")
+                };
+                self.close_with(then_close, &format!(" else if ({}) {{", cond.to_source()));
+                self.indent += 1;
+                self.block_start = true;
+
+                self.branches(stmt.span.lo(), then_branch, else_branch);
+            }
+            Some(Else::Block(else_branch)) => {
+                self.close_with(then_close, " else {");
+                // `else {` opens another block
+                self.indent += 1;
+                self.block_start = true;
+
+                let else_close = self.block_after(then_close + 1);
+                self.stmts(else_branch);
+                self.close(else_close);
+            }
+            None => self.close(then_close),
+        }
+    }
+
+    // Prints comments and a blank line before the declaration or statement around `pos`.
+    fn item(&mut self, pos: BytePos, separate: bool) {
+        let start = self.item_start(pos);
+        self.trailing_comments(start);
+        if separate {
+            self.blank_line();
+        }
+        self.comments_before(start);
+        if self.blank_before.contains(&start) {
+            self.blank_line();
+        }
+    }
+
+    fn open(&mut self, header: String) {
+        self.line(header + " {");
+        self.indent += 1;
+        self.block_start = true;
+    }
+
+    fn close(&mut self, close: BytePos) {
+        self.close_with(close, "");
+    }
+
+    // Prints `}` at `close` followed by `suffix`.
+    fn close_with(&mut self, close: BytePos, suffix: &str) {
+        self.trailing_comments(close);
+        self.comments_before(close);
+        self.indent -= 1;
+
+        if self.block_start {
+            // empty block
+            self.out.pop();
+            self.out.push_str(&format!("}}{suffix}\n"));
+            self.block_start = false;
+        } else {
+            self.line(format!("}}{suffix}"));
+        }
+    }
+
+    fn line(&mut self, text: String) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(&text);
+        self.out.push('\n');
+        self.block_start = false;
+    }
+
+    fn blank_line(&mut self) {
+        if !self.block_start && !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    // Appends comments on the same line as the last printed token.
+    fn trailing_comments(&mut self, before: BytePos) {
+        while let Some(comment) = self.comments.front() {
+            if !comment.trailing || comment.lo >= before {
+                break;
+            }
+
+            let comment = self.comments.pop_front().unwrap();
+            self.append(comment.text);
+        }
+    }
+
+    fn comments_before(&mut self, before: BytePos) {
+        while let Some(comment) = self.comments.front() {
+            if comment.lo >= before {
+                break;
+            }
+
+            let comment = self.comments.pop_front().unwrap();
+            if comment.trailing && !self.out.ends_with("\n\n") {
+                self.append(comment.text);
+            } else {
+                if comment.blank_before {
+                    self.blank_line();
+                }
+                self.line(comment.text.to_string());
+            }
+        }
+    }
+
+    fn append(&mut self, text: &str) {
+        if self.out.is_empty() {
+            return self.line(text.to_string());
+        }
+
+        self.out.pop();
+        self.out.push(' ');
+        self.out.push_str(text);
+        self.out.push('\n');
+        self.block_start = false;
+    }
+
+    // index of the first token at or after `pos`
+    fn token_index(&self, pos: BytePos) -> usize {
+        self.tokens.partition_point(|token| token.span.lo() < pos)
+    }
+
+    // position of the first token of the declaration or statement around `pos`
+    fn item_start(&self, pos: BytePos) -> BytePos {
+        let mut i = self.token_index(pos).min(self.tokens.len() - 1);
+        while i > 0
+            && !matches!(
+                self.tokens[i - 1].kind,
+                TokenKind::Semicolon | TokenKind::LBrace | TokenKind::RBrace
+            )
+        {
+            i -= 1;
+        }
+
+        self.tokens[i].span.lo()
+    }
+
+    // position of `}` closing the first block opened after `pos`
+    fn block_after(&self, pos: BytePos) -> BytePos {
+        let mut depth = 0;
+        for token in &self.tokens[self.token_index(pos)..] {
+            match token.kind {
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace if depth == 1 => return token.span.lo(),
+                TokenKind::RBrace => depth -= 1,
+                _ => {}
+            }
+        }
+
+        // the parser guarantees that blocks are closed
+        unreachable!("unclosed block")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    fn fmt(src: &str) -> String {
+        let mut sources = SourceMap::new();
+        let id = sources.add_file("Main.jack", src.to_owned());
+        format(sources.file(id)).unwrap()
+    }
+
+    #[test]
+    fn canonical_style() {
+        let src = r#"class Main{ field int x,y;static boolean  flag;
+  method   void run(int a,Array b){var int i;
+let i=-a+(b[0]*2);
+  if(~flag){do Output.printString("hi");}else{}
+    while (i<10) {let i = i+1;}
+  return;}
+function int zero() { return 0; } }"#;
+
+        similar_asserts::assert_eq!(
+            fmt(src),
+            r#"class Main {
+    field int x, y;
+    static boolean flag;
+
+    method void run(int a, Array b) {
+        var int i;
+        let i = -a + (b[0] * 2);
+        if (~flag) {
+            do Output.printString("hi");
+        } else {}
+        while (i < 10) {
+            let i = i + 1;
+        }
+        return;
+    }
+
+    function int zero() {
+        return 0;
+    }
+}
+"#
+        );
+    }
+
+    #[test]
+    fn keep_literals_as_written() {
+        let src = "class Main {\n    function int f() {\n        return (0x00FF & 0b1010) + 'A';\n    }\n}\n";
+        assert_eq!(fmt(src), src);
+    }
+
+    #[test]
+    fn format_extensions() {
+        let src = "class Main { function void f() { while (a) {
+if (b) { break; } // done
+else if (c) {} else if (d) { continue; } else { let a = 1; } }
+return; } }";
+
+        similar_asserts::assert_eq!(
+            fmt(src),
+            "class Main {
+    function void f() {
+        while (a) {
+            if (b) {
+                break;
+            } else if (c) { // done
+            } else if (d) {
+                continue;
+            } else {
+                let a = 1;
+            }
+        }
+        return;
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn format_constants() {
+        let src = "class Main { static int x;
+// sizes
+const int SIZE=16;   field int y;
+enum Dir { UP, // first
+DOWN=Dir.UP+2 } function int f() { return Main.SIZE + Dir.DOWN; } }";
+
+        similar_asserts::assert_eq!(
+            fmt(src),
+            "class Main {
+    static int x;
+    // sizes
+    const int SIZE = 16;
+    field int y;
+    enum Dir {
+        UP, // first
+        DOWN = Dir.UP + 2
+    }
+
+    function int f() {
+        return Main.SIZE + Dir.DOWN;
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn keep_comments_and_blank_lines() {
+        let src = r#"// header
+
+/** Main class */
+class Main {
+  field int x; // trailing
+
+
+  /* before run */
+  method void run() {
+    var int i;
+
+    // loop
+    while (true) { // forever
+      let i = i + /* inline */ 1;
+      // nothing more
+    }
+    return;
+  }
+}
+// footer
+"#;
+
+        similar_asserts::assert_eq!(
+            fmt(src),
+            r#"// header
+
+/** Main class */
+class Main {
+    field int x; // trailing
+
+    /* before run */
+    method void run() {
+        var int i;
+
+        // loop
+        while (true) { // forever
+            let i = i + 1; /* inline */
+            // nothing more
+        }
+        return;
+    }
+}
+// footer
+"#
+        );
+    }
+
+    #[test]
+    fn refuse_to_drop_code() {
+        // the parser gives up on the function silently
+        let src = "class A { function void f() { let x = ; } }";
+        let mut sources = SourceMap::new();
+        let id = sources.add_file("A.jack", src.to_owned());
+        assert!(format(sources.file(id)).is_err());
+    }
+
+    // Formatting the fixtures must keep every token and comment, and be idempotent.
+    #[test]
+    fn format_fixtures() {
+        fn tokens(src: &str) -> Vec<TokenKind<'_>> {
+            Lexer::new(src).map(|t| t.unwrap().kind).collect()
+        }
+        fn comments(src: &str) -> Vec<&str> {
+            let mut lexer = Lexer::with_trivia(src, FileId::NONE);
+            let mut comments = Vec::new();
+            loop {
+                let (trivia, token) = lexer.lex_with_trivia().unwrap();
+                comments.extend(trivia.into_iter().filter_map(|t| match t.kind {
+                    TriviaKind::Comment(text) => Some(text.trim_end()),
+                    TriviaKind::Newline => None,
+                }));
+                if token.is_none() {
+                    return comments;
+                }
+            }
+        }
+
+        let fixtures =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../jack-compiler/tests/fixtures");
+        for project in fs::read_dir(fixtures).unwrap() {
+            for entry in fs::read_dir(project.unwrap().path()).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().map_or(true, |ext| ext != "jack") {
+                    continue;
+                }
+
+                let src = fs::read_to_string(&path).unwrap();
+                let formatted = fmt(&src);
+                assert_eq!(tokens(&formatted), tokens(&src), "{}", path.display());
+                assert_eq!(comments(&formatted), comments(&src), "{}", path.display());
+                assert_eq!(fmt(&formatted), formatted, "{}", path.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Formatter of Jack sources.
+mod format;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use anyhow::Result;
+use jack_ast::{SourceFile, SourceMap};
+use jack_compiler::diagnosis::DiagnosticReporter;
+
+fn help() -> ! {
+    println!(
+        "\
+Usage: cargo run -p jack-fmt -- [options] <jack file or directory>...
+
+Options:
+    --check             do not write files, but exit with 1 if any of them is not formatted"
+    );
+    exit(0);
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if path.is_dir() {
+        for entry in path.read_dir()?.filter_map(|p| p.map(|p| p.path()).ok()) {
+            if entry.is_dir() || entry.extension().map_or(false, |ext| ext == "jack") {
+                collect_files(&entry, files)?;
+            }
+        }
+    } else {
+        files.push(path.into());
+    }
+
+    Ok(())
+}
+
+// Prints the first line which differs from the formatted one.
+fn print_diff(file: &SourceFile, formatted: &str) {
+    let mut original = file.src.lines();
+    let mut formatted = formatted.lines();
+    for line in 1.. {
+        let (old, new) = (original.next(), formatted.next());
+        if old == new && old.is_some() {
+            continue;
+        }
+
+        println!("Diff in {} at line {line}:", file.name());
+        if let Some(old) = old {
+            println!("-{old}");
+        }
+        if let Some(new) = new {
+            println!("+{new}");
+        }
+        return;
+    }
+}
+
+fn main() -> Result<()> {
+    let mut check = false;
+    let mut files = Vec::new();
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "-h" | "--help" => help(),
+            "--check" => check = true,
+            _ => collect_files(Path::new(&arg), &mut files)?,
+        }
+    }
+    if files.is_empty() {
+        help();
+    }
+    files.sort();
+
+    let mut sources = SourceMap::new();
+    for path in files {
+        let src = fs::read_to_string(&path)?;
+        sources.add_file(path, src);
+    }
+
+    let reporter = DiagnosticReporter::new(&sources);
+    // whether any file could not be formatted, or is not formatted in `--check` mode
+    let mut failed = false;
+    for file in sources.files() {
+        match format::format(file) {
+            Ok(formatted) if formatted == file.src => {}
+            Ok(formatted) if check => {
+                print_diff(file, &formatted);
+                failed = true;
+            }
+            Ok(formatted) => fs::write(&file.path, formatted)?,
+            Err(e) => {
+                reporter.report(&e);
+                failed = true;
+            }
+        }
+    }
+    reporter.finish();
+
+    exit(failed.into());
+}
@@ -0,0 +1,60 @@
+use thiserror::Error;
+
+use crate::interp::MAX_DEPTH;
+
+/// An error which stops the program, as `Sys.error` does on the Hack platform.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// `Sys.error` called by the program or the OS with the error code
+    #[error("Sys.error({0}): {}", os_error(*.0))]
+    Sys(i16),
+
+    #[error("undefined function `{0}`")]
+    UndefinedFunction(String),
+
+    #[error("undefined variable `{0}`")]
+    UndefinedVariable(String),
+
+    #[error("memory access out of range: {0}")]
+    MemoryAccess(i32),
+
+    #[error("deallocating {0}, which is not allocated")]
+    InvalidDealloc(i16),
+
+    #[error("the keyboard is read after the end of the input")]
+    InputExhausted,
+
+    #[error("too deep recursion, over {MAX_DEPTH} calls")]
+    StackOverflow,
+
+    #[error("did not return within {0} steps")]
+    StepLimit(usize),
+
+    /// code the compiler rejects, e.g. a wrong number of arguments
+    #[error("invalid program: {0}")]
+    InvalidProgram(String),
+}
+
+// the messages of the error codes of the Jack OS
+fn os_error(code: i16) -> &'static str {
+    match code {
+        1 => "duration must be positive",
+        2 => "array size must be positive",
+        3 => "division by zero",
+        4 => "cannot compute square root of a negative number",
+        5 => "allocated memory size must be positive",
+        6 => "heap overflow",
+        7 => "illegal pixel coordinates",
+        8 => "illegal line coordinates",
+        9 => "illegal rectangle coordinates",
+        12 => "illegal center coordinates",
+        13 => "illegal radius",
+        14 => "maximum length must be non-negative",
+        15 | 16 => "string index out of bounds",
+        17 => "string is full",
+        18 => "string is empty",
+        19 => "insufficient string capacity",
+        20 => "illegal cursor location",
+        _ => "error of the program",
+    }
+}
@@ -0,0 +1,766 @@
+//! Evaluation of statements and expressions, in the order the compiler generates their code, so
+//! that side effects of calls happen as on the Hack platform.
+use std::collections::VecDeque;
+
+use jack_ast::{visit::*, *};
+use jack_compiler::{
+    constant,
+    symbol::{FnCtxt, SymbolTable},
+};
+use rustc_hash::FxHashMap;
+
+use crate::{memory::Memory, RuntimeError};
+
+/// Statements and calls executed before giving up with `RuntimeError::StepLimit` by default.
+pub const MAX_STEPS: usize = 10_000_000;
+/// Depth of calls, about as many frames as fit in the stack of the Hack platform.
+pub const MAX_DEPTH: usize = 400;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub max_steps: usize,
+    /// keys read by `Keyboard`, where `\n` is the newline key
+    pub input: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            max_steps: MAX_STEPS,
+            input: String::new(),
+        }
+    }
+}
+
+/// How a call ended without errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Returned(i16),
+    /// `Sys.halt` was called
+    Halted,
+}
+
+// what stops the program midway through a call
+pub(crate) enum Interrupt {
+    Halt,
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Interrupt {
+    fn from(e: RuntimeError) -> Self {
+        Interrupt::Error(e)
+    }
+}
+
+pub(crate) type Result<T> = std::result::Result<T, Interrupt>;
+
+// where the control goes after a statement
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(i16),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Slot {
+    Arg(usize),
+    Local(usize),
+    Field(usize),
+    Static(usize),
+}
+
+struct Function<'a, 's> {
+    def: &'a FnDef<'s>,
+    // variables in scope, with their types to dispatch methods
+    vars: FxHashMap<Ident<'s>, (Slot, Type<'s>)>,
+    n_args: usize,
+}
+
+struct ClassInfo<'a, 's> {
+    n_fields: usize,
+    functions: FxHashMap<Ident<'s>, Function<'a, 's>>,
+    // constants referred to in the class, by `(scope, name)` as `SymbolTable::lookup_const`
+    consts: FxHashMap<(Option<Ident<'s>>, Ident<'s>), i16>,
+}
+
+// the immutable part of a program
+pub(crate) struct Program<'a, 's> {
+    classes: FxHashMap<Ident<'s>, ClassInfo<'a, 's>>,
+    // kinds of the OS functions
+    os: FxHashMap<(&'static str, &'static str), FnKind>,
+}
+
+pub(crate) struct State {
+    pub memory: Memory,
+    statics: Vec<i16>,
+    pub output: String,
+    pub input: VecDeque<u16>,
+    // color of `Screen`, black if true
+    pub color: bool,
+    steps: usize,
+    max_steps: usize,
+    depth: usize,
+}
+
+// a call being executed
+struct Frame<'p, 'a, 's> {
+    class: Ident<'s>,
+    f: &'p Function<'a, 's>,
+    this: i16,
+    args: Vec<i16>,
+    locals: Vec<i16>,
+}
+
+pub(crate) struct Exec<'p, 'a, 's> {
+    program: &'p Program<'a, 's>,
+    pub state: &'p mut State,
+}
+
+/// Interpreter of a program, whose state such as the heap and the captured output persists
+/// across calls.
+pub struct Interpreter<'a, 's> {
+    program: Program<'a, 's>,
+    state: State,
+}
+
+impl<'a, 's> Interpreter<'a, 's> {
+    /// Loads `classes`, which are lowered by `jack_compiler::lower` as `jack_interp::parse` does.
+    pub fn new(
+        classes: &'a [Class<'s>],
+        options: Options,
+    ) -> std::result::Result<Self, RuntimeError> {
+        let mut table = SymbolTable::new();
+        let refs: Vec<_> = classes.iter().collect();
+        constant::register(&refs, &mut table)
+            .map_err(|e| RuntimeError::InvalidProgram(e.to_string()))?;
+
+        let mut n_statics = 0;
+        let mut infos = FxHashMap::default();
+        for class in classes {
+            table.sess(class.name);
+            let mut consts = ConstRefs {
+                table: &table,
+                values: FxHashMap::default(),
+            };
+            consts.visit_class(class);
+            let consts = consts.values;
+
+            let mut members = FxHashMap::default();
+            let mut n_fields = 0;
+            for var in class.variables.iter() {
+                let slot = match var.kind {
+                    VariableDefKind::Field => {
+                        n_fields += 1;
+                        Slot::Field(n_fields - 1)
+                    }
+                    _ => {
+                        n_statics += 1;
+                        Slot::Static(n_statics - 1)
+                    }
+                };
+                members.insert(var.name, (slot, var.ty));
+            }
+
+            let functions = class
+                .functions
+                .iter()
+                .map(|def| (def.name, Function::new(class, def, &members)))
+                .collect();
+            let info = ClassInfo {
+                n_fields,
+                functions,
+                consts,
+            };
+            if infos.insert(class.name, info).is_some() {
+                let msg = format!("class `{}` is defined twice", class.name);
+                return Err(RuntimeError::InvalidProgram(msg));
+            }
+        }
+
+        let os = SymbolTable::new()
+            .functions()
+            .map(
+                |&FnCtxt {
+                     class, name, kind, ..
+                 }| ((class, name), kind),
+            )
+            .collect();
+        let input = options
+            .input
+            .chars()
+            .map(|c| match c {
+                '\n' => charset::NEWLINE,
+                c => c as u16,
+            })
+            .collect();
+        Ok(Interpreter {
+            program: Program { classes: infos, os },
+            state: State {
+                memory: Memory::new(),
+                statics: vec![0; n_statics],
+                output: String::new(),
+                input,
+                color: true,
+                steps: 0,
+                max_steps: options.max_steps,
+                depth: 0,
+            },
+        })
+    }
+
+    /// Calls the function `Class.name` with `args`, which start with `this` for a method. The
+    /// program starts with `Sys.init`, which runs `Main.main` after initializing the OS.
+    pub fn call(&mut self, name: &str, args: &[i16]) -> std::result::Result<Exit, RuntimeError> {
+        let Some((class, fn_name)) = name.split_once('.') else {
+            return Err(RuntimeError::UndefinedFunction(name.to_string()));
+        };
+        let mut exec = Exec {
+            program: &self.program,
+            state: &mut self.state,
+        };
+        match exec.invoke(class, fn_name, args.to_vec()) {
+            Ok(value) => Ok(Exit::Returned(value)),
+            Err(Interrupt::Halt) => Ok(Exit::Halted),
+            Err(Interrupt::Error(e)) => Err(e),
+        }
+    }
+
+    /// Text printed by `Output` so far.
+    pub fn output(&self) -> &str {
+        &self.state.output
+    }
+
+    /// The RAM, including the heap and the screen.
+    pub fn memory(&self) -> &[i16] {
+        self.state.memory.ram()
+    }
+}
+
+impl<'a, 's> Function<'a, 's> {
+    fn new(
+        class: &Class<'s>,
+        def: &'a FnDef<'s>,
+        members: &FxHashMap<Ident<'s>, (Slot, Type<'s>)>,
+    ) -> Self {
+        // fields are only accessible through `this`
+        let mut vars: FxHashMap<_, _> = members
+            .iter()
+            .filter(|(_, (slot, _))| {
+                def.kind != FnKind::Function || matches!(slot, Slot::Static(_))
+            })
+            .map(|(name, var)| (*name, *var))
+            .collect();
+
+        let this = usize::from(def.kind == FnKind::Method);
+        if this == 1 {
+            vars.insert("this", (Slot::Arg(0), Type::Class(class.name)));
+        }
+        for (i, param) in def.params.iter().enumerate() {
+            vars.insert(param.name, (Slot::Arg(this + i), param.ty));
+        }
+        for (i, var) in def.body.variables.iter().enumerate() {
+            vars.insert(var.name, (Slot::Local(i), var.ty));
+        }
+        Function {
+            def,
+            vars,
+            n_args: this + def.params.len(),
+        }
+    }
+}
+
+// collects the values of the constants referred to in a class
+struct ConstRefs<'t, 'ctx, 's> {
+    table: &'t SymbolTable<'ctx, 's>,
+    values: FxHashMap<(Option<Ident<'s>>, Ident<'s>), i16>,
+}
+
+impl<'s> Visitor<'s> for ConstRefs<'_, '_, 's> {
+    fn visit_term(&mut self, term: &Term<'s>) {
+        let key = match term.kind {
+            TermKind::Qualified { scope, name } => (Some(scope), name),
+            TermKind::Variable(ref var) => (None, var.name),
+            _ => return walk_term(self, term),
+        };
+        if let Some(ctxt) = self.table.lookup_const(key.0, key.1) {
+            self.values.insert(key, ctxt.value);
+        }
+        walk_term(self, term);
+    }
+}
+
+impl<'p, 'a, 's> Exec<'p, 'a, 's> {
+    /// Calls `class.name`, which is defined by the program or else by the OS.
+    pub(crate) fn invoke(&mut self, class: &str, name: &str, args: Vec<i16>) -> Result<i16> {
+        self.step()?;
+        if self.state.depth >= MAX_DEPTH {
+            return Err(RuntimeError::StackOverflow.into());
+        }
+
+        self.state.depth += 1;
+        let program = self.program;
+        let result = match program.classes.get_key_value(class) {
+            Some((&class, info)) if info.functions.contains_key(name) => {
+                self.run(class, &info.functions[name], args)
+            }
+            _ => self.os(class, name, &args),
+        };
+        self.state.depth -= 1;
+        result
+    }
+
+    /// Whether `class.name` is a function of the OS API.
+    pub(crate) fn is_os(&self, class: &str, name: &str) -> bool {
+        self.program.os.contains_key(&(class, name))
+    }
+
+    fn run(&mut self, class: Ident<'s>, f: &'p Function<'a, 's>, args: Vec<i16>) -> Result<i16> {
+        if args.len() != f.n_args {
+            let msg = format!(
+                "`{class}.{}` takes {} arguments but {} were given",
+                f.def.name,
+                f.n_args,
+                args.len()
+            );
+            return Err(RuntimeError::InvalidProgram(msg).into());
+        }
+
+        let this = match f.def.kind {
+            FnKind::Ctor => {
+                let size = self.program.classes[class].n_fields as i16;
+                self.invoke("Memory", "alloc", vec![size])?
+            }
+            FnKind::Method => args[0],
+            FnKind::Function => 0,
+        };
+        let mut frame = Frame {
+            class,
+            f,
+            this,
+            args,
+            locals: vec![0; f.def.body.variables.len()],
+        };
+        match self.block(&mut frame, &f.def.body.statements)? {
+            Flow::Return(value) => Ok(value),
+            // the compiler rejects a function without `return` at the end
+            _ => Ok(0),
+        }
+    }
+
+    fn step(&mut self) -> Result<()> {
+        self.state.steps += 1;
+        if self.state.steps > self.state.max_steps {
+            return Err(RuntimeError::StepLimit(self.state.max_steps).into());
+        }
+        Ok(())
+    }
+
+    fn block(&mut self, frame: &mut Frame<'p, 'a, 's>, stmts: &'a [Stmt<'s>]) -> Result<Flow> {
+        for stmt in stmts {
+            match self.statement(frame, stmt)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, frame: &mut Frame<'p, 'a, 's>, stmt: &'a Stmt<'s>) -> Result<Flow> {
+        self.step()?;
+        match &stmt.kind {
+            StmtKind::Let { lhs, rhs } => {
+                let value = self.expr(frame, rhs)?;
+                match &lhs.index_accessor {
+                    Some(index) => {
+                        let index = self.expr(frame, index)?;
+                        let base = self.read(frame, lhs.name)?;
+                        self.state.memory.write(base.wrapping_add(index), value)?;
+                    }
+                    None => self.write(frame, lhs.name, value)?,
+                }
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let stmts = match self.expr(frame, cond)? {
+                    0 => else_branch.as_ref().map_or(&[][..], Else::stmts),
+                    _ => then_branch,
+                };
+                return self.block(frame, stmts);
+            }
+            StmtKind::While { cond, body } => {
+                while self.expr(frame, cond)? != 0 {
+                    // an empty loop takes steps too
+                    self.step()?;
+                    match self.block(frame, body)? {
+                        Flow::Next | Flow::Continue => {}
+                        Flow::Break => break,
+                        flow @ Flow::Return(_) => return Ok(flow),
+                    }
+                }
+            }
+            StmtKind::Do(call) => {
+                self.fn_call(frame, call)?;
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(expr) => self.expr(frame, expr)?,
+                    None => 0,
+                };
+                return Ok(Flow::Return(value));
+            }
+            StmtKind::Break => return Ok(Flow::Break),
+            StmtKind::Continue => return Ok(Flow::Continue),
+            StmtKind::CompoundLet { .. } | StmtKind::For { .. } => {
+                let msg = format!("`{stmt}` is not lowered");
+                return Err(RuntimeError::InvalidProgram(msg).into());
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn expr(&mut self, frame: &mut Frame<'p, 'a, 's>, expr: &'a Expr<'s>) -> Result<i16> {
+        let lhs = self.term(frame, &expr.lhs)?;
+        let Some(box (op, rhs)) = &expr.rhs else {
+            return Ok(lhs);
+        };
+        let rhs = self.term(frame, rhs)?;
+        match op {
+            BinOp::Mul => self.invoke("Math", "multiply", vec![lhs, rhs]),
+            BinOp::Div => self.invoke("Math", "divide", vec![lhs, rhs]),
+            op => Ok(constant::binary(op, lhs, rhs).unwrap_or_default()),
+        }
+    }
+
+    fn term(&mut self, frame: &mut Frame<'p, 'a, 's>, term: &'a Term<'s>) -> Result<i16> {
+        match &term.kind {
+            TermKind::Const(c) => match c {
+                Constant::Integer(_) | Constant::Radix(..) | Constant::Char(_) => {
+                    let n = c.int_value().unwrap_or_default();
+                    if n > Constant::MAX_INTEGER {
+                        let msg = format!("integer {n} is out of range");
+                        return Err(RuntimeError::InvalidProgram(msg).into());
+                    }
+                    Ok(n as i16)
+                }
+                Constant::Str(s) => {
+                    let Ok(codes) = charset::decode(s) else {
+                        let msg = format!("invalid string literal \"{s}\"");
+                        return Err(RuntimeError::InvalidProgram(msg).into());
+                    };
+                    let mut string = self.invoke("String", "new", vec![codes.len() as i16])?;
+                    for c in codes {
+                        string = self.invoke("String", "appendChar", vec![string, c as i16])?;
+                    }
+                    Ok(string)
+                }
+                Constant::True => Ok(-1),
+                Constant::False | Constant::Null => Ok(0),
+                Constant::This => Ok(frame.this),
+            },
+            TermKind::Variable(var) => match &var.index_accessor {
+                Some(index) => {
+                    let index = self.expr(frame, index)?;
+                    let base = self.read(frame, var.name)?;
+                    Ok(self.state.memory.read(base.wrapping_add(index))?)
+                }
+                None => self.read(frame, var.name),
+            },
+            TermKind::FnCall(call) => self.fn_call(frame, call),
+            TermKind::Expr(expr) => self.expr(frame, expr),
+            TermKind::Unary { op, term } => {
+                let value = self.term(frame, term)?;
+                Ok(constant::unary(op, value))
+            }
+            TermKind::Qualified { scope, name } => {
+                let consts = &self.program.classes[frame.class].consts;
+                match consts.get(&(Some(*scope), *name)) {
+                    Some(value) => Ok(*value),
+                    None => Err(RuntimeError::UndefinedVariable(term.to_string()).into()),
+                }
+            }
+        }
+    }
+
+    fn fn_call(&mut self, frame: &mut Frame<'p, 'a, 's>, call: &'a FnCall<'s>) -> Result<i16> {
+        // a receiver is either an object or a class as `SymbolTable::lookup_fn` resolves it
+        let (class, instance) = match call.receiver {
+            Some(receiver) => match frame.f.vars.get(receiver) {
+                Some(&(_, Type::Class(class))) => (class, Some(self.read(frame, receiver)?)),
+                _ => (receiver, None),
+            },
+            None => (frame.class, None),
+        };
+        let kind = match self.program.classes.get(class) {
+            Some(info) if info.functions.contains_key(call.fn_name) => {
+                Some(info.functions[call.fn_name].def.kind)
+            }
+            _ => self.program.os.get(&(class, call.fn_name)).copied(),
+        };
+        let Some(kind) = kind else {
+            return Err(RuntimeError::UndefinedFunction(format!("{class}.{}", call.fn_name)).into());
+        };
+
+        let this = match (call.receiver, kind) {
+            (Some(_), FnKind::Method) => match instance {
+                Some(this) => Some(this),
+                None => {
+                    let msg = format!("method `{call}` is called without an instance");
+                    return Err(RuntimeError::InvalidProgram(msg).into());
+                }
+            },
+            (Some(_), _) if instance.is_some() => {
+                let msg = format!("`{call}` is called through an instance");
+                return Err(RuntimeError::InvalidProgram(msg).into());
+            }
+            (None, FnKind::Method) => Some(frame.this),
+            _ => None,
+        };
+
+        let mut args = Vec::with_capacity(call.args.len() + 1);
+        args.extend(this);
+        for arg in call.args.iter() {
+            args.push(self.expr(frame, arg)?);
+        }
+        self.invoke(class, call.fn_name, args)
+    }
+
+    fn slot(&self, frame: &Frame<'p, 'a, 's>, name: Ident<'s>) -> Result<Slot> {
+        match frame.f.vars.get(name) {
+            Some((slot, _)) => Ok(*slot),
+            None => Err(RuntimeError::UndefinedVariable(name.to_string()).into()),
+        }
+    }
+
+    fn read(&mut self, frame: &Frame<'p, 'a, 's>, name: Ident<'s>) -> Result<i16> {
+        if !frame.f.vars.contains_key(name) {
+            let consts = &self.program.classes[frame.class].consts;
+            if let Some(value) = consts.get(&(None, name)) {
+                return Ok(*value);
+            }
+        }
+        Ok(match self.slot(frame, name)? {
+            Slot::Arg(i) => frame.args[i],
+            Slot::Local(i) => frame.locals[i],
+            Slot::Field(i) => self.state.memory.read(frame.this.wrapping_add(i as i16))?,
+            Slot::Static(i) => self.state.statics[i],
+        })
+    }
+
+    fn write(&mut self, frame: &mut Frame<'p, 'a, 's>, name: Ident<'s>, value: i16) -> Result<()> {
+        match self.slot(frame, name)? {
+            Slot::Arg(i) => frame.args[i] = value,
+            Slot::Local(i) => frame.locals[i] = value,
+            Slot::Field(i) => {
+                let addr = frame.this.wrapping_add(i as i16);
+                self.state.memory.write(addr, value)?;
+            }
+            Slot::Static(i) => self.state.statics[i] = value,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use jack_compiler::parser::Dialect;
+
+    use super::*;
+
+    // runs `Sys.init` of the classes of `src`, returning how it ended and the output
+    pub(crate) fn run(
+        src: &[&str],
+        input: &str,
+    ) -> (std::result::Result<Exit, RuntimeError>, String) {
+        let mut sources = SourceMap::new();
+        for (i, src) in src.iter().enumerate() {
+            sources.add_file(format!("{i}.jack"), src.to_string());
+        }
+        let classes = crate::parse(&sources, Dialect::Extended).unwrap();
+        let options = Options {
+            max_steps: 100_000,
+            input: input.to_string(),
+        };
+        let mut interp = Interpreter::new(&classes, options).unwrap();
+        let exit = interp.call("Sys.init", &[]);
+        (exit, interp.output().to_string())
+    }
+
+    #[test]
+    fn wrapping_arithmetic_and_comparison() {
+        let src = "\
+class Main {
+    function void main() {
+        var int x;
+        let x = 32767 + 1;
+        do Output.printInt(x);
+        do Output.printInt(-x - 1);
+        do Output.printInt(200 * 200);
+        do Output.printInt(-7 / 2);
+        do Output.printInt((x < 0) & (~(x > 0)));
+        return;
+    }
+}
+";
+        let (exit, output) = run(&[src], "");
+        assert_eq!(exit, Ok(Exit::Halted));
+        assert_eq!(output, "-3276832767-25536-3-1");
+    }
+
+    #[test]
+    fn objects_fields_and_methods() {
+        let point = "\
+class Point {
+    field int x, y;
+    static int count;
+
+    constructor Point new(int ax, int ay) {
+        let x = ax;
+        let y = ay;
+        let count = count + 1;
+        return this;
+    }
+
+    method int sum() { return x + y; }
+    method Point plus(Point other) { return Point.new(x + other.x(), y + other.y()); }
+    method int x() { return x; }
+    method int y() { return y; }
+    function int count() { return count; }
+}
+";
+        let main = "\
+class Main {
+    function void main() {
+        var Point p;
+        let p = Point.new(1, 2);
+        let p = p.plus(Point.new(10, 20));
+        do Output.printInt(p.sum());
+        do Output.println();
+        do Output.printInt(Point.count());
+        return;
+    }
+}
+";
+        let (exit, output) = run(&[point, main], "");
+        assert_eq!(exit, Ok(Exit::Halted));
+        assert_eq!(output, "33\n3");
+    }
+
+    #[test]
+    fn arrays_loops_and_extensions() {
+        let src = "\
+class Main {
+    enum Color { RED, GREEN = 5, BLUE }
+    const int N = Color.BLUE - 1;
+
+    function void main() {
+        var Array a;
+        var int i, sum;
+        let a = Array.new(N);
+        for (let i = 0; i < N; let i += 1) {
+            if (i = 2) {
+                continue;
+            }
+            let a[i] = i * i;
+        }
+        let i = 0;
+        while (true) {
+            if (i = N) {
+                break;
+            } else if (~(a[i] = 0)) {
+                let sum += a[i];
+            }
+            let i = i + 1;
+        }
+        do a.dispose();
+        do Output.printInt(sum);
+        return;
+    }
+}
+";
+        let (exit, output) = run(&[src], "");
+        assert_eq!(exit, Ok(Exit::Halted));
+        // 1 + 9 + 16
+        assert_eq!(output, "26");
+    }
+
+    #[test]
+    fn call_any_function() {
+        let src = "\
+class Main {
+    function int fib(int n) {
+        if (n < 2) {
+            return n;
+        }
+        return Main.fib(n - 1) + Main.fib(n - 2);
+    }
+    function void main() { return; }
+}
+";
+        let mut sources = SourceMap::new();
+        sources.add_file("Main.jack", src.to_string());
+        let classes = crate::parse(&sources, Dialect::Standard).unwrap();
+        let mut interp = Interpreter::new(&classes, Options::default()).unwrap();
+        assert_eq!(interp.call("Main.fib", &[20]), Ok(Exit::Returned(6765)));
+        assert_eq!(
+            interp.call("Main.fib", &[]),
+            Err(RuntimeError::InvalidProgram(
+                "`Main.fib` takes 1 arguments but 0 were given".to_string()
+            ))
+        );
+        assert_eq!(
+            interp.call("Main.nothing", &[]),
+            Err(RuntimeError::UndefinedFunction("Main.nothing".to_string()))
+        );
+    }
+
+    #[test]
+    fn user_classes_override_the_os() {
+        let math = "\
+class Math {
+    function int multiply(int x, int y) { return 42; }
+}
+";
+        let main = "\
+class Main {
+    function void main() {
+        do Output.printInt(3 * 3);
+        do Output.printInt(Math.max(3, 4));
+        return;
+    }
+}
+";
+        // `Math.max` is not defined by the program, but by the OS
+        let (exit, output) = run(&[math, main], "");
+        assert_eq!(exit, Ok(Exit::Halted));
+        assert_eq!(output, "424");
+    }
+
+    #[test]
+    fn stop_runaway_programs() {
+        let loops = "\
+class Main {
+    function void main() {
+        while (true) {}
+        return;
+    }
+}
+";
+        let (exit, _) = run(&[loops], "");
+        assert_eq!(exit, Err(RuntimeError::StepLimit(100_000)));
+
+        let recursion = "\
+class Main {
+    function void main() {
+        do Main.main();
+        return;
+    }
+}
+";
+        let (exit, _) = run(&[recursion], "");
+        assert_eq!(exit, Err(RuntimeError::StackOverflow));
+    }
+}
@@ -0,0 +1,39 @@
+//! Tree-walking interpreter of Jack, which runs a program without compiling it, e.g. to test the
+//! logic of a game in milliseconds.
+//!
+//! - values are 16-bit words with the wrapping arithmetic of the Hack machine
+//! - objects and arrays are allocated in the heap of a RAM laid out as the Hack platform, so that
+//!   `Memory.peek`, `Memory.poke` and the screen work as on it
+//! - the OS is implemented natively, except the classes which the program defines by itself
+#![forbid(unsafe_code)]
+#![feature(box_patterns)]
+
+mod error;
+mod interp;
+mod memory;
+mod os;
+
+use jack_ast::{Class, SourceMap};
+use jack_compiler::{lower, parser, JackError};
+
+pub use crate::{
+    error::RuntimeError,
+    interp::{Exit, Interpreter, Options, MAX_DEPTH, MAX_STEPS},
+    memory::{HEAP, KEYBOARD, RAM_SIZE, SCREEN},
+};
+
+/// Parses every file of `sources` and lowers the classes to be interpreted, stopping at the first
+/// error.
+pub fn parse(
+    sources: &SourceMap,
+    dialect: parser::Dialect,
+) -> Result<Vec<Class<'_>>, JackError<'_>> {
+    sources
+        .files()
+        .map(|file| {
+            let mut class = parser::parse_file(file, dialect)?;
+            lower::lower(&mut class);
+            Ok(class)
+        })
+        .collect()
+}
@@ -0,0 +1,102 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use anyhow::{bail, Result};
+use jack_ast::SourceMap;
+use jack_compiler::{compiler, diagnosis::DiagnosticReporter};
+use jack_interp::{Exit, Interpreter, Options};
+
+fn help() -> ! {
+    println!(
+        "\
+Usage: cargo run -p jack-interp -- [options] <jack file or directory>
+
+Runs `Sys.init` of the program, which calls `Main.main`, and prints the output.
+
+Options:
+    --input <text>      keys read by `Keyboard`, where `\\n` is the newline key
+    --max-steps <n>     give up after executing n statements and calls
+    --dialect <standard|extended>
+                        accept the language extensions"
+    );
+    exit(0);
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if path.is_dir() {
+        for entry in path.read_dir()?.filter_map(|p| p.map(|p| p.path()).ok()) {
+            if entry.is_dir() || entry.extension().map_or(false, |ext| ext == "jack") {
+                collect_files(&entry, files)?;
+            }
+        }
+    } else {
+        files.push(path.into());
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let mut compiler_options = compiler::Options::default();
+    let mut options = Options::default();
+    let mut files = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => help(),
+            "--input" => {
+                let Some(input) = args.next() else { bail!("--input requires an argument") };
+                options.input = input.replace("\\n", "\n");
+            }
+            "--max-steps" => {
+                let Some(n) = args.next() else { bail!("--max-steps requires an argument") };
+                options.max_steps = n.parse()?;
+            }
+            "--dialect" => {
+                let Some(dialect) = args.next() else { bail!("--dialect requires an argument") };
+                compiler_options.dialect = dialect.parse().map_err(anyhow::Error::msg)?;
+            }
+            _ => collect_files(Path::new(&arg), &mut files)?,
+        }
+    }
+    if files.is_empty() {
+        help();
+    }
+    files.sort();
+
+    let mut sources = SourceMap::new();
+    for path in files {
+        let src = fs::read_to_string(&path)?;
+        sources.add_file(path, src);
+    }
+
+    // the interpreter trusts the program to be what the compiler accepts
+    let reporter = DiagnosticReporter::new(&sources);
+    let has_error = compiler::check(&sources, &reporter, &compiler_options);
+    reporter.finish();
+    if has_error {
+        exit(1);
+    }
+    let classes = match jack_interp::parse(&sources, compiler_options.dialect) {
+        Ok(classes) => classes,
+        Err(e) => {
+            reporter.report(&e);
+            exit(1);
+        }
+    };
+
+    let mut interp = Interpreter::new(&classes, options)?;
+    let result = interp.call("Sys.init", &[]);
+    print!("{}", interp.output());
+    match result {
+        Ok(Exit::Halted | Exit::Returned(_)) => Ok(()),
+        Err(e) => {
+            eprintln!("\nerror: {e}");
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+//! RAM of the Hack platform, with the heap managed by `Memory.alloc` and `Memory.deAlloc`.
+use std::ops::Range;
+
+use rustc_hash::FxHashMap;
+
+use crate::RuntimeError;
+
+/// Words of the RAM, up to the keyboard.
+pub const RAM_SIZE: usize = KEYBOARD + 1;
+/// Addresses of the heap, as the Jack OS lays it out.
+pub const HEAP: Range<usize> = 2048..16384;
+/// Base address of the screen, where each row of 512 pixels is 32 words.
+pub const SCREEN: usize = 16384;
+/// Address of the key being pressed.
+pub const KEYBOARD: usize = 24576;
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+pub struct Memory {
+    ram: Vec<i16>,
+    // free blocks of the heap in the order of their addresses, which are never adjacent
+    free: Vec<Range<usize>>,
+    // sizes of the allocated blocks by their addresses
+    allocated: FxHashMap<usize, usize>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory {
+            ram: vec![0; RAM_SIZE],
+            free: vec![HEAP],
+            allocated: FxHashMap::default(),
+        }
+    }
+
+    pub fn ram(&self) -> &[i16] {
+        &self.ram
+    }
+
+    pub fn read(&self, addr: i16) -> Result<i16> {
+        Ok(self.ram[index(addr)?])
+    }
+
+    pub fn write(&mut self, addr: i16, value: i16) -> Result<()> {
+        self.ram[index(addr)?] = value;
+        Ok(())
+    }
+
+    /// Allocates `size` words at the lowest address possible.
+    pub fn alloc(&mut self, size: i16) -> Result<i16> {
+        if size <= 0 {
+            return Err(RuntimeError::Sys(5));
+        }
+        let size = size as usize;
+        let Some(i) = self.free.iter().position(|block| block.len() >= size) else {
+            return Err(RuntimeError::Sys(6));
+        };
+
+        let base = self.free[i].start;
+        self.free[i].start += size;
+        if self.free[i].is_empty() {
+            self.free.remove(i);
+        }
+        self.allocated.insert(base, size);
+        Ok(base as i16)
+    }
+
+    pub fn dealloc(&mut self, addr: i16) -> Result<()> {
+        let Some(size) = usize::try_from(addr)
+            .ok()
+            .and_then(|base| self.allocated.remove(&base))
+        else {
+            return Err(RuntimeError::InvalidDealloc(addr));
+        };
+
+        let block = addr as usize..addr as usize + size;
+        let i = self.free.partition_point(|free| free.start < block.start);
+        self.free.insert(i, block);
+        // merges the block with the next one, then with the previous one
+        for i in [i, i.saturating_sub(1)] {
+            if i + 1 < self.free.len() && self.free[i].end == self.free[i + 1].start {
+                self.free[i].end = self.free.remove(i + 1).end;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn index(addr: i16) -> Result<usize> {
+    usize::try_from(addr)
+        .ok()
+        .filter(|addr| *addr < RAM_SIZE)
+        .ok_or(RuntimeError::MemoryAccess(addr.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse_freed_blocks() {
+        let mut memory = Memory::new();
+        let a = memory.alloc(3).unwrap();
+        let b = memory.alloc(2).unwrap();
+        let c = memory.alloc(1).unwrap();
+        assert_eq!([a, b, c], [2048, 2051, 2053]);
+
+        memory.dealloc(a).unwrap();
+        memory.dealloc(b).unwrap();
+        // `a` and `b` are merged
+        assert_eq!(memory.alloc(4), Ok(2048));
+        assert_eq!(memory.alloc(2), Ok(2054));
+        assert_eq!(memory.alloc(1), Ok(2052));
+
+        assert_eq!(memory.dealloc(b), Err(RuntimeError::InvalidDealloc(b)));
+        assert_eq!(memory.alloc(0), Err(RuntimeError::Sys(5)));
+        assert_eq!(memory.alloc(16384), Err(RuntimeError::Sys(6)));
+        assert_eq!(memory.read(-1), Err(RuntimeError::MemoryAccess(-1)));
+    }
+}
@@ -0,0 +1,451 @@
+//! The Jack OS implemented natively, with the error codes of the original one.
+//!
+//! `Output` captures text instead of drawing characters on the screen: `\n` for a newline, and
+//! a backspace erases the last character of the line. Characters out of the Hack character set
+//! are captured as `U+FFFD`. `Keyboard` reads the keys of `Options::input` in order, and
+//! `Keyboard.keyPressed` takes the next key too, or `0` once the input is exhausted.
+use jack_ast::charset::NEWLINE;
+
+use crate::{
+    interp::{Exec, Interrupt, Result},
+    memory::{KEYBOARD, SCREEN},
+    RuntimeError,
+};
+
+const BACKSPACE: u16 = 129;
+const DOUBLE_QUOTE: i16 = 34;
+const WIDTH: i16 = 512;
+const HEIGHT: i16 = 256;
+
+impl Exec<'_, '_, '_> {
+    /// Calls the OS function `class.name`.
+    pub(crate) fn os(&mut self, class: &str, name: &str, args: &[i16]) -> Result<i16> {
+        match (class, name, args) {
+            (_, "init", []) if class != "Sys" && self.is_os(class, name) => Ok(0),
+
+            ("Math", "abs", &[x]) => Ok(x.wrapping_abs()),
+            ("Math", "multiply", &[x, y]) => Ok(x.wrapping_mul(y)),
+            ("Math", "divide", &[_, 0]) => sys_error(3),
+            ("Math", "divide", &[x, y]) => Ok(x.wrapping_div(y)),
+            ("Math", "min", &[x, y]) => Ok(x.min(y)),
+            ("Math", "max", &[x, y]) => Ok(x.max(y)),
+            ("Math", "sqrt", &[x]) if x < 0 => sys_error(4),
+            ("Math", "sqrt", &[x]) => Ok(f64::from(x).sqrt() as i16),
+
+            ("Memory", "peek", &[addr]) => Ok(self.state.memory.read(addr)?),
+            ("Memory", "poke", &[addr, value]) => {
+                self.unit(|exec| exec.state.memory.write(addr, value))
+            }
+            ("Memory", "alloc", &[size]) => Ok(self.state.memory.alloc(size)?),
+            ("Memory", "deAlloc", &[addr]) => self.unit(|exec| exec.state.memory.dealloc(addr)),
+
+            ("Array", "new", &[size]) if size <= 0 => sys_error(2),
+            ("Array", "new", &[size]) => self.invoke("Memory", "alloc", vec![size]),
+            ("Array", "dispose", &[this]) => self.invoke("Memory", "deAlloc", vec![this]),
+
+            ("String", ..) => self.string(name, args),
+
+            ("Output", "moveCursor", &[i, j]) if !(0..23).contains(&i) || !(0..64).contains(&j) => {
+                sys_error(20)
+            }
+            ("Output", "moveCursor", &[_, _]) => Ok(0),
+            ("Output", "printChar", &[c]) => self.print(&[c as u16]),
+            ("Output", "printString", &[s]) => {
+                let chars = self.chars(s)?;
+                self.print(&chars)
+            }
+            ("Output", "printInt", &[n]) => {
+                let digits: Vec<_> = n.to_string().bytes().map(u16::from).collect();
+                self.print(&digits)
+            }
+            ("Output", "println", []) => self.print(&[NEWLINE]),
+            ("Output", "backSpace", []) => self.print(&[BACKSPACE]),
+
+            ("Screen", "clearScreen", []) => {
+                for addr in SCREEN..KEYBOARD {
+                    self.state.memory.write(addr as i16, 0)?;
+                }
+                Ok(0)
+            }
+            ("Screen", "setColor", &[color]) => {
+                self.state.color = color != 0;
+                Ok(0)
+            }
+            ("Screen", "drawPixel", &[x, y]) if !on_screen(x, y) => sys_error(7),
+            ("Screen", "drawPixel", &[x, y]) => self.pixel(x, y),
+            ("Screen", "drawLine", &[x1, y1, x2, y2])
+                if !on_screen(x1, y1) || !on_screen(x2, y2) =>
+            {
+                sys_error(8)
+            }
+            ("Screen", "drawLine", &[x1, y1, x2, y2]) => self.line(x1, y1, x2, y2),
+            ("Screen", "drawRectangle", &[x1, y1, x2, y2])
+                if x1 > x2 || y1 > y2 || !on_screen(x1, y1) || !on_screen(x2, y2) =>
+            {
+                sys_error(9)
+            }
+            ("Screen", "drawRectangle", &[x1, y1, x2, y2]) => {
+                for y in y1..=y2 {
+                    self.line(x1, y, x2, y)?;
+                }
+                Ok(0)
+            }
+            ("Screen", "drawCircle", &[x, y, _]) if !on_screen(x, y) => sys_error(12),
+            ("Screen", "drawCircle", &[_, _, r]) if !(0..=181).contains(&r) => sys_error(13),
+            ("Screen", "drawCircle", &[x, y, r]) => self.circle(x, y, r),
+
+            ("Keyboard", "keyPressed", []) => {
+                let key = self.state.input.pop_front().unwrap_or(0);
+                self.state.memory.write(KEYBOARD as i16, key as i16)?;
+                Ok(key as i16)
+            }
+            ("Keyboard", "readChar", []) => {
+                let c = self.key()?;
+                self.print(&[c])?;
+                Ok(c as i16)
+            }
+            ("Keyboard", "readLine", &[message]) => self.read_line(message),
+            ("Keyboard", "readInt", &[message]) => {
+                let line = self.read_line(message)?;
+                let n = self.string("intValue", &[line])?;
+                self.invoke("String", "dispose", vec![line])?;
+                Ok(n)
+            }
+
+            ("Sys", "init", []) => {
+                for class in ["Memory", "Math", "Screen", "Output", "Keyboard"] {
+                    self.invoke(class, "init", Vec::new())?;
+                }
+                self.invoke("Main", "main", Vec::new())?;
+                self.invoke("Sys", "halt", Vec::new())
+            }
+            ("Sys", "halt", []) => Err(Interrupt::Halt),
+            ("Sys", "error", &[code]) => sys_error(code),
+            ("Sys", "wait", &[duration]) if duration < 0 => sys_error(1),
+            ("Sys", "wait", &[_]) => Ok(0),
+
+            _ if self.is_os(class, name) => {
+                let msg = format!("`{class}.{name}` is called with {} arguments", args.len());
+                Err(RuntimeError::InvalidProgram(msg).into())
+            }
+            _ => Err(RuntimeError::UndefinedFunction(format!("{class}.{name}")).into()),
+        }
+    }
+
+    // a void function which returns `0`
+    fn unit(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> std::result::Result<(), RuntimeError>,
+    ) -> Result<i16> {
+        f(self)?;
+        Ok(0)
+    }
+
+    // `String`, which is laid out as `[max length, length, chars..]`
+    fn string(&mut self, name: &str, args: &[i16]) -> Result<i16> {
+        let memory = &mut self.state.memory;
+        match (name, args) {
+            ("new", &[max]) if max < 0 => sys_error(14),
+            ("new", &[max]) => {
+                let this = self.invoke("Memory", "alloc", vec![max.wrapping_add(2)])?;
+                self.state.memory.write(this, max)?;
+                self.state.memory.write(this.wrapping_add(1), 0)?;
+                Ok(this)
+            }
+            ("dispose", &[this]) => self.invoke("Memory", "deAlloc", vec![this]),
+            ("length", &[this]) => Ok(memory.read(this.wrapping_add(1))?),
+            ("charAt", &[this, j]) => {
+                let len = memory.read(this.wrapping_add(1))?;
+                if !(0..len).contains(&j) {
+                    return sys_error(15);
+                }
+                Ok(memory.read(this.wrapping_add(2 + j))?)
+            }
+            ("setCharAt", &[this, j, c]) => {
+                let len = memory.read(this.wrapping_add(1))?;
+                if !(0..len).contains(&j) {
+                    return sys_error(16);
+                }
+                memory.write(this.wrapping_add(2 + j), c)?;
+                Ok(0)
+            }
+            ("appendChar", &[this, c]) => {
+                let (max, len) = (memory.read(this)?, memory.read(this.wrapping_add(1))?);
+                if len >= max {
+                    return sys_error(17);
+                }
+                memory.write(this.wrapping_add(2 + len), c)?;
+                memory.write(this.wrapping_add(1), len + 1)?;
+                Ok(this)
+            }
+            ("eraseLastChar", &[this]) => {
+                let len = memory.read(this.wrapping_add(1))?;
+                if len == 0 {
+                    return sys_error(18);
+                }
+                memory.write(this.wrapping_add(1), len - 1)?;
+                Ok(0)
+            }
+            ("intValue", &[this]) => {
+                let chars = self.chars(this)?;
+                let (sign, digits) = match chars.split_first() {
+                    Some((&c, rest)) if c == u16::from(b'-') => (-1i16, rest),
+                    _ => (1, &chars[..]),
+                };
+                let n = digits
+                    .iter()
+                    .map_while(|&c| char::from_u32(c.into())?.to_digit(10))
+                    .fold(0i16, |n, d| n.wrapping_mul(10).wrapping_add(d as i16));
+                Ok(n.wrapping_mul(sign))
+            }
+            ("setInt", &[this, n]) => {
+                let digits: Vec<_> = n.to_string().bytes().map(i16::from).collect();
+                if digits.len() > memory.read(this)? as usize {
+                    return sys_error(19);
+                }
+                for (i, c) in digits.iter().enumerate() {
+                    memory.write(this.wrapping_add(2 + i as i16), *c)?;
+                }
+                memory.write(this.wrapping_add(1), digits.len() as i16)?;
+                Ok(0)
+            }
+            ("backSpace", []) => Ok(BACKSPACE as i16),
+            ("doubleQuote", []) => Ok(DOUBLE_QUOTE),
+            ("newLine", []) => Ok(NEWLINE as i16),
+            _ if self.is_os("String", name) => {
+                let msg = format!("`String.{name}` is called with {} arguments", args.len());
+                Err(RuntimeError::InvalidProgram(msg).into())
+            }
+            _ => Err(RuntimeError::UndefinedFunction(format!("String.{name}")).into()),
+        }
+    }
+
+    // characters of the string `s`
+    fn chars(&self, s: i16) -> Result<Vec<u16>> {
+        let len = self.state.memory.read(s.wrapping_add(1))?;
+        (0..len)
+            .map(|i| Ok(self.state.memory.read(s.wrapping_add(2 + i))? as u16))
+            .collect()
+    }
+
+    fn print(&mut self, chars: &[u16]) -> Result<i16> {
+        let output = &mut self.state.output;
+        for &c in chars {
+            match c {
+                NEWLINE => output.push('\n'),
+                BACKSPACE => {
+                    if !output.ends_with('\n') {
+                        output.pop();
+                    }
+                }
+                32..=126 => output.push(c as u8 as char),
+                _ => output.push(char::REPLACEMENT_CHARACTER),
+            }
+        }
+        Ok(0)
+    }
+
+    fn key(&mut self) -> Result<u16> {
+        match self.state.input.pop_front() {
+            Some(key) => Ok(key),
+            None => Err(RuntimeError::InputExhausted.into()),
+        }
+    }
+
+    // reads keys up to a newline, echoing them after `message`
+    fn read_line(&mut self, message: i16) -> Result<i16> {
+        let message = self.chars(message)?;
+        self.print(&message)?;
+        let mut line = Vec::new();
+        loop {
+            let c = self.key()?;
+            self.print(&[c])?;
+            match c {
+                NEWLINE => break,
+                BACKSPACE => {
+                    line.pop();
+                }
+                c => line.push(c),
+            }
+        }
+
+        let s = self.invoke("String", "new", vec![line.len() as i16])?;
+        for c in line {
+            self.invoke("String", "appendChar", vec![s, c as i16])?;
+        }
+        Ok(s)
+    }
+
+    fn pixel(&mut self, x: i16, y: i16) -> Result<i16> {
+        let addr = SCREEN as i16 + y * 32 + x / 16;
+        let bit = 1i16 << (x % 16);
+        let word = self.state.memory.read(addr)?;
+        let word = if self.state.color {
+            word | bit
+        } else {
+            word & !bit
+        };
+        self.state.memory.write(addr, word)?;
+        Ok(0)
+    }
+
+    // Bresenham's line between two points on the screen
+    fn line(&mut self, x1: i16, y1: i16, x2: i16, y2: i16) -> Result<i16> {
+        let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+        let (sx, sy) = ((x2 - x1).signum(), (y2 - y1).signum());
+        let (mut x, mut y, mut err) = (x1, y1, dx + dy);
+        loop {
+            self.pixel(x, y)?;
+            if (x, y) == (x2, y2) {
+                return Ok(0);
+            }
+            if 2 * err >= dy {
+                err += dy;
+                x += sx;
+            }
+            if 2 * err <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    // filled circle, clipped to the screen
+    fn circle(&mut self, x: i16, y: i16, r: i16) -> Result<i16> {
+        for dy in -r..=r {
+            if !(0..HEIGHT).contains(&(y + dy)) {
+                continue;
+            }
+            let dx = f64::from(r * r - dy * dy).sqrt() as i16;
+            let (x1, x2) = ((x - dx).max(0), (x + dx).min(WIDTH - 1));
+            self.line(x1, y + dy, x2, y + dy)?;
+        }
+        Ok(0)
+    }
+}
+
+fn on_screen(x: i16, y: i16) -> bool {
+    (0..WIDTH).contains(&x) && (0..HEIGHT).contains(&y)
+}
+
+fn sys_error(code: i16) -> Result<i16> {
+    Err(RuntimeError::Sys(code).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interp::tests::run, Exit, Interpreter, Options};
+
+    #[test]
+    fn strings() {
+        let src = "\
+class Main {
+    function void main() {
+        var String s;
+        let s = String.new(6);
+        do s.setInt(-123);
+        do s.appendChar(52);
+        do Output.printInt(s.intValue() + 1);
+        do s.eraseLastChar();
+        do s.setCharAt(0, 43);
+        do Output.printString(s);
+        do Output.printChar(String.doubleQuote());
+        do Output.printString(\"bye\\n\");
+        do s.dispose();
+        return;
+    }
+}
+";
+        let (exit, output) = run(&[src], "");
+        assert_eq!(exit, Ok(Exit::Halted));
+        assert_eq!(output, "-1233+123\"bye\n");
+    }
+
+    #[test]
+    fn read_keyboard() {
+        let src = "\
+class Main {
+    function void main() {
+        var String name;
+        var int n;
+        let name = Keyboard.readLine(\"name? \");
+        let n = Keyboard.readInt(\"n? \");
+        do Output.printString(name);
+        do Output.printInt(n * 2);
+        do Output.printChar(Keyboard.readChar());
+        do Output.printChar(Keyboard.keyPressed());
+        do Output.printInt(Keyboard.keyPressed());
+        return;
+    }
+}
+";
+        let (exit, output) = run(&[src], "Jackx\u{81}\n-21\n!?");
+        assert_eq!(exit, Ok(Exit::Halted));
+        // the backspace erases the echoed key as well
+        assert_eq!(output, "name? Jack\nn? -21\nJack-42!!?0");
+
+        let (exit, _) = run(&[src], "Jack\n");
+        assert_eq!(exit, Err(RuntimeError::InputExhausted));
+    }
+
+    #[test]
+    fn draw_on_screen() {
+        let src = "\
+class Main {
+    function void main() {
+        do Screen.drawRectangle(0, 0, 16, 1);
+        do Screen.setColor(false);
+        do Screen.drawPixel(1, 1);
+        do Screen.setColor(true);
+        do Screen.drawLine(511, 255, 509, 253);
+        do Screen.drawCircle(100, 100, 0);
+        return;
+    }
+}
+";
+        let mut sources = jack_ast::SourceMap::new();
+        sources.add_file("Main.jack", src.to_string());
+        let classes = crate::parse(&sources, Default::default()).unwrap();
+        let mut interp = Interpreter::new(&classes, Options::default()).unwrap();
+        assert_eq!(interp.call("Sys.init", &[]), Ok(Exit::Halted));
+
+        let screen = &interp.memory()[SCREEN..KEYBOARD];
+        assert_eq!(screen[..2], [-1, 1]);
+        assert_eq!(screen[32..34], [!2, 1]);
+        assert_eq!(screen[100 * 32 + 6], 1 << 4);
+        // the diagonal from the bottom right corner
+        let rows: Vec<_> = (253..256).map(|y| screen[y * 32 + 31]).collect();
+        assert_eq!(rows, [1 << 13, 1 << 14, i16::MIN]);
+        assert_eq!(screen.iter().filter(|w| **w != 0).count(), 8);
+    }
+
+    #[test]
+    fn os_errors() {
+        for (call, code) in [
+            ("Math.divide(1, 0)", 3),
+            ("Math.sqrt(-1)", 4),
+            ("Array.new(0)", 2),
+            ("Screen.drawPixel(512, 0)", 7),
+            ("Screen.drawRectangle(2, 0, 1, 0)", 9),
+            ("Screen.drawCircle(0, 0, 182)", 13),
+            ("String.new(1).charAt(1)", 15),
+            ("Output.moveCursor(23, 0)", 20),
+            ("Sys.error(99)", 99),
+        ] {
+            let (receiver, call) = match call.strip_prefix("String.new(1).") {
+                Some(method) => ("let s = String.new(1);", format!("s.{method}")),
+                None => ("", call.to_string()),
+            };
+            let src = format!(
+                "class Main {{ function void main() {{ var String s; {receiver} do {call}; return; }} }}"
+            );
+            let (exit, _) = run(&[&src], "");
+            assert_eq!(exit, Err(RuntimeError::Sys(code)), "{call}");
+        }
+        assert_eq!(
+            RuntimeError::Sys(3).to_string(),
+            "Sys.error(3): division by zero"
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! Semantic queries on a Jack program.
+//!
+//! A program is every class in a directory, as `jack-compiler` compiles them together.
+use jack_ast::*;
+use jack_compiler::{
+    lexer::Lexer,
+    parser::{self, Dialect},
+    symbol::{FnCtxt, SymbolTable, VariableCtxt},
+    token::{Token, TokenKind},
+};
+
+pub struct Program<'s> {
+    // classes which could be parsed
+    classes: Vec<(&'s SourceFile, Class<'s>)>,
+    table: SymbolTable<'s, 's>,
+}
+
+/// A name in the program and its definition.
+pub enum Symbol<'a, 's> {
+    Class {
+        file: &'s SourceFile,
+        class: &'a Class<'s>,
+    },
+    Variable {
+        file: &'s SourceFile,
+        ctxt: VariableCtxt<'s>,
+    },
+    Fn {
+        ctxt: &'a FnCtxt<'s>,
+        // OS functions are not defined in the program
+        def: Option<(&'s SourceFile, &'a FnDef<'s>)>,
+    },
+}
+
+impl<'a, 's> Symbol<'a, 's> {
+    pub fn definition(&self) -> Option<(&'s SourceFile, Span)> {
+        match self {
+            Symbol::Class { file, class } => Some((file, class.span)),
+            Symbol::Variable { file, ctxt } => Some((file, ctxt.span)),
+            Symbol::Fn { def, .. } => def.map(|(file, f)| (file, f.span)),
+        }
+    }
+
+    pub fn hover(&self) -> String {
+        match self {
+            Symbol::Class { class, .. } => format!("class {}", class.name),
+            Symbol::Variable { ctxt, .. } => ctxt.to_string(),
+            Symbol::Fn { ctxt, def } => match def {
+                Some((_, f)) => {
+                    let params = f
+                        .params
+                        .iter()
+                        .map(|p| format!("{} {}", p.ty.to_source(), p.name))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    format!("{ctxt}({params})")
+                }
+                None => ctxt.to_string(),
+            },
+        }
+    }
+}
+
+impl<'s> Program<'s> {
+    /// Parses every file in `sources` in `dialect`. Files with syntax errors are left out.
+    pub fn new(sources: &'s SourceMap, dialect: Dialect) -> Self {
+        let classes: Vec<_> = sources
+            .files()
+            .filter_map(|file| Some((file, parser::parse_file(file, dialect).ok()?)))
+            .collect();
+
+        let mut table = SymbolTable::new();
+        for (_, class) in classes.iter() {
+            for f in class.functions.iter() {
+                // conflicts are reported by diagnostics
+                let _ = table.register_fn(class.name, f.name, f.kind, f.ret, f.span);
+            }
+        }
+
+        Program { classes, table }
+    }
+
+    pub fn class_of(&self, file: FileId) -> Option<&Class<'s>> {
+        self.classes
+            .iter()
+            .find(|(f, _)| f.id == file)
+            .map(|(_, class)| class)
+    }
+
+    fn class(&self, name: &str) -> Option<(&'s SourceFile, &Class<'s>)> {
+        self.classes
+            .iter()
+            .find(|(_, class)| class.name == name)
+            .map(|(file, class)| (*file, class))
+    }
+
+    /// Finds the symbol under the cursor at `offset`.
+    pub fn symbol_at(&self, file: &'s SourceFile, offset: BytePos) -> Option<Symbol<'_, 's>> {
+        let tokens = tokenize(file);
+        let i = tokens.iter().position(|token| {
+            matches!(token.kind, TokenKind::Ident(_))
+                && token.span.lo() <= offset
+                && offset <= token.span.hi()
+        })?;
+        let TokenKind::Ident(name) = tokens[i].kind else { unreachable!() };
+
+        let class = self.class_of(file.id)?;
+        let f = enclosing_fn(class, offset);
+        let prev = |n: usize| i.checked_sub(n).map(|j| &tokens[j].kind);
+
+        // `receiver.name`
+        if let (Some(TokenKind::Dot), Some(TokenKind::Ident(receiver))) = (prev(1), prev(2)) {
+            let (receiver_class, _) = self.receiver_class(class, f, receiver);
+            return self.function(receiver_class, name);
+        }
+
+        // `name(..)` calls a function of the current class
+        if let Some(TokenKind::LParen) = tokens.get(i + 1).map(|t| &t.kind) {
+            return self.function(class.name, name);
+        }
+
+        if let Some(ctxt) = lookup_variable(class, f, name) {
+            return Some(Symbol::Variable { file, ctxt });
+        }
+
+        self.class(name)
+            .map(|(file, class)| Symbol::Class { file, class })
+    }
+
+    /// Lists functions which can be called after `receiver.` just before the cursor at `offset`.
+    pub fn completions(&self, file: &'s SourceFile, offset: BytePos) -> Vec<&FnCtxt<'s>> {
+        let tokens = tokenize(file);
+        // tokens before the cursor, excluding the name being typed
+        let mut end = tokens
+            .iter()
+            .take_while(|token| token.span.hi() <= offset)
+            .count();
+        if end > 0 && matches!(tokens[end - 1].kind, TokenKind::Ident(_)) {
+            end -= 1;
+        }
+
+        let receiver = match &tokens[..end] {
+            [.., Token {
+                kind: TokenKind::Ident(receiver),
+                ..
+            }, Token {
+                kind: TokenKind::Dot,
+                ..
+            }] => *receiver,
+            _ => return Vec::new(),
+        };
+
+        let (class_name, is_instance) = match self.class_of(file.id) {
+            Some(class) => self.receiver_class(class, enclosing_fn(class, offset), receiver),
+            None => (receiver, false),
+        };
+
+        let mut functions: Vec<_> = self
+            .table
+            .functions()
+            .filter(|f| f.class == class_name && (f.kind == FnKind::Method) == is_instance)
+            .collect();
+        functions.sort_by_key(|f| f.name);
+
+        functions
+    }
+
+    // class of the receiver, and whether it's an instance (otherwise, the receiver is a class)
+    fn receiver_class(
+        &self,
+        class: &Class<'s>,
+        f: Option<&FnDef<'s>>,
+        receiver: Ident<'s>,
+    ) -> (Ident<'s>, bool) {
+        match lookup_variable(class, f, receiver) {
+            Some(VariableCtxt {
+                ty: Type::Class(name),
+                ..
+            }) => (name, true),
+            _ => (receiver, false),
+        }
+    }
+
+    fn function(&self, class: Ident<'s>, name: Ident<'s>) -> Option<Symbol<'_, 's>> {
+        let ctxt = self.table.lookup_fn(Some(class), name)?;
+        let def = self.class(class).and_then(|(file, class)| {
+            let f = class.functions.iter().find(|f| f.name == name)?;
+            Some((file, f))
+        });
+
+        Some(Symbol::Fn { ctxt, def })
+    }
+}
+
+// tokens of the file until the first lexical error
+fn tokenize(file: &SourceFile) -> Vec<Token<'_>> {
+    Lexer::with_file(&file.src, file.id)
+        .map_while(Result::ok)
+        .collect()
+}
+
+// the function whose definition starts last before `offset`
+fn enclosing_fn<'a, 's>(class: &'a Class<'s>, offset: BytePos) -> Option<&'a FnDef<'s>> {
+    class
+        .functions
+        .iter()
+        .take_while(|f| f.span.lo() <= offset)
+        .last()
+}
+
+fn lookup_variable<'s>(
+    class: &Class<'s>,
+    f: Option<&FnDef<'s>>,
+    name: Ident<'_>,
+) -> Option<VariableCtxt<'s>> {
+    if let Some(f) = f {
+        if let Some(param) = f.params.iter().find(|p| p.name == name) {
+            return Some(param.into());
+        }
+        if let Some(var) = f.body.variables.iter().find(|v| v.name == name) {
+            return Some(var.into());
+        }
+    }
+
+    class
+        .variables
+        .iter()
+        .find(|v| v.name == name)
+        .map(|v| v.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAIN: &str = "\
+class Main {
+    field Counter counter;
+
+    function void main() {
+        var Counter c;
+        let c = Counter.new();
+        do c.increment(1);
+        do Output.printInt(c.get());
+        return;
+    }
+}";
+
+    const COUNTER: &str = "\
+class Counter {
+    field int count;
+
+    constructor Counter new() {
+        let count = 0;
+        return this;
+    }
+
+    method void increment(int amount) {
+        let count = count + amount;
+        return;
+    }
+
+    method int get() {
+        return count;
+    }
+}";
+
+    fn sources() -> SourceMap {
+        let mut sources = SourceMap::new();
+        sources.add_file("Counter.jack", COUNTER.to_owned());
+        sources.add_file("Main.jack", MAIN.to_owned());
+        sources
+    }
+
+    fn main_file(sources: &SourceMap) -> &SourceFile {
+        sources.files().find(|f| f.src == MAIN).unwrap()
+    }
+
+    #[test]
+    fn definition_of_method() {
+        let sources = sources();
+        let program = Program::new(&sources, Dialect::Standard);
+        let main = main_file(&sources);
+
+        let offset = MAIN.find("increment").unwrap() + 2;
+        let (file, span) = program
+            .symbol_at(main, offset)
+            .unwrap()
+            .definition()
+            .unwrap();
+        assert_eq!(file.path.to_str(), Some("Counter.jack"));
+        assert_eq!(&COUNTER[span.lo()..span.hi()], "increment");
+    }
+
+    #[test]
+    fn definition_of_variable_and_class() {
+        let sources = sources();
+        let program = Program::new(&sources, Dialect::Standard);
+        let main = main_file(&sources);
+
+        // `c` in `let c = ..`
+        let offset = MAIN.find("let c").unwrap() + 4;
+        let (file, span) = program
+            .symbol_at(main, offset)
+            .unwrap()
+            .definition()
+            .unwrap();
+        assert_eq!(file.id, main.id);
+        assert_eq!(span.lo(), MAIN.find("c;").unwrap());
+
+        // `Counter` in `var Counter c;`
+        let offset = MAIN.find("var Counter").unwrap() + 5;
+        let (file, span) = program
+            .symbol_at(main, offset)
+            .unwrap()
+            .definition()
+            .unwrap();
+        assert_eq!(file.path.to_str(), Some("Counter.jack"));
+        assert_eq!(&COUNTER[span.lo()..span.hi()], "Counter");
+    }
+
+    #[test]
+    fn hover() {
+        let sources = sources();
+        let program = Program::new(&sources, Dialect::Standard);
+        let main = main_file(&sources);
+
+        let offset = MAIN.find("increment").unwrap();
+        let symbol = program.symbol_at(main, offset).unwrap();
+        assert_eq!(symbol.hover(), "method void Counter.increment(int amount)");
+
+        let offset = MAIN.find("printInt").unwrap();
+        let symbol = program.symbol_at(main, offset).unwrap();
+        assert!(symbol.definition().is_none());
+        assert_eq!(symbol.hover(), "function void Output.printInt");
+    }
+
+    #[test]
+    fn complete_after_dot() {
+        let sources = sources();
+        let program = Program::new(&sources, Dialect::Standard);
+        let main = main_file(&sources);
+
+        let names = |offset| {
+            program
+                .completions(main, offset)
+                .iter()
+                .map(|f| f.name)
+                .collect::<Vec<_>>()
+        };
+
+        // methods of an instance
+        let offset = MAIN.find("c.increment").unwrap() + 2;
+        assert_eq!(names(offset), ["get", "increment"]);
+        // constructors and functions of a class
+        let offset = MAIN.find("Counter.new").unwrap() + "Counter.".len();
+        assert_eq!(names(offset), ["new"]);
+        // not after `.`
+        assert!(names(MAIN.find("return;").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn extended_dialect() {
+        let src = "\
+class Loop {
+    function void run() {
+        var int i;
+        for (let i = 0; i < 3; let i += 1) {
+            do Loop.run();
+        }
+        return;
+    }
+}";
+        let mut sources = SourceMap::new();
+        let file = sources.add_file("Loop.jack", src.to_owned());
+
+        assert!(Program::new(&sources, Dialect::Standard)
+            .class_of(file)
+            .is_none());
+        assert!(Program::new(&sources, Dialect::Extended)
+            .class_of(file)
+            .is_some());
+    }
+}
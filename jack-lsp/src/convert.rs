@@ -0,0 +1,65 @@
+//! Conversions between byte offsets of Jack sources and LSP positions.
+//!
+//! LSP counts columns in UTF-16 code units by default.
+use jack_ast::{BytePos, Span};
+use lsp_types::{Position, Range};
+
+pub fn offset_to_position(src: &str, offset: BytePos) -> Position {
+    let before = &src[..offset.min(src.len())];
+    let line_start = before.rfind('\n').map_or(0, |nl| nl + 1);
+    let line = before.matches('\n').count();
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+
+    Position::new(line as u32, character as u32)
+}
+
+/// Positions beyond the end of a line (or of the source) are clamped to it.
+pub fn position_to_offset(src: &str, position: Position) -> BytePos {
+    let mut offset = 0;
+    for _ in 0..position.line {
+        match src[offset..].find('\n') {
+            Some(nl) => offset += nl + 1,
+            None => return src.len(),
+        }
+    }
+
+    let mut character = 0;
+    for (i, c) in src[offset..].char_indices() {
+        if c == '\n' || character >= position.character as usize {
+            return offset + i;
+        }
+        character += c.len_utf16();
+    }
+
+    src.len()
+}
+
+pub fn span_to_range(src: &str, span: Span) -> Range {
+    Range::new(
+        offset_to_position(src, span.lo()),
+        offset_to_position(src, span.hi()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let src = "class Main {\n  field int x;\n}";
+        for offset in 0..=src.len() {
+            let position = offset_to_position(src, offset);
+            assert_eq!(position_to_offset(src, position), offset);
+        }
+
+        assert_eq!(offset_to_position(src, 15), Position::new(1, 2));
+    }
+
+    #[test]
+    fn clamp_position() {
+        let src = "class Main {\n}";
+        assert_eq!(position_to_offset(src, Position::new(0, 100)), 12);
+        assert_eq!(position_to_offset(src, Position::new(5, 0)), src.len());
+    }
+}
@@ -0,0 +1,414 @@
+//! A language server for Jack, speaking LSP over stdio.
+//!
+//! Every `.jack` file in the directory of a document is analyzed together, with the contents of
+//! open documents taking precedence over the files on disk.
+//!
+//! The language extensions are accepted once the client passes `{ "dialect": "extended" }` as
+//! `initializationOptions`.
+mod analysis;
+mod convert;
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use jack_ast::{FileId, FnKind, SourceFile, SourceMap, VariableDefKind};
+use jack_compiler::{
+    compiler::{self, Options},
+    diagnosis::{format::Message as Diagnostic, DiagnosticReporter},
+    parser::Dialect,
+};
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::{
+    notification::{self, Notification as _},
+    request::{self, Request as _},
+    CompletionItem, CompletionItemKind, CompletionOptions, DiagnosticSeverity, DocumentSymbol,
+    DocumentSymbolResponse, GotoDefinitionResponse, Hover, HoverContents, HoverProviderCapability,
+    InitializeParams, Location, MarkupContent, MarkupKind, NumberOrString, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, SymbolKind, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+use rustc_hash::FxHashMap;
+use serde_json::Value;
+
+use analysis::Program;
+
+fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_string()]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let params: InitializeParams =
+        serde_json::from_value(connection.initialize(serde_json::to_value(capabilities)?)?)?;
+    let options = Options {
+        dialect: dialect(params.initialization_options.as_ref())?,
+        ..Default::default()
+    };
+
+    Server {
+        connection: &connection,
+        documents: FxHashMap::default(),
+        options,
+    }
+    .run()?;
+    // the I/O threads finish once the connection is closed
+    drop(connection);
+    io_threads.join()?;
+
+    Ok(())
+}
+
+struct Server<'c> {
+    connection: &'c Connection,
+    // contents of open documents
+    documents: FxHashMap<PathBuf, String>,
+    options: Options,
+}
+
+impl Server<'_> {
+    fn run(&mut self) -> Result<()> {
+        for msg in &self.connection.receiver {
+            match msg {
+                Message::Request(req) => {
+                    if self.connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    self.handle_request(req)?;
+                }
+                Message::Notification(not) => self.handle_notification(not)?,
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_request(&self, req: Request) -> Result<()> {
+        let id = req.id.clone();
+        let result = match req.method.as_str() {
+            request::GotoDefinition::METHOD => {
+                let params = extract::<request::GotoDefinition>(req)?;
+                let pos = params.text_document_position_params;
+                self.definition(&pos.text_document.uri, pos.position)
+                    .map(GotoDefinitionResponse::Scalar)
+                    .map(serde_json::to_value)
+                    .transpose()?
+            }
+            request::HoverRequest::METHOD => {
+                let params = extract::<request::HoverRequest>(req)?;
+                let pos = params.text_document_position_params;
+                self.hover(&pos.text_document.uri, pos.position)
+                    .map(serde_json::to_value)
+                    .transpose()?
+            }
+            request::DocumentSymbolRequest::METHOD => {
+                let params = extract::<request::DocumentSymbolRequest>(req)?;
+                self.document_symbols(&params.text_document.uri)
+                    .map(DocumentSymbolResponse::Nested)
+                    .map(serde_json::to_value)
+                    .transpose()?
+            }
+            request::Completion::METHOD => {
+                let params = extract::<request::Completion>(req)?;
+                let pos = params.text_document_position;
+                Some(serde_json::to_value(
+                    self.completions(&pos.text_document.uri, pos.position),
+                )?)
+            }
+            _ => {
+                let resp = Response::new_err(
+                    id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request: {}", req.method),
+                );
+                self.connection.sender.send(resp.into())?;
+                return Ok(());
+            }
+        };
+
+        self.respond(id, result.unwrap_or(Value::Null))
+    }
+
+    fn handle_notification(&mut self, not: Notification) -> Result<()> {
+        let uri = match not.method.as_str() {
+            notification::DidOpenTextDocument::METHOD => {
+                let params = extract_notification::<notification::DidOpenTextDocument>(not)?;
+                let doc = params.text_document;
+                if let Ok(path) = doc.uri.to_file_path() {
+                    self.documents.insert(path, doc.text);
+                }
+                doc.uri
+            }
+            notification::DidChangeTextDocument::METHOD => {
+                let params = extract_notification::<notification::DidChangeTextDocument>(not)?;
+                // the whole document is sent on every change (`TextDocumentSyncKind::FULL`)
+                if let (Ok(path), Some(change)) = (
+                    params.text_document.uri.to_file_path(),
+                    params.content_changes.into_iter().last(),
+                ) {
+                    self.documents.insert(path, change.text);
+                }
+                params.text_document.uri
+            }
+            notification::DidCloseTextDocument::METHOD => {
+                let params = extract_notification::<notification::DidCloseTextDocument>(not)?;
+                if let Ok(path) = params.text_document.uri.to_file_path() {
+                    self.documents.remove(&path);
+                }
+                params.text_document.uri
+            }
+            _ => return Ok(()),
+        };
+
+        self.publish_diagnostics(&uri)
+    }
+
+    fn respond(&self, id: RequestId, result: Value) -> Result<()> {
+        let resp = Response {
+            id,
+            result: Some(result),
+            error: None,
+        };
+        self.connection.sender.send(resp.into())?;
+        Ok(())
+    }
+
+    // Collects the document and the other files in its directory.
+    fn sources(&self, uri: &Url) -> Option<(SourceMap, FileId)> {
+        let path = uri.to_file_path().ok()?;
+        let dir = path.parent()?;
+
+        let mut paths: BTreeSet<PathBuf> = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == "jack"))
+            .collect();
+        // open documents which are not saved yet
+        paths.extend(
+            self.documents
+                .keys()
+                .filter(|path| path.parent() == Some(dir))
+                .cloned(),
+        );
+        paths.insert(path.clone());
+
+        let mut sources = SourceMap::new();
+        let mut id = None;
+        for p in paths {
+            let Some(src) = self.read(&p) else { continue };
+            let file = sources.add_file(&p, src);
+            if p == path {
+                id = Some(file);
+            }
+        }
+
+        Some((sources, id?))
+    }
+
+    fn read(&self, path: &Path) -> Option<String> {
+        match self.documents.get(path) {
+            Some(src) => Some(src.clone()),
+            None => fs::read_to_string(path).ok(),
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: &Url) -> Result<()> {
+        let Some((sources, _)) = self.sources(uri) else { return Ok(()) };
+
+        let mut reporter = DiagnosticReporter::new(&sources);
+        reporter.capture();
+        compiler::check(&sources, &reporter, &self.options);
+        let messages = reporter.take_messages();
+
+        // publish for every file of the program, so that fixed ones are cleared
+        for file in sources.files() {
+            let Ok(uri) = Url::from_file_path(&file.path) else { continue };
+            let name = file.name();
+            let diagnostics = messages
+                .iter()
+                .filter(|msg| msg.file.as_deref() == Some(name.as_str()))
+                .map(|msg| to_lsp_diagnostic(file, msg))
+                .collect();
+
+            let params = PublishDiagnosticsParams {
+                uri,
+                diagnostics,
+                version: None,
+            };
+            let not =
+                Notification::new(notification::PublishDiagnostics::METHOD.to_string(), params);
+            self.connection.sender.send(not.into())?;
+        }
+
+        Ok(())
+    }
+
+    fn definition(&self, uri: &Url, position: Position) -> Option<Location> {
+        let (sources, id) = self.sources(uri)?;
+        let file = sources.file(id);
+        let program = Program::new(&sources, self.options.dialect);
+
+        let offset = convert::position_to_offset(&file.src, position);
+        let (def_file, span) = program.symbol_at(file, offset)?.definition()?;
+
+        Some(Location {
+            uri: Url::from_file_path(&def_file.path).ok()?,
+            range: convert::span_to_range(&def_file.src, span),
+        })
+    }
+
+    fn hover(&self, uri: &Url, position: Position) -> Option<Hover> {
+        let (sources, id) = self.sources(uri)?;
+        let file = sources.file(id);
+        let program = Program::new(&sources, self.options.dialect);
+
+        let offset = convert::position_to_offset(&file.src, position);
+        let symbol = program.symbol_at(file, offset)?;
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```jack\n{}\n```", symbol.hover()),
+            }),
+            range: None,
+        })
+    }
+
+    fn document_symbols(&self, uri: &Url) -> Option<Vec<DocumentSymbol>> {
+        let (sources, id) = self.sources(uri)?;
+        let file = sources.file(id);
+        let program = Program::new(&sources, self.options.dialect);
+        let class = program.class_of(id)?;
+
+        let range = |span| convert::span_to_range(&file.src, span);
+        let variables = class.variables.iter().map(|v| {
+            let kind = match v.kind {
+                VariableDefKind::Field => SymbolKind::FIELD,
+                _ => SymbolKind::VARIABLE,
+            };
+            symbol(v.name, kind, range(v.span), None)
+        });
+        let functions = class.functions.iter().map(|f| {
+            let kind = match f.kind {
+                FnKind::Ctor => SymbolKind::CONSTRUCTOR,
+                FnKind::Function => SymbolKind::FUNCTION,
+                FnKind::Method => SymbolKind::METHOD,
+            };
+            symbol(f.name, kind, range(f.span), None)
+        });
+        let children = variables.chain(functions).collect();
+
+        Some(vec![symbol(
+            class.name,
+            SymbolKind::CLASS,
+            range(class.span),
+            Some(children),
+        )])
+    }
+
+    fn completions(&self, uri: &Url, position: Position) -> Vec<CompletionItem> {
+        let Some((sources, id)) = self.sources(uri) else { return Vec::new() };
+        let file = sources.file(id);
+        let program = Program::new(&sources, self.options.dialect);
+
+        let offset = convert::position_to_offset(&file.src, position);
+        program
+            .completions(file, offset)
+            .into_iter()
+            .map(|f| {
+                let kind = match f.kind {
+                    FnKind::Ctor => CompletionItemKind::CONSTRUCTOR,
+                    FnKind::Function => CompletionItemKind::FUNCTION,
+                    FnKind::Method => CompletionItemKind::METHOD,
+                };
+                CompletionItem {
+                    label: f.name.to_string(),
+                    kind: Some(kind),
+                    detail: Some(f.to_string()),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
+
+// Reads the dialect from the `initializationOptions` of the client, the standard one by default.
+fn dialect(init: Option<&Value>) -> Result<Dialect> {
+    match init.and_then(|init| init.get("dialect")) {
+        None | Some(Value::Null) => Ok(Dialect::default()),
+        Some(Value::String(dialect)) => dialect.parse().map_err(anyhow::Error::msg),
+        Some(value) => bail!("dialect must be a string, got {value}"),
+    }
+}
+
+fn extract<R: lsp_types::request::Request>(req: Request) -> Result<R::Params> {
+    Ok(req.extract(R::METHOD)?.1)
+}
+
+fn extract_notification<N: lsp_types::notification::Notification>(
+    not: Notification,
+) -> Result<N::Params> {
+    Ok(not.extract(N::METHOD)?)
+}
+
+fn to_lsp_diagnostic(file: &SourceFile, msg: &Diagnostic) -> lsp_types::Diagnostic {
+    let range = match msg.labels.first() {
+        Some(label) => Range::new(
+            convert::offset_to_position(&file.src, label.byte_start),
+            convert::offset_to_position(&file.src, label.byte_end),
+        ),
+        None => Range::default(),
+    };
+    let severity = match msg.severity {
+        miette::Severity::Error => DiagnosticSeverity::ERROR,
+        miette::Severity::Warning => DiagnosticSeverity::WARNING,
+        miette::Severity::Advice => DiagnosticSeverity::HINT,
+    };
+    let message = match &msg.help {
+        Some(help) => format!("{}\nhelp: {help}", msg.text),
+        None => msg.text.clone(),
+    };
+
+    lsp_types::Diagnostic {
+        range,
+        severity: Some(severity),
+        code: msg
+            .code
+            .map(|code| NumberOrString::String(code.to_string())),
+        source: Some("jack".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+#[allow(deprecated)]
+fn symbol(
+    name: &str,
+    kind: SymbolKind,
+    range: Range,
+    children: Option<Vec<DocumentSymbol>>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name: name.to_string(),
+        detail: None,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range: range,
+        children,
+    }
+}
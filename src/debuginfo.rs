@@ -0,0 +1,369 @@
+//! Debug info of a whole program, which links the source maps of every stage: Jack statements to
+//! VM lines (`jack_compiler::vm_map`), VM lines to assembly lines (`HackVm::source_map`), and
+//! assembly lines to ROM addresses (`hack_asm::parser::addresses`). An emulator or a debugger can
+//! find the Jack statement at the program counter, and the variables of its function.
+//!
+//! It's written into a single file:
+//!
+//! ```text
+//! function Main.main 61 140 Main.jack
+//! args
+//! locals x i
+//! 61 5:9
+//! 72 6:9
+//! ```
+//!
+//! where a function is given by its range of ROM addresses and its source, followed by the ROM
+//! address, line and column of each statement.
+use std::{collections::HashMap, fmt, ops::Range, path::PathBuf, str::FromStr};
+
+use anyhow::{bail, Context as _};
+use hack_vm::{parser, HackVm, SourceLine};
+use jack_ast::SourceMap;
+use jack_compiler::{compiler, diagnosis::DiagnosticReporter};
+
+/// Machine code of a program with its debug info.
+#[derive(Debug)]
+pub struct Program {
+    pub binary: Vec<String>,
+    pub debug_info: DebugInfo,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// functions compiled from Jack, in the order of their addresses
+    pub functions: Vec<Function>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub source: PathBuf,
+    pub rom: Range<u16>,
+    /// names of the `argument` segment, starting with `this` for a method
+    pub args: Vec<String>,
+    /// names of the `local` segment
+    pub locals: Vec<String>,
+    /// statements in the order of their addresses
+    pub statements: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Statement {
+    pub rom: u16,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Compiles `sources` down to machine code, linked with the VM code of `os` given as pairs of the
+/// file name and the code, which has no debug info.
+pub fn build(
+    sources: &SourceMap,
+    reporter: &DiagnosticReporter<'_>,
+    options: &compiler::Options,
+    os: &[(&str, &str)],
+) -> anyhow::Result<Program> {
+    let Some(classes) = compiler::compile_with_source_maps(sources, reporter, options) else {
+        bail!("failed to compile");
+    };
+
+    let names: Vec<String> = classes
+        .iter()
+        .map(|(_, map)| {
+            let stem = map.source.file_stem().and_then(|stem| stem.to_str());
+            stem.map(str::to_string)
+                .with_context(|| format!("invalid file name: {}", map.source.display()))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let files = names
+        .iter()
+        .map(String::as_str)
+        .zip(classes.iter().map(|(vm, _)| vm.as_str()))
+        .chain(os.iter().copied());
+    let mut instructions = Vec::new();
+    for (name, vm) in files {
+        let (is, errors) = parser::parse(vm)?;
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e.context(format!("invalid VM code of {name}")));
+        }
+        instructions.push((name, is));
+    }
+
+    let mut asm = Vec::new();
+    let mut translator = HackVm::new(&mut asm);
+    translator.interpret(instructions)?;
+    let source_map = translator.source_map().to_vec();
+    let asm = String::from_utf8(asm)?;
+    let addresses = hack_asm::parser::addresses(&asm);
+    let binary = hack_asm::compile(&asm.chars().filter(|c| *c != ' ').collect::<String>())?;
+
+    let mut files: HashMap<&str, Range<usize>> = HashMap::new();
+    for (i, line) in source_map.iter().enumerate() {
+        files.entry(line.file).or_insert(i..i).end = i + 1;
+    }
+    let linker = Linker {
+        lines: &source_map,
+        files,
+        addresses,
+        end: u16::try_from(binary.len()).context("too large program")?,
+    };
+    let mut functions = Vec::new();
+    for (name, (_, map)) in names.iter().zip(classes.iter()) {
+        for (i, f) in map.functions.iter().enumerate() {
+            let end = match map.functions.get(i + 1) {
+                Some(next) => linker.rom(name, next.vm_line),
+                None => linker.end_of(name),
+            };
+            functions.push(Function {
+                name: f.name.clone(),
+                source: map.source.clone(),
+                rom: linker.rom(name, f.vm_line)..end,
+                args: f.args.clone(),
+                locals: f.locals.clone(),
+                statements: f
+                    .statements
+                    .iter()
+                    .map(|stmt| Statement {
+                        rom: linker.rom(name, stmt.vm_line),
+                        line: stmt.line,
+                        column: stmt.column,
+                    })
+                    .collect(),
+            });
+        }
+    }
+    functions.sort_by_key(|f| f.rom.start);
+
+    Ok(Program {
+        binary,
+        debug_info: DebugInfo { functions },
+    })
+}
+
+// resolves lines of VM files into ROM addresses
+struct Linker<'a, 's> {
+    lines: &'a [SourceLine<'s>],
+    // range of `lines` of each file, which are in the order of the files
+    files: HashMap<&'s str, Range<usize>>,
+    // ROM address of every line of the assembly
+    addresses: Vec<u16>,
+    // size of the ROM
+    end: u16,
+}
+
+impl Linker<'_, '_> {
+    // address of the first instruction at or after `vm_line` of `file`
+    fn rom(&self, file: &str, vm_line: usize) -> u16 {
+        let lines = self
+            .files
+            .get(file)
+            .map_or(&[][..], |range| &self.lines[range.clone()]);
+        let i = lines.partition_point(|line| line.vm_line < vm_line);
+        match lines.get(i) {
+            Some(line) => self.address(line.asm_line),
+            None => self.end_of(file),
+        }
+    }
+
+    // address just after the code of `file`, where the next file starts
+    fn end_of(&self, file: &str) -> u16 {
+        let next = self
+            .files
+            .get(file)
+            .and_then(|range| self.lines.get(range.end));
+        match next {
+            Some(next) => self.address(next.asm_line),
+            None => self.end,
+        }
+    }
+
+    fn address(&self, asm_line: usize) -> u16 {
+        self.addresses.get(asm_line).copied().unwrap_or(self.end)
+    }
+}
+
+impl DebugInfo {
+    /// The function whose code contains `rom`.
+    pub fn function(&self, rom: u16) -> Option<&Function> {
+        let i = self.functions.partition_point(|f| f.rom.start <= rom);
+        let f = self.functions.get(i.checked_sub(1)?)?;
+        f.rom.contains(&rom).then_some(f)
+    }
+
+    /// The statement whose code contains `rom`, with its function.
+    pub fn statement(&self, rom: u16) -> Option<(&Function, &Statement)> {
+        let f = self.function(rom)?;
+        let i = f.statements.partition_point(|stmt| stmt.rom <= rom);
+        Some((f, f.statements.get(i.checked_sub(1)?)?))
+    }
+}
+
+impl fmt::Display for DebugInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for function in self.functions.iter() {
+            writeln!(
+                f,
+                "function {} {} {} {}",
+                function.name,
+                function.rom.start,
+                function.rom.end,
+                function.source.display()
+            )?;
+            writeln!(f, "args{}", words(&function.args))?;
+            writeln!(f, "locals{}", words(&function.locals))?;
+            for stmt in function.statements.iter() {
+                writeln!(f, "{} {}:{}", stmt.rom, stmt.line, stmt.column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn words(names: &[String]) -> String {
+    names.iter().map(|name| format!(" {name}")).collect()
+}
+
+impl FromStr for DebugInfo {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut functions: Vec<Function> = Vec::new();
+        for (row, line) in s.lines().enumerate() {
+            parse_line(line, &mut functions).with_context(|| format!("at line {}", row + 1))?;
+        }
+        Ok(DebugInfo { functions })
+    }
+}
+
+// parses `line` into the last of `functions`, or a new one
+fn parse_line(line: &str, functions: &mut Vec<Function>) -> anyhow::Result<()> {
+    let mut words = line.split(' ');
+    let head = words.next().unwrap_or_default();
+    if head == "function" {
+        let fields: Vec<_> = line.splitn(5, ' ').skip(1).collect();
+        let [name, start, end, source] = fields[..] else { bail!("invalid function: {line}") };
+        functions.push(Function {
+            name: name.to_string(),
+            source: source.into(),
+            rom: start.parse()?..end.parse()?,
+            args: Vec::new(),
+            locals: Vec::new(),
+            statements: Vec::new(),
+        });
+        return Ok(());
+    }
+
+    let Some(f) = functions.last_mut() else { bail!("no function before: {line}") };
+    let names = || words.clone().map(str::to_string).collect();
+    match head {
+        "args" => f.args = names(),
+        "locals" => f.locals = names(),
+        _ => {
+            let (line, column) = words
+                .next()
+                .and_then(|pos| pos.split_once(':'))
+                .with_context(|| format!("invalid statement: {line}"))?;
+            f.statements.push(Statement {
+                rom: head.parse()?,
+                line: line.parse()?,
+                column: column.parse()?,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use hack_asm::emulator::{Cpu, Exit};
+
+    use super::*;
+    use crate::gen::OS_VM;
+
+    const SRC: &str = "\
+class Main {
+    function int twice(int n) { return n + n; }
+    function int main() {
+        var int x, i;
+        let x = Main.twice(3);
+        while (true) {
+            let i = i + 1;
+            if (i = 5) {
+                while (true) {}
+            }
+        }
+        return x;
+    }
+}
+";
+
+    fn build_main(optimize: bool) -> Program {
+        let mut sources = SourceMap::new();
+        sources.add_file("Main.jack", SRC.to_string());
+        let mut reporter = DiagnosticReporter::new(&sources);
+        reporter.capture();
+        let options = compiler::Options {
+            optimize,
+            ..Default::default()
+        };
+        build(&sources, &reporter, &options, &[("Sys", OS_VM)]).unwrap()
+    }
+
+    #[test]
+    fn locate_statement_and_locals_at_halt() {
+        for optimize in [false, true] {
+            let Program { binary, debug_info } = build_main(optimize);
+            let mut cpu = Cpu::new(&binary).unwrap();
+            assert_eq!(cpu.run(100_000).unwrap(), Exit::Halted);
+
+            let pc = cpu.pc() as u16;
+            let (f, stmt) = debug_info.statement(pc).unwrap();
+            assert_eq!(f.name, "Main.main");
+            assert_eq!(
+                (stmt.line, stmt.column),
+                (9, 17),
+                "with optimize: {optimize}"
+            );
+
+            // `local` of the halted function
+            let lcl = cpu.ram()[1] as usize;
+            let locals: Vec<_> = f
+                .locals
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.as_str(), cpu.ram()[lcl + i]))
+                .collect();
+            assert_eq!(locals, [("x", 6), ("i", 5)]);
+        }
+    }
+
+    #[test]
+    fn lookup_addresses_outside_of_jack() {
+        let Program { debug_info, .. } = build_main(false);
+        let [twice, main] = &debug_info.functions[..] else { panic!("{debug_info}") };
+        assert_eq!(twice.rom.end, main.rom.start);
+
+        // the bootstrap and `Sys`
+        assert_eq!(debug_info.function(0), None);
+        assert_eq!(debug_info.function(main.rom.end), None);
+        assert_eq!(debug_info.function(main.rom.end - 1), Some(main));
+        // the prologue of a function is in no statement
+        assert!(debug_info.statement(twice.rom.start).is_none());
+        assert_eq!(
+            debug_info
+                .statement(twice.rom.end - 1)
+                .map(|(_, stmt)| stmt.line),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn write_and_read() {
+        let Program { debug_info, .. } = build_main(false);
+        let text = debug_info.to_string();
+        assert!(text.starts_with("function Main.twice "));
+        assert!(text.contains("\nargs n\nlocals\n"));
+        assert_eq!(text.parse::<DebugInfo>().unwrap(), debug_info);
+        assert!("10 1:1".parse::<DebugInfo>().is_err());
+    }
+}
@@ -0,0 +1,431 @@
+//! Differential testing of the toolchain: a Jack program is compiled into VM code, which is run
+//! by the VM emulator, and also translated into Hack assembly and machine code, which is run by
+//! the CPU emulator. Both must end with the same memory, as `hack_vm::emulator` lays out the
+//! memory in the same way as the translator. The program compiled with `-O`, and the one compiled
+//! by the native backend straight to assembly, must return the same value as well.
+use std::ops::Range;
+
+use anyhow::{bail, Context as _};
+use hack_asm::emulator::{self as cpu, Cpu};
+use hack_vm::{
+    emulator::{self as vm, Emulator},
+    parser, HackVm,
+};
+use jack_ast::SourceMap;
+use jack_compiler::{
+    compiler::{self, Backend},
+    diagnosis::DiagnosticReporter,
+    parser::Dialect,
+};
+
+use crate::gen::{JackProgram, OS_VM};
+
+/// Instructions the VM may execute before a program is considered not to halt.
+pub const MAX_VM_STEPS: usize = 1_000_000;
+
+// no VM instruction takes this many machine instructions, including `call` and `return`
+const CPU_STEPS_PER_VM_STEP: usize = 200;
+
+// memory which must match after halting: the pointers and `temp` in `RAM[0..13]`, the static
+// variables, and the start of the heap where `Main.main` allocates `arr` and `obj`. `RAM[13..16]`
+// are scratch registers of the translator, and the stack keeps return addresses which are
+// instruction indices in the VM but ROM addresses on the CPU.
+const COMPARED: [Range<usize>; 3] = [0..13, 16..256, 2048..2064];
+
+/// The result of a program on which the VM and the CPU agree.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// both halted, with `Main.main` returning the value
+    Halted(i16),
+    /// the VM did not halt within `MAX_VM_STEPS`, so the CPU was not run
+    StepLimit,
+}
+
+/// Runs `program` through the toolchain, and returns an error describing the bug if any stage
+/// fails or the VM and the CPU diverge.
+pub fn run(program: &JackProgram) -> anyhow::Result<Outcome> {
+    let classes: Vec<_> = program
+        .0
+        .iter()
+        .map(|class| (class.name, class.to_source()))
+        .collect();
+    check(&classes).with_context(|| {
+        let src: Vec<_> = classes.iter().map(|(_, src)| src.as_str()).collect();
+        format!("in the program:\n{}", src.join("\n"))
+    })
+}
+
+// `classes` are pairs of the name and the source of a class
+fn check(classes: &[(&str, String)]) -> anyhow::Result<Outcome> {
+    let (outcome, cpu_steps) = execute(&compile(classes, Backend::Vm, false)?)?;
+    let Outcome::Halted(expected) = outcome else { return Ok(outcome) };
+
+    let (optimized, _) = execute(&compile(classes, Backend::Vm, true)?).context("with `-O`")?;
+    if let Outcome::Halted(actual) = optimized {
+        if expected != actual {
+            bail!("`Main.main` returns {actual} with `-O`, but {expected} without it");
+        }
+    }
+
+    let actual = execute_native(&compile(classes, Backend::Asm, false)?, cpu_steps)
+        .context("with `--backend asm`")?;
+    if expected != actual {
+        bail!("`Main.main` returns {actual} with `--backend asm`, but {expected} with the VM");
+    }
+
+    Ok(outcome)
+}
+
+// runs the VM code of the classes on the VM and the CPU, and returns the steps taken by the CPU
+fn execute(classes: &[(&str, String)]) -> anyhow::Result<(Outcome, usize)> {
+    let files = classes
+        .iter()
+        .map(|(name, code)| (*name, code.as_str()))
+        .chain([("Sys", OS_VM)]);
+    let mut instructions = Vec::new();
+    for (name, code) in files {
+        let (is, errors) = parser::parse(code)?;
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e.context(format!("invalid VM code of {name}:\n{code}")));
+        }
+        instructions.push((name, is));
+    }
+
+    let mut vm = Emulator::new(&instructions)?;
+    if vm.run(MAX_VM_STEPS).context("VM emulator")? == vm::Exit::StepLimit {
+        return Ok((Outcome::StepLimit, 0));
+    }
+    let (vm_ram, vm_steps) = (vm.ram().to_vec(), vm.steps());
+    drop(vm);
+
+    let mut asm = Vec::new();
+    HackVm::new(&mut asm).interpret(instructions)?;
+    let asm: String = String::from_utf8(asm)?
+        .chars()
+        .filter(|c| *c != ' ')
+        .collect();
+    let binary = hack_asm::compile(&asm)?;
+
+    let mut cpu = Cpu::new(&binary)?;
+    let max_steps = vm_steps * CPU_STEPS_PER_VM_STEP + 1000;
+    if cpu.run(max_steps).context("CPU emulator")? == cpu::Exit::StepLimit {
+        bail!("the CPU did not halt after {max_steps} steps while the VM did");
+    }
+
+    for range in COMPARED {
+        for addr in range {
+            let (expected, actual) = (vm_ram[addr], cpu.ram()[addr]);
+            if expected != actual {
+                bail!("RAM[{addr}] is {actual} on the CPU, but {expected} on the VM");
+            }
+        }
+    }
+
+    // `temp 0`
+    Ok((Outcome::Halted(vm_ram[5]), cpu.steps()))
+}
+
+// runs the assembly of the classes on the CPU with the OS translated from VM code, and returns the
+// value of `Main.main`. The native code takes fewer steps than the translated VM code of the
+// classes, which took `cpu_steps`.
+fn execute_native(classes: &[(&str, String)], cpu_steps: usize) -> anyhow::Result<i16> {
+    let (sys, errors) = parser::parse(OS_VM)?;
+    if let Some(e) = errors.into_iter().next() {
+        return Err(e);
+    }
+    let mut program = Vec::new();
+    HackVm::new(&mut program).interpret(vec![("Sys", sys)])?;
+    let program: String = String::from_utf8(program)?
+        .chars()
+        .chain(classes.iter().flat_map(|(_, asm)| asm.chars()))
+        .filter(|c| *c != ' ')
+        .collect();
+    let binary = hack_asm::compile(&program)?;
+
+    let mut cpu = Cpu::new(&binary)?;
+    let max_steps = cpu_steps * 2 + 1000;
+    if cpu.run(max_steps).context("CPU emulator")? == cpu::Exit::StepLimit {
+        bail!("the CPU did not halt after {max_steps} steps while the VM did");
+    }
+
+    // `temp 0`, which `Sys.init` sets after `Main.main` returns
+    Ok(cpu.ram()[5])
+}
+
+// the code of every class of `classes`, which is VM code or assembly by `backend`
+fn compile<'c>(
+    classes: &[(&'c str, String)],
+    backend: Backend,
+    optimize: bool,
+) -> anyhow::Result<Vec<(&'c str, String)>> {
+    let mut sources = SourceMap::new();
+    for (name, src) in classes {
+        sources.add_file(format!("{name}.jack"), src.clone());
+    }
+
+    let mut reporter = DiagnosticReporter::new(&sources);
+    reporter.capture();
+    let options = compiler::Options {
+        dialect: Dialect::Extended,
+        optimize,
+        backend,
+        ..Default::default()
+    };
+    match compiler::compile_to_vm(&sources, &reporter, &options) {
+        Some(code) => Ok(classes.iter().map(|(name, _)| *name).zip(code).collect()),
+        None => {
+            let errors: Vec<_> = reporter
+                .take_messages()
+                .into_iter()
+                .filter(|msg| msg.severity == miette::Severity::Error)
+                .map(|msg| msg.text)
+                .collect();
+            bail!("failed to compile: {}", errors.join("\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    use super::*;
+
+    // xorshift, to derive fuzzer-like input from a seed without extra dependencies
+    fn bytes(mut seed: u64, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect()
+    }
+
+    fn check_main(src: &str) -> Outcome {
+        check(&[("Main", src.to_string())]).unwrap()
+    }
+
+    #[test]
+    fn vm_and_cpu_agree_on_generated_programs() {
+        let mut halted = 0;
+        for seed in 1..=300 {
+            let data = bytes(seed, 4096);
+            let program = JackProgram::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            match run(&program) {
+                Ok(Outcome::Halted(_)) => halted += 1,
+                Ok(Outcome::StepLimit) => {}
+                Err(e) => panic!("seed {seed}: {e:?}"),
+            }
+        }
+
+        assert!(halted > 250, "only {halted} programs halted");
+    }
+
+    #[test]
+    fn returns_checksum_of_main() {
+        let src = "\
+class Main {
+    function int main() {
+        var int x;
+        let x = 3;
+        return x + 4;
+    }
+}
+";
+        assert_eq!(check_main(src), Outcome::Halted(7));
+    }
+
+    #[test]
+    fn compare_values_of_different_signs() {
+        // `32767 - (-2)` overflows
+        let src = "\
+class Main {
+    function int main() {
+        return 32767 > -2;
+    }
+}
+";
+        assert_eq!(check_main(src), Outcome::Halted(-1));
+    }
+
+    #[test]
+    fn loop_jumps_and_else_if() {
+        // sum of the odd numbers below 7, leaving the outer loop at once
+        let src = "\
+class Main {
+    function int main() {
+        var int i, sum;
+        while (true) {
+            let i = 0;
+            while (i < 100) {
+                let i = i + 1;
+                if (i = 7) {
+                    break;
+                } else if ((i & 1) = 0) {
+                    continue;
+                }
+                let sum = sum + i;
+            }
+            break;
+        }
+        return sum;
+    }
+}
+";
+        assert_eq!(check_main(src), Outcome::Halted(1 + 3 + 5));
+    }
+
+    #[test]
+    fn for_and_compound_let() {
+        // the step runs before `continue`: 2 + 4 + 6 + 8, minus 1 per odd number
+        let src = "\
+class Main {
+    function int main() {
+        var int i, sum;
+        for (let i = 0; i < 10; let i += 1) {
+            if ((i & 1) = 1) {
+                let sum -= 1;
+                continue;
+            }
+            let sum += i;
+        }
+        return sum;
+    }
+}
+";
+        assert_eq!(check_main(src), Outcome::Halted(20 - 5));
+    }
+
+    #[test]
+    fn constants_and_enums() {
+        let src = "\
+class Main {
+    const int MIN = -32767 - 1;
+    enum Dir { UP = 3, DOWN }
+    function int main() {
+        return (MIN + Main.MIN) + Dir.DOWN;
+    }
+}
+";
+        assert_eq!(check_main(src), Outcome::Halted(4));
+    }
+
+    #[test]
+    fn folded_and_doubled() {
+        // every multiplication is folded or doubled with `-O`
+        let src = "\
+class Main {
+    const int K = 3 * 5;
+    function int main() {
+        var int x;
+        let x = ~~(K - (2 * 6));
+        if (K < 10) {
+            return x * 1000;
+        } else {
+            let x = x * 8;
+        }
+        while (false) {
+            let x = x * 7;
+        }
+        return (x * 1) + (2 * x);
+    }
+}
+";
+        assert_eq!(check_main(src), Outcome::Halted(24 * 3));
+    }
+
+    #[test]
+    fn multiply_and_divide_wrapping() {
+        let src = "\
+class Main {
+    function int main() {
+        var int min, x;
+        let min = -32767 - 1;
+        let x = 300;
+        if (~((x * x) = (300 * 300))) {
+            return 1;
+        }
+        if (~((min / 1) = min) | ~((x * -x) = -(300 * 300))) {
+            return 2;
+        }
+        return ((-7 / 2) + (min / 32767)) + ((x / 7) * 100);
+    }
+}
+";
+        assert_eq!(check_main(src), Outcome::Halted(-3 - 1 + 4200));
+    }
+
+    #[test]
+    fn methods_constructors_and_arrays() {
+        let cell = "\
+class Cell {
+    field int x0, x1;
+    constructor Cell new(int a0) {
+        let x0 = a0;
+        return this;
+    }
+    method int get() {
+        return x0 + x1;
+    }
+    method int add(int a0) {
+        let x1 = x1 + a0;
+        return get();
+    }
+}
+";
+        let main = "\
+class Main {
+    static Array arr;
+    static Cell obj;
+    function int main() {
+        var int i;
+        let arr = Array.new(4);
+        let obj = Cell.new(10);
+        while (i < 4) {
+            let arr[i] = obj.add(i);
+            let i = i + 1;
+        }
+        return ((arr[0] + arr[1]) + arr[2]) + arr[3];
+    }
+}
+";
+        let classes = [("Cell", cell.to_string()), ("Main", main.to_string())];
+        assert_eq!(check(&classes).unwrap(), Outcome::Halted(10 + 11 + 13 + 16));
+    }
+
+    #[test]
+    fn inlined_functions() {
+        let src = "\
+class Main {
+    static int calls;
+    function int abs(int x) {
+        let calls = calls + 1;
+        if (x < 0) {
+            return -x;
+        }
+        return x;
+    }
+    function int main() {
+        var int a;
+        let a = Main.abs(-7) + Main.abs(Main.abs(2) - 5);
+        return a + calls;
+    }
+}
+";
+        assert_eq!(check_main(src), Outcome::Halted(7 + 3 + 3));
+    }
+
+    #[test]
+    fn radix_and_char_literals() {
+        let src = "\
+class Main {
+    function int main() {
+        return (0x10 | 0b1) + 'A';
+    }
+}
+";
+        assert_eq!(check_main(src), Outcome::Halted(17 + 65));
+    }
+}
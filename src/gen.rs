@@ -0,0 +1,718 @@
+//! Generator of well-typed Jack programs from unstructured fuzzer input.
+//!
+//! Programs are kept within the subset which runs on the minimal OS of `OS_VM`:
+//! - a class `Cell` of `int` fields, a constructor and `method`s returning `int`
+//! - a class `Main` of `int` statics and `function`s returning `int`, and the statics `arr` of
+//!   `ARRAY_LEN` elements and `obj` of `Cell`, which `Main.main` allocates before anything else
+//! - indices of `arr` are masked into its bounds, and divisors are positive
+//! - no strings, which would call `String`
+//! - functions and methods only call the ones defined before them, and methods of `Cell`, and
+//!   every `while` counts up to a small bound with a counter nothing else assigns, so that every
+//!   program terminates
+//! - `else if`, `break`, `continue`, `for`, `+=`/`-=`, `const` and `enum` of the extended dialect
+//!
+//! `Main.main` returns a checksum of its computation, and is called by `OS_VM`.
+use arbitrary::{Arbitrary, Result, Unstructured};
+use jack_ast::*;
+
+/// The OS of generated programs:
+/// - `Sys.init`, which calls `Main.main`, stores its result to `temp 0` and halts
+/// - `Memory.alloc`, which never frees, and `Array.new`
+/// - `Math.multiply`, and `Math.divide` of a positive divisor, which follow the 16-bit wrapping
+///   arithmetic of constant folding
+pub const OS_VM: &str = "\
+function Sys.init 0
+call Main.main 0
+pop temp 0
+label END
+goto END
+function Memory.alloc 0
+push static 0
+push constant 2048
+add
+push static 0
+push argument 0
+add
+pop static 0
+return
+function Array.new 0
+push argument 0
+call Memory.alloc 1
+return
+// adds `x` for every bit of `y` from the top
+function Math.multiply 2
+push constant 16
+pop local 1
+label LOOP
+push local 0
+push local 0
+add
+pop local 0
+push argument 1
+push constant 0
+lt
+not
+if-goto NEXT
+push local 0
+push argument 0
+add
+pop local 0
+label NEXT
+push argument 1
+push argument 1
+add
+pop argument 1
+push local 1
+push constant 1
+sub
+pop local 1
+push local 1
+push constant 0
+eq
+not
+if-goto LOOP
+push local 0
+return
+// long division of `|x|` as an unsigned number, whose remainder `r` may overflow when doubled
+function Math.divide 4
+push argument 0
+push constant 0
+lt
+pop local 3
+push local 3
+not
+if-goto ABS
+push argument 0
+neg
+pop argument 0
+label ABS
+push constant 16
+pop local 2
+label LOOP
+push local 1
+push local 1
+add
+pop local 1
+push argument 0
+push constant 0
+lt
+not
+if-goto SHIFT
+push local 1
+push constant 1
+add
+pop local 1
+label SHIFT
+push argument 0
+push argument 0
+add
+pop argument 0
+push local 0
+push local 0
+add
+pop local 0
+push local 1
+push constant 0
+lt
+push local 1
+push argument 1
+lt
+not
+or
+not
+if-goto NEXT
+push local 1
+push argument 1
+sub
+pop local 1
+push local 0
+push constant 1
+add
+pop local 0
+label NEXT
+push local 2
+push constant 1
+sub
+pop local 2
+push local 2
+push constant 0
+eq
+not
+if-goto LOOP
+push local 3
+not
+if-goto POSITIVE
+push local 0
+neg
+return
+label POSITIVE
+push local 0
+return
+";
+
+/// Number of elements of `Main.arr`.
+pub const ARRAY_LEN: u32 = 4;
+
+const STATICS: [&str; 3] = ["s0", "s1", "s2"];
+const FIELDS: [&str; 3] = ["x0", "x1", "x2"];
+const PARAMS: [&str; 3] = ["a0", "a1", "a2"];
+const LOCALS: [&str; 3] = ["v0", "v1", "v2"];
+const COUNTERS: [&str; 2] = ["i0", "i1"];
+const FUNCTIONS: [&str; 4] = ["f0", "f1", "f2", "f3"];
+const METHODS: [&str; 3] = ["m0", "m1", "m2"];
+const CONSTANTS: [&str; 2] = ["C0", "C1"];
+// members of the enum `E`
+const MEMBERS: [&str; 3] = ["A", "B", "C"];
+
+const MAX_STMTS: usize = 4;
+const MAX_STMT_DEPTH: usize = 2;
+const MAX_EXPR_DEPTH: usize = 3;
+const MAX_ITERATIONS: u32 = 5;
+
+/// A program of the classes `Cell` and `Main`.
+#[derive(Debug)]
+pub struct JackProgram(pub Vec<Class<'static>>);
+
+impl<'a> Arbitrary<'a> for JackProgram {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut generator = Generator::new(u);
+        let cell = generator.cell()?;
+        let main = generator.main()?;
+        Ok(JackProgram(vec![cell, main]))
+    }
+}
+
+struct Generator<'u, 'a> {
+    u: &'u mut Unstructured<'a>,
+    // `Cell` or `Main`, which is being generated
+    class: &'static str,
+    // `int` variables of the class, which are the fields of `Cell` or the statics of `Main`
+    class_vars: &'static [&'static str],
+    constants: usize,
+    members: usize,
+    // number of parameters of every function of `Main` and method of `Cell` generated so far,
+    // and of the constructor of `Cell`
+    functions: Vec<usize>,
+    methods: Vec<usize>,
+    ctor_params: usize,
+    // variables of the function being generated
+    params: usize,
+    locals: usize,
+    // number of `while` enclosing the current statement, and the most in the function
+    loops: usize,
+    max_loops: usize,
+}
+
+impl<'u, 'a> Generator<'u, 'a> {
+    fn new(u: &'u mut Unstructured<'a>) -> Self {
+        Generator {
+            u,
+            class: "Cell",
+            class_vars: &[],
+            constants: 0,
+            members: 0,
+            functions: Vec::new(),
+            methods: Vec::new(),
+            ctor_params: 0,
+            params: 0,
+            locals: 0,
+            loops: 0,
+            max_loops: 0,
+        }
+    }
+
+    fn cell(&mut self) -> Result<Class<'static>> {
+        self.class = "Cell";
+        self.class_vars = &FIELDS[..self.u.int_in_range(1..=FIELDS.len())?];
+        let variables = self
+            .class_vars
+            .iter()
+            .map(|name| var_def(name, VariableDefKind::Field, Type::Int))
+            .collect();
+
+        self.ctor_params = self.u.int_in_range(0..=PARAMS.len())?;
+        let mut functions = vec![self.fn_def("new", FnKind::Ctor, self.ctor_params)?];
+        for name in &METHODS[..self.u.int_in_range(0..=METHODS.len())?] {
+            let n_params = self.u.int_in_range(0..=PARAMS.len())?;
+            functions.push(self.fn_def(name, FnKind::Method, n_params)?);
+            self.methods.push(n_params);
+        }
+
+        Ok(Class {
+            name: "Cell",
+            span: span(),
+            variables,
+            constants: Vec::new(),
+            enums: Vec::new(),
+            functions,
+        })
+    }
+
+    fn main(&mut self) -> Result<Class<'static>> {
+        self.class = "Main";
+        self.class_vars = &STATICS[..self.u.int_in_range(0..=STATICS.len())?];
+        let variables = self
+            .class_vars
+            .iter()
+            .map(|name| var_def(name, VariableDefKind::Static, Type::Int))
+            .chain([
+                var_def("arr", VariableDefKind::Static, Type::Class("Array")),
+                var_def("obj", VariableDefKind::Static, Type::Class("Cell")),
+            ])
+            .collect();
+
+        // a constant may refer to the ones before it
+        let mut constants = Vec::new();
+        for name in &CONSTANTS[..self.u.int_in_range(0..=CONSTANTS.len())?] {
+            let value = match self.named_constant()? {
+                Some(kind) if self.u.arbitrary()? => expr(term(kind)),
+                _ => expr(term(TermKind::Const(self.constant()?))),
+            };
+            constants.push(ConstDef {
+                name,
+                ty: Type::Int,
+                value,
+                span: span(),
+            });
+            self.constants += 1;
+        }
+
+        self.members = self.u.int_in_range(0..=MEMBERS.len())?;
+        let mut enums = Vec::new();
+        if self.members > 0 {
+            let mut members = Vec::new();
+            for name in &MEMBERS[..self.members] {
+                let value = match self.u.arbitrary()? {
+                    true => Some(expr(term(TermKind::Const(self.constant()?)))),
+                    false => None,
+                };
+                members.push(EnumMember {
+                    name,
+                    value,
+                    span: span(),
+                });
+            }
+            enums.push(EnumDef {
+                name: "E",
+                span: span(),
+                members,
+            });
+        }
+
+        let n_functions = self.u.int_in_range(0..=FUNCTIONS.len())?;
+        let mut functions = Vec::new();
+        for name in &FUNCTIONS[..n_functions] {
+            let n_params = self.u.int_in_range(0..=PARAMS.len())?;
+            functions.push(self.fn_def(name, FnKind::Function, n_params)?);
+            self.functions.push(n_params);
+        }
+        functions.push(self.fn_def("main", FnKind::Function, 0)?);
+
+        Ok(Class {
+            name: "Main",
+            span: span(),
+            variables,
+            constants,
+            enums,
+            functions,
+        })
+    }
+
+    fn fn_def(
+        &mut self,
+        name: &'static str,
+        kind: FnKind,
+        n_params: usize,
+    ) -> Result<FnDef<'static>> {
+        self.params = n_params;
+        self.locals = self.u.int_in_range(0..=LOCALS.len())?;
+        self.loops = 0;
+        self.max_loops = 0;
+
+        let mut statements = Vec::new();
+        if (self.class, name) == ("Main", "main") {
+            statements.extend(self.allocation()?);
+        }
+        statements.extend(self.stmts(0)?);
+        let (ret, value) = match kind {
+            FnKind::Ctor => (
+                Type::Class("Cell"),
+                expr(term(TermKind::Const(Constant::This))),
+            ),
+            _ => (Type::Int, self.expr(MAX_EXPR_DEPTH)?),
+        };
+        statements.push(stmt(StmtKind::Return(Some(value))));
+
+        let variables = LOCALS[..self.locals]
+            .iter()
+            .chain(&COUNTERS[..self.max_loops])
+            .map(|name| var_def(name, VariableDefKind::Var, Type::Int))
+            .collect();
+        let params = PARAMS[..n_params]
+            .iter()
+            .map(|name| Parameter {
+                name,
+                ty: Type::Int,
+                span: span(),
+            })
+            .collect();
+
+        Ok(FnDef {
+            name,
+            span: span(),
+            kind,
+            ret,
+            params,
+            body: FnBody {
+                variables,
+                statements,
+            },
+        })
+    }
+
+    // let arr = Array.new(ARRAY_LEN);
+    // let obj = Cell.new(..);
+    fn allocation(&mut self) -> Result<[Stmt<'static>; 2]> {
+        let arr = stmt(StmtKind::Let {
+            lhs: variable("arr"),
+            rhs: expr(term(TermKind::FnCall(call(
+                Some("Array"),
+                "new",
+                vec![expr(int(ARRAY_LEN))],
+            )))),
+        });
+        // constants, as a call to a function could use `obj` before it's allocated
+        let args = (0..self.ctor_params)
+            .map(|_| Ok(expr(term(TermKind::Const(self.constant()?)))))
+            .collect::<Result<_>>()?;
+        let obj = stmt(StmtKind::Let {
+            lhs: variable("obj"),
+            rhs: expr(term(TermKind::FnCall(call(Some("Cell"), "new", args)))),
+        });
+        Ok([arr, obj])
+    }
+
+    fn stmts(&mut self, depth: usize) -> Result<Vec<Stmt<'static>>> {
+        let n = self.u.int_in_range(0..=MAX_STMTS)?;
+        let mut stmts = Vec::new();
+        for _ in 0..n {
+            stmts.extend(self.stmt(depth)?);
+        }
+        Ok(stmts)
+    }
+
+    // Generates a statement, or a `while` with the initialization of its counter.
+    fn stmt(&mut self, depth: usize) -> Result<Vec<Stmt<'static>>> {
+        let nested = depth < MAX_STMT_DEPTH;
+        let kind = match self.u.int_in_range(0..=4)? {
+            1 if nested => self.if_stmt(depth)?,
+            2 if nested && self.loops < COUNTERS.len() => return self.counted_loop(depth),
+            3 if self.has_callees() => StmtKind::Do(self.fn_call(MAX_EXPR_DEPTH)?),
+            4 if self.loops > 0 => {
+                if self.u.arbitrary()? {
+                    StmtKind::Break
+                } else {
+                    StmtKind::Continue
+                }
+            }
+            _ => {
+                let lhs = match self.assignable() {
+                    Some(_) if self.class == "Main" && self.u.ratio(1, 4)? => {
+                        self.element(MAX_EXPR_DEPTH)?
+                    }
+                    Some(lhs) => variable(lhs),
+                    None => return Ok(Vec::new()),
+                };
+                let rhs = self.expr(MAX_EXPR_DEPTH)?;
+                match self.u.int_in_range(0..=3)? {
+                    0 => StmtKind::CompoundLet {
+                        lhs,
+                        op: BinOp::Plus,
+                        rhs,
+                    },
+                    1 => StmtKind::CompoundLet {
+                        lhs,
+                        op: BinOp::Minus,
+                        rhs,
+                    },
+                    _ => StmtKind::Let { lhs, rhs },
+                }
+            }
+        };
+
+        Ok(vec![stmt(kind)])
+    }
+
+    fn if_stmt(&mut self, depth: usize) -> Result<StmtKind<'static>> {
+        let cond = self.expr(MAX_EXPR_DEPTH)?;
+        let then_branch = self.stmts(depth + 1)?;
+        let else_branch = match self.u.int_in_range(0..=2)? {
+            0 => None,
+            1 if depth + 1 < MAX_STMT_DEPTH => {
+                Some(Else::If(Box::new(stmt(self.if_stmt(depth + 1)?))))
+            }
+            _ => Some(Else::Block(self.stmts(depth + 1)?)),
+        };
+
+        Ok(StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    // let i = 0;
+    // while (i < n) { let i = i + 1; .. }
+    //
+    // the counter is incremented first, so that `continue` does not skip it. `for` increments it
+    // last, as `continue` of `for` runs the step.
+    fn counted_loop(&mut self, depth: usize) -> Result<Vec<Stmt<'static>>> {
+        let counter = COUNTERS[self.loops];
+        let n = self.u.int_in_range(0..=MAX_ITERATIONS)?;
+        let init = stmt(StmtKind::Let {
+            lhs: variable(counter),
+            rhs: expr(int(0)),
+        });
+        let cond = binary(var_term(counter), BinOp::Lt, int(n));
+        let step = stmt(StmtKind::CompoundLet {
+            lhs: variable(counter),
+            op: BinOp::Plus,
+            rhs: expr(int(1)),
+        });
+
+        self.loops += 1;
+        self.max_loops = self.max_loops.max(self.loops);
+        let mut body = self.stmts(depth + 1)?;
+        self.loops -= 1;
+
+        if self.u.arbitrary()? {
+            return Ok(vec![stmt(StmtKind::For {
+                init: Box::new(init),
+                cond,
+                step: Box::new(step),
+                body,
+            })]);
+        }
+
+        body.insert(0, step);
+        Ok(vec![init, stmt(StmtKind::While { cond, body })])
+    }
+
+    fn expr(&mut self, depth: usize) -> Result<Expr<'static>> {
+        let lhs = self.term(depth)?;
+        if depth == 0 || self.u.ratio(1, 3)? {
+            return Ok(expr(lhs));
+        }
+
+        let op = self
+            .u
+            .choose(&[
+                BinOp::Plus,
+                BinOp::Minus,
+                BinOp::Mul,
+                BinOp::Div,
+                BinOp::And,
+                BinOp::Or,
+                BinOp::Equal,
+                BinOp::Lt,
+                BinOp::Gt,
+            ])?
+            .clone();
+        let rhs = match op {
+            BinOp::Div => self.divisor(depth - 1)?,
+            _ => self.term(depth - 1)?,
+        };
+        Ok(Expr {
+            lhs: Box::new(lhs),
+            rhs: Some(Box::new((op, rhs))),
+        })
+    }
+
+    fn term(&mut self, depth: usize) -> Result<Term<'static>> {
+        let nested = depth > 0;
+        let kind = match self.u.int_in_range(0..=5)? {
+            0 => match self.named_constant()? {
+                Some(kind) => kind,
+                None => TermKind::Const(self.constant()?),
+            },
+            1 if nested && self.class == "Main" && self.u.arbitrary()? => {
+                TermKind::Variable(self.element(depth - 1)?)
+            }
+            1 | 2 => match self.readable() {
+                Some(name) => TermKind::Variable(variable(name)),
+                None => TermKind::Const(self.constant()?),
+            },
+            3 if nested && self.has_callees() => TermKind::FnCall(self.fn_call(depth - 1)?),
+            4 if nested => TermKind::Expr(Box::new(self.expr(depth - 1)?)),
+            5 if nested => TermKind::Unary {
+                op: self.u.choose(&[UnaryOp::Minus, UnaryOp::Not])?.clone(),
+                term: Box::new(self.term(depth - 1)?),
+            },
+            _ => TermKind::Const(self.constant()?),
+        };
+
+        Ok(term(kind))
+    }
+
+    // a literal, or `((t | 1) & 127)` for any term `t`
+    fn divisor(&mut self, depth: usize) -> Result<Term<'static>> {
+        if self.u.arbitrary()? {
+            let n = *self.u.choose(&[1, 2, 3, 7, 10, 127, 16384, 32767])?;
+            return Ok(int(n));
+        }
+
+        let odd = binary(self.term(depth)?, BinOp::Or, int(1));
+        let masked = binary(term(TermKind::Expr(Box::new(odd))), BinOp::And, int(127));
+        Ok(term(TermKind::Expr(Box::new(masked))))
+    }
+
+    // `arr[(e) & (ARRAY_LEN - 1)]`
+    fn element(&mut self, depth: usize) -> Result<Variable<'static>> {
+        let index = term(TermKind::Expr(Box::new(self.expr(depth)?)));
+        Ok(Variable {
+            name: "arr",
+            index_accessor: Some(binary(index, BinOp::And, int(ARRAY_LEN - 1))),
+            span: span(),
+        })
+    }
+
+    fn constant(&mut self) -> Result<Constant<'static>> {
+        let c = match self.u.int_in_range(0..=3)? {
+            0 => Constant::True,
+            1 => Constant::False,
+            // values around the boundaries of 16-bit arithmetic
+            2 => Constant::Integer(*self.u.choose(&[0, 1, 2, 16383, 16384, 32766, 32767])?),
+            _ => Constant::Integer(self.u.int_in_range(0..=32767)?),
+        };
+        Ok(c)
+    }
+
+    // `C0`, `Main.C0` or `E.A`, out of the ones defined so far
+    fn named_constant(&mut self) -> Result<Option<TermKind<'static>>> {
+        let n = self.constants + self.members;
+        if n == 0 {
+            return Ok(None);
+        }
+
+        let i = self.u.choose_index(n)?;
+        let kind = match CONSTANTS[..self.constants].get(i) {
+            Some(name) if self.u.arbitrary()? => TermKind::Variable(variable(name)),
+            Some(name) => TermKind::Qualified {
+                scope: "Main",
+                name,
+            },
+            None => TermKind::Qualified {
+                scope: "E",
+                name: MEMBERS[i - self.constants],
+            },
+        };
+        Ok(Some(kind))
+    }
+
+    fn has_callees(&self) -> bool {
+        !(self.functions.is_empty() && self.methods.is_empty())
+    }
+
+    // `Main.f0(..)`, or `m0(..)` in `Cell` and `obj.m0(..)` in `Main`
+    fn fn_call(&mut self, depth: usize) -> Result<FnCall<'static>> {
+        let index = self
+            .u
+            .choose_index(self.functions.len() + self.methods.len())?;
+        let (receiver, fn_name, n_params) = match index.checked_sub(self.functions.len()) {
+            None => (Some("Main"), FUNCTIONS[index], self.functions[index]),
+            Some(i) if self.class == "Main" => (Some("obj"), METHODS[i], self.methods[i]),
+            Some(i) => (None, METHODS[i], self.methods[i]),
+        };
+        let args = (0..n_params)
+            .map(|_| self.expr(depth))
+            .collect::<Result<_>>()?;
+
+        Ok(call(receiver, fn_name, args))
+    }
+
+    // a variable which `let` may assign, which excludes the counters of loops
+    fn assignable(&mut self) -> Option<&'static str> {
+        let candidates: Vec<_> = self
+            .class_vars
+            .iter()
+            .chain(&PARAMS[..self.params])
+            .chain(&LOCALS[..self.locals])
+            .collect();
+        self.u.choose(&candidates).ok().copied().copied()
+    }
+
+    fn readable(&mut self) -> Option<&'static str> {
+        let candidates: Vec<_> = self
+            .class_vars
+            .iter()
+            .chain(&PARAMS[..self.params])
+            .chain(&LOCALS[..self.locals])
+            .chain(&COUNTERS[..self.loops])
+            .collect();
+        self.u.choose(&candidates).ok().copied().copied()
+    }
+}
+
+fn span() -> Span {
+    Span::DUMMY
+}
+
+fn stmt(kind: StmtKind<'static>) -> Stmt<'static> {
+    Stmt { kind, span: span() }
+}
+
+fn term(kind: TermKind<'static>) -> Term<'static> {
+    Term { kind, span: span() }
+}
+
+fn int(n: u32) -> Term<'static> {
+    term(TermKind::Const(Constant::Integer(n)))
+}
+
+fn var_term(name: &'static str) -> Term<'static> {
+    term(TermKind::Variable(variable(name)))
+}
+
+fn variable(name: &'static str) -> Variable<'static> {
+    Variable {
+        name,
+        index_accessor: None,
+        span: span(),
+    }
+}
+
+fn var_def(name: &'static str, kind: VariableDefKind, ty: Type<'static>) -> VariableDef<'static> {
+    VariableDef {
+        name,
+        kind,
+        ty,
+        span: span(),
+    }
+}
+
+fn call(
+    receiver: Option<&'static str>,
+    fn_name: &'static str,
+    args: Vec<Expr<'static>>,
+) -> FnCall<'static> {
+    FnCall {
+        receiver,
+        fn_name,
+        args,
+        span: span(),
+    }
+}
+
+fn expr(lhs: Term<'static>) -> Expr<'static> {
+    Expr {
+        lhs: Box::new(lhs),
+        rhs: None,
+    }
+}
+
+fn binary(lhs: Term<'static>, op: BinOp, rhs: Term<'static>) -> Expr<'static> {
+    Expr {
+        lhs: Box::new(lhs),
+        rhs: Some(Box::new((op, rhs))),
+    }
+}
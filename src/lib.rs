@@ -0,0 +1,7 @@
+//! The whole toolchain put together, Jack → VM → Hack assembly → machine code, for testing it
+//! end to end and for building the debug info of a program.
+#![forbid(unsafe_code)]
+
+pub mod debuginfo;
+pub mod difftest;
+pub mod gen;